@@ -0,0 +1,89 @@
+//! Disagreements between the crates.io index and the db dump about versions of crates, as both are used as source of
+//! truth by different parts of the pipeline.
+use serde_derive::{Deserialize, Serialize};
+
+/// The name of the directory containing the discrepancy report.
+/// It can't clash with the directory of a crate as crate names can't start with an underscore.
+pub const DISCREPANCIES_DIR_NAME: &str = "__discrepancies__";
+
+/// What the index and the db dump disagree about
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub enum DiscrepancyKind {
+    /// The version is yanked according to one source, but not according to the other
+    Yanked,
+    /// The checksums of the crate archive differ
+    Checksum,
+}
+
+impl DiscrepancyKind {
+    /// The name of the kind, as stored in the database
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DiscrepancyKind::Yanked => "yanked",
+            DiscrepancyKind::Checksum => "checksum",
+        }
+    }
+
+    /// Parse the name of a kind as returned by `as_str()`
+    pub fn parse(name: &str) -> Option<DiscrepancyKind> {
+        Some(match name {
+            "yanked" => DiscrepancyKind::Yanked,
+            "checksum" => DiscrepancyKind::Checksum,
+            _ => return None,
+        })
+    }
+}
+
+/// The state of a crate version according to one source
+pub struct VersionState<'a> {
+    pub yanked: bool,
+    /// The hex-encoded SHA-256 checksum of the crate archive, if the source knows it
+    pub checksum: Option<&'a str>,
+}
+
+/// A version of a crate the index and the db dump disagree about
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct VersionDiscrepancy {
+    pub crate_name: String,
+    pub crate_version: String,
+    pub kind: DiscrepancyKind,
+    /// The value according to the crates.io index
+    pub index: String,
+    /// The value according to the db dump
+    pub db_dump: String,
+}
+
+impl VersionDiscrepancy {
+    /// Compare the state of a crate version according to the `index` and the `db_dump`, and return all discrepancies.
+    /// Checksums are only compared if both sources know them.
+    pub fn find(
+        crate_name: &str,
+        crate_version: &str,
+        index: VersionState<'_>,
+        db_dump: VersionState<'_>,
+    ) -> Vec<VersionDiscrepancy> {
+        let discrepancy = |kind, index: String, db_dump: String| VersionDiscrepancy {
+            crate_name: crate_name.to_owned(),
+            crate_version: crate_version.to_owned(),
+            kind,
+            index,
+            db_dump,
+        };
+        let mut discrepancies = Vec::new();
+        if index.yanked != db_dump.yanked {
+            discrepancies.push(discrepancy(
+                DiscrepancyKind::Yanked,
+                index.yanked.to_string(),
+                db_dump.yanked.to_string(),
+            ));
+        }
+        if let (Some(index), Some(db_dump)) = (index.checksum, db_dump.checksum) {
+            if !index.eq_ignore_ascii_case(db_dump) {
+                discrepancies.push(discrepancy(DiscrepancyKind::Checksum, index.into(), db_dump.into()));
+            }
+        }
+        discrepancies
+    }
+}
@@ -0,0 +1,18 @@
+//! Crates without any version and versions whose archive is empty, which can't be part of any report but are worth
+//! knowing about as they would otherwise go unnoticed.
+use serde_derive::{Deserialize, Serialize};
+
+/// The name of the directory containing the report of empty artifacts.
+/// It can't clash with the directory of a crate as crate names can't start with an underscore.
+pub const EMPTY_ARTIFACTS_DIR_NAME: &str = "__empty__";
+
+/// A crate without any version, or a version of a crate whose archive is empty
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct EmptyArtifact {
+    pub crate_name: String,
+    /// The version whose archive is empty, or None if the crate has no version at all
+    pub crate_version: Option<String>,
+    /// Why the artifact is considered empty
+    pub reason: String,
+}
@@ -0,0 +1,123 @@
+//! Statistics about where the repositories of crates are hosted, to see how much of the registry depends on
+//! a few hosting services.
+use super::{project::normalize_repository_url, total_info, AggregateVersionInfo, Dict};
+use serde_derive::{Deserialize, Serialize};
+use std::{cmp::Reverse, collections::BTreeMap};
+
+/// The name of the directory containing all host reports.
+/// It can't clash with the directory of a crate as crate names can't start with an underscore.
+pub const HOSTS_DIR_NAME: &str = "__hosts__";
+
+/// Hosts of well-known hosting services along with their name.
+/// Their repositories are always identified by the first two segments of their path, like `owner/repo`.
+const HOSTING_SERVICES: &[(&str, &str)] = &[
+    ("github.com", "GitHub"),
+    ("gitlab.com", "GitLab"),
+    ("bitbucket.org", "Bitbucket"),
+    ("codeberg.org", "Codeberg"),
+    ("git.sr.ht", "sr.ht"),
+];
+
+/// Return the name of the hosting service at the normalized `host`, or None if it's probably self-hosted.
+pub fn hosting_service(host: &str) -> Option<&'static str> {
+    HOSTING_SERVICES
+        .iter()
+        .find(|(service_host, _)| *service_host == host)
+        .map(|(_, name)| *name)
+}
+
+/// Return the normalized host of the repository at `url`, like `github.com`, or None if it doesn't look like the url
+/// of a repository.
+pub fn repository_host(url: &str) -> Option<String> {
+    normalize_repository_url(url).and_then(|repository| repository.split('/').next().map(ToOwned::to_owned))
+}
+
+/// All crates whose repository is on the same host
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct Host {
+    /// The normalized name of the host, like `github.com`
+    pub name: String,
+    /// The name of the hosting service, or None if the host is probably self-hosted
+    pub service: Option<String>,
+    /// The names of the crates published from each repository on the host, by normalized repository url
+    pub crates_by_repository: Dict<Vec<String>>,
+    pub info_by_crate: Dict<AggregateVersionInfo>,
+}
+
+impl Host {
+    /// The combined size, waste and potential gains of all crates on the host
+    pub fn total(&self) -> AggregateVersionInfo {
+        total_info(self.info_by_crate.values())
+    }
+
+    /// The path of the host report relative to the hosts directory
+    pub fn path(&self) -> String {
+        format!("{}.html", self.name)
+    }
+}
+
+/// How the repositories of all crates are distributed across hosts
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct HostingDistribution {
+    /// All hosts with at least one crate, the ones with the most crates first
+    pub hosts: Vec<Host>,
+    /// The amount of crates without a repository, or with one we don't recognize as such
+    pub crates_without_repository: u64,
+}
+
+impl HostingDistribution {
+    /// Distribute the crates in `info_by_crate` across the hosts of their repository, as provided by `repositories` as
+    /// pairs of crate name and repository url.
+    pub fn new<'a>(
+        repositories: impl IntoIterator<Item = (&'a str, &'a str)>,
+        info_by_crate: &Dict<AggregateVersionInfo>,
+    ) -> HostingDistribution {
+        let mut hosts = BTreeMap::<String, Host>::new();
+        for (crate_name, url) in repositories {
+            if let (Some(info), Some(repository)) = (info_by_crate.get(crate_name), normalize_repository_url(url)) {
+                let name = repository
+                    .split('/')
+                    .next()
+                    .expect("normalized url with host")
+                    .to_owned();
+                let host = hosts.entry(name.clone()).or_insert_with(|| Host {
+                    service: hosting_service(&name).map(ToOwned::to_owned),
+                    name,
+                    crates_by_repository: Dict::new(),
+                    info_by_crate: Dict::new(),
+                });
+                host.crates_by_repository
+                    .entry(repository)
+                    .or_default()
+                    .push(crate_name.to_owned());
+                host.info_by_crate.insert(crate_name.to_owned(), info.clone());
+            }
+        }
+        let mut hosts: Vec<_> = hosts.into_values().collect();
+        hosts.sort_by_key(|host| Reverse(host.info_by_crate.len()));
+        let crates_with_repository: usize = hosts.iter().map(|host| host.info_by_crate.len()).sum();
+        HostingDistribution {
+            hosts,
+            crates_without_repository: info_by_crate.len().saturating_sub(crates_with_repository) as u64,
+        }
+    }
+
+    /// The amount of crates with a repository on any host
+    pub fn crates_with_repository(&self) -> u64 {
+        self.hosts.iter().map(|host| host.info_by_crate.len() as u64).sum()
+    }
+
+    /// The amount of crates on each hosting service, with all self-hosted crates counted under None, the services with
+    /// the most crates first
+    pub fn crates_by_service(&self) -> Vec<(Option<String>, u64)> {
+        let mut crates_by_service = BTreeMap::<Option<String>, u64>::new();
+        for host in &self.hosts {
+            *crates_by_service.entry(host.service.clone()).or_default() += host.info_by_crate.len() as u64;
+        }
+        let mut crates_by_service: Vec<_> = crates_by_service.into_iter().collect();
+        crates_by_service.sort_by_key(|(_, crates)| Reverse(*crates));
+        crates_by_service
+    }
+}
@@ -1,4 +1,8 @@
-use super::{AggregateFileInfo, Dict, Fix, Report, VersionInfo};
+use super::{
+    discrepancy::DISCREPANCIES_DIR_NAME, empty::EMPTY_ARTIFACTS_DIR_NAME, hosting::HOSTS_DIR_NAME,
+    project::PROJECTS_DIR_NAME, AggregateFileInfo, Dict, DiscrepancyKind, EmptyArtifact, Fix, Host,
+    HostingDistribution, Owner, Project, Provenance, Report, Spotlight, VersionDiscrepancy, VersionInfo,
+};
 use bytesize::ByteSize;
 use dia_semver::Semver;
 use horrorshow::{box_html, helper::doctype, html, Render, RenderBox, RenderOnce, TemplateBuffer};
@@ -76,6 +80,109 @@ fn savings_section(d: Option<AggregateFileInfo>) -> Box<dyn Render> {
     }
 }
 
+fn spotlight_section(spotlight: Option<Spotlight>) -> Box<dyn RenderBox> {
+    let spotlight = spotlight.map(|spotlight| match spotlight {
+        Spotlight::MostImproved {
+            crate_name,
+            crate_version,
+            previous_waste,
+            waste,
+        } => (
+            "Most improved",
+            format!("{}/{}.html", crate_name, crate_version),
+            format!("{} v{}", crate_name, crate_version),
+            format!(
+                " now wastes {} in {} files, down from {} in {} files",
+                ByteSize(waste.total_bytes),
+                waste.total_files,
+                ByteSize(previous_waste.total_bytes),
+                previous_waste.total_files
+            ),
+        ),
+        Spotlight::NewlyAnalyzed { crate_name, all, waste } => (
+            "Newly analyzed",
+            crate_name.clone(),
+            crate_name,
+            format!(
+                " with {} in {} files, of which {} in {} files are wasted",
+                ByteSize(all.total_bytes),
+                all.total_files,
+                ByteSize(waste.total_bytes),
+                waste.total_files
+            ),
+        ),
+        Spotlight::MostWasteful {
+            crate_name,
+            crate_version,
+            waste,
+        } => (
+            "Most wasteful",
+            format!("{}/{}.html", crate_name, crate_version),
+            format!("{} v{}", crate_name, crate_version),
+            format!(" wastes {} in {} files", ByteSize(waste.total_bytes), waste.total_files),
+        ),
+    });
+    box_html! {
+        @ if let Some((title, href, name, description)) = spotlight {
+            section(id="spotlight") {
+                h3: title;
+                p {
+                    a(href=href): name;
+                    : description;
+                }
+            }
+        }
+    }
+}
+
+fn owners_section(owners: Vec<Owner>) -> Box<dyn RenderBox> {
+    box_html! {
+        @ if !owners.is_empty() {
+            section(id="owners") {
+                h3: "Owners";
+                ul {
+                    @ for Owner { login, name, is_team } in owners.into_iter() {
+                        li {
+                            a(href=format!("https://crates.io/{}/{}", if is_team { "teams" } else { "users" }, login)): name.unwrap_or_else(|| login.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn deleted_section(deleted_at: Option<String>) -> Box<dyn RenderBox> {
+    box_html! {
+        @ if let Some(deleted_at) = deleted_at {
+            section(id="deleted") {
+                p: format!("Deleted from crates.io at {}", deleted_at);
+            }
+        }
+    }
+}
+
+fn provenance_section(provenance: Provenance) -> Box<dyn RenderBox> {
+    let Provenance {
+        heuristic_version,
+        input_files,
+        truncated_files,
+        confidence,
+    } = provenance;
+    box_html! {
+        section(id="provenance") {
+            h3: "Provenance";
+            p: format!("{} confidence, computed by heuristics version {}", confidence.as_str(), heuristic_version);
+            @ if !input_files.is_empty() {
+                p: format!("Based on {}", input_files.join(", "));
+            }
+            @ if !truncated_files.is_empty() {
+                p: format!("Only a sample of {} was analyzed", truncated_files.join(", "));
+            }
+        }
+    }
+}
+
 fn title_section(title: impl Into<String>) -> Box<dyn RenderBox> {
     let title = title.into();
     box_html! {
@@ -93,7 +200,7 @@ fn page_head(title: impl Into<String>) -> Box<dyn RenderBox> {
     }
 }
 
-fn info_section(name: String, info: VersionInfo) -> Box<dyn RenderBox> {
+fn info_section(prefix: String, name: String, info: VersionInfo) -> Box<dyn RenderBox> {
     let VersionInfo {
         all,
         waste,
@@ -113,7 +220,7 @@ fn info_section(name: String, info: VersionInfo) -> Box<dyn RenderBox> {
             section(id="child-waste-latest-version") {
                 h3 {
                     : "Waste in ";
-                    a(href=format!("{}/{}.html", name, child_name)): child_name;
+                    a(href=format!("{}{}/{}.html", prefix, name, child_name)): child_name;
                 }
                 p: format!("{} wasted in {} files", ByteSize(info.total_bytes), info.total_files);
             }
@@ -156,12 +263,14 @@ enum SortOrder {
     Waste,
 }
 
+/// Render all children in `info_by_child`, marking the ones listed in `yanked` as such.
 fn child_items_section(
     title: impl Into<String>,
     info_by_child: Dict<VersionInfo>,
     prefix: String,
     suffix: impl Into<String>,
     order: SortOrder,
+    yanked: Vec<String>,
 ) -> Box<dyn RenderBox> {
     let title = title.into();
     let suffix = suffix.into();
@@ -183,8 +292,11 @@ fn child_items_section(
                             a(href=format!("{}{}{}", prefix, name, suffix)) {
                                 : name.clone()
                             }
+                            @ if yanked.contains(&name) {
+                                : " (yanked)"
+                            }
                         }
-                        : info_section(name, info);
+                        : info_section(prefix.clone(), name, info);
                     }
                 }
             }
@@ -246,6 +358,7 @@ impl RenderOnce for Report {
                 total_size_in_bytes,
                 mut wasted_files,
                 suggested_fix,
+                provenance,
             } => {
                 wasted_files.sort_by_key(|(_, s)| *s);
                 let title = format!("{}:{}", crate_name, crate_version);
@@ -268,6 +381,9 @@ impl RenderOnce for Report {
                                 } else {
                                     p: "Perfectly lean!"
                                 }
+                                @ if let Some(provenance) = provenance {
+                                    : provenance_section(provenance);
+                                }
                                 @ if !wasted_files.is_empty() {
                                     section {
                                         h3: format!("{} wasted files", wasted_files.len());
@@ -291,6 +407,9 @@ impl RenderOnce for Report {
                 total_files,
                 info_by_version,
                 wasted_by_extension,
+                owners,
+                yanked_versions,
+                deleted_at,
             } => {
                 let gains = potential_savings(&info_by_version);
                 let no_prefix = String::new();
@@ -301,10 +420,12 @@ impl RenderOnce for Report {
                         body {
                             article {
                                 : title_section(crate_name.clone());
+                                : deleted_section(deleted_at);
+                                : owners_section(owners);
                                 : total_section(total_size_in_bytes, total_files);
                                 : savings_section(gains);
                                 : by_extension_section(wasted_by_extension);
-                                : child_items_section("Versions", info_by_version, no_prefix, ".html", SortOrder::Semver);
+                                : child_items_section("Versions", info_by_version, no_prefix, ".html", SortOrder::Semver, yanked_versions);
                             }
                         }
                         : page_footer();
@@ -316,6 +437,7 @@ impl RenderOnce for Report {
                 total_files,
                 info_by_crate,
                 wasted_by_extension,
+                spotlight,
             } => {
                 let title = "Crates.io Waste Report";
                 let no_prefix = String::new();
@@ -333,13 +455,26 @@ impl RenderOnce for Report {
                         body {
                             article {
                                 : title_section(title);
+                                : spotlight_section(spotlight);
                                 : total_section(total_size_in_bytes, total_files);
                                 section {
                                     h3: format!("{} wasted in {} files", ByteSize(waste_in_bytes), wasted_files_count);
                                 }
                                 : savings_section(gains);
+                                section(id="projects") {
+                                    a(href=format!("{}/index.html", PROJECTS_DIR_NAME)): "Crates by repository";
+                                }
+                                section(id="hosts") {
+                                    a(href=format!("{}/index.html", HOSTS_DIR_NAME)): "Crates by repository host";
+                                }
+                                section(id="discrepancies") {
+                                    a(href=format!("{}/index.html", DISCREPANCIES_DIR_NAME)): "Discrepancies between index and db dump";
+                                }
+                                section(id="empty") {
+                                    a(href=format!("{}/index.html", EMPTY_ARTIFACTS_DIR_NAME)): "Crates without versions and empty crate archives";
+                                }
                                 : by_extension_section(wasted_by_extension);
-                                : child_items_section("Crates", info_by_crate, no_prefix, no_suffix, SortOrder::Waste);
+                                : child_items_section("Crates", info_by_crate, no_prefix, no_suffix, SortOrder::Waste, Vec::new());
                             }
                         }
                         : page_footer();
@@ -349,3 +484,351 @@ impl RenderOnce for Report {
         }
     }
 }
+
+impl RenderOnce for Project {
+    fn render_once(self, tmpl: &mut TemplateBuffer<'_>)
+    where
+        Self: Sized,
+    {
+        let total = self.total();
+        let prefix = self.path_to_crates();
+        let Project {
+            repository,
+            info_by_crate,
+            shared_owners,
+        } = self;
+        tmpl << html! {
+            : doctype::HTML;
+            html {
+                : page_head(repository.clone());
+                body {
+                    article {
+                        : title_section(repository.clone());
+                        h1 {
+                            a(href=format!("https://{}", repository)): repository;
+                        }
+                        : owners_section(shared_owners);
+                        : total_section(total.all.total_bytes, total.all.total_files);
+                        section(id="total-waste") {
+                            h3: format!("{} wasted in {} files", ByteSize(total.waste.total_bytes), total.waste.total_files);
+                        }
+                        : savings_section(total.potential_gains);
+                        : child_items_section("Crates", info_by_crate, prefix, String::new(), SortOrder::Waste, Vec::new());
+                    }
+                }
+                : page_footer();
+            }
+        }
+    }
+}
+
+/// An overview of all projects, i.e. crates sharing a repository, sorted by their combined waste.
+pub struct ProjectIndex(pub Vec<Project>);
+
+impl RenderOnce for ProjectIndex {
+    fn render_once(self, tmpl: &mut TemplateBuffer<'_>)
+    where
+        Self: Sized,
+    {
+        let title = "Crates by Repository";
+        let mut projects: Vec<_> = self.0.into_iter().map(|p| (p.total(), p)).collect();
+        projects.sort_by_key(|(total, _)| total.waste.total_bytes);
+        tmpl << html! {
+            : doctype::HTML;
+            html {
+                : page_head(title);
+                body {
+                    article {
+                        : title_section(title);
+                        p {
+                            a(href="../index.html"): "All crates";
+                        }
+                        ol {
+                            @ for (total, project) in projects.into_iter().rev() {
+                                li {
+                                    h3 {
+                                        a(href=project.path()): project.repository.clone();
+                                    }
+                                    p: format!(
+                                        "{} crates with {} total in {} files, of which {} in {} files are wasted",
+                                        project.info_by_crate.len(),
+                                        ByteSize(total.all.total_bytes),
+                                        total.all.total_files,
+                                        ByteSize(total.waste.total_bytes),
+                                        total.waste.total_files
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+                : page_footer();
+            }
+        }
+    }
+}
+
+fn share(part: u64, total: u64) -> String {
+    format!("{:.1}%", part as f64 * 100.0 / total.max(1) as f64)
+}
+
+impl RenderOnce for Host {
+    fn render_once(self, tmpl: &mut TemplateBuffer<'_>)
+    where
+        Self: Sized,
+    {
+        let total = self.total();
+        let Host {
+            name,
+            service,
+            crates_by_repository,
+            info_by_crate,
+        } = self;
+        let title = match service {
+            Some(service) => format!("{} ({})", name, service),
+            None => format!("{} (self-hosted)", name),
+        };
+        tmpl << html! {
+            : doctype::HTML;
+            html {
+                : page_head(title.clone());
+                body {
+                    article {
+                        : title_section(title.clone());
+                        p {
+                            a(href="index.html"): "All hosts";
+                        }
+                        : total_section(total.all.total_bytes, total.all.total_files);
+                        section(id="total-waste") {
+                            h3: format!("{} wasted in {} files", ByteSize(total.waste.total_bytes), total.waste.total_files);
+                        }
+                        : savings_section(total.potential_gains);
+                        section(id="repositories") {
+                            h1: format!("{} Repositories", crates_by_repository.len());
+                            ul {
+                                @ for (repository, crate_names) in crates_by_repository.into_iter() {
+                                    li {
+                                        a(href=format!("https://{}", repository)): repository;
+                                        : format!(": {}", crate_names.join(", "));
+                                    }
+                                }
+                            }
+                        }
+                        : child_items_section("Crates", info_by_crate, "../".into(), String::new(), SortOrder::Waste, Vec::new());
+                    }
+                }
+                : page_footer();
+            }
+        }
+    }
+}
+
+/// An overview of how crates are distributed across the hosts of their repositories.
+impl RenderOnce for HostingDistribution {
+    fn render_once(self, tmpl: &mut TemplateBuffer<'_>)
+    where
+        Self: Sized,
+    {
+        let title = "Crates by Repository Host";
+        let crates_with_repository = self.crates_with_repository();
+        let all_crates = crates_with_repository + self.crates_without_repository;
+        let crates_by_service = self.crates_by_service();
+        let HostingDistribution {
+            hosts,
+            crates_without_repository,
+        } = self;
+        tmpl << html! {
+            : doctype::HTML;
+            html {
+                : page_head(title);
+                body {
+                    article {
+                        : title_section(title);
+                        p {
+                            a(href="../index.html"): "All crates";
+                        }
+                        section(id="services") {
+                            h1: "Hosting Services";
+                            ol {
+                                @ for (service, crates) in crates_by_service.into_iter() {
+                                    li: format!(
+                                        "{}: {} crates ({})",
+                                        service.unwrap_or_else(|| "self-hosted".into()),
+                                        crates,
+                                        share(crates, all_crates)
+                                    );
+                                }
+                                li: format!(
+                                    "no repository: {} crates ({})",
+                                    crates_without_repository,
+                                    share(crates_without_repository, all_crates)
+                                );
+                            }
+                        }
+                        section(id="hosts") {
+                            h1: format!("{} Hosts", hosts.len());
+                            ol {
+                                @ for host in hosts.into_iter() {
+                                    li {
+                                        h3 {
+                                            a(href=host.path()): host.name.clone();
+                                        }
+                                        p: format!(
+                                            "{} crates in {} repositories ({} of crates with a repository)",
+                                            host.info_by_crate.len(),
+                                            host.crates_by_repository.len(),
+                                            share(host.info_by_crate.len() as u64, crates_with_repository)
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                : page_footer();
+            }
+        }
+    }
+}
+
+/// An overview of all versions of crates the crates.io index and the db dump disagree about.
+pub struct DiscrepancyIndex(pub Vec<VersionDiscrepancy>);
+
+impl RenderOnce for DiscrepancyIndex {
+    fn render_once(self, tmpl: &mut TemplateBuffer<'_>)
+    where
+        Self: Sized,
+    {
+        let title = "Discrepancies between Index and DB Dump";
+        let mut discrepancies = self.0;
+        discrepancies.sort_by(|a, b| {
+            a.crate_name
+                .cmp(&b.crate_name)
+                .then_with(|| a.crate_version.cmp(&b.crate_version))
+        });
+        let by_kind: Vec<_> = [DiscrepancyKind::Yanked, DiscrepancyKind::Checksum]
+            .iter()
+            .map(|kind| {
+                let of_kind: Vec<_> = discrepancies.iter().filter(|d| d.kind == *kind).cloned().collect();
+                (*kind, of_kind)
+            })
+            .collect();
+        tmpl << html! {
+            : doctype::HTML;
+            html {
+                : page_head(title);
+                body {
+                    article {
+                        : title_section(title);
+                        p {
+                            a(href="../index.html"): "All crates";
+                        }
+                        @ if discrepancies.is_empty() {
+                            p: "The index and the db dump agree about all crate versions.";
+                        }
+                        @ for (kind, discrepancies) in by_kind.into_iter().filter(|(_, d)| !d.is_empty()) {
+                            section(id=kind.as_str()) {
+                                h1: format!("{} versions disagreeing about their {} state", discrepancies.len(), kind.as_str());
+                                table {
+                                    tr {
+                                        th: "Crate version";
+                                        th: "Index";
+                                        th: "DB Dump";
+                                    }
+                                    @ for discrepancy in discrepancies.into_iter() {
+                                        tr {
+                                            td {
+                                                a(href=format!("https://crates.io/crates/{}/{}", discrepancy.crate_name, discrepancy.crate_version)):
+                                                    format!("{} v{}", discrepancy.crate_name, discrepancy.crate_version);
+                                            }
+                                            td: discrepancy.index;
+                                            td: discrepancy.db_dump;
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                : page_footer();
+            }
+        }
+    }
+}
+
+/// An overview of all crates without versions and all versions of crates with an empty archive, which are not part of
+/// any other report.
+pub struct EmptyArtifactIndex(pub Vec<EmptyArtifact>);
+
+impl RenderOnce for EmptyArtifactIndex {
+    fn render_once(self, tmpl: &mut TemplateBuffer<'_>)
+    where
+        Self: Sized,
+    {
+        let title = "Crates without Versions and empty Crate Archives";
+        let mut artifacts = self.0;
+        artifacts.sort_by(|a, b| {
+            a.crate_name
+                .cmp(&b.crate_name)
+                .then_with(|| a.crate_version.cmp(&b.crate_version))
+        });
+        let (crates, versions): (Vec<_>, Vec<_>) = artifacts.into_iter().partition(|a| a.crate_version.is_none());
+        tmpl << html! {
+            : doctype::HTML;
+            html {
+                : page_head(title);
+                body {
+                    article {
+                        : title_section(title);
+                        p {
+                            a(href="../index.html"): "All crates";
+                        }
+                        @ if crates.is_empty() && versions.is_empty() {
+                            p: "All crates have versions, and no crate archive is empty.";
+                        }
+                        @ if !crates.is_empty() {
+                            section(id="crates") {
+                                h1: format!("{} crates without versions", crates.len());
+                                table {
+                                    tr {
+                                        th: "Crate";
+                                        th: "Reason";
+                                    }
+                                    @ for artifact in crates.into_iter() {
+                                        tr {
+                                            td {
+                                                a(href=format!("https://crates.io/crates/{}", artifact.crate_name)): &artifact.crate_name;
+                                            }
+                                            td: artifact.reason;
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        @ if !versions.is_empty() {
+                            section(id="versions") {
+                                h1: format!("{} versions with an empty crate archive", versions.len());
+                                table {
+                                    tr {
+                                        th: "Crate version";
+                                        th: "Reason";
+                                    }
+                                    @ for artifact in versions.into_iter() {
+                                        tr {
+                                            td {
+                                                a(href=format!("https://crates.io/crates/{}/{}", artifact.crate_name, artifact.crate_version.as_deref().unwrap_or_default())):
+                                                    format!("{} v{}", artifact.crate_name, artifact.crate_version.as_deref().unwrap_or_default());
+                                            }
+                                            td: artifact.reason;
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                : page_footer();
+            }
+        }
+    }
+}
@@ -3,9 +3,18 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod discrepancy;
+pub mod empty;
+pub mod hosting;
 #[cfg(feature = "html")]
 pub mod html;
+pub mod patch;
+pub mod project;
+pub mod provenance;
 pub mod result;
+#[cfg(feature = "json-schema")]
+pub mod schema;
+pub mod spotlight;
 
 #[cfg(test)]
 mod test;
@@ -13,12 +22,20 @@ mod test;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
+pub use discrepancy::{DiscrepancyKind, VersionDiscrepancy};
+pub use empty::EmptyArtifact;
+pub use hosting::{Host, HostingDistribution};
+pub use patch::PullRequest;
+pub use project::Project;
+pub use provenance::{Confidence, Provenance};
 pub use result::{globset_from_patterns, tar_path_to_utf8_str};
+pub use spotlight::Spotlight;
 
 pub type Patterns = Vec<String>;
 
 /// An entry in a tar archive, including the most important meta-data
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct TarHeader {
     /// The normalized path of the entry. May not be unicode encoded.
     pub path: Vec<u8>,
@@ -40,12 +57,14 @@ pub struct TarPackage {
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct PotentialWaste {
     pub patterns_to_fix: Patterns,
     pub potential_waste: Vec<TarHeader>,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub enum Fix {
     ImprovedInclude {
         include: Patterns,
@@ -141,6 +160,7 @@ pub struct PackageSection {
     pub include: Option<Patterns>,
     pub exclude: Option<Patterns>,
     pub build: Option<toml::value::Value>,
+    pub repository: Option<String>,
 }
 
 impl PackageSection {
@@ -152,6 +172,7 @@ impl PackageSection {
 pub type WastedFile = (String, u64);
 
 #[derive(Default, Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct AggregateFileInfo {
     pub total_bytes: u64,
     pub total_files: u64,
@@ -212,7 +233,18 @@ pub fn add_optional_aggregate(
     })
 }
 
+/// Sum up the size, waste and potential gains of all `infos`
+pub(crate) fn total_info<'a>(infos: impl IntoIterator<Item = &'a VersionInfo>) -> VersionInfo {
+    infos.into_iter().fold(VersionInfo::default(), |mut total, info| {
+        total.all += info.all.clone();
+        total.waste += info.waste.clone();
+        total.potential_gains = add_optional_aggregate(total.potential_gains.clone(), info.potential_gains.clone());
+        total
+    })
+}
+
 #[derive(Default, Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct VersionInfo {
     pub all: AggregateFileInfo,
     pub waste: AggregateFileInfo,
@@ -222,9 +254,20 @@ pub struct VersionInfo {
 
 pub type AggregateVersionInfo = VersionInfo;
 
+/// A user or team owning a crate on crates.io
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct Owner {
+    /// The GitHub login of a user, or the qualified name of a team like `github:org:team`
+    pub login: String,
+    pub name: Option<String>,
+    pub is_team: bool,
+}
+
 pub type Dict<T> = BTreeMap<String, T>;
 
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub enum Report {
     Version {
         crate_name: String,
@@ -233,6 +276,9 @@ pub enum Report {
         total_files: u64,
         wasted_files: Vec<WastedFile>,
         suggested_fix: Option<Fix>,
+        /// Where the wasted files and the suggested fix come from, or None for reports written before it was recorded
+        #[serde(default)]
+        provenance: Option<Provenance>,
     },
     Crate {
         crate_name: String,
@@ -240,12 +286,24 @@ pub enum Report {
         total_files: u64,
         info_by_version: Dict<VersionInfo>,
         wasted_by_extension: Dict<AggregateFileInfo>,
+        /// The owners of the crate as listed on crates.io, which are not part of any version report
+        #[serde(default)]
+        owners: Vec<Owner>,
+        /// The versions of the crate which are yanked, sorted by semantic version
+        #[serde(default)]
+        yanked_versions: Vec<String>,
+        /// The time at which the crate was deleted from the index as RFC 3339 timestamp, or None if it wasn't
+        #[serde(default)]
+        deleted_at: Option<String>,
     },
     CrateCollection {
         total_size_in_bytes: u64,
         total_files: u64,
         info_by_crate: Dict<AggregateVersionInfo>,
         wasted_by_extension: Dict<AggregateFileInfo>,
+        /// A notable finding to feature on the homepage, selected whenever the report of a run is merged into the previous one
+        #[serde(default)]
+        spotlight: Option<Spotlight>,
     },
 }
 
@@ -266,6 +324,7 @@ impl Report {
         }: TarPackage,
     ) -> Report {
         remove_implicit_entries(&mut entries_meta_data);
+        let provenance = Provenance::from_entries(&entries);
         let total_size_in_bytes = entries_meta_data.iter().map(|e| e.size).sum();
         let total_files = entries_meta_data.len() as u64;
         let cargo_config = Self::cargo_config_from_entries(&entries);
@@ -296,6 +355,7 @@ impl Report {
             total_files,
             wasted_files,
             suggested_fix,
+            provenance: Some(provenance),
         }
     }
 }
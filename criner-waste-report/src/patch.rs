@@ -0,0 +1,253 @@
+//! Turn suggested fixes into patches of the crate manifest, ready to be applied upstream.
+use super::{AggregateFileInfo, CargoConfig, Confidence, Fix, Patterns, Report, WastedFile};
+use serde_derive::{Deserialize, Serialize};
+
+/// The maximum amount of wasted files to list in the description of a pull request
+const MAX_LISTED_FILES: usize = 20;
+
+/// Everything needed by tooling to open a pull request upstream which applies a suggested fix
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct PullRequest {
+    pub crate_name: String,
+    pub crate_version: String,
+    /// The repository as declared in the manifest, if any
+    pub repository: Option<String>,
+    pub title: String,
+    pub body: String,
+    /// The path of the manifest to patch, relative to the root of the crate
+    pub manifest_path: String,
+    /// A unified diff to apply to the manifest at `manifest_path`
+    pub patch: String,
+    /// The amount of files and bytes that would not be published anymore
+    pub savings: AggregateFileInfo,
+    /// How much the suggested fix can be trusted, or None if the report it is based on doesn't know
+    #[serde(default)]
+    pub confidence: Option<Confidence>,
+}
+
+impl Report {
+    /// Create a pull request for the suggested fix of a version report, applied to the given `manifest`, which should be
+    /// the original `Cargo.toml` as written by the authors.
+    /// Returns None if this is not a version report or if there is nothing to change.
+    pub fn pull_request(&self, manifest: &str) -> Option<PullRequest> {
+        match self {
+            Report::Version {
+                crate_name,
+                crate_version,
+                wasted_files,
+                suggested_fix: Some(fix),
+                provenance,
+                ..
+            } => {
+                let patch = manifest_patch(manifest, fix)?;
+                let savings = AggregateFileInfo {
+                    total_bytes: wasted_files.iter().map(|(_, size)| size).sum(),
+                    total_files: wasted_files.len() as u64,
+                };
+                Some(PullRequest {
+                    crate_name: crate_name.clone(),
+                    crate_version: crate_version.clone(),
+                    repository: toml::from_str::<CargoConfig>(manifest)
+                        .ok()
+                        .and_then(|c| c.package)
+                        .and_then(|p| p.repository),
+                    title: format!("Only publish files needed to build '{}'", crate_name),
+                    body: description(crate_version, fix, &savings, wasted_files),
+                    manifest_path: "Cargo.toml".into(),
+                    patch,
+                    savings,
+                    confidence: provenance.as_ref().map(|p| p.confidence),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn description(crate_version: &str, fix: &Fix, savings: &AggregateFileInfo, wasted_files: &[WastedFile]) -> String {
+    let mut body = format!(
+        "Version {} of this crate was published with {} files and a total of {} bytes which are not needed to build it.\n\n{}\n",
+        crate_version,
+        savings.total_files,
+        savings.total_bytes,
+        match fix {
+            Fix::ImprovedInclude { .. } => "This adjusts the `include` directive to only match files needed to build the crate.",
+            Fix::EnrichedExclude { .. } => "This adds patterns to the `exclude` directive to stop publishing files not needed to build the crate.",
+            Fix::NewInclude { .. } => "This adds an `include` directive to only publish files needed to build the crate.",
+            Fix::RemoveExcludeAndUseInclude { .. } => "This replaces the `exclude` directive with an `include` directive which only matches files needed to build the crate.",
+            Fix::RemoveExclude => "This removes the `exclude` directive as the `include` directive already makes it unnecessary.",
+        }
+    );
+    if !wasted_files.is_empty() {
+        body.push_str("\nThe following files will not be published anymore:\n\n");
+        for (path, size) in wasted_files.iter().take(MAX_LISTED_FILES) {
+            body.push_str(&format!("* `{}` ({} bytes)\n", path, size));
+        }
+        if wasted_files.len() > MAX_LISTED_FILES {
+            body.push_str(&format!("* …and {} more\n", wasted_files.len() - MAX_LISTED_FILES));
+        }
+    }
+    body
+}
+
+/// Apply `fix` to the `manifest` and return a unified diff of the changes, or None if nothing changes.
+pub fn manifest_patch(manifest: &str, fix: &Fix) -> Option<String> {
+    let old: Vec<&str> = manifest.lines().collect();
+    let new = apply_fix(&old, fix)?;
+    unified_diff("Cargo.toml", &old, &new)
+}
+
+fn apply_fix(lines: &[&str], fix: &Fix) -> Option<Vec<String>> {
+    let package = section(lines, "package")?;
+    let mut lines: Vec<String> = lines.iter().map(|l| (*l).to_owned()).collect();
+    let (include, remove_exclude, exclude) = match fix {
+        Fix::ImprovedInclude { include, .. } | Fix::NewInclude { include, .. } => (Some(include), false, None),
+        Fix::RemoveExcludeAndUseInclude { include, .. } => (Some(include), true, None),
+        Fix::EnrichedExclude { exclude, .. } => (None, false, Some(exclude)),
+        Fix::RemoveExclude => (None, true, None),
+    };
+
+    // Edit from bottom to top to keep line numbers of the other directive valid
+    let mut edits = Vec::new();
+    if let Some(patterns) = include {
+        edits.push((
+            directive(&lines, package.clone(), "include"),
+            format_array("include", patterns),
+        ));
+    }
+    if let Some(patterns) = exclude {
+        edits.push((
+            directive(&lines, package.clone(), "exclude"),
+            format_array("exclude", patterns),
+        ));
+    }
+    if remove_exclude {
+        if let Some(range) = directive(&lines, package.clone(), "exclude") {
+            edits.push((Some(range), Vec::new()));
+        }
+    }
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.as_ref().map_or(usize::MAX, |r| r.start)));
+    for (range, replacement) in edits {
+        let range = range.unwrap_or_else(|| {
+            let end = last_non_empty_line(&lines, package.clone()) + 1;
+            end..end
+        });
+        lines.splice(range, replacement);
+    }
+    Some(lines)
+}
+
+/// Return the range of lines belonging to the table with the given `name`, excluding its header.
+fn section(lines: &[&str], name: &str) -> Option<std::ops::Range<usize>> {
+    let header = format!("[{}]", name);
+    let start = lines.iter().position(|l| l.trim() == header)? + 1;
+    let end = lines[start..]
+        .iter()
+        .position(|l| l.trim_start().starts_with('['))
+        .map_or(lines.len(), |p| start + p);
+    Some(start..end)
+}
+
+fn last_non_empty_line(lines: &[String], section: std::ops::Range<usize>) -> usize {
+    let start = section.start;
+    section
+        .rev()
+        .find(|&l| {
+            let line = lines[l].trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+        .unwrap_or(start - 1)
+}
+
+/// Return the range of lines making up the array assigned to `key` within `section`.
+fn directive(lines: &[String], section: std::ops::Range<usize>, key: &str) -> Option<std::ops::Range<usize>> {
+    let start = section
+        .clone()
+        .find(|&l| match lines[l].trim_start().strip_prefix(key) {
+            Some(rest) => rest.trim_start().starts_with('='),
+            None => false,
+        })?;
+    let mut depth = 0;
+    let mut string_delimiter = None::<char>;
+    for (offset, line) in lines[start..section.end].iter().enumerate() {
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            match (string_delimiter, c) {
+                (Some('"'), '\\') => {
+                    chars.next();
+                }
+                (Some(d), c) if c == d => string_delimiter = None,
+                (Some(_), _) => {}
+                (None, '"') | (None, '\'') => string_delimiter = Some(c),
+                (None, '#') => break,
+                (None, '[') => depth += 1,
+                (None, ']') => depth -= 1,
+                (None, _) => {}
+            }
+        }
+        if depth <= 0 {
+            return Some(start..start + offset + 1);
+        }
+    }
+    Some(start..section.end)
+}
+
+fn format_array(key: &str, patterns: &Patterns) -> Vec<String> {
+    let quoted: Vec<_> = patterns.iter().map(|p| format!("{:?}", p)).collect();
+    let single_line = format!("{} = [{}]", key, quoted.join(", "));
+    if single_line.len() <= 100 {
+        vec![single_line]
+    } else {
+        std::iter::once(format!("{} = [", key))
+            .chain(quoted.into_iter().map(|p| format!("    {},", p)))
+            .chain(std::iter::once("]".to_owned()))
+            .collect()
+    }
+}
+
+/// Produce a unified diff with a single hunk spanning all changed lines, or None if there is no change.
+fn unified_diff(path: &str, old: &[&str], new: &[String]) -> Option<String> {
+    const CONTEXT: usize = 3;
+    let prefix = old.iter().zip(new).take_while(|(o, n)| *o == n).count();
+    if prefix == old.len() && prefix == new.len() {
+        return None;
+    }
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| *o == n)
+        .count();
+    let start = prefix.saturating_sub(CONTEXT);
+    let old_end = (old.len() - suffix + CONTEXT).min(old.len());
+    let new_end = (new.len() - suffix + CONTEXT).min(new.len());
+
+    let mut out = format!("--- a/{0}\n+++ b/{0}\n", path);
+    out.push_str(&format!(
+        "@@ -{} +{} @@\n",
+        hunk_range(start, old_end - start),
+        hunk_range(start, new_end - start)
+    ));
+    for line in &old[start..prefix] {
+        out.push_str(&format!(" {}\n", line));
+    }
+    for line in &old[prefix..old.len() - suffix] {
+        out.push_str(&format!("-{}\n", line));
+    }
+    for line in &new[prefix..new.len() - suffix] {
+        out.push_str(&format!("+{}\n", line));
+    }
+    for line in &old[old.len() - suffix..old_end] {
+        out.push_str(&format!(" {}\n", line));
+    }
+    Some(out)
+}
+
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        len => format!("{},{}", start + 1, len),
+    }
+}
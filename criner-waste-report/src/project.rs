@@ -0,0 +1,115 @@
+//! Group crates published from the same repository into projects, as many findings are best addressed per repository
+//! rather than per crate.
+use super::{hosting::hosting_service, total_info, AggregateVersionInfo, Dict, Owner};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The name of the directory containing all project reports.
+/// It can't clash with the directory of a crate as crate names can't start with an underscore.
+pub const PROJECTS_DIR_NAME: &str = "__projects__";
+
+/// Path segments which start a path within a repository, i.e. `tree` in `github.com/owner/repo/tree/main/crates/foo`
+const PATH_MARKERS: &[&str] = &["tree", "blob", "src", "-"];
+
+/// Crates which share a repository, usually members of a cargo workspace
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct Project {
+    /// The normalized url of the repository without scheme, like `github.com/the-lean-crate/criner`
+    pub repository: String,
+    pub info_by_crate: Dict<AggregateVersionInfo>,
+    /// The owners of all crates in the project
+    pub shared_owners: Vec<Owner>,
+}
+
+impl Project {
+    /// Group the crates in `info_by_crate` by their repository, as provided by `repositories` as pairs of crate name and
+    /// repository url, and return all projects with more than one crate.
+    /// `owners_of` provides the owners of a crate by name.
+    pub fn group<'a>(
+        repositories: impl IntoIterator<Item = (&'a str, &'a str)>,
+        info_by_crate: &Dict<AggregateVersionInfo>,
+        mut owners_of: impl FnMut(&str) -> Vec<Owner>,
+    ) -> Vec<Project> {
+        let mut crates_by_repository = BTreeMap::<String, Dict<AggregateVersionInfo>>::new();
+        for (crate_name, url) in repositories {
+            if let (Some(info), Some(repository)) = (info_by_crate.get(crate_name), normalize_repository_url(url)) {
+                crates_by_repository
+                    .entry(repository)
+                    .or_default()
+                    .insert(crate_name.to_owned(), info.clone());
+            }
+        }
+        crates_by_repository
+            .into_iter()
+            .filter(|(_, crates)| crates.len() > 1)
+            .map(|(repository, info_by_crate)| {
+                let mut crate_names = info_by_crate.keys();
+                let mut shared_owners = crate_names.next().map(|name| owners_of(name)).unwrap_or_default();
+                for crate_name in crate_names {
+                    let owners = owners_of(crate_name);
+                    shared_owners.retain(|owner| owners.contains(owner));
+                }
+                Project {
+                    repository,
+                    info_by_crate,
+                    shared_owners,
+                }
+            })
+            .collect()
+    }
+
+    /// The combined size, waste and potential gains of all crates in the project
+    pub fn total(&self) -> AggregateVersionInfo {
+        total_info(self.info_by_crate.values())
+    }
+
+    /// The path of the project report relative to the projects directory
+    pub fn path(&self) -> String {
+        format!("{}.html", self.repository)
+    }
+
+    /// The relative path from the project report to the directory containing all crate reports
+    pub fn path_to_crates(&self) -> String {
+        "../".repeat(self.repository.matches('/').count() + 1)
+    }
+}
+
+/// Normalize the `url` of a repository so that urls pointing to the same repository are equal, or return None if it
+/// doesn't look like the url of a repository.
+///
+/// Urls pointing into the repository, as is common for members of a workspace, are normalized to the repository itself.
+pub fn normalize_repository_url(url: &str) -> Option<String> {
+    let url = url.trim();
+    let url = match url.find("://") {
+        Some(pos) => &url[pos + 3..],
+        None => url,
+    };
+    // scp-like urls as in `git@github.com:owner/repo.git`
+    let url = match url.find('@') {
+        Some(pos) if !url[..pos].contains('/') => url[pos + 1..].replacen(':', "/", 1),
+        _ => url.to_owned(),
+    }
+    .to_lowercase();
+
+    let mut segments = url.split(&['/', '?', '#'][..]).filter(|s| !s.is_empty());
+    let host = segments.next()?;
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    if !host.contains('.') {
+        return None;
+    }
+    // Repositories of hosting services are always identified by the first two segments of their path
+    let mut path: Vec<_> = if hosting_service(host).is_some() {
+        segments.take(2).collect()
+    } else {
+        segments.take_while(|s| !PATH_MARKERS.contains(s)).collect()
+    };
+    if let Some(last) = path.last_mut() {
+        *last = last.strip_suffix(".git").unwrap_or(last);
+    }
+    path.retain(|s| !s.is_empty());
+    if path.is_empty() {
+        return None;
+    }
+    Some(format!("{}/{}", host, path.join("/")))
+}
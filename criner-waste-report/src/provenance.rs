@@ -0,0 +1,73 @@
+//! Where the findings of a version report come from and how much they can be trusted, so consumers can filter
+//! findings they don't consider reliable enough.
+use super::{tar_path_to_utf8_str, CargoConfig, TarHeader};
+use serde_derive::{Deserialize, Serialize};
+
+/// The version of the heuristics computing wasted files and suggested fixes.
+/// Increment it whenever they change in a way that can change findings for the same crate archive.
+pub const WASTE_HEURISTIC_VERSION: &str = "1.0.0";
+
+/// How much a finding can be trusted, ordered from least to most trustworthy
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub enum Confidence {
+    /// The manifest is missing or could not be parsed, so findings are based on cargo's defaults alone
+    Low,
+    /// Some sources which may include files at compile time were only read partially
+    Medium,
+    /// The manifest and all sources which may include files at compile time were read completely
+    High,
+}
+
+impl Confidence {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        }
+    }
+}
+
+/// The inputs and heuristics the findings of a version report are based on
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct Provenance {
+    /// The version of the heuristics that produced the findings, see `WASTE_HEURISTIC_VERSION`
+    pub heuristic_version: String,
+    /// The paths of the files in the crate archive whose content was analyzed
+    pub input_files: Vec<String>,
+    /// The paths of input files of which only a sample was analyzed
+    pub truncated_files: Vec<String>,
+    pub confidence: Confidence,
+}
+
+impl Provenance {
+    /// Determine the provenance of findings based on the `entries` of a crate archive whose content was analyzed.
+    pub fn from_entries(entries: &[(TarHeader, Vec<u8>)]) -> Provenance {
+        let manifest_parses = entries.iter().any(|(header, content)| {
+            tar_path_to_utf8_str(&header.path) == "Cargo.toml" && toml::from_slice::<CargoConfig>(content).is_ok()
+        });
+        let input_files = entries
+            .iter()
+            .map(|(header, _)| tar_path_to_utf8_str(&header.path).to_owned())
+            .collect();
+        let truncated_files: Vec<_> = entries
+            .iter()
+            .filter(|(header, content)| (content.len() as u64) < header.size)
+            .map(|(header, _)| tar_path_to_utf8_str(&header.path).to_owned())
+            .collect();
+        Provenance {
+            heuristic_version: WASTE_HEURISTIC_VERSION.into(),
+            input_files,
+            confidence: if !manifest_parses {
+                Confidence::Low
+            } else if !truncated_files.is_empty() {
+                Confidence::Medium
+            } else {
+                Confidence::High
+            },
+            truncated_files,
+        }
+    }
+}
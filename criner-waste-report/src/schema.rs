@@ -0,0 +1,34 @@
+//! JSON schemas of the reports and pull requests we write as JSON, generated from their types, as a contract
+//! for tools consuming them.
+use super::{HostingDistribution, Project, PullRequest, Report};
+use schemars::{schema::RootSchema, schema_for};
+
+/// The schema of a report of a crate version, a crate or all crates
+pub fn report() -> RootSchema {
+    schema_for!(Report)
+}
+
+/// The schema of a list of pull requests, one per crate
+pub fn pull_requests() -> RootSchema {
+    schema_for!(Vec<PullRequest>)
+}
+
+/// The schema of a project, i.e. crates sharing a repository
+pub fn project() -> RootSchema {
+    schema_for!(Project)
+}
+
+/// The schema of the distribution of crates across the hosts of their repositories
+pub fn hosting() -> RootSchema {
+    schema_for!(HostingDistribution)
+}
+
+/// All schemas along with the name of the file they are written to
+pub fn all() -> Vec<(&'static str, RootSchema)> {
+    vec![
+        ("report.schema.json", report()),
+        ("pull-requests.schema.json", pull_requests()),
+        ("project.schema.json", project()),
+        ("hosting.schema.json", hosting()),
+    ]
+}
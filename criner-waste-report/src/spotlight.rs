@@ -0,0 +1,110 @@
+//! Select a notable finding to feature on the homepage of the report, rotating through different kinds of findings by day.
+use super::{AggregateFileInfo, AggregateVersionInfo, Dict};
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(PartialEq, Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub enum Spotlight {
+    /// The crate whose latest version wastes the least bytes compared to its previously latest version
+    MostImproved {
+        crate_name: String,
+        crate_version: String,
+        previous_waste: AggregateFileInfo,
+        waste: AggregateFileInfo,
+    },
+    /// The crate analyzed for the first time with the most bytes in all of its versions, which tends to favour established crates
+    NewlyAnalyzed {
+        crate_name: String,
+        all: AggregateFileInfo,
+        waste: AggregateFileInfo,
+    },
+    /// The crate with the most wasted bytes in the most recently analyzed version
+    MostWasteful {
+        crate_name: String,
+        crate_version: String,
+        waste: AggregateFileInfo,
+    },
+}
+
+impl Spotlight {
+    pub fn crate_name(&self) -> &str {
+        match self {
+            Spotlight::MostImproved { crate_name, .. }
+            | Spotlight::NewlyAnalyzed { crate_name, .. }
+            | Spotlight::MostWasteful { crate_name, .. } => crate_name,
+        }
+    }
+
+    /// Select a spotlight from the crates analyzed in `current`, given the crates that were known `previously`.
+    /// All kinds of spotlights for which a candidate exists are rotated through by `day`, which is typically the
+    /// amount of days since the unix epoch, to make the selection deterministic.
+    pub fn select(
+        previous: &Dict<AggregateVersionInfo>,
+        current: &Dict<AggregateVersionInfo>,
+        day: u64,
+    ) -> Option<Spotlight> {
+        let candidates: Vec<_> = vec![
+            most_improved(previous, current),
+            newly_analyzed(previous, current),
+            most_wasteful(current),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let index = (day % candidates.len() as u64) as usize;
+        candidates.into_iter().nth(index)
+    }
+}
+
+fn most_improved(previous: &Dict<AggregateVersionInfo>, current: &Dict<AggregateVersionInfo>) -> Option<Spotlight> {
+    current
+        .iter()
+        .filter_map(|(crate_name, info)| {
+            let (previous_version, previous_waste) = previous.get(crate_name)?.waste_latest_version.as_ref()?;
+            let (version, waste) = info.waste_latest_version.as_ref()?;
+            if version != previous_version && waste.total_bytes < previous_waste.total_bytes {
+                Some((crate_name, version, previous_waste, waste))
+            } else {
+                None
+            }
+        })
+        .max_by_key(|(_, _, previous_waste, waste)| previous_waste.total_bytes - waste.total_bytes)
+        .map(|(crate_name, version, previous_waste, waste)| Spotlight::MostImproved {
+            crate_name: crate_name.to_owned(),
+            crate_version: version.to_owned(),
+            previous_waste: previous_waste.to_owned(),
+            waste: waste.to_owned(),
+        })
+}
+
+fn newly_analyzed(previous: &Dict<AggregateVersionInfo>, current: &Dict<AggregateVersionInfo>) -> Option<Spotlight> {
+    current
+        .iter()
+        .filter(|(crate_name, _)| !previous.contains_key(*crate_name))
+        .max_by_key(|(_, info)| info.all.total_bytes)
+        .map(|(crate_name, info)| Spotlight::NewlyAnalyzed {
+            crate_name: crate_name.to_owned(),
+            all: info.all.clone(),
+            waste: info.waste.clone(),
+        })
+}
+
+fn most_wasteful(current: &Dict<AggregateVersionInfo>) -> Option<Spotlight> {
+    current
+        .iter()
+        .filter_map(|(crate_name, info)| {
+            info.waste_latest_version
+                .as_ref()
+                .map(|(version, waste)| (crate_name, version, waste))
+        })
+        .filter(|(_, _, waste)| waste.total_bytes > 0)
+        .max_by_key(|(_, _, waste)| waste.total_bytes)
+        .map(|(crate_name, version, waste)| Spotlight::MostWasteful {
+            crate_name: crate_name.to_owned(),
+            crate_version: version.to_owned(),
+            waste: waste.to_owned(),
+        })
+}
@@ -0,0 +1,79 @@
+use super::super::discrepancy::{DiscrepancyKind, VersionDiscrepancy, VersionState};
+
+#[test]
+fn agreeing_sources_have_no_discrepancies() {
+    assert_eq!(
+        VersionDiscrepancy::find(
+            "a",
+            "1.0.0",
+            VersionState {
+                yanked: true,
+                checksum: Some("ABCDEF")
+            },
+            VersionState {
+                yanked: true,
+                checksum: Some("abcdef")
+            },
+        ),
+        Vec::new(),
+        "checksums are compared case-insensitively"
+    );
+    assert_eq!(
+        VersionDiscrepancy::find(
+            "a",
+            "1.0.0",
+            VersionState {
+                yanked: false,
+                checksum: Some("abcdef")
+            },
+            VersionState {
+                yanked: false,
+                checksum: None
+            },
+        ),
+        Vec::new(),
+        "unknown checksums can't disagree"
+    );
+}
+
+#[test]
+fn disagreements_about_yanked_state_and_checksum_are_discrepancies() {
+    assert_eq!(
+        VersionDiscrepancy::find(
+            "a",
+            "1.0.0",
+            VersionState {
+                yanked: true,
+                checksum: Some("abc")
+            },
+            VersionState {
+                yanked: false,
+                checksum: Some("def")
+            },
+        ),
+        vec![
+            VersionDiscrepancy {
+                crate_name: "a".into(),
+                crate_version: "1.0.0".into(),
+                kind: DiscrepancyKind::Yanked,
+                index: "true".into(),
+                db_dump: "false".into(),
+            },
+            VersionDiscrepancy {
+                crate_name: "a".into(),
+                crate_version: "1.0.0".into(),
+                kind: DiscrepancyKind::Checksum,
+                index: "abc".into(),
+                db_dump: "def".into(),
+            }
+        ]
+    );
+}
+
+#[test]
+fn kinds_round_trip_through_their_name() {
+    for kind in &[DiscrepancyKind::Yanked, DiscrepancyKind::Checksum] {
+        assert_eq!(DiscrepancyKind::parse(kind.as_str()), Some(*kind));
+    }
+    assert_eq!(DiscrepancyKind::parse("unknown"), None);
+}
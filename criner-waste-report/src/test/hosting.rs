@@ -0,0 +1,69 @@
+use super::super::{
+    hosting::{hosting_service, repository_host, HostingDistribution},
+    Dict,
+};
+use super::info;
+
+#[test]
+fn repository_hosts_are_normalized() {
+    assert_eq!(
+        repository_host("https://www.GitHub.com/org/repo.git").as_deref(),
+        Some("github.com")
+    );
+    assert_eq!(
+        repository_host("git@git.sr.ht:~user/repo").as_deref(),
+        Some("git.sr.ht")
+    );
+    assert_eq!(repository_host("https://example.com"), None, "there is no repository");
+    assert_eq!(hosting_service("git.sr.ht"), Some("sr.ht"));
+    assert_eq!(hosting_service("git.example.com"), None, "it's self-hosted");
+}
+
+#[test]
+fn crates_are_distributed_across_the_hosts_of_their_repositories() {
+    let mut info_by_crate = Dict::new();
+    for name in &["a", "a-derive", "b", "c", "d"] {
+        info_by_crate.insert((*name).to_owned(), info(10));
+    }
+
+    let distribution = HostingDistribution::new(
+        vec![
+            ("a", "https://github.com/org/a"),
+            ("a-derive", "https://github.com/org/a/tree/main/a-derive"),
+            ("b", "https://gitlab.com/org/b"),
+            ("c", "https://git.example.com/c"),
+            ("d", "not a url"),
+            ("not-analyzed", "https://github.com/org/e"),
+        ],
+        &info_by_crate,
+    );
+    assert_eq!(
+        distribution
+            .hosts
+            .iter()
+            .map(|host| (host.name.as_str(), host.info_by_crate.len()))
+            .collect::<Vec<_>>(),
+        vec![("github.com", 2), ("git.example.com", 1), ("gitlab.com", 1)],
+        "hosts with the most crates come first"
+    );
+    assert_eq!(distribution.crates_with_repository(), 4);
+    assert_eq!(distribution.crates_without_repository, 1);
+
+    let github = &distribution.hosts[0];
+    assert_eq!(github.service.as_deref(), Some("GitHub"));
+    assert_eq!(
+        github.crates_by_repository.get("github.com/org/a"),
+        Some(&vec!["a".to_owned(), "a-derive".to_owned()])
+    );
+    assert_eq!(github.total().all.total_bytes, 20);
+    assert_eq!(github.path(), "github.com.html");
+
+    assert_eq!(
+        distribution.crates_by_service(),
+        vec![
+            (Some("GitHub".to_owned()), 2),
+            (None, 1),
+            (Some("GitLab".to_owned()), 1)
+        ]
+    );
+}
@@ -1 +1,37 @@
+use super::{AggregateFileInfo, AggregateVersionInfo};
+
+mod discrepancy;
 mod from_package;
+mod hosting;
+mod patch;
+mod project;
+#[cfg(feature = "json-schema")]
+mod schema;
+mod spotlight;
+
+fn file(total_bytes: u64) -> AggregateFileInfo {
+    AggregateFileInfo {
+        total_bytes,
+        total_files: 1,
+    }
+}
+
+/// The information about a crate with a single file of `all_bytes` and no waste, to add waste to as needed.
+fn info(all_bytes: u64) -> AggregateVersionInfo {
+    AggregateVersionInfo {
+        all: file(all_bytes),
+        ..Default::default()
+    }
+}
+
+impl AggregateVersionInfo {
+    fn with_waste(mut self, waste_bytes: u64) -> Self {
+        self.waste = file(waste_bytes);
+        self
+    }
+
+    fn with_latest_version_waste(mut self, latest_version: &str, waste_bytes: u64) -> Self {
+        self.waste_latest_version = Some((latest_version.into(), file(waste_bytes)));
+        self
+    }
+}
@@ -0,0 +1,88 @@
+use super::super::{patch::manifest_patch, AggregateFileInfo, Fix, Report};
+
+#[test]
+fn new_include_is_added_at_the_end_of_the_package_section() {
+    assert_eq!(
+        manifest_patch(
+            "[package]\nname = \"a\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n[dependencies]\nserde = \"1\"\n",
+            &Fix::NewInclude {
+                include: vec!["src/**/*".into(), "Cargo.toml".into()],
+                has_build_script: false,
+            }
+        )
+        .unwrap(),
+        r#"--- a/Cargo.toml
++++ b/Cargo.toml
+@@ -2,6 +2,7 @@
+ name = "a"
+ version = "0.1.0"
+ edition = "2018"
++include = ["src/**/*", "Cargo.toml"]
+ 
+ [dependencies]
+ serde = "1"
+"#
+    );
+}
+
+#[test]
+fn multi_line_exclude_is_replaced_with_include() {
+    assert_eq!(
+        manifest_patch(
+            "[package]\nname = \"b\"\nexclude = [\n    \"tests/*\",\n]\ninclude = [\"src/**/*\"]\n",
+            &Fix::RemoveExcludeAndUseInclude {
+                include_added: vec!["Cargo.toml".into()],
+                include: vec!["src/**/*".into(), "Cargo.toml".into()],
+                include_removed: vec![],
+            }
+        )
+        .unwrap(),
+        r#"--- a/Cargo.toml
++++ b/Cargo.toml
+@@ -1,6 +1,3 @@
+ [package]
+ name = "b"
+-exclude = [
+-    "tests/*",
+-]
+-include = ["src/**/*"]
++include = ["src/**/*", "Cargo.toml"]
+"#
+    );
+}
+
+#[test]
+fn manifests_without_package_section_cannot_be_patched() {
+    assert_eq!(manifest_patch("[workspace]\n", &Fix::RemoveExclude), None);
+}
+
+#[test]
+fn pull_request_for_version_report() {
+    let report = Report::Version {
+        crate_name: "c".into(),
+        crate_version: "1".into(),
+        total_size_in_bytes: 100,
+        total_files: 2,
+        wasted_files: vec![("a.txt".into(), 10)],
+        suggested_fix: Some(Fix::RemoveExclude),
+        provenance: None,
+    };
+    let pr = report
+        .pull_request(
+            "[package]\nname = \"c\"\nrepository = \"https://example.com/c\"\ninclude = [\"src/**/*\"]\nexclude = [\"*.txt\"]\n",
+        )
+        .unwrap();
+    assert_eq!(pr.repository.as_deref(), Some("https://example.com/c"));
+    assert_eq!(pr.manifest_path, "Cargo.toml");
+    assert_eq!(
+        pr.savings,
+        AggregateFileInfo {
+            total_bytes: 10,
+            total_files: 1
+        }
+    );
+    assert!(pr.patch.contains("\n-exclude = [\"*.txt\"]\n"));
+    assert!(pr
+        .body
+        .starts_with("Version 1 of this crate was published with 1 files and a total of 10 bytes"));
+}
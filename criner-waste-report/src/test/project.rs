@@ -0,0 +1,80 @@
+use super::super::{
+    project::{normalize_repository_url, Project},
+    Dict, Owner,
+};
+use super::info;
+
+#[test]
+fn repository_urls_are_normalized_to_the_repository() {
+    for url in &[
+        "https://github.com/the-lean-crate/criner",
+        "https://github.com/the-lean-crate/criner/",
+        "http://www.github.com/The-Lean-Crate/criner.git",
+        "git@github.com:the-lean-crate/criner.git",
+        "https://github.com/the-lean-crate/criner/tree/master/criner-waste-report",
+        "github.com/the-lean-crate/criner#readme",
+    ] {
+        assert_eq!(
+            normalize_repository_url(url).as_deref(),
+            Some("github.com/the-lean-crate/criner"),
+            "{}",
+            url
+        );
+    }
+    assert_eq!(
+        normalize_repository_url("https://git.example.com/group/sub/repo/-/tree/main").as_deref(),
+        Some("git.example.com/group/sub/repo"),
+        "paths on unknown hosts are kept until they point into the repository"
+    );
+    assert_eq!(
+        normalize_repository_url("https://example.com"),
+        None,
+        "there is no repository"
+    );
+    assert_eq!(normalize_repository_url("not a url"), None);
+}
+
+fn owner(login: &str) -> Owner {
+    Owner {
+        login: login.into(),
+        name: None,
+        is_team: false,
+    }
+}
+
+#[test]
+fn crates_sharing_a_repository_are_grouped_with_their_shared_owners() {
+    let mut info_by_crate = Dict::new();
+    info_by_crate.insert("a".to_owned(), info(100).with_waste(10));
+    info_by_crate.insert("a-derive".to_owned(), info(50).with_waste(5));
+    info_by_crate.insert("b".to_owned(), info(10).with_waste(0));
+
+    let projects = Project::group(
+        vec![
+            ("a", "https://github.com/org/a"),
+            ("a-derive", "https://github.com/org/a/tree/main/a-derive"),
+            ("b", "https://github.com/org/b"),
+            ("not-analyzed", "https://github.com/org/b"),
+        ],
+        &info_by_crate,
+        |crate_name| match crate_name {
+            "a" => vec![owner("alice"), owner("bob")],
+            _ => vec![owner("bob")],
+        },
+    );
+    assert_eq!(
+        projects.len(),
+        1,
+        "only repositories with multiple analyzed crates are projects"
+    );
+    let project = &projects[0];
+    assert_eq!(project.repository, "github.com/org/a");
+    assert_eq!(project.info_by_crate.keys().collect::<Vec<_>>(), vec!["a", "a-derive"]);
+    assert_eq!(project.shared_owners, vec![owner("bob")]);
+
+    let total = project.total();
+    assert_eq!(total.all.total_bytes, 150);
+    assert_eq!(total.waste.total_bytes, 15);
+    assert_eq!(project.path(), "github.com/org/a.html");
+    assert_eq!(project.path_to_crates(), "../../../");
+}
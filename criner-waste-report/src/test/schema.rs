@@ -0,0 +1,137 @@
+use super::super::{
+    schema, AggregateFileInfo, Dict, Fix, HostingDistribution, Owner, Project, Report, TarPackage, VersionInfo,
+};
+use jsonschema::JSONSchema;
+use schemars::schema::RootSchema;
+use std::path::Path;
+
+fn assert_valid(schema: RootSchema, instance: &impl serde::Serialize) {
+    let schema = serde_json::to_value(schema).unwrap();
+    let schema = JSONSchema::compile(&schema).expect("generated schemas to be valid");
+    let instance = serde_json::to_value(instance).unwrap();
+    if let Err(errors) = schema.validate(&instance) {
+        panic!(
+            "{} does not match its schema: {}",
+            instance,
+            errors.map(|e| e.to_string()).collect::<Vec<_>>().join(", ")
+        );
+    }
+}
+
+fn version_report() -> Report {
+    let package: TarPackage = rmp_serde::from_slice(
+        std::fs::read(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures")
+                .join("avr_libc-0.1.3extract_crate-1.0.0.package.rmp"),
+        )
+        .unwrap()
+        .as_slice(),
+    )
+    .unwrap();
+    Report::from_package("avr_libc", "0.1.3", package)
+}
+
+fn info() -> VersionInfo {
+    VersionInfo {
+        all: AggregateFileInfo {
+            total_bytes: 100,
+            total_files: 2,
+        },
+        waste: AggregateFileInfo {
+            total_bytes: 10,
+            total_files: 1,
+        },
+        waste_latest_version: Some((
+            "1.0.0".into(),
+            AggregateFileInfo {
+                total_bytes: 10,
+                total_files: 1,
+            },
+        )),
+        potential_gains: None,
+    }
+}
+
+#[test]
+fn reports_match_their_schema() {
+    let version_report = version_report();
+    assert!(
+        matches!(
+            version_report,
+            Report::Version {
+                suggested_fix: Some(_),
+                ..
+            }
+        ),
+        "the fixture has a suggested fix, which is the most complex part of a report"
+    );
+    assert_valid(schema::report(), &version_report);
+
+    let mut info_by_version = Dict::new();
+    info_by_version.insert("1.0.0".to_owned(), info());
+    assert_valid(
+        schema::report(),
+        &Report::Crate {
+            crate_name: "a".into(),
+            total_size_in_bytes: 100,
+            total_files: 2,
+            info_by_version,
+            wasted_by_extension: Dict::new(),
+            owners: vec![Owner {
+                login: "github:org:team".into(),
+                name: None,
+                is_team: true,
+            }],
+            yanked_versions: vec!["0.1.0".into()],
+            deleted_at: Some("2020-01-01T00:00:00Z".into()),
+        },
+    );
+}
+
+#[test]
+fn pull_requests_match_their_schema() {
+    let report = Report::Version {
+        crate_name: "c".into(),
+        crate_version: "1".into(),
+        total_size_in_bytes: 100,
+        total_files: 2,
+        wasted_files: vec![("a.txt".into(), 10)],
+        suggested_fix: Some(Fix::RemoveExclude),
+        provenance: None,
+    };
+    let pull_request = report
+        .pull_request("[package]\nname = \"c\"\ninclude = [\"src/**/*\"]\nexclude = [\"*.txt\"]\n")
+        .unwrap();
+    assert_valid(schema::pull_requests(), &vec![pull_request]);
+}
+
+#[test]
+fn projects_match_their_schema() {
+    let mut info_by_crate = Dict::new();
+    info_by_crate.insert("a".to_owned(), info());
+    info_by_crate.insert("a-derive".to_owned(), info());
+    assert_valid(
+        schema::project(),
+        &Project {
+            repository: "github.com/org/a".into(),
+            info_by_crate,
+            shared_owners: Vec::new(),
+        },
+    );
+}
+
+#[test]
+fn hosting_distributions_match_their_schema() {
+    let mut info_by_crate = Dict::new();
+    info_by_crate.insert("a".to_owned(), info());
+    info_by_crate.insert("b".to_owned(), info());
+    info_by_crate.insert("c".to_owned(), info());
+    assert_valid(
+        schema::hosting(),
+        &HostingDistribution::new(
+            vec![("a", "https://github.com/org/a"), ("b", "https://git.example.com/b")],
+            &info_by_crate,
+        ),
+    );
+}
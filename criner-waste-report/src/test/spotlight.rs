@@ -0,0 +1,34 @@
+use super::super::{Dict, Spotlight};
+use super::{file, info};
+
+#[test]
+fn selection_rotates_through_all_candidates_by_day() {
+    let mut previous = Dict::new();
+    previous.insert("a".to_owned(), info(100).with_latest_version_waste("1", 50));
+    let mut current = Dict::new();
+    current.insert("a".to_owned(), info(200).with_latest_version_waste("2", 10));
+    current.insert("b".to_owned(), info(1000).with_latest_version_waste("1", 20));
+
+    let names: Vec<_> = (0..4)
+        .map(|day| Spotlight::select(&previous, &current, day).map(|s| s.crate_name().to_owned()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![Some("a".into()), Some("b".into()), Some("b".into()), Some("a".into())],
+        "most improved, newly analyzed, most wasteful, and around again"
+    );
+    assert_eq!(
+        Spotlight::select(&previous, &current, 0),
+        Some(Spotlight::MostImproved {
+            crate_name: "a".into(),
+            crate_version: "2".into(),
+            previous_waste: file(50),
+            waste: file(10),
+        })
+    );
+}
+
+#[test]
+fn nothing_to_select_without_crates() {
+    assert_eq!(Spotlight::select(&Dict::new(), &Dict::new(), 42), None);
+}
@@ -0,0 +1,233 @@
+//! Measure the throughput of the operations which dominate mining on the hardware at hand, as a basis for choosing
+//! the amount of processors and the size of report chunks.
+use crate::{
+    engine::{
+        report::{generic::Generator, waste},
+        run::ExtractionProfile,
+        work::cpubound,
+    },
+    model,
+    persistence::{self, new_key_value_insertion, Db, Keyed, TableAccess},
+    Result,
+};
+use rusqlite::params;
+use std::{
+    io::Write,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// The name of all synthetic crates, which differ in their version only
+const SYNTHETIC_CRATE_NAME: &str = "criner-bench";
+/// The amount of source files in the synthetic crate archive besides its manifest, about as many as a typical crate has
+const SYNTHETIC_SOURCE_FILES: usize = 40;
+/// The size of each source file in the synthetic crate archive
+const SYNTHETIC_SOURCE_FILE_SIZE: usize = 4 * 1024;
+
+/// Run all benchmarks `rounds` times against a database created in `scratch_dir`, which is removed afterwards,
+/// and write a summary to `out`.
+pub fn run_blocking(scratch_dir: impl AsRef<Path>, rounds: usize, mut out: impl Write) -> Result<()> {
+    let db_dir = scratch_dir
+        .as_ref()
+        .join(format!("criner-bench-{}", std::process::id()));
+    let res = run(&db_dir, rounds.max(1), &mut out);
+    std::fs::remove_dir_all(&db_dir).ok();
+    res
+}
+
+fn run(db_dir: &Path, rounds: usize, out: &mut impl Write) -> Result<()> {
+    let db = Db::open(db_dir)?;
+    let root = prodash::TreeOptions::default().create();
+    let mut progress = root.add_child("bench");
+    writeln!(
+        out,
+        "Running {} rounds of each benchmark in '{}'",
+        rounds,
+        db_dir.display()
+    )?;
+
+    let versions: Vec<_> = (0..rounds).map(synthetic_crate_version).collect();
+    let elapsed = timed(|| {
+        let mut connection = db.open_connection_no_async_with_busy_wait()?;
+        let transaction = connection.transaction()?;
+        {
+            let mut statement = new_key_value_insertion(persistence::CrateVersionTable::table_name(), &transaction)?;
+            for version in &versions {
+                statement.execute(params![version.key(), rmp_serde::to_vec(version)?])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    })?;
+    write_rate(out, "inserts in one transaction", rounds, "rows", elapsed)?;
+
+    let tasks = db.open_tasks()?;
+    let elapsed = timed(|| {
+        let task = cpubound::default_persisted_extraction_task();
+        for version in &versions {
+            tasks.insert(&mut progress, version.key(), &task)?;
+        }
+        Ok(())
+    })?;
+    write_rate(out, "inserts in own transaction", rounds, "rows", elapsed)?;
+
+    let crate_versions = db.open_crate_versions()?;
+    let mut latencies = Vec::with_capacity(rounds);
+    for round in 0..rounds {
+        // Scatter reads across the table to not only measure reads of pages which were just read
+        let version = &versions[round * 7919 % rounds];
+        let start = Instant::now();
+        crate_versions.get(version.key())?;
+        latencies.push(start.elapsed());
+    }
+    latencies.sort();
+    writeln!(
+        out,
+        "{:<28} {:>10?} median, {:?} at the 99th percentile",
+        "blob read latency",
+        latencies[latencies.len() / 2],
+        latencies[latencies.len() * 99 / 100]
+    )?;
+
+    let archive = synthetic_crate_archive()?;
+    let archive_path = db_dir.join("synthetic.crate");
+    std::fs::write(&archive_path, &archive)?;
+    let (results, tar_headers) = (db.open_results()?, db.open_tar_headers()?);
+    let profile = ExtractionProfile::default();
+    let selected_paths = cpubound::selected_paths(&profile)?;
+    let mut key_buf = String::new();
+    let elapsed = timed(|| {
+        for version in &versions {
+            key_buf.clear();
+            waste::Generator::fq_result_key(&version.name, &version.version, &mut key_buf);
+            cpubound::extract_crate(
+                &results,
+                &tar_headers,
+                &key_buf,
+                (&version.name, &version.version),
+                &mut progress,
+                archive_path.clone(),
+                &selected_paths,
+                &profile,
+            )?;
+        }
+        Ok(())
+    })?;
+    write_rate(out, "crate extractions", rounds, "crates", elapsed)?;
+    writeln!(
+        out,
+        "{:<28} {:>10.1} MB/s of {} byte crate archives",
+        "",
+        (archive.len() * rounds) as f64 / 1_000_000.0 / seconds(elapsed),
+        archive.len()
+    )?;
+
+    let connection = db.open_connection()?;
+    let mut packages = Vec::with_capacity(rounds);
+    for version in &versions {
+        key_buf.clear();
+        if let Some(model::TaskResult::ExplodedCrate {
+            entries_meta_data,
+            selected_entries,
+            ..
+        }) = waste::Generator::get_result(connection.clone(), &version.name, &version.version, &mut key_buf)?
+        {
+            let package = waste::TarPackage {
+                entries_meta_data,
+                entries: selected_entries,
+            };
+            packages.push((version, package));
+        }
+    }
+    let mut reports = Vec::with_capacity(packages.len());
+    let elapsed = timed(|| {
+        for (version, package) in packages {
+            reports.push(waste::Report::from_package(&version.name, &version.version, package));
+        }
+        Ok(())
+    })?;
+    write_rate(out, "report computations", reports.len(), "reports", elapsed)?;
+
+    let mut html = Vec::new();
+    let elapsed = timed(|| {
+        use horrorshow::Template;
+        for report in &reports {
+            html.clear();
+            report.write_to_io(&mut html)?;
+        }
+        Ok(())
+    })?;
+    write_rate(out, "report renderings", reports.len(), "reports", elapsed)?;
+
+    writeln!(
+        out,
+        "\nThe extraction rate is the most a single CPU-bound processor achieves - use more processors if downloads \
+         outpace it. Report chunks are computed and rendered at the rates above per CPU-bound report processor."
+    )?;
+    Ok(())
+}
+
+fn synthetic_crate_version(round: usize) -> model::CrateVersion {
+    model::CrateVersion {
+        name: SYNTHETIC_CRATE_NAME.into(),
+        version: format!("1.0.{}", round),
+        checksum: format!("{:064x}", round),
+        ..Default::default()
+    }
+}
+
+/// A gzip compressed crate archive with a manifest and source files which compress like source code does, roughly
+fn synthetic_crate_archive() -> Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(libflate::gzip::Encoder::new(Vec::new())?);
+    let mut append = |path: String, content: &[u8]| {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, format!("{}-1.0.0/{}", SYNTHETIC_CRATE_NAME, path), content)
+    };
+    append(
+        "Cargo.toml".into(),
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"1.0.0\"\nedition = \"2018\"\n",
+            SYNTHETIC_CRATE_NAME
+        )
+        .as_bytes(),
+    )?;
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz_(){};:= \n";
+    for file_index in 0..SYNTHETIC_SOURCE_FILES {
+        let content: Vec<u8> = (0..SYNTHETIC_SOURCE_FILE_SIZE)
+            .map(|i| ALPHABET[(i * 31 + i / 7 + file_index) % ALPHABET.len()])
+            .collect();
+        let path = match file_index {
+            0 => "src/lib.rs".into(),
+            _ if file_index % 4 == 0 => format!("tests/fixture_{}.txt", file_index),
+            _ => format!("src/module_{}.rs", file_index),
+        };
+        append(path, &content)?;
+    }
+    Ok(builder.into_inner()?.finish().into_result()?)
+}
+
+fn timed(f: impl FnOnce() -> Result<()>) -> Result<Duration> {
+    let start = Instant::now();
+    f()?;
+    Ok(start.elapsed())
+}
+
+fn seconds(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+fn write_rate(out: &mut impl Write, what: &str, count: usize, unit: &str, elapsed: Duration) -> Result<()> {
+    writeln!(
+        out,
+        "{:<28} {:>10.0} {}/s ({} in {:?})",
+        what,
+        count as f64 / seconds(elapsed),
+        unit,
+        count,
+        elapsed
+    )?;
+    Ok(())
+}
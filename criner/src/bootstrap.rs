@@ -0,0 +1,198 @@
+//! Create a database from a snapshot of the database of another instance instead of mining crates.io from scratch,
+//! which takes days, to work on reports and analyses right away.
+use crate::{
+    engine::{run::DownloadSettings, work::iobound},
+    persistence,
+    persistence::Db,
+    Error, Result,
+};
+use rusqlite::{Connection, OptionalExtension, NO_PARAMS};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// The extension of snapshots which are compressed with zstd, like `db.msgpack.sqlite.zst`
+const ZSTD_EXTENSION: &str = "zst";
+
+/// Log the download progress whenever this amount of bytes was received
+const DOWNLOAD_PROGRESS_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// A successful download of a snapshot, recorded in the request log once the database it contains is installed
+struct SnapshotRequest {
+    url: String,
+    requested_at: SystemTime,
+    duration: Duration,
+    status: u16,
+    bytes: u64,
+}
+
+/// Create the database at `db` from the snapshot at `source`, which is either a URL or the path to a copy of the sqlite
+/// database file of another instance, and write what happens to `out`.
+///
+/// The snapshot is decompressed if its name ends with `.zst`. It must be consistent, which is why copies of databases
+/// which are in use should be made with `VACUUM INTO`.
+/// Downloaded crates are not part of it, but the results of their extraction are, which is all reports need.
+/// Snapshots are downloaded like crates with `download_settings`.
+pub fn run_blocking(
+    source: &str,
+    db: impl AsRef<Path>,
+    allow_newer_database: bool,
+    download_settings: &DownloadSettings,
+    mut out: impl Write,
+) -> Result<()> {
+    let db = db.as_ref();
+    let db_file = db.join(persistence::SQLITE_FILE_NAME);
+    if db_file.exists() {
+        return Err(Error::Message(format!(
+            "There is already a database at '{}' - only new databases can be bootstrapped",
+            db_file.display()
+        )));
+    }
+    std::fs::create_dir_all(db)?;
+
+    let start = SystemTime::now();
+    let snapshot = db_file.with_extension("sqlite.bootstrap");
+    let res = fetch(source, &snapshot, download_settings, &mut out)
+        .and_then(|request| install(&snapshot, &db_file).map(|_| request));
+    std::fs::remove_file(&snapshot).ok();
+    let request = res?;
+
+    let db = Db::open_with_skew_check(db, allow_newer_database).map_err(|err| {
+        std::fs::remove_file(&db_file).ok();
+        err
+    })?;
+    if let Some(SnapshotRequest {
+        url,
+        requested_at,
+        duration,
+        status,
+        bytes,
+    }) = request
+    {
+        if let Err(err) = db
+            .open_request_log()
+            .and_then(|log| log.record_with_duration(&url, requested_at, duration, Some(status), bytes, None))
+        {
+            log::warn!("Could not record request to '{}' in request log: {}", url, err);
+        }
+    }
+    let counts = db
+        .open_context()?
+        .most_recent()?
+        .map(|(_, context)| context.counts)
+        .unwrap_or_default();
+    writeln!(
+        out,
+        "Bootstrapped '{}' with {} crates and {} crate versions in {}",
+        db_file.display(),
+        counts.crates,
+        counts.crate_versions,
+        humantime::format_duration(SystemTime::now().duration_since(start).unwrap_or_default())
+    )?;
+    Ok(())
+}
+
+/// Place the uncompressed snapshot at `source` into `destination`, and return the request made to download it with
+/// `download_settings` if it is a URL.
+fn fetch(
+    source: &str,
+    destination: &Path,
+    download_settings: &DownloadSettings,
+    out: &mut impl Write,
+) -> Result<Option<SnapshotRequest>> {
+    let is_compressed = Path::new(source).extension().and_then(|ext| ext.to_str()) == Some(ZSTD_EXTENSION);
+    if source.starts_with("http://") || source.starts_with("https://") {
+        writeln!(out, "Downloading snapshot from {}", source)?;
+        let client = iobound::client_builder(download_settings, source)?.build()?;
+        if !is_compressed {
+            return futures_lite::future::block_on(crate::spawn(download(
+                client,
+                source.to_owned(),
+                destination.to_owned(),
+            )))
+            .map(Some);
+        }
+        let compressed = destination.with_extension("bootstrap.zst");
+        let res = futures_lite::future::block_on(crate::spawn(download(client, source.to_owned(), compressed.clone())))
+            .and_then(|request| decompress(&compressed, destination).map(|_| Some(request)));
+        std::fs::remove_file(&compressed).ok();
+        return res;
+    }
+
+    let source = Path::new(source);
+    if !source.is_file() {
+        return Err(Error::Message(format!(
+            "The snapshot at '{}' is neither a file nor an http(s) URL",
+            source.display()
+        )));
+    }
+    if is_compressed {
+        writeln!(out, "Decompressing snapshot at '{}'", source.display())?;
+        decompress(source, destination)?;
+    } else {
+        writeln!(out, "Copying snapshot at '{}'", source.display())?;
+        std::fs::copy(source, destination)?;
+    }
+    Ok(None)
+}
+
+async fn download(client: reqwest::Client, url: String, destination: PathBuf) -> Result<SnapshotRequest> {
+    let requested_at = SystemTime::now();
+    let mut response = client.get(&url).send().await?;
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus(response.status()));
+    }
+    let mut file = std::fs::File::create(&destination)?;
+    let mut received = 0;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk)?;
+        let previously_received = received;
+        received += chunk.len() as u64;
+        if received / DOWNLOAD_PROGRESS_INTERVAL != previously_received / DOWNLOAD_PROGRESS_INTERVAL {
+            log::info!("Received {} MB of the snapshot", received / (1024 * 1024));
+        }
+    }
+    file.flush()?;
+    Ok(SnapshotRequest {
+        status: response.status().as_u16(),
+        duration: SystemTime::now().duration_since(requested_at).unwrap_or_default(),
+        url,
+        requested_at,
+        bytes: received,
+    })
+}
+
+fn decompress(source: &Path, destination: &Path) -> Result<()> {
+    let input = std::fs::File::open(source)?;
+    let output = std::fs::File::create(destination)?;
+    zstd::stream::copy_decode(input, output)?;
+    Ok(())
+}
+
+/// Move `snapshot` to `db_file` once it is known to be an intact criner database.
+fn install(snapshot: &Path, db_file: &Path) -> Result<()> {
+    {
+        let connection = Connection::open(snapshot)?;
+        let integrity: String = connection.query_row("PRAGMA quick_check", NO_PARAMS, |r| r.get(0))?;
+        if integrity != "ok" {
+            return Err(Error::Message(format!("The snapshot is corrupt: {}", integrity)));
+        }
+        let has_schema_version = connection
+            .query_row(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+                NO_PARAMS,
+                |r| r.get::<_, String>(0),
+            )
+            .optional()?
+            .is_some();
+        if !has_schema_version {
+            return Err(Error::Message(
+                "The snapshot isn't a criner database as it doesn't record its schema version".into(),
+            ));
+        }
+    }
+    std::fs::rename(snapshot, db_file)?;
+    Ok(())
+}
@@ -0,0 +1,44 @@
+//! Show which crates are the most expensive to process, to help choosing which versions to process and which crates
+//! to exclude when capacity is limited.
+use crate::{persistence::Db, Result};
+use std::{io::Write, path::Path, time::Duration};
+
+/// Write the `top` crates of the database at `db` which took the longest to download, extract and report on
+/// in all of their versions to `out`.
+pub fn run_blocking(db: impl AsRef<Path>, top: usize, mut out: impl Write) -> Result<()> {
+    let db = Db::open_for_reading(db)?;
+    let costs = db.open_processing_costs()?.most_expensive(top)?;
+    if costs.is_empty() {
+        writeln!(out, "No processing costs were recorded yet")?;
+        return Ok(());
+    }
+
+    writeln!(
+        out,
+        "{:<32} {:>8} {:>12} {:>12} {:>12} {:>12} {:>10} {:>10}",
+        "crate", "versions", "total", "download", "extract", "report", "MB down", "MB report"
+    )?;
+    for cost in costs {
+        writeln!(
+            out,
+            "{:<32} {:>8} {:>12} {:>12} {:>12} {:>12} {:>10.1} {:>10.1}",
+            cost.crate_name,
+            cost.num_versions,
+            seconds(cost.total_duration()),
+            seconds(cost.download.duration),
+            seconds(cost.extract.duration),
+            seconds(cost.report.duration),
+            megabytes(cost.download.bytes),
+            megabytes(cost.report.bytes)
+        )?;
+    }
+    Ok(())
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.2}s", duration.as_secs_f64())
+}
+
+fn megabytes(bytes: u64) -> f64 {
+    bytes as f64 / 1_000_000.0
+}
@@ -0,0 +1,39 @@
+//! Compare two crates.io database dumps, to see how the registry changed between them without ingesting either.
+use crate::{engine::stage::db_download::diff, Result};
+use std::{io::Write, path::Path};
+
+/// Compare the db dump at `old` with the one at `new` row by row and write the differences of each table to `out`,
+/// with up to `samples` keys of rows that were added, removed or changed.
+pub fn run_blocking(old: impl AsRef<Path>, new: impl AsRef<Path>, samples: usize, mut out: impl Write) -> Result<()> {
+    let root = prodash::TreeOptions::default().create();
+    let mut progress = root.add_child("diff db dumps");
+    let diff = diff::diff(old.as_ref(), new.as_ref(), &mut progress)?;
+
+    for table in &diff.tables {
+        writeln!(
+            out,
+            "{}: {} added, {} removed, {} changed",
+            table.table,
+            table.added.len(),
+            table.removed.len(),
+            table.changed.len()
+        )?;
+        for (kind, keys) in &[("+", &table.added), ("-", &table.removed), ("~", &table.changed)] {
+            for key in keys.iter().take(samples) {
+                writeln!(out, "  {} {}", kind, key)?;
+            }
+            if keys.len() > samples {
+                writeln!(out, "  {} … and {} more", kind, keys.len() - samples)?;
+            }
+        }
+    }
+    let (old_skipped, new_skipped) = diff.skipped_rows;
+    if old_skipped + new_skipped > 0 {
+        writeln!(
+            out,
+            "Skipped {} rows of the old and {} rows of the new db dump which couldn't be decoded",
+            old_skipped, new_skipped
+        )?;
+    }
+    Ok(())
+}
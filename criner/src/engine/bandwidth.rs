@@ -0,0 +1,140 @@
+//! Caps on the bandwidth of downloads and the rate of requests to crates.io, shared by all IO-bound processors.
+use async_io::Timer;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// The amount of bytes per second all downloads may receive together, or no limit if it's the default.
+///
+/// Clones share the limit, so it's created once and handed to every processor.
+#[derive(Clone, Default)]
+pub struct Bandwidth {
+    bucket: Option<Arc<parking_lot::Mutex<Bucket>>>,
+}
+
+/// The amount of requests per second which may be sent to crates.io and its subdomains, or no limit if it's
+/// the default. The crawler policy of crates.io asks for at most one request per second.
+///
+/// Clones share the limit, so it's created once and handed to everything sending requests.
+#[derive(Clone, Default)]
+pub struct RequestRate {
+    bucket: Option<Arc<parking_lot::Mutex<Bucket>>>,
+}
+
+/// A token bucket which holds up to `capacity` tokens, so short bursts pass without waiting.
+/// Its balance becomes negative if more was consumed than it held, which all following consumers wait out.
+struct Bucket {
+    per_second: f64,
+    capacity: f64,
+    balance: f64,
+    refilled_at: Instant,
+    /// The time all consumers waited for in total
+    waited: Duration,
+}
+
+impl Bandwidth {
+    /// Limit all downloads sharing this instance to `bytes_per_second`, which must not be 0.
+    pub fn limited_to(bytes_per_second: u64) -> Self {
+        assert!(bytes_per_second > 0, "a bandwidth of 0 would never finish a download");
+        let bytes_per_second = bytes_per_second as f64;
+        Bandwidth {
+            bucket: Some(Arc::new(parking_lot::Mutex::new(Bucket::new(
+                bytes_per_second,
+                bytes_per_second,
+            )))),
+        }
+    }
+
+    /// Account for `bytes` which were just received, and wait until receiving them is within the limit.
+    pub async fn consume(&self, bytes: usize) {
+        let wait = match &self.bucket {
+            Some(bucket) => bucket.lock().consume(bytes as f64),
+            None => return,
+        };
+        if let Some(wait) = wait {
+            Timer::after(wait).await;
+        }
+    }
+}
+
+impl RequestRate {
+    /// Limit the requests to crates.io sharing this instance to `requests_per_second`, allowing up to `burst` of them
+    /// at once after a pause. `requests_per_second` must be positive.
+    pub fn limited_to(requests_per_second: f64, burst: u32) -> Self {
+        assert!(
+            requests_per_second > 0.0,
+            "a request rate of 0 would never send a request"
+        );
+        RequestRate {
+            bucket: Some(Arc::new(parking_lot::Mutex::new(Bucket::new(
+                requests_per_second,
+                f64::from(burst.max(1)),
+            )))),
+        }
+    }
+
+    /// Wait until a request to `url` is within the limit if it goes to crates.io or one of its subdomains, and
+    /// tell `progress` about it if the limit is what's holding it up.
+    pub async fn wait_for(&self, url: &str, progress: Option<&mut prodash::tree::Item>) {
+        let bucket = match &self.bucket {
+            Some(bucket) if is_crates_io(url) => bucket,
+            _ => return,
+        };
+        let wait = bucket.lock().consume(1.0);
+        if let Some(wait) = wait {
+            if let Some(progress) = progress {
+                progress.blocked(
+                    "waiting for the crates.io request rate limit",
+                    Some(std::time::SystemTime::now() + wait),
+                );
+            }
+            Timer::after(wait).await;
+        }
+    }
+
+    /// The time all requests waited for the limit in total
+    pub fn waited(&self) -> Duration {
+        self.bucket
+            .as_ref()
+            .map(|bucket| bucket.lock().waited)
+            .unwrap_or_default()
+    }
+}
+
+fn is_crates_io(url: &str) -> bool {
+    let url = match reqwest::Url::parse(url) {
+        Ok(url) => url,
+        Err(_) => return false,
+    };
+    url.host_str()
+        .map(|host| host == "crates.io" || host.ends_with(".crates.io"))
+        .unwrap_or(false)
+}
+
+impl Bucket {
+    fn new(per_second: f64, capacity: f64) -> Self {
+        Bucket {
+            per_second,
+            capacity,
+            balance: capacity,
+            refilled_at: Instant::now(),
+            waited: Duration::default(),
+        }
+    }
+
+    fn consume(&mut self, amount: f64) -> Option<Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.refilled_at = now;
+        self.balance = (self.balance + elapsed * self.per_second).min(self.capacity);
+        self.balance -= amount;
+        if self.balance < 0.0 {
+            let wait = Duration::from_secs_f64(-self.balance / self.per_second);
+            self.waited += wait;
+            Some(wait)
+        } else {
+            None
+        }
+    }
+}
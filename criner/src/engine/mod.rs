@@ -1,4 +1,8 @@
+pub mod bandwidth;
+pub mod notify;
+#[cfg(feature = "reports")]
 pub mod report;
+pub mod schedule;
 pub mod stage;
 pub mod work;
 
@@ -0,0 +1,135 @@
+//! Tell downstream systems about crate versions as soon as they are stored, so they can react to publishes in near
+//! real time.
+use crate::{
+    engine::{
+        run::{DownloadSettings, EventSink},
+        work::iobound,
+    },
+    model, persistence, Error, Result,
+};
+use serde_derive::Serialize;
+use std::{
+    io::Write,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+/// The maximum amount of events sent to a webhook in one request
+const WEBHOOK_BATCH_SIZE: usize = 500;
+/// The time after which a request to a webhook is abandoned
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A crate version which was stored for the first time
+#[derive(Debug, Clone, Serialize)]
+pub struct NewCrateVersion {
+    /// Always `new_crate_version`, to allow for other kinds of events in future
+    pub event: &'static str,
+    /// The name of the crate, qualified by the name of its registry unless it's on crates.io
+    pub name: String,
+    pub version: String,
+    pub checksum: String,
+    pub yanked: bool,
+    /// The time at which the version was published as RFC 3339 timestamp, if known
+    pub published_at: Option<String>,
+}
+
+impl From<&model::CrateVersion> for NewCrateVersion {
+    fn from(version: &model::CrateVersion) -> Self {
+        NewCrateVersion {
+            event: "new_crate_version",
+            name: version.name.clone(),
+            version: version.version.clone(),
+            checksum: version.checksum.clone(),
+            yanked: version.kind == model::ChangeKind::Yanked,
+            published_at: version
+                .published_at
+                .map(|time| humantime::format_rfc3339_seconds(time).to_string()),
+        }
+    }
+}
+
+/// Send `events` to all `sinks`, logging failures instead of returning them as the events describe changes which are
+/// stored already. A sink failing doesn't keep the others from receiving the events.
+/// Requests to webhooks are sent like downloads with `download_settings`, and recorded in the request log of `db`.
+pub fn emit(
+    db: &persistence::Db,
+    sinks: &[EventSink],
+    download_settings: &DownloadSettings,
+    events: &[NewCrateVersion],
+) {
+    if events.is_empty() {
+        return;
+    }
+    for sink in sinks {
+        let res = match sink {
+            EventSink::Webhook(url) => db.open_request_log().and_then(|request_log| {
+                let client = iobound::client_builder(download_settings, url)?
+                    .timeout(WEBHOOK_TIMEOUT)
+                    .build()?;
+                let events = events.to_vec();
+                futures_lite::future::block_on(crate::spawn(post_to_webhook(url.clone(), events, client, request_log)))
+            }),
+            EventSink::File(path) => append_to_file(path, events),
+        };
+        if let Err(err) = res {
+            log::warn!("Could not emit {} events to {}: {}", events.len(), sink, err);
+        }
+    }
+}
+
+/// Post `events` as JSON arrays to the webhook at `url` with `client`, in batches of at most `WEBHOOK_BATCH_SIZE`
+/// events, and record each request in `request_log`.
+async fn post_to_webhook(
+    url: String,
+    events: Vec<NewCrateVersion>,
+    client: reqwest::Client,
+    request_log: persistence::RequestLogTable,
+) -> Result<()> {
+    for batch in events.chunks(WEBHOOK_BATCH_SIZE) {
+        let requested_at = SystemTime::now();
+        let res = client
+            .post(&url)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(batch)?)
+            .send()
+            .await;
+        let (status, bytes) = match &res {
+            Ok(response) => (Some(response.status().as_u16()), response.content_length().unwrap_or(0)),
+            Err(_) => (None, 0),
+        };
+        let res = res.map_err(Error::from).and_then(|response| {
+            if response.status().is_success() {
+                Ok(())
+            } else {
+                Err(Error::HttpStatus(response.status()))
+            }
+        });
+        if let Err(err) = request_log.record(
+            &url,
+            requested_at,
+            status,
+            bytes,
+            res.as_ref().err().map(ToString::to_string).as_deref(),
+        ) {
+            log::warn!("Could not record request to '{}' in request log: {}", url, err);
+        }
+        res?;
+    }
+    Ok(())
+}
+
+/// Append `events` to the file at `path` with one JSON object per line, creating it if needed.
+fn append_to_file(path: &Path, events: &[NewCrateVersion]) -> Result<()> {
+    let mut buf = Vec::new();
+    for event in events {
+        serde_json::to_writer(&mut buf, event)?;
+        buf.push(b'\n');
+    }
+    // Writing all events at once keeps other writers from interleaving with them
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&buf)?;
+    Ok(())
+}
@@ -1,35 +1,64 @@
-use crate::persistence::{CrateVersionTable, TableAccess};
+use crate::persistence::{CrateVersionTable, LatestVersionTable, TableAccess};
 use crate::{
     error::Result,
     model, persistence,
     persistence::{new_key_insertion, ReportsTree},
+    utils::Cancellation,
 };
 use async_trait::async_trait;
 use rusqlite::{params, TransactionBehavior};
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
 fn all_but_recently_yanked(
     crate_name: &str,
-    versions: &[String],
+    krate: &model::Crate,
     table: &CrateVersionTable,
     key_buf: &mut String,
 ) -> Result<usize> {
     let mut num_yanked = 0;
-    for version in versions.iter().rev() {
-        key_buf.clear();
-        model::CrateVersion::key_from(&crate_name, &version, key_buf);
-
-        let is_yanked = table
-            .get(&key_buf)?
-            .map(|v| v.kind == crates_index_diff::ChangeKind::Yanked)
-            .unwrap_or(true);
+    for version in krate.versions.iter().rev() {
+        let is_yanked = match krate.is_yanked(version) {
+            Some(is_yanked) => is_yanked,
+            None => {
+                key_buf.clear();
+                model::CrateVersion::key_from(&crate_name, &version, key_buf);
+                table
+                    .get(&key_buf)?
+                    .map(|v| v.kind == model::ChangeKind::Yanked)
+                    .unwrap_or(true)
+            }
+        };
         if is_yanked {
             num_yanked += 1;
         } else {
             break;
         }
     }
-    Ok(versions.len() - num_yanked)
+    Ok(krate.versions.len() - num_yanked)
+}
+
+/// Return the amount of versions of `krate` to report on, which are all but the most recent ones that are yanked.
+/// The materialized latest version is used if known, to not have to look at each of the yanked versions.
+fn num_versions_to_report(
+    crate_name: &str,
+    krate: &model::Crate,
+    latest_versions: &LatestVersionTable,
+    crate_versions: &CrateVersionTable,
+    key_buf: &mut String,
+) -> Result<usize> {
+    match latest_versions.get(crate_name)?.map(|latest| latest.version) {
+        Some(None) => return Ok(0),
+        Some(Some(latest)) => {
+            if let Some(pos) = krate.versions.iter().rposition(|v| *v == latest) {
+                return Ok(pos + 1);
+            }
+        }
+        None => {}
+    }
+    all_but_recently_yanked(crate_name, krate, crate_versions, key_buf)
 }
 
 #[derive(Clone)]
@@ -48,12 +77,32 @@ pub type WriteCallbackState = Option<async_channel::Sender<WriteRequest>>;
 pub type WriteCallback =
     fn(WriteRequest, &WriteCallbackState) -> futures_util::future::BoxFuture<Result<WriteInstruction>>;
 
+/// A write callback provided by library users to take the place of the built-in git integration, for example to
+/// upload report files elsewhere.
+///
+/// `write` is called with every file to be written along with `state`, and decides whether the file is also
+/// written to disk. Requests sent through the channel in `state` can be received on a thread owned by the caller,
+/// which sees the channel close once the engine and with it the last copy of `state` is dropped.
+#[derive(Clone)]
+pub struct CustomWriteCallback {
+    pub write: WriteCallback,
+    pub state: WriteCallbackState,
+}
+
 #[async_trait]
 pub trait Aggregate
 where
     Self: Sized,
 {
     fn merge(self, other: Self) -> Self;
+    /// Merge the report of the current run into the `previous` one loaded from the cache, if there is one.
+    /// Implementors may use this to derive information from the difference between both.
+    fn merge_with_previous(previous: Option<Self>, current: Self) -> Self {
+        match previous {
+            Some(previous) => previous.merge(current),
+            None => current,
+        }
+    }
     async fn complete(&mut self, progress: &mut prodash::tree::Item, out: &mut Vec<u8>) -> Result<()>;
     async fn load_previous_state(&self, out_dir: &Path, progress: &mut prodash::tree::Item) -> Option<Self>;
     async fn load_previous_top_level_state(out_dir: &Path, progress: &mut prodash::tree::Item) -> Option<Self>;
@@ -81,6 +130,7 @@ pub trait Generator {
     ) -> Result<Option<Self::DBResult>>;
 
     async fn merge_reports(
+        db: persistence::Db,
         out_dir: PathBuf,
         cache_dir: Option<PathBuf>,
         mut progress: prodash::tree::Item,
@@ -114,10 +164,7 @@ pub trait Generator {
                 },
                 None => None,
             };
-            report = match previous_report {
-                Some(previous_report) => previous_report.merge(report),
-                None => report,
-            };
+            report = Self::Report::merge_with_previous(previous_report, report);
             {
                 complete_and_write_report(
                     &mut report,
@@ -129,6 +176,7 @@ pub trait Generator {
                 )
                 .await?;
             }
+            Self::write_derived_reports(db, &report, &out_dir, &mut progress, write, &write_state).await?;
             if let Some(cd) = cache_dir {
                 report.store_current_state(&cd, &mut progress).await?;
             }
@@ -136,6 +184,30 @@ pub trait Generator {
         Ok(())
     }
 
+    /// Write reports derived from the final top-level `report` into `out_dir`, like ones spanning multiple crates,
+    /// right after the top-level report itself was written.
+    async fn write_derived_reports(
+        _db: persistence::Db,
+        _report: &Self::Report,
+        _out_dir: &Path,
+        _progress: &mut prodash::tree::Item,
+        _write: WriteCallback,
+        _write_state: &WriteCallbackState,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Add information to the `report` of the crate with the given `crate_name` which isn't part of any of its versions,
+    /// like the yanked state of versions in `krate`, right before it is written.
+    fn annotate_crate_report(
+        _connection: persistence::ThreadSafeConnection,
+        _crate_name: &str,
+        _krate: &model::Crate,
+        _report: &mut Self::Report,
+    ) -> Result<()> {
+        Ok(())
+    }
+
     async fn generate_report(
         crate_name: &str,
         crate_version: &str,
@@ -143,6 +215,9 @@ pub trait Generator {
         progress: &mut prodash::tree::Item,
     ) -> Result<Self::Report>;
 
+    /// Generate reports for all `krates`, stopping early at the next crate boundary if `cancel` is triggered.
+    /// Crates that were completed are still marked done and returned as part of the chunk report.
+    #[allow(clippy::too_many_arguments)]
     async fn write_files(
         db: persistence::Db,
         out_dir: PathBuf,
@@ -151,10 +226,13 @@ pub trait Generator {
         mut progress: prodash::tree::Item,
         write: WriteCallback,
         write_state: WriteCallbackState,
+        cancel: Cancellation,
     ) -> Result<Option<Self::Report>> {
         let mut chunk_report = None::<Self::Report>;
         let crate_versions = db.open_crate_versions()?;
+        let latest_versions = db.open_latest_versions()?;
         let mut reports_to_mark_done = Vec::new();
+        let mut costs = Vec::new();
         let mut out_buf = Vec::new();
         {
             let connection = db.open_connection()?;
@@ -162,15 +240,20 @@ pub trait Generator {
             let mut key_buf = String::with_capacity(32);
             // delaying writes works because we don't have overlap on work
             for (name, krate) in krates.into_iter() {
+                if cancel.is_cancelled() {
+                    progress.info("abandoning the remaining crates of this chunk");
+                    break;
+                }
                 let c: model::Crate = krate.as_slice().into();
                 let crate_dir = crate_dir(&out_dir, &name);
                 progress.init(Some(c.versions.len()), Some("versions".into()));
                 progress.set_name(&name);
 
                 let mut crate_report = None::<Self::Report>;
-                for version in c.versions.iter().take(all_but_recently_yanked(
+                for version in c.versions.iter().take(num_versions_to_report(
                     &name,
-                    &c.versions,
+                    &c,
+                    &latest_versions,
                     &crate_versions,
                     &mut key_buf,
                 )?) {
@@ -188,11 +271,14 @@ pub trait Generator {
                         key_buf.clear();
 
                         if let Some(result) = Self::get_result(connection.clone(), &name, &version, &mut key_buf)? {
+                            let start = Instant::now();
                             let mut version_report =
                                 Self::generate_report(&name, &version, result, &mut progress).await?;
 
-                            out_buf = complete_and_write_report(
-                                &mut version_report,
+                            out_buf.clear();
+                            version_report.complete(&mut progress, &mut out_buf).await?;
+                            let report_bytes = out_buf.len() as u64;
+                            out_buf = write_content(
                                 out_buf,
                                 &mut progress,
                                 version_html_path(&crate_dir, &version),
@@ -200,6 +286,13 @@ pub trait Generator {
                                 &write_state,
                             )
                             .await?;
+                            costs.push(persistence::ProcessingCost {
+                                crate_name: name.clone(),
+                                crate_version: version.clone(),
+                                stage: persistence::ProcessingStage::Report,
+                                duration: start.elapsed(),
+                                bytes: report_bytes,
+                            });
 
                             crate_report = Some(match crate_report {
                                 Some(crate_report) => crate_report.merge(version_report),
@@ -211,6 +304,7 @@ pub trait Generator {
                     }
                 }
                 if let Some(mut crate_report) = crate_report {
+                    Self::annotate_crate_report(connection.clone(), &name, &c, &mut crate_report)?;
                     let previous_state = match cache_dir.as_ref() {
                         Some(cd) => crate_report.load_previous_state(&cd, &mut progress).await,
                         None => None,
@@ -271,6 +365,11 @@ pub trait Generator {
             }
             transaction.commit()?;
         }
+        if !costs.is_empty() {
+            if let Err(err) = db.open_processing_costs().and_then(|table| table.record(&costs)) {
+                progress.info(format!("Could not record the cost of generating reports: {}", err));
+            }
+        }
         Ok(chunk_report)
     }
 }
@@ -296,11 +395,23 @@ async fn complete_and_write_report(
 ) -> Result<Vec<u8>> {
     out.clear();
     report.complete(progress, &mut out).await?;
+    write_content(out, progress, path, write, write_state).await
+}
+
+/// Send the rendered `content` of a report to the `write` callback and write it to `path` if instructed to do so,
+/// returning the buffer for reuse.
+pub async fn write_content(
+    content: Vec<u8>,
+    progress: &mut prodash::tree::Item,
+    path: impl AsRef<Path>,
+    write: WriteCallback,
+    write_state: &WriteCallbackState,
+) -> Result<Vec<u8>> {
     progress.blocked("sending report to writer", None);
     match write(
         WriteRequest {
             path: path.as_ref().to_path_buf(),
-            content: out,
+            content,
         },
         write_state,
     )
@@ -1,11 +1,13 @@
-use super::{AggregateFileInfo, AggregateVersionInfo, Dict, Fix, Report, VersionInfo, WastedFile};
+use super::{AggregateFileInfo, AggregateVersionInfo, Dict, Fix, Report, Spotlight, VersionInfo, WastedFile};
 use crate::Result;
 use async_trait::async_trait;
 use criner_waste_report::{add_optional_aggregate, html::NO_EXT_MARKER};
 use std::{
+    borrow::Cow,
     collections::BTreeMap,
     ops::AddAssign,
     path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 const TOP_LEVEL_REPORT_NAME: &str = "__top-level-report__";
@@ -150,6 +152,7 @@ pub fn collection_from_crate(
         total_files,
         info_by_crate: crate_collection_info_from_version_info(crate_name, info_by_version),
         wasted_by_extension,
+        spotlight: None,
     }
 }
 
@@ -162,6 +165,7 @@ pub fn crate_from_version(version: Report) -> Report {
             total_files,
             wasted_files,
             suggested_fix,
+            provenance: _,
         } => Report::Crate {
             crate_name,
             info_by_version: version_to_new_version_map(
@@ -174,6 +178,9 @@ pub fn crate_from_version(version: Report) -> Report {
             total_size_in_bytes,
             total_files,
             wasted_by_extension: into_map_by_extension(wasted_files),
+            owners: Vec::new(),
+            yanked_versions: Vec::new(),
+            deleted_at: None,
         },
         _ => unreachable!("must only be called with version variant"),
     }
@@ -198,6 +205,9 @@ impl crate::engine::report::generic::Aggregate for Report {
                     total_files: lhs_tf,
                     info_by_version,
                     wasted_by_extension,
+                    owners,
+                    yanked_versions,
+                    deleted_at,
                 },
                 Version {
                     crate_name: rhs_crate_name,
@@ -206,6 +216,7 @@ impl crate::engine::report::generic::Aggregate for Report {
                     total_files: rhs_tf,
                     wasted_files,
                     suggested_fix,
+                    provenance,
                 },
             ) => {
                 if lhs_crate_name == rhs_crate_name {
@@ -224,6 +235,9 @@ impl crate::engine::report::generic::Aggregate for Report {
                             ),
                         ),
                         wasted_by_extension: vec_into_map_by_extension(wasted_by_extension, wasted_files),
+                        owners,
+                        yanked_versions,
+                        deleted_at,
                     }
                 } else {
                     collection_from_crate(lhs_crate_name, lhs_tsb, lhs_tf, info_by_version, wasted_by_extension).merge(
@@ -234,6 +248,7 @@ impl crate::engine::report::generic::Aggregate for Report {
                             total_files: rhs_tf,
                             wasted_files,
                             suggested_fix,
+                            provenance,
                         },
                     )
                 }
@@ -245,6 +260,9 @@ impl crate::engine::report::generic::Aggregate for Report {
                     total_files: lhs_tf,
                     info_by_version: lhs_ibv,
                     wasted_by_extension: lhs_wbe,
+                    owners: lhs_owners,
+                    yanked_versions: _,
+                    deleted_at: _,
                 },
                 Crate {
                     crate_name: rhs_crate_name,
@@ -252,6 +270,9 @@ impl crate::engine::report::generic::Aggregate for Report {
                     total_files: rhs_tf,
                     info_by_version: rhs_ibv,
                     wasted_by_extension: rhs_wbe,
+                    owners: rhs_owners,
+                    yanked_versions: rhs_yanked_versions,
+                    deleted_at: rhs_deleted_at,
                 },
             ) => {
                 if lhs_crate_name != rhs_crate_name {
@@ -261,6 +282,9 @@ impl crate::engine::report::generic::Aggregate for Report {
                         total_files: rhs_tf,
                         info_by_version: rhs_ibv,
                         wasted_by_extension: rhs_wbe,
+                        owners: rhs_owners,
+                        yanked_versions: rhs_yanked_versions,
+                        deleted_at: rhs_deleted_at,
                     })
                 } else {
                     Crate {
@@ -269,6 +293,10 @@ impl crate::engine::report::generic::Aggregate for Report {
                         total_files: lhs_tf + rhs_tf,
                         info_by_version: map_into_map(lhs_ibv, rhs_ibv),
                         wasted_by_extension: map_into_map(lhs_wbe, rhs_wbe),
+                        owners: if rhs_owners.is_empty() { lhs_owners } else { rhs_owners },
+                        // versions may have been unyanked since, so only the more recent state counts
+                        yanked_versions: rhs_yanked_versions,
+                        deleted_at: rhs_deleted_at,
                     }
                 }
             }
@@ -278,18 +306,21 @@ impl crate::engine::report::generic::Aggregate for Report {
                     total_files: lhs_tf,
                     info_by_crate: lhs_ibc,
                     wasted_by_extension: lhs_wbe,
+                    spotlight: lhs_spotlight,
                 },
                 CrateCollection {
                     total_size_in_bytes: rhs_tsb,
                     total_files: rhs_tf,
                     info_by_crate: rhs_ibc,
                     wasted_by_extension: rhs_wbe,
+                    spotlight: rhs_spotlight,
                 },
             ) => CrateCollection {
                 total_size_in_bytes: lhs_tsb + rhs_tsb,
                 total_files: lhs_tf + rhs_tf,
                 info_by_crate: map_into_map(lhs_ibc, rhs_ibc),
                 wasted_by_extension: map_into_map(lhs_wbe, rhs_wbe),
+                spotlight: rhs_spotlight.or(lhs_spotlight),
             },
             (
                 CrateCollection {
@@ -297,6 +328,7 @@ impl crate::engine::report::generic::Aggregate for Report {
                     total_files: lhs_tf,
                     info_by_crate,
                     wasted_by_extension: lhs_wbe,
+                    spotlight,
                 },
                 Crate {
                     crate_name,
@@ -304,6 +336,9 @@ impl crate::engine::report::generic::Aggregate for Report {
                     total_files: rhs_tf,
                     info_by_version,
                     wasted_by_extension: rhs_wbe,
+                    owners: _,
+                    yanked_versions: _,
+                    deleted_at: _,
                 },
             ) => CrateCollection {
                 total_size_in_bytes: lhs_tsb + rhs_tsb,
@@ -313,10 +348,46 @@ impl crate::engine::report::generic::Aggregate for Report {
                     info_by_crate,
                     crate_collection_info_from_version_info(crate_name, info_by_version),
                 ),
+                spotlight,
             },
         }
     }
 
+    fn merge_with_previous(previous: Option<Self>, current: Self) -> Self {
+        let spotlight = {
+            let no_crates = Dict::new();
+            let previous_crates = match &previous {
+                Some(Report::CrateCollection { info_by_crate, .. }) => info_by_crate,
+                _ => &no_crates,
+            };
+            let current_crates = match &current {
+                Report::CrateCollection { info_by_crate, .. } => Cow::Borrowed(info_by_crate),
+                Report::Crate {
+                    crate_name,
+                    info_by_version,
+                    ..
+                } => Cow::Owned(crate_collection_info_from_version_info(
+                    crate_name.clone(),
+                    info_by_version.clone(),
+                )),
+                Report::Version { .. } => Cow::Owned(Dict::new()),
+            };
+            let days_since_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() / (24 * 60 * 60))
+                .unwrap_or_default();
+            Spotlight::select(previous_crates, &current_crates, days_since_epoch)
+        };
+        let mut report = match previous {
+            Some(previous) => previous.merge(current),
+            None => current,
+        };
+        if let (Report::CrateCollection { spotlight: current, .. }, Some(spotlight)) = (&mut report, spotlight) {
+            *current = Some(spotlight);
+        }
+        report
+    }
+
     async fn complete(&mut self, _progress: &mut prodash::tree::Item, out: &mut Vec<u8>) -> Result<()> {
         use horrorshow::Template;
 
@@ -1,6 +1,13 @@
+use super::generic::{write_content, WriteCallback, WriteCallbackState};
 use crate::persistence::TableAccess;
-use crate::{error::Result, model::TaskResult, persistence};
+use crate::{
+    error::Result,
+    model::{self, db_dump, TaskResult},
+    persistence,
+};
 use async_trait::async_trait;
+use rusqlite::NO_PARAMS;
+use std::path::Path;
 
 pub use criner_waste_report::*;
 
@@ -28,6 +35,7 @@ impl super::generic::Generator for Generator {
         let dummy_result = TaskResult::ExplodedCrate {
             entries_meta_data: Default::default(),
             selected_entries: Default::default(),
+            truncated_entries: Default::default(),
         };
         dummy_result.fq_key(crate_name, crate_version, &dummy_task, key_buf);
     }
@@ -39,8 +47,144 @@ impl super::generic::Generator for Generator {
         key_buf: &mut String,
     ) -> Result<Option<TaskResult>> {
         Self::fq_result_key(crate_name, crate_version, key_buf);
-        let table = persistence::TaskResultTable { inner: connection };
-        table.get(&key_buf)
+        let table = persistence::TaskResultTable {
+            inner: connection.clone(),
+        };
+        Ok(match table.get(&key_buf)? {
+            // Results written before entries were moved into their own table still carry them
+            Some(TaskResult::ExplodedCrate {
+                entries_meta_data,
+                selected_entries,
+                truncated_entries,
+            }) if entries_meta_data.is_empty() => {
+                let entries_meta_data =
+                    persistence::TarHeaderTable { inner: connection }.get_all(crate_name, crate_version)?;
+                // Empty crate archives are listed in a report of their own and must not skew the aggregates
+                if entries_meta_data.is_empty() {
+                    return Ok(None);
+                }
+                Some(TaskResult::ExplodedCrate {
+                    entries_meta_data,
+                    selected_entries,
+                    truncated_entries,
+                })
+            }
+            res => res,
+        })
+    }
+
+    fn annotate_crate_report(
+        connection: persistence::ThreadSafeConnection,
+        crate_name: &str,
+        krate: &model::Crate,
+        report: &mut Report,
+    ) -> Result<()> {
+        let crate_owners = persistence::CrateOwnerTable { inner: connection }.owners_of(crate_name)?;
+        let crate_yanked_versions = krate.yanked_versions();
+        if crate_owners.is_empty() && crate_yanked_versions.is_empty() && krate.deleted_at.is_none() {
+            return Ok(());
+        }
+        // Crates with a single new version are still reported as such, but only crates can have owners,
+        // yanked versions and be deleted
+        if let Report::Version { .. } = report {
+            *report = merge::crate_from_version(report.clone());
+        }
+        if let Report::Crate {
+            owners,
+            yanked_versions,
+            deleted_at,
+            ..
+        } = report
+        {
+            *owners = crate_owners.into_iter().map(owner_from_actor).collect();
+            *yanked_versions = crate_yanked_versions;
+            *deleted_at = krate
+                .deleted_at
+                .map(|time| humantime::format_rfc3339_seconds(time).to_string());
+        }
+        Ok(())
+    }
+
+    async fn write_derived_reports(
+        db: persistence::Db,
+        report: &Report,
+        out_dir: &Path,
+        progress: &mut prodash::tree::Item,
+        write: WriteCallback,
+        write_state: &WriteCallbackState,
+    ) -> Result<()> {
+        use horrorshow::Template;
+
+        let info_by_crate = match report {
+            Report::CrateCollection { info_by_crate, .. } => info_by_crate.clone(),
+            _ => return Ok(()),
+        };
+        progress.blocked("grouping crates by repository", None);
+        let (projects, hosting) = blocking::unblock({
+            let db = db.clone();
+            move || {
+                let repositories = repositories(&db)?;
+                let by_crate = || repositories.iter().map(|(name, url)| (name.as_str(), url.as_str()));
+                Ok::<_, crate::Error>((
+                    projects(db, by_crate(), &info_by_crate)?,
+                    HostingDistribution::new(by_crate(), &info_by_crate),
+                ))
+            }
+        })
+        .await?;
+
+        let projects_dir = out_dir.join(project::PROJECTS_DIR_NAME);
+        let mut out = Vec::new();
+        html::ProjectIndex(projects.clone()).write_to_io(&mut out)?;
+        out = write_content(out, progress, projects_dir.join("index.html"), write, write_state).await?;
+        for project in projects.into_iter() {
+            out.clear();
+            let path = projects_dir.join(project.path());
+            project.write_to_io(&mut out)?;
+            out = write_content(out, progress, path, write, write_state).await?;
+        }
+
+        let hosts_dir = out_dir.join(hosting::HOSTS_DIR_NAME);
+        let hosts = hosting.hosts.clone();
+        out.clear();
+        hosting.write_to_io(&mut out)?;
+        out = write_content(out, progress, hosts_dir.join("index.html"), write, write_state).await?;
+        for host in hosts.into_iter() {
+            out.clear();
+            let path = hosts_dir.join(host.path());
+            host.write_to_io(&mut out)?;
+            out = write_content(out, progress, path, write, write_state).await?;
+        }
+
+        let discrepancies = blocking::unblock({
+            let db = db.clone();
+            move || db.open_version_discrepancies()?.all()
+        })
+        .await?;
+        out.clear();
+        html::DiscrepancyIndex(discrepancies).write_to_io(&mut out)?;
+        out = write_content(
+            out,
+            progress,
+            out_dir.join(discrepancy::DISCREPANCIES_DIR_NAME).join("index.html"),
+            write,
+            write_state,
+        )
+        .await?;
+
+        progress.blocked("collecting empty artifacts", None);
+        let empty_artifacts = blocking::unblock(move || empty_artifacts(&db)).await?;
+        out.clear();
+        html::EmptyArtifactIndex(empty_artifacts).write_to_io(&mut out)?;
+        write_content(
+            out,
+            progress,
+            out_dir.join(empty::EMPTY_ARTIFACTS_DIR_NAME).join("index.html"),
+            write,
+            write_state,
+        )
+        .await?;
+        Ok(())
     }
 
     async fn generate_report(
@@ -53,6 +197,7 @@ impl super::generic::Generator for Generator {
             TaskResult::ExplodedCrate {
                 entries_meta_data,
                 selected_entries,
+                ..
             } => Report::from_package(
                 crate_name,
                 crate_version,
@@ -66,5 +211,71 @@ impl super::generic::Generator for Generator {
     }
 }
 
+/// Return all crates without versions along with all crate versions whose archive is empty
+fn empty_artifacts(db: &persistence::Db) -> Result<Vec<EmptyArtifact>> {
+    let mut artifacts: Vec<_> = db
+        .open_crates()?
+        .without_versions()?
+        .into_iter()
+        .map(|crate_name| EmptyArtifact {
+            crate_name,
+            crate_version: None,
+            reason: "the crate has no versions in the index".into(),
+        })
+        .collect();
+    artifacts.extend(db.open_empty_artifacts()?.all()?);
+    Ok(artifacts)
+}
+
+fn owner_from_actor(actor: db_dump::Actor) -> Owner {
+    Owner {
+        login: actor.github_login,
+        name: actor.name,
+        is_team: actor.kind == db_dump::ActorKind::Team,
+    }
+}
+
+/// Return the names of all crates in the db dump along with the url of the repository they are published from.
+fn repositories(db: &persistence::Db) -> Result<Vec<(String, String)>> {
+    let connection = db.open_connection()?;
+    let guard = connection.lock();
+    let mut statement = guard.prepare("SELECT data FROM 'crates.io-crate'")?;
+    let mut rows = statement.query(NO_PARAMS)?;
+    let mut repositories = Vec::new();
+    while let Some(row) = rows.next()? {
+        let data: Vec<u8> = row.get(0)?;
+        let krate: db_dump::Crate = rmp_serde::from_slice(&data)?;
+        if let Some(repository) = krate.repository {
+            repositories.push((krate.name, repository));
+        }
+    }
+    Ok(repositories)
+}
+
+/// Group the crates in `info_by_crate` into projects by the repository they are published from.
+fn projects<'a>(
+    db: persistence::Db,
+    repositories: impl IntoIterator<Item = (&'a str, &'a str)>,
+    info_by_crate: &Dict<VersionInfo>,
+) -> Result<Vec<Project>> {
+    let owners = persistence::CrateOwnerTable {
+        inner: db.open_connection()?,
+    };
+    let mut error = None;
+    let projects = Project::group(repositories, info_by_crate, |crate_name| {
+        match owners.owners_of(crate_name) {
+            Ok(actors) => actors.into_iter().map(owner_from_actor).collect(),
+            Err(err) => {
+                error.get_or_insert(err);
+                Vec::new()
+            }
+        }
+    });
+    match error {
+        Some(err) => Err(err),
+        None => Ok(projects),
+    }
+}
+
 #[cfg(test)]
 mod report_test;
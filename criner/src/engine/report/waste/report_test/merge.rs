@@ -15,6 +15,7 @@ fn crate_merging_version_equivalent_to_version_merging_crate() {
         total_files: 4,
         wasted_files: vec![("a.a".into(), 20)],
         suggested_fix: Some(Fix::RemoveExclude),
+        provenance: None,
     };
 
     let krate = Report::Crate {
@@ -27,6 +28,9 @@ fn crate_merging_version_equivalent_to_version_merging_crate() {
             "b".into()  => AggregateFileInfo {total_files: 3, total_bytes: 80},
             "c".into()  => AggregateFileInfo {total_files: 1, total_bytes: 90},
         },
+        owners: Vec::new(),
+        yanked_versions: Vec::new(),
+        deleted_at: None,
     };
     assert_eq!(version.clone().merge(krate.clone()), krate.merge(version));
 }
@@ -60,6 +64,9 @@ fn crate_and_crate_of_different_name() {
                 "b".into()  => AggregateFileInfo {total_files: 2, total_bytes: 20},
                 "c".into()  => AggregateFileInfo {total_files: 3, total_bytes: 30},
             },
+            owners: Vec::new(),
+            yanked_versions: Vec::new(),
+            deleted_at: None,
         }
         .merge(Report::Crate {
             crate_name: "b".into(),
@@ -78,6 +85,9 @@ fn crate_and_crate_of_different_name() {
                 "b".into()  => AggregateFileInfo {total_files: 2, total_bytes: 20},
                 "d".into()  => AggregateFileInfo {total_files: 1, total_bytes: 10},
             },
+            owners: Vec::new(),
+            yanked_versions: Vec::new(),
+            deleted_at: None,
         }),
         Report::CrateCollection {
             total_size_in_bytes: 12,
@@ -105,6 +115,7 @@ fn crate_and_crate_of_different_name() {
                 "c".into()  => AggregateFileInfo {total_files: 3, total_bytes: 30},
                 "d".into()  => AggregateFileInfo {total_files: 1, total_bytes: 10},
             },
+            spotlight: None,
         }
     );
 }
@@ -131,6 +142,7 @@ fn two_crate_collections() {
             "c".into()  => AggregateFileInfo {total_files: 3, total_bytes: 30},
             "d".into()  => AggregateFileInfo {total_files: 1, total_bytes: 10},
         },
+        spotlight: None,
     };
     let rhs_collection = Report::CrateCollection {
         total_size_in_bytes: 12,
@@ -159,6 +171,7 @@ fn two_crate_collections() {
             "d".into()  => AggregateFileInfo {total_files: 1, total_bytes: 10},
             "e".into()  => AggregateFileInfo {total_files: 4, total_bytes: 2},
         },
+        spotlight: None,
     };
     assert_eq!(
         lhs_collection.merge(rhs_collection),
@@ -189,6 +202,7 @@ fn two_crate_collections() {
                 "d".into()  => AggregateFileInfo {total_files: 1*2, total_bytes: 10*2},
                 "e".into()  => AggregateFileInfo {total_files: 4, total_bytes: 2},
             },
+            spotlight: None,
         }
     );
 }
@@ -216,6 +230,9 @@ fn crate_and_crate_of_same_name() {
                 "b".into()  => AggregateFileInfo {total_files: 2, total_bytes: 20},
                 "c".into()  => AggregateFileInfo {total_files: 3, total_bytes: 30},
             },
+            owners: Vec::new(),
+            yanked_versions: Vec::new(),
+            deleted_at: None,
         }
         .merge(Report::Crate {
             crate_name: "a".into(),
@@ -237,6 +254,9 @@ fn crate_and_crate_of_same_name() {
                 "b".into()  => AggregateFileInfo {total_files: 2, total_bytes: 20},
                 "d".into()  => AggregateFileInfo {total_files: 1, total_bytes: 10},
             },
+            owners: Vec::new(),
+            yanked_versions: Vec::new(),
+            deleted_at: None,
         }),
         Report::Crate {
             crate_name: "a".to_string(),
@@ -268,6 +288,9 @@ fn crate_and_crate_of_same_name() {
                 "c".into()  => AggregateFileInfo {total_files: 3, total_bytes: 30},
                 "d".into()  => AggregateFileInfo {total_files: 1, total_bytes: 10},
             },
+            owners: Vec::new(),
+            yanked_versions: Vec::new(),
+            deleted_at: None,
         }
     );
 }
@@ -293,7 +316,8 @@ fn two_versions_of_same_crate() {
                     }]
                 }),
                 has_build_script: false
-            })
+            }),
+            provenance: None,
         }
         .merge(Report::Version {
             crate_name: "a".into(),
@@ -313,7 +337,8 @@ fn two_versions_of_same_crate() {
                     }]
                 }),
                 has_build_script: false
-            })
+            }),
+            provenance: None,
         }),
         Report::Crate {
             crate_name: "a".into(),
@@ -338,6 +363,9 @@ fn two_versions_of_same_crate() {
                 "b".into()  => AggregateFileInfo {total_files: 3, total_bytes: 80},
                 "c".into()  => AggregateFileInfo {total_files: 1, total_bytes: 90},
             },
+            owners: Vec::new(),
+            yanked_versions: Vec::new(),
+            deleted_at: None,
         }
     );
 }
@@ -363,7 +391,8 @@ fn two_versions_of_different_crate() {
                     }]
                 }),
                 has_build_script: false
-            })
+            }),
+            provenance: None,
         }
         .merge(Report::Version {
             crate_name: "b".into(),
@@ -383,7 +412,8 @@ fn two_versions_of_different_crate() {
                     }]
                 }),
                 has_build_script: false
-            })
+            }),
+            provenance: None,
         }),
         Report::CrateCollection {
             total_size_in_bytes: 3,
@@ -407,6 +437,7 @@ fn two_versions_of_different_crate() {
                 "b".into()  => AggregateFileInfo {total_files: 3, total_bytes: 80},
                 "c".into()  => AggregateFileInfo {total_files: 1, total_bytes: 90},
             },
+            spotlight: None,
         }
     );
 }
@@ -1,15 +1,31 @@
-use crate::{engine::stage, error::Result, model, persistence::Db, utils::*};
-use futures_util::{
-    future::{Either, FutureExt},
-    stream::StreamExt,
+use crate::{
+    engine::{schedule, stage},
+    error::Result,
+    model,
+    persistence::Db,
+    utils::*,
 };
+#[cfg(feature = "tui")]
+use bytesize::ByteSize;
+use futures_util::future::FutureExt;
+#[cfg(feature = "tui")]
+use futures_util::{future::Either, stream::StreamExt};
 use log::{info, warn};
+#[cfg(feature = "tui")]
 use prodash::render::tui::{Event, Line};
 use std::{
     path::{Path, PathBuf},
     time::{Duration, SystemTime},
 };
 
+pub use crate::engine::bandwidth::{Bandwidth, RequestRate};
+#[cfg(feature = "reports")]
+pub use crate::engine::report::generic::{
+    CustomWriteCallback, WriteCallback, WriteCallbackState, WriteInstruction, WriteRequest,
+};
+pub use crate::engine::schedule::Schedule;
+pub use crate::redact::Redaction;
+
 pub struct StageRunSettings {
     /// Wait for the given duration after the stage ran
     pub every: Duration,
@@ -17,89 +33,462 @@ pub struct StageRunSettings {
     pub at_most: Option<usize>,
 }
 
+/// A stage of the pipeline, any selection of which can run on its own, for instance to debug it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PipelineStage {
+    /// Fetch new and yanked crate versions from the crates.io index and alternate registries
+    Changes,
+    /// Download and ingest the crates.io database dump
+    DbDownload,
+    /// Download crate versions
+    Download,
+    /// Extract downloaded crate versions
+    Extract,
+    /// Generate reports
+    Report,
+    /// Export the database once all other stages are done, which is up to the caller as the engine doesn't export.
+    Export,
+}
+
+impl PipelineStage {
+    /// All stages run by the engine, which is all of them but `Export`
+    pub const ENGINE: &'static [PipelineStage] = &[
+        PipelineStage::Changes,
+        PipelineStage::DbDownload,
+        PipelineStage::Download,
+        PipelineStage::Extract,
+        PipelineStage::Report,
+    ];
+}
+
+impl std::str::FromStr for PipelineStage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "changes" => PipelineStage::Changes,
+            "db-download" => PipelineStage::DbDownload,
+            "download" => PipelineStage::Download,
+            "extract" => PipelineStage::Extract,
+            "report" => PipelineStage::Report,
+            "export" => PipelineStage::Export,
+            _ => {
+                return Err(format!(
+                    "Expected one of 'changes', 'db-download', 'download', 'extract', 'report' or 'export', got '{}'",
+                    s
+                ))
+            }
+        })
+    }
+}
+
 /// Like `StageRunSettings`, but also provides a glob pattern
 pub struct GlobStageRunSettings {
     pub glob: Option<String>,
     pub run: StageRunSettings,
 }
 
+/// Settings for generating reports in chunks of crates
+#[cfg(feature = "reports")]
+pub struct ReportGenerationSettings {
+    /// The amount of crates to put into a chunk, which is the unit of work of a CPU- and output-bound processor.
+    pub chunk_size: usize,
+    /// The amount of chunks that may wait for a processor to pick them up.
+    ///
+    /// Chunks that are waiting or in progress when the deadline is reached are abandoned at the next crate boundary.
+    pub in_flight_chunks: usize,
+    /// If set, it is used to write report files instead of the git integration selected by the report directory.
+    pub write_callback: Option<CustomWriteCallback>,
+}
+
+#[cfg(feature = "reports")]
+impl Default for ReportGenerationSettings {
+    fn default() -> Self {
+        ReportGenerationSettings {
+            chunk_size: 500,
+            in_flight_chunks: 1,
+            write_callback: None,
+        }
+    }
+}
+
+/// Settings for extracting crates, controlling how much of the selected files of a crate are stored
+#[derive(Clone)]
+pub struct ExtractionProfile {
+    /// Selected files larger than this amount of bytes are truncated to it, with their size and hash recorded
+    /// in the result instead.
+    pub sample_size: usize,
+    /// Paths of files relative to the crate root which are always stored completely, as analyses depend on them.
+    pub complete_paths: Vec<String>,
+    /// Glob patterns of further files to select relative to the crate root, like 'README*' or 'src/**/*.rs'.
+    /// The manifests, the build script and the targets declared in the manifest are always selected.
+    pub selected_paths: Vec<String>,
+    /// The maximum amount of bytes of all selected files of a crate together, or None if it's unlimited. Once they
+    /// reach it, further selected files are truncated to nothing but their size and hash, unless they are to be
+    /// stored completely.
+    pub max_selected_bytes: Option<usize>,
+    /// The amount of times extracting a crate version may crash before it is quarantined and not processed anymore.
+    /// Crashes are panics, or the process stopping while extracting, which happens if it runs out of memory.
+    pub max_crashes: usize,
+}
+
+impl Default for ExtractionProfile {
+    fn default() -> Self {
+        ExtractionProfile {
+            sample_size: 128 * 1024,
+            complete_paths: vec!["Cargo.toml".into(), "Cargo.toml.orig".into(), "Cargo.lock".into()],
+            selected_paths: Vec::new(),
+            max_selected_bytes: None,
+            max_crashes: 3,
+        }
+    }
+}
+
+/// A version of the TLS protocol
+#[derive(Clone, Copy, Debug)]
+pub enum TlsVersion {
+    Tls10,
+    Tls11,
+    Tls12,
+}
+
+/// Restrictions for TLS connections to a particular host, for hardened deployments
+#[derive(Clone, Debug)]
+pub struct TlsPolicy {
+    /// The name of the host the policy applies to, like 'static.crates.io'
+    pub host: String,
+    /// PEM encoded certificates which are the only ones trusted as roots when connecting to `host`.
+    /// If empty, the roots of the system are trusted.
+    pub pinned_certificates: Vec<Vec<u8>>,
+    /// If set, connections to `host` with an older version of TLS are refused.
+    pub min_version: Option<TlsVersion>,
+}
+
+/// Settings for all downloads performed by IO-bound processors
+#[derive(Clone, Default)]
+pub struct DownloadSettings {
+    /// Policies for hosts whose TLS connections are restricted, at most one per host
+    pub tls: Vec<TlsPolicy>,
+    /// The template of the url to download crate versions from, or None to download them from crates.io.
+    ///
+    /// It has the format of the `dl` field in the `config.json` of a registry index, supporting markers like `{crate}`
+    /// and `{version}`, and gets `/{crate}/{version}/download` appended if it has none.
+    pub crate_url_template: Option<String>,
+    /// Templates of urls of mirrors to download crate versions from if downloading them with `crate_url_template`
+    /// fails or times out, tried in order. They have the same format, and don't apply to alternate registries.
+    pub crate_mirror_templates: Vec<String>,
+    /// The bandwidth all downloads share, which is unlimited by default
+    pub bandwidth: Bandwidth,
+    /// The rate of requests to crates.io, which is unlimited by default. Fetches from the sparse index share it.
+    pub request_rate: RequestRate,
+    /// The url of a proxy to send all downloads through, like 'http://proxy:3128'. SOCKS5 proxies are supported too,
+    /// like 'socks5://localhost:1080', or 'socks5h://localhost:9050' to also resolve host names through the proxy.
+    ///
+    /// If None, the proxies configured by the `HTTP_PROXY` and `HTTPS_PROXY` environment variables are used.
+    /// Either way, hosts listed in `NO_PROXY` are connected to directly.
+    pub proxy: Option<String>,
+    /// The User-Agent header to send with all downloads, which identifies criner along with the url of its repository
+    /// if None. The crawler policy of crates.io asks for one which says how to contact whoever runs criner.
+    pub user_agent: Option<String>,
+    /// If set, crate versions are downloaded by fewer of the IO-bound processors at once while the server responds
+    /// slowly, asks to slow down with a 429 or 503 status, or times out, and by more of them once it recovers.
+    pub adaptive_concurrency: bool,
+    /// If set, downloads are paused while the volume they are stored on has less than the given amount of bytes
+    /// available, and resume once enough space was reclaimed.
+    pub min_free_space: Option<u64>,
+    /// If set, crate versions are extracted right after downloading them into memory, and their archives are never
+    /// written to disk. This saves the disk space of all archives, but they have to be downloaded again to extract
+    /// them anew. Crate versions downloaded before are still extracted from disk.
+    pub stream_extraction: bool,
+}
+
+/// Where to learn about new and yanked crate versions from
+#[derive(Clone, Debug)]
+pub enum IndexSource {
+    /// A bare clone of the git index at the given path, which is cloned if it doesn't exist
+    Git(PathBuf),
+    /// A bare clone of the git index at the given path which is kept up to date by another process, like a mirror.
+    /// It's only read up to the commit its HEAD points to, and never fetched or written to.
+    Mirror(PathBuf),
+    /// The sparse index served via HTTP at the given url, like 'https://index.crates.io'
+    Sparse(String),
+}
+
+/// Where to send events about crate versions which were stored for the first time
+#[derive(Clone, Debug)]
+pub enum EventSink {
+    /// A url to which events are posted as JSON arrays
+    Webhook(String),
+    /// A file to which events are appended as JSON objects, one per line
+    File(PathBuf),
+}
+
+impl std::fmt::Display for EventSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventSink::Webhook(url) => write!(f, "webhook '{}'", url),
+            EventSink::File(path) => write!(f, "file '{}'", path.display()),
+        }
+    }
+}
+
+/// An alternate registry to mine alongside crates.io, like the private registry of a company.
+///
+/// Its crates are stored with names qualified by the name of the registry, like `my-registry/serde`.
+#[derive(Clone, Debug)]
+pub struct Registry {
+    /// The name of the registry, which must not contain `/`
+    pub name: String,
+    /// A clone of the git index of the registry, which is fetched from its `origin` remote. It's not cloned if missing.
+    pub index_path: PathBuf,
+    /// The template of the url to download crate versions from, see `DownloadSettings::crate_url_template`.
+    pub crate_url_template: String,
+}
+
+/// Settings for downloading and ingesting the crates.io database dump
+#[derive(Clone)]
+pub struct DbDownloadSettings {
+    /// The url from which to download the database dump.
+    pub url: String,
+    /// Urls to download the database dump from, in order, if downloading from `url` fails.
+    pub mirrors: Vec<String>,
+    /// The amount of most recent database dumps to keep on disk after a successful ingestion, including the one just ingested.
+    pub keep_last: usize,
+    /// If set, database dumps younger than the given duration are kept as well.
+    pub keep_for: Option<Duration>,
+    /// If set, the database dump is ingested by streaming its large tables through a staging database on disk,
+    /// using roughly the given amount of bytes of memory. Otherwise all tables are held in memory, which requires gigabytes of it.
+    pub ingest_memory_budget: Option<u64>,
+    /// If true and no memory budget is set, all tables are extracted to disk first to be decoded in parallel.
+    pub parallel_ingestion: bool,
+    /// If true, the tables we ingest are also extracted as CSV files into a directory named after the day of the download,
+    /// next to the database dump, and removed along with it.
+    pub retain_csv: bool,
+    /// The names of the tables of the database dump to ingest, like `crate_owners`, or all of them if empty.
+    ///
+    /// `crates` and `versions` are always ingested. Crates lack the information of tables which aren't ingested,
+    /// like their owners, keywords or categories.
+    pub tables: Vec<String>,
+    /// If true, rows of the database dump which can't be decoded are skipped and recorded in the `db_dump_quarantine`
+    /// table along with their line and content. Otherwise the ingestion fails on the first of them.
+    pub lenient_csv: bool,
+    /// The time of day in UTC at which a new database dump is published. If set, a database dump downloaded before
+    /// that time is considered to be the one of the previous day, to download the new one once it's published.
+    pub published_at_utc: Option<time::Time>,
+    /// What to do with personally identifiable fields of users and teams, like their GitHub login, before they are
+    /// stored. Their ids are kept to be able to associate them with crates.
+    pub redaction: Redaction,
+}
+
+impl Default for DbDownloadSettings {
+    fn default() -> Self {
+        DbDownloadSettings {
+            url: "https://static.crates.io/db-dump.tar.gz".into(),
+            mirrors: Vec::new(),
+            keep_last: 1,
+            keep_for: None,
+            ingest_memory_budget: None,
+            parallel_ingestion: false,
+            retain_csv: false,
+            tables: Vec::new(),
+            lenient_csv: false,
+            published_at_utc: None,
+            redaction: Redaction::Keep,
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
-/// Runs the statistics and mining engine.
+/// Runs the statistics and mining engine, with only the given `stages` of the pipeline.
 /// May run for a long time unless a deadline is specified.
 /// Even though timeouts can be achieved from outside of the future, knowing the deadline may be used
 /// by the engine to manage its time even more efficiently.
 pub async fn non_blocking(
     db: Db,
-    crates_io_path: PathBuf,
+    index_source: IndexSource,
+    alternate_registries: Vec<Registry>,
     deadline: Option<SystemTime>,
     progress: prodash::Tree,
     io_bound_processors: u32,
     cpu_bound_processors: u32,
     cpu_o_bound_processors: u32,
     interrupt_control: InterruptControlEvents,
-    fetch_settings: StageRunSettings,
-    process_settings: StageRunSettings,
-    report_settings: GlobStageRunSettings,
+    stages: Vec<PipelineStage>,
+    fetch_settings: GlobStageRunSettings,
+    fetch_batch_size: usize,
+    process_settings: GlobStageRunSettings,
+    requeue_failed: Option<String>,
+    extraction_profile: ExtractionProfile,
+    #[cfg(feature = "reports")] report_settings: GlobStageRunSettings,
+    #[cfg(feature = "reports")] report_generation_settings: ReportGenerationSettings,
     download_crates_io_database_every_24_hours_starting_at: Option<time::Time>,
+    download_crates_io_database_every: Duration,
+    schedule: Schedule,
+    db_download_settings: DbDownloadSettings,
+    download_settings: DownloadSettings,
+    event_sinks: Vec<EventSink>,
+    fused_download_processors: u32,
     assets_dir: PathBuf,
 ) -> Result<()> {
     check(deadline)?;
     let startup_time = SystemTime::now();
+    let runs = |stage| stages.contains(&stage);
+    let disabled_unless = |stage, at_most: Option<usize>| if runs(stage) { at_most } else { Some(0) };
 
-    let db_download_handle = crate::spawn(repeat_daily_at(
+    let db_download_handle = crate::spawn(repeat_every_at(
         download_crates_io_database_every_24_hours_starting_at,
+        {
+            let schedule = schedule.clone();
+            move || schedule.every(schedule::Stage::DbDump, download_crates_io_database_every)
+        },
         {
             let p = progress.clone();
             move || p.add_child("Crates.io DB Digest")
         },
         deadline,
+        disabled_unless(PipelineStage::DbDownload, None),
         {
             let db = db.clone();
             let assets_dir = assets_dir.clone();
             let progress = progress.clone();
+            let download_settings = download_settings.clone();
+            let index_source = index_source.clone();
             move || {
-                stage::db_download::schedule(
+                let download = stage::db_download::schedule(
                     db.clone(),
                     assets_dir.clone(),
                     progress.add_child("fetching crates-io db"),
                     startup_time,
-                )
+                    db_download_settings.clone(),
+                    download_settings.clone(),
+                    deadline,
+                );
+                let (db, progress, index_source) = (db.clone(), progress.clone(), index_source.clone());
+                async move {
+                    download.await?;
+                    // Reconciling is expensive, and the db dump it compares with changes once a day
+                    stage::changes::reconcile_with_index(
+                        index_source,
+                        db,
+                        progress.add_child("reconciling crate counts"),
+                        deadline,
+                    )
+                    .await
+                }
             }
         },
     ));
 
-    let run = fetch_settings;
-    let fetch_handle = crate::spawn(repeat_every_s(
-        run.every.as_secs() as usize,
+    // Downloads are only fused while fetches run, as these are the only ones to schedule them
+    let fused_downloads =
+        if fused_download_processors > 0 && runs(PipelineStage::Changes) && runs(PipelineStage::Download) {
+            Some(stage::processing::FusedDownloads::spawn(
+                db.clone(),
+                fused_download_processors,
+                progress.clone(),
+                assets_dir.clone(),
+                download_settings.clone(),
+                alternate_registries.clone(),
+                Some(extraction_profile.clone()).filter(|_| runs(PipelineStage::Extract)),
+                startup_time,
+                deadline,
+            )?)
+        } else {
+            None
+        };
+
+    // Requeued work is picked up by the processors of fused downloads and processing runs
+    if let Some(glob) = requeue_failed {
+        let (download, extract) = (runs(PipelineStage::Download), runs(PipelineStage::Extract));
+        if download || extract {
+            stage::processing::requeue_failed(
+                db.clone(),
+                glob,
+                progress.add_child("Requeue Failed Tasks"),
+                assets_dir.clone(),
+                download_settings.clone(),
+                alternate_registries.clone(),
+                download,
+                extract,
+                startup_time,
+            )
+            .await?;
+        }
+    }
+
+    let stage = fetch_settings;
+    let fetch_handle = crate::spawn(repeat_every(
+        {
+            let (schedule, every) = (schedule.clone(), stage.run.every);
+            move || schedule.every(schedule::Stage::Fetch, every)
+        },
         {
             let p = progress.clone();
             move || p.add_child("Fetch Timer")
         },
         deadline,
-        run.at_most,
+        disabled_unless(PipelineStage::Changes, stage.run.at_most),
         {
             let db = db.clone();
             let progress = progress.clone();
+            let alternate_registries = alternate_registries.clone();
+            let download_settings = download_settings.clone();
             move || {
-                stage::changes::fetch(
-                    crates_io_path.clone(),
-                    db.clone(),
-                    progress.add_child("crates.io refresh"),
-                    deadline,
-                )
+                let (index_source, alternate_registries) = (index_source.clone(), alternate_registries.clone());
+                let (db, progress, event_sinks) = (db.clone(), progress.clone(), event_sinks.clone());
+                let (glob, fused_downloads) = (stage.glob.clone(), fused_downloads.clone());
+                let download_settings = download_settings.clone();
+                async move {
+                    stage::changes::fetch(
+                        index_source,
+                        db.clone(),
+                        glob.clone(),
+                        fetch_batch_size,
+                        event_sinks.clone(),
+                        fused_downloads.clone(),
+                        download_settings.clone(),
+                        progress.add_child("crates.io refresh"),
+                        deadline,
+                    )
+                    .await?;
+                    for registry in alternate_registries {
+                        let progress = progress.add_child(format!("{} refresh", registry.name));
+                        stage::changes::fetch_registry(
+                            registry,
+                            db.clone(),
+                            glob.clone(),
+                            fetch_batch_size,
+                            event_sinks.clone(),
+                            fused_downloads.clone(),
+                            download_settings.clone(),
+                            progress,
+                            deadline,
+                        )
+                        .await?;
+                    }
+                    Ok::<_, crate::Error>(())
+                }
             }
         },
     ));
 
     let stage = process_settings;
-    let processing_handle = crate::spawn(repeat_every_s(
-        stage.every.as_secs() as usize,
+    let (download, extract) = (runs(PipelineStage::Download), runs(PipelineStage::Extract));
+    let processing_handle = crate::spawn(repeat_every(
+        {
+            let (schedule, every) = (schedule.clone(), stage.run.every);
+            move || schedule.every(schedule::Stage::Process, every)
+        },
         {
             let p = progress.clone();
             move || p.add_child("Processing Timer")
         },
         deadline,
-        stage.at_most,
+        if download || extract {
+            stage.run.at_most
+        } else {
+            Some(0)
+        },
         {
             let progress = progress.clone();
             let db = db.clone();
@@ -107,26 +496,40 @@ pub async fn non_blocking(
             move || {
                 stage::processing::process(
                     db.clone(),
+                    stage.glob.clone(),
                     progress.add_child("Process Crate Versions"),
                     io_bound_processors,
                     cpu_bound_processors,
                     progress.add_child("Downloads"),
                     assets_dir.clone(),
+                    extraction_profile.clone(),
+                    download_settings.clone(),
+                    alternate_registries.clone(),
+                    download,
+                    extract,
                     startup_time,
+                    deadline,
                 )
             }
         },
     ));
 
+    #[cfg(not(feature = "reports"))]
+    drop((interrupt_control, cpu_o_bound_processors));
+    #[cfg(feature = "reports")]
     let stage = report_settings;
-    let report_handle = crate::spawn(repeat_every_s(
-        stage.run.every.as_secs() as usize,
+    #[cfg(feature = "reports")]
+    let report_handle = crate::spawn(repeat_every(
+        {
+            let every = stage.run.every;
+            move || schedule.every(schedule::Stage::Report, every)
+        },
         {
             let p = progress.clone();
             move || p.add_child("Reporting Timer")
         },
         deadline,
-        stage.run.at_most,
+        disabled_unless(PipelineStage::Report, stage.run.at_most),
         {
             move || {
                 let progress = progress.clone();
@@ -134,6 +537,11 @@ pub async fn non_blocking(
                 let assets_dir = assets_dir.clone();
                 let glob = stage.glob.clone();
                 let interrupt_control = interrupt_control.clone();
+                let (chunk_size, in_flight_chunks) = (
+                    report_generation_settings.chunk_size,
+                    report_generation_settings.in_flight_chunks,
+                );
+                let write_callback = report_generation_settings.write_callback.clone();
                 async move {
                     let ctrl = interrupt_control;
                     ctrl.send(Interruptible::Deferred).await.ok(); // there might be no TUI
@@ -144,6 +552,9 @@ pub async fn non_blocking(
                         glob.clone(),
                         deadline,
                         cpu_o_bound_processors,
+                        chunk_size,
+                        in_flight_chunks,
+                        write_callback,
                     )
                     .await;
                     ctrl.send(Interruptible::Instantly).await.ok(); // there might be no TUI
@@ -155,6 +566,7 @@ pub async fn non_blocking(
 
     fetch_handle.await?;
     db_download_handle.await?;
+    #[cfg(feature = "reports")]
     report_handle.await?;
     processing_handle.await
 }
@@ -166,6 +578,14 @@ pub enum Interruptible {
 
 pub type InterruptControlEvents = async_channel::Sender<Interruptible>;
 
+/// Options for the terminal user interface, which can only be shown with the `tui` feature.
+#[cfg(feature = "tui")]
+pub type GuiOptions = prodash::render::tui::Options;
+/// Options for the terminal user interface, which can only be shown with the `tui` feature.
+#[cfg(not(feature = "tui"))]
+pub enum GuiOptions {}
+
+#[cfg(feature = "tui")]
 impl From<Interruptible> for prodash::render::tui::Event {
     fn from(v: Interruptible) -> Self {
         match v {
@@ -177,44 +597,75 @@ impl From<Interruptible> for prodash::render::tui::Event {
 
 #[allow(clippy::too_many_arguments)]
 /// For convenience, run the engine and block until done.
+/// Once done or interrupted, processors get up to `shutdown_grace_period` to finish the tasks they are working on,
+/// without starting on new ones.
 pub fn blocking(
     db: impl AsRef<Path>,
-    crates_io_path: impl AsRef<Path>,
+    index_source: IndexSource,
+    alternate_registries: Vec<Registry>,
     deadline: Option<SystemTime>,
     io_bound_processors: u32,
     cpu_bound_processors: u32,
     cpu_o_bound_processors: u32,
-    fetch_settings: StageRunSettings,
-    process_settings: StageRunSettings,
-    report_settings: GlobStageRunSettings,
+    stages: Vec<PipelineStage>,
+    fetch_settings: GlobStageRunSettings,
+    fetch_batch_size: usize,
+    process_settings: GlobStageRunSettings,
+    requeue_failed: Option<String>,
+    extraction_profile: ExtractionProfile,
+    #[cfg(feature = "reports")] report_settings: GlobStageRunSettings,
+    #[cfg(feature = "reports")] report_generation_settings: ReportGenerationSettings,
     download_crates_io_database_every_24_hours_starting_at: Option<time::Time>,
+    download_crates_io_database_every: Duration,
+    schedule: Schedule,
+    db_download_settings: DbDownloadSettings,
+    download_settings: DownloadSettings,
+    event_sinks: Vec<EventSink>,
+    fused_download_processors: u32,
+    shutdown_grace_period: Duration,
+    allow_newer_database: bool,
     root: prodash::Tree,
-    gui: Option<prodash::render::tui::Options>,
+    gui: Option<GuiOptions>,
 ) -> Result<()> {
     let start_of_computation = SystemTime::now();
     let assets_dir = db.as_ref().join("assets");
-    let db = Db::open(db)?;
+    let db = Db::open_with_skew_check(db, allow_newer_database)?;
     std::fs::create_dir_all(&assets_dir)?;
     let (interrupt_control_sink, interrupt_control_stream) = async_channel::bounded::<Interruptible>(1);
 
     // dropping the work handle will stop (non-blocking) futures
     let work_handle = non_blocking(
         db.clone(),
-        crates_io_path.as_ref().into(),
+        index_source,
+        alternate_registries,
         deadline,
         root.clone(),
         io_bound_processors,
         cpu_bound_processors,
         cpu_o_bound_processors,
         interrupt_control_sink,
+        stages,
         fetch_settings,
+        fetch_batch_size,
         process_settings,
+        requeue_failed,
+        extraction_profile,
+        #[cfg(feature = "reports")]
         report_settings,
+        #[cfg(feature = "reports")]
+        report_generation_settings,
         download_crates_io_database_every_24_hours_starting_at,
+        download_crates_io_database_every,
+        schedule,
+        db_download_settings,
+        download_settings,
+        event_sinks,
+        fused_download_processors,
         assets_dir,
     );
 
     match gui {
+        #[cfg(feature = "tui")]
         Some(gui_options) => {
             let gui = crate::spawn(prodash::render::tui::render_with_input(
                 std::io::stdout(),
@@ -240,6 +691,8 @@ pub fn blocking(
                 Either::Right((_, _work_handle)) => {}
             }
         }
+        #[cfg(not(feature = "tui"))]
+        Some(gui_options) => match gui_options {},
         None => {
             drop(interrupt_control_stream);
             let work_result = futures_lite::future::block_on(handle_ctrl_c_and_sigterm(work_handle.boxed_local()));
@@ -249,6 +702,19 @@ pub fn blocking(
         }
     };
 
+    // Let processors finish the tasks they are working on to store their actual state, unless interrupted once more
+    match futures_lite::future::block_on(handle_ctrl_c_and_sigterm(
+        crate::engine::work::generic::drain(shutdown_grace_period).boxed_local(),
+    )) {
+        Ok(0) => {}
+        Ok(abandoned) => warn!(
+            "Abandoned {} tasks which were still in progress after {}",
+            abandoned,
+            humantime::format_duration(shutdown_grace_period)
+        ),
+        Err(_) => warn!("Abandoned all tasks in progress as the process was interrupted while they were finishing"),
+    }
+
     // at this point, we forget all currently running computation, and since it's in the local thread, it's all
     // destroyed/dropped properly.
     info!("{}", wallclock(start_of_computation));
@@ -262,6 +728,7 @@ fn wallclock(since: SystemTime) -> String {
     )
 }
 
+#[cfg(feature = "tui")]
 fn context_stream(db: &Db, start_of_computation: SystemTime) -> impl futures_util::stream::Stream<Item = Event> {
     prodash::render::tui::ticker(Duration::from_secs(1)).map({
         let db = db.clone();
@@ -271,14 +738,47 @@ fn context_stream(db: &Db, start_of_computation: SystemTime) -> impl futures_uti
                 .and_then(|c| c.most_recent().ok())
                 .flatten()
                 .map(|(_, c): (_, model::Context)| {
-                    let lines = vec![
+                    let mut lines = vec![
                         Line::Text(wallclock(start_of_computation)),
                         Line::Title("Durations".into()),
                         Line::Text(format!("fetch-crate-versions: {:?}", c.durations.fetch_crate_versions)),
+                        Line::Text(format!("      ingest-db-dump: {:?}", c.durations.ingest_db_dump)),
+                        Line::Text(format!("            download: {:?}", c.durations.download)),
                         Line::Title("Counts".into()),
                         Line::Text(format!("crate-versions: {}", c.counts.crate_versions)),
                         Line::Text(format!("        crates: {}", c.counts.crates)),
+                        Line::Text(format!(" db-dump-bytes: {}", ByteSize(c.counts.db_dump_bytes))),
+                        Line::Text(format!(
+                            "  db-dump-rows: {}",
+                            c.counts.db_dump_rows.values().sum::<u64>()
+                        )),
+                        Line::Text(format!("     downloads: {}", c.counts.downloads)),
+                        Line::Text(format!("    downloaded: {}", ByteSize(c.counts.downloaded_bytes))),
                     ];
+                    if let Some(queues) = db.open_queue().and_then(|q| q.lengths()).ok().filter(|q| !q.is_empty()) {
+                        lines.push(Line::Title("Queues (queued/claimed)".into()));
+                        lines.extend(queues.into_iter().map(|(name, length)| {
+                            Line::Text(format!("{}: {}/{}", name, length.queued, length.claimed))
+                        }));
+                    }
+                    if let Some(r) = c.reconciliation {
+                        lines.extend(vec![
+                            Line::Title("Reconciliation (crates/versions)".into()),
+                            Line::Text(format!("     index: {}/{}", r.index.crates, r.index.crate_versions)),
+                            Line::Text(format!("   db-dump: {}/{}", r.db_dump.crates, r.db_dump.crate_versions)),
+                            Line::Text(format!("  database: {}/{}", r.db.crates, r.db.crate_versions)),
+                            Line::Text(format!("   missing: {}", r.missing_in_db.count)),
+                            Line::Text(format!("tombstones: {}", r.tombstones.count)),
+                        ]);
+                    }
+                    if let Some(p) = db.open_publishes().and_then(|p| p.most_recent()).ok().flatten() {
+                        lines.extend(vec![
+                            Line::Title("Last published".into()),
+                            Line::Text(format!("    at: {}", humantime::format_rfc3339_seconds(p.published_at))),
+                            Line::Text(format!("commit: {}", p.commit.get(..12).unwrap_or(&p.commit))),
+                            Line::Text(format!("pushed: {}", if p.is_pushed() { "yes" } else { "no" })),
+                        ]);
+                    }
                     Event::SetInformation(lines)
                 })
                 .unwrap_or(Event::Tick)
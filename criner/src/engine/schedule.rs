@@ -0,0 +1,115 @@
+//! The intervals at which the stages of the engine run, which operators can tune in a file while the engine runs.
+use crate::{Error, Result};
+use serde_derive::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// A stage of the engine which runs repeatedly
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Fetch,
+    Process,
+    Report,
+    DbDump,
+}
+
+/// The contents of a schedule file, with the interval of each stage specified in humantime, like
+///
+/// ```toml
+/// [fetch]
+/// every = "5min"
+///
+/// [db_dump]
+/// every = "24h"
+///
+/// [report]
+/// every = "1h"
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScheduleFile {
+    fetch: Option<StageSchedule>,
+    process: Option<StageSchedule>,
+    report: Option<StageSchedule>,
+    db_dump: Option<StageSchedule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StageSchedule {
+    every: String,
+}
+
+/// Where stages look up how long to wait until they run again. Stages not configured by the schedule file, or all
+/// stages if there is none, use the intervals given on the command-line.
+#[derive(Clone, Default)]
+pub struct Schedule {
+    file: Option<PathBuf>,
+}
+
+impl Schedule {
+    /// Use the schedule in the TOML `file` if there is one, failing if it can't be read.
+    ///
+    /// The file is read again each time a stage finished running, so changes apply without restarting the engine.
+    pub fn from_file(file: Option<PathBuf>) -> Result<Self> {
+        if let Some(file) = &file {
+            read(file)?;
+        }
+        Ok(Schedule { file })
+    }
+
+    /// Return the time to wait between two runs of `stage`, or `default` if the schedule doesn't configure it.
+    /// As the file may change at any time, errors reading it are logged and the `default` is used instead.
+    pub fn every(&self, stage: Stage, default: Duration) -> Duration {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return default,
+        };
+        match read(file) {
+            Ok(intervals) => intervals
+                .into_iter()
+                .find(|(s, _)| *s == stage)
+                .map(|(_, every)| every)
+                .unwrap_or(default),
+            Err(err) => {
+                log::warn!("Using the default interval of the {:?} stage: {}", stage, err);
+                default
+            }
+        }
+    }
+}
+
+/// Return the intervals of all stages configured in the schedule `file`.
+fn read(file: &Path) -> Result<Vec<(Stage, Duration)>> {
+    let error = |msg: String| Error::Message(format!("Could not read schedule file '{}': {}", file.display(), msg));
+    let schedule: ScheduleFile =
+        toml::from_str(&std::fs::read_to_string(file)?).map_err(|err| error(err.to_string()))?;
+    let ScheduleFile {
+        fetch,
+        process,
+        report,
+        db_dump,
+    } = schedule;
+    let mut intervals = Vec::new();
+    for (stage, schedule) in [
+        (Stage::Fetch, fetch),
+        (Stage::Process, process),
+        (Stage::Report, report),
+        (Stage::DbDump, db_dump),
+    ]
+    .iter()
+    {
+        if let Some(StageSchedule { every }) = schedule {
+            let every = humantime::parse_duration(every).map_err(|err| {
+                error(format!(
+                    "invalid interval '{}' of the {:?} stage: {}",
+                    every, stage, err
+                ))
+            })?;
+            intervals.push((*stage, every));
+        }
+    }
+    Ok(intervals)
+}
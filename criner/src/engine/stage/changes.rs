@@ -1,146 +1,1170 @@
-use crate::persistence::{key_value_iter, new_key_value_query_old_to_new, CrateTable};
+use super::reconcile;
+use crate::persistence::{
+    key_value_iter, new_key_value_query_old_to_new, CrateTable, IndexFetchTable, LatestVersionTable,
+};
 use crate::{
+    engine::{
+        notify::{self, NewCrateVersion},
+        run::{DownloadSettings, EventSink, IndexSource, Registry, RequestRate},
+        stage::processing::FusedDownloads,
+        work::{iobound, schedule},
+    },
     error::{Error, Result},
     model,
     persistence::{self, new_key_value_insertion, CrateVersionTable, Keyed, TableAccess},
-    utils::enforce_threaded,
+    utils::{check, enforce_threaded, CancelOnDrop, Cancellation},
 };
 use crates_index_diff::Index;
-use rusqlite::params;
+use futures_util::StreamExt;
+use rusqlite::{params, OptionalExtension, NO_PARAMS};
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     ops::Add,
-    path::Path,
+    path::{Path, PathBuf},
     time::{Duration, SystemTime},
 };
 
+/// The name under which the progress of fetches of the crates.io git index is recorded
+const CRATES_IO_REGISTRY_NAME: &str = "crates.io";
+/// The branch of the remote crates.io git index as fetched into clones of it
+const INDEX_REMOTE_BRANCH: &str = "refs/remotes/origin/master";
+/// The url of the crates.io git index
+const CRATES_IO_INDEX_URL: &str = "https://github.com/rust-lang/crates.io-index";
+/// The only branch fetched into clones of the crates.io git index, as all others aren't needed to find changes
+const INDEX_REFSPEC: &str = "+refs/heads/master:refs/remotes/origin/master";
+/// The amount of crate versions to store per transaction unless configured otherwise
+pub const DEFAULT_BATCH_SIZE: usize = 10_000;
+/// The id of the tree without any entries, which git knows without storing it
+const EMPTY_TREE_ID: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+/// The amount of times fetching a git index is retried after failing due to the network
+const INDEX_FETCH_RETRIES: usize = 4;
+/// The time to wait before retrying to fetch a git index the first time, which doubles with each retry
+const INDEX_FETCH_BACKOFF: Duration = Duration::from_secs(2);
+/// The amount of crates whose sparse index file is requested concurrently
+const SPARSE_INDEX_CONCURRENCY: usize = 32;
+/// The time after which a request for the file of a crate in the sparse index is abandoned
+const SPARSE_INDEX_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fetch new and yanked crate versions from the crates.io index in the given `source` and store them in `db`,
+/// emitting an event to all `event_sinks` for each crate version that was stored for the first time.
+/// If downloads are `fused`, these crate versions are queued for download right away as well.
+///
+/// If `glob` is set, only crates whose name matches it are stored. As all other changes are ignored, such a focused
+/// fetch doesn't advance the point from which the next fetch looks for changes.
+/// Crate versions are stored in transactions of `batch_size` versions each. Requests to the sparse index are sent
+/// like downloads with `download_settings`, and count towards its rate of requests to crates.io.
+#[allow(clippy::too_many_arguments)]
 pub async fn fetch(
-    crates_io_path: impl AsRef<Path>,
+    source: IndexSource,
+    db: persistence::Db,
+    glob: Option<String>,
+    batch_size: usize,
+    event_sinks: Vec<EventSink>,
+    fused: Option<FusedDownloads>,
+    download_settings: DownloadSettings,
+    progress: prodash::tree::Item,
+    deadline: Option<SystemTime>,
+) -> Result<()> {
+    let glob = glob.as_deref().map(glob::Pattern::new).transpose()?;
+    match source {
+        IndexSource::Git(crates_io_path) => {
+            fetch_git(
+                crates_io_path,
+                None,
+                false,
+                db,
+                glob,
+                batch_size,
+                event_sinks,
+                fused,
+                download_settings,
+                progress,
+                deadline,
+            )
+            .await
+        }
+        IndexSource::Mirror(mirror_path) => {
+            fetch_git(
+                mirror_path,
+                None,
+                true,
+                db,
+                glob,
+                batch_size,
+                event_sinks,
+                fused,
+                download_settings,
+                progress,
+                deadline,
+            )
+            .await
+        }
+        IndexSource::Sparse(url) => {
+            fetch_sparse(
+                url,
+                db,
+                glob,
+                batch_size,
+                event_sinks,
+                fused,
+                download_settings,
+                progress,
+                deadline,
+            )
+            .await
+        }
+    }
+}
+
+/// Fetch new and yanked crate versions from the git index of the alternate `registry` and store them in `db`,
+/// with their crate names qualified by the name of the registry. Like with `fetch()`, a `glob` limits which crates are
+/// stored, and is matched against the qualified crate names.
+#[allow(clippy::too_many_arguments)]
+pub async fn fetch_registry(
+    registry: Registry,
+    db: persistence::Db,
+    glob: Option<String>,
+    batch_size: usize,
+    event_sinks: Vec<EventSink>,
+    fused: Option<FusedDownloads>,
+    download_settings: DownloadSettings,
+    progress: prodash::tree::Item,
+    deadline: Option<SystemTime>,
+) -> Result<()> {
+    // Cloning would clone the crates.io index instead
+    if !registry.index_path.is_dir() {
+        return Err(Error::Message(format!(
+            "The index of registry '{}' at '{}' doesn't exist and needs to be cloned first",
+            registry.name,
+            registry.index_path.display()
+        )));
+    }
+    let glob = glob.as_deref().map(glob::Pattern::new).transpose()?;
+    fetch_git(
+        registry.index_path,
+        Some(registry.name),
+        false,
+        db,
+        glob,
+        batch_size,
+        event_sinks,
+        fused,
+        download_settings,
+        progress,
+        deadline,
+    )
+    .await
+}
+
+/// Fetch changes from the git index at `index_path`, which is the one of crates.io unless it's the one of the alternate
+/// `registry` with the given name. If it `is_mirror`, it's only read instead of being fetched.
+#[allow(clippy::too_many_arguments)]
+async fn fetch_git(
+    index_path: impl AsRef<Path>,
+    registry: Option<String>,
+    is_mirror: bool,
     db: persistence::Db,
+    glob: Option<glob::Pattern>,
+    batch_size: usize,
+    event_sinks: Vec<EventSink>,
+    fused: Option<FusedDownloads>,
+    download_settings: DownloadSettings,
     mut progress: prodash::tree::Item,
     deadline: Option<SystemTime>,
 ) -> Result<()> {
     let start = SystemTime::now();
-    let mut subprogress = progress.add_child("Fetching changes from crates.io index");
+    let mut subprogress = progress.add_child(format!(
+        "Fetching changes from {} index",
+        registry.as_deref().unwrap_or(CRATES_IO_REGISTRY_NAME)
+    ));
     subprogress.blocked("potentially cloning", None);
     let index = enforce_threaded(
         deadline.unwrap_or_else(|| SystemTime::now().add(Duration::from_secs(60 * 60))),
         {
-            let path = crates_io_path.as_ref().to_path_buf();
-            if !path.is_dir() {
-                std::fs::create_dir(&path)?;
+            let path = index_path.as_ref().to_path_buf();
+            let mut clone_progress = subprogress.add_child("cloning crates.io index");
+            move || -> Result<_> {
+                // Mirrors are maintained by someone else, so we never clone them
+                if !is_mirror {
+                    clone_index_if_missing(&path, &mut clone_progress)?;
+                } else if !path.is_dir() {
+                    return Err(Error::Message(format!(
+                        "The index mirror at '{}' doesn't exist",
+                        path.display()
+                    )));
+                }
+                Ok(Index::from_path_or_cloned(path)?)
             }
-            || Index::from_path_or_cloned(path)
         },
     )
     .await??;
-    let (crate_versions, last_seen_git_object) = enforce_threaded(
-        deadline.unwrap_or_else(|| SystemTime::now().add(Duration::from_secs(10 * 60))),
+
+    let without_time_limit_unless_one_is_set =
+        deadline.unwrap_or_else(|| SystemTime::now().add(Duration::from_secs(24 * 60 * 60)));
+    // A fetch which was cut short is resumed from the changes it didn't store yet, without fetching the index again
+    if glob.is_none() {
+        let is_resumed = enforce_threaded(without_time_limit_unless_one_is_set, {
+            let (db, event_sinks, fused) = (db.clone(), event_sinks.clone(), fused.clone());
+            let download_settings = download_settings.clone();
+            let (index_path, registry) = (index_path.as_ref().to_path_buf(), registry.clone());
+            let mut store_progress = progress.add_child("resuming interrupted fetch");
+            move || -> Result<_> {
+                let registry_name = registry.as_deref().unwrap_or(CRATES_IO_REGISTRY_NAME);
+                match resume_fetch(
+                    &db,
+                    index_path,
+                    registry_name,
+                    is_mirror,
+                    batch_size,
+                    deadline,
+                    &mut store_progress,
+                    |new_versions| announce(&db, &event_sinks, &download_settings, fused.as_ref(), new_versions),
+                )? {
+                    Some(counts) => {
+                        record_counts(&db, start, counts, None)?;
+                        Ok(true)
+                    }
+                    None => Ok(false),
+                }
+            }
+        })
+        .await??;
+        if is_resumed {
+            return Ok(());
+        }
+    }
+
+    let fetch_deadline = deadline.unwrap_or_else(|| SystemTime::now().add(Duration::from_secs(10 * 60)));
+    // Let libgit2 abort the fetch once we gave up on it, instead of leaving a thread behind which completes it for no one
+    let cancel = Cancellation::new(Some(fetch_deadline));
+    let _cancel_on_drop = CancelOnDrop(cancel.clone());
+    let fetched = enforce_threaded(fetch_deadline, {
+        let db = db.clone();
+        let registry_name = registry.clone().unwrap_or_else(|| CRATES_IO_REGISTRY_NAME.into());
+        move || -> Result<_> {
+            let (crate_versions, last_seen_git_object) = if is_mirror {
+                let last_seen = last_seen(&db, &index, &registry_name, is_mirror)?;
+                mirror_changes(&index, last_seen)?
+            } else {
+                fetch_changes(&index, &cancel, &mut subprogress)?
+            };
+            // New versions were published at the latest when the commit we fetched was made
+            let committed_at = index.repository().find_commit(last_seen_git_object)?.time().seconds();
+            Ok((
+                crate_versions,
+                last_seen_git_object,
+                std::time::UNIX_EPOCH + Duration::from_secs(committed_at.max(0) as u64),
+            ))
+        }
+    })
+    .await?;
+    let (mut crate_versions, last_seen_git_object, committed_at) = match fetched {
+        Ok(fetched) => fetched,
+        // Being offline shouldn't fail the engine, the next fetch sees all changes we missed
+        Err(Error::Git2(err)) if is_network_error(&err) => {
+            let message = format!(
+                "Skipping fetch as the {} index is unreachable: {}",
+                registry.as_deref().unwrap_or(CRATES_IO_REGISTRY_NAME),
+                err
+            );
+            log::warn!("{}", message);
+            progress.fail(message);
+            return Ok(());
+        }
+        Err(err) => return Err(err),
+    };
+
+    progress.done(format!("Fetched {} changed crates", crate_versions.len()));
+    if let Some(registry) = registry.as_deref() {
+        for version in &mut crate_versions {
+            version.name = model::qualified_crate_name(Some(registry), &version.name);
+        }
+    }
+    let is_focused = glob.is_some();
+    if let Some(glob) = &glob {
+        crate_versions.retain(|version| glob.matches(&version.name));
+    }
+    // Recorded changes carry the commit they were observed in, so resumed fetches store it as well
+    let observed_in = model::IndexCommit {
+        id: last_seen_git_object.to_string(),
+        committed_at,
+    };
+    let crate_versions: Vec<_> = crate_versions
+        .into_iter()
+        .map(|version| model::CrateVersion {
+            observed_in: Some(observed_in.clone()),
+            ..model::CrateVersion::from(version)
+        })
+        .collect();
+
+    let mut store_progress = progress.add_child("processing new crates");
+
+    enforce_threaded(without_time_limit_unless_one_is_set, {
+        let db = db.clone();
+        let index_path = index_path.as_ref().to_path_buf();
         move || {
+            let index = Index::from_path_or_cloned(index_path)?;
+            let registry_name = registry.as_deref().unwrap_or(CRATES_IO_REGISTRY_NAME);
+            let last_seen = last_seen(&db, &index, registry_name, is_mirror)?;
+            let deleted_crates: Vec<_> = deleted_crates(index.repository(), last_seen, last_seen_git_object)?
+                .into_iter()
+                .map(|name| model::qualified_crate_name(registry.as_deref(), &name))
+                .filter(|name| glob.as_ref().map(|glob| glob.matches(name)).unwrap_or(true))
+                .collect();
+
+            // The changes to all other crates were skipped, so the next full fetch has to see them again
+            if is_focused {
+                tombstone(&db, &deleted_crates, committed_at, &mut store_progress)?;
+                let counts = store(
+                    &db,
+                    crate_versions,
+                    Some(committed_at),
+                    batch_size,
+                    None,
+                    deadline,
+                    &mut store_progress,
+                    |new_versions| announce(&db, &event_sinks, &download_settings, fused.as_ref(), new_versions),
+                )?;
+                return record_counts(&db, start, counts, None);
+            }
+
+            // Record where we are going and the changes to store before storing anything, and only advance the index
+            // reference once all changes are stored. The changes which weren't stored yet when the fetch is cut short
+            // are stored by the next one. After a crash, storing changes again is idempotent.
+            let fetches = db.open_index_fetches()?;
+            if let Some(target) = fetches.pending(registry_name)? {
+                if last_seen.map(|oid| oid.to_string()).as_deref() != Some(target.as_str()) {
+                    log::warn!(
+                        "The previous fetch of the {} index up to {} was interrupted, storing its changes again",
+                        registry_name,
+                        target
+                    );
+                }
+            }
+            fetches.begin(registry_name, &last_seen_git_object.to_string())?;
+            fetches.record_changes(registry_name, &crate_versions)?;
+            // Deletions aren't part of the recorded changes, so they are stored before storing can be cut short
+            tombstone(&db, &deleted_crates, committed_at, &mut store_progress)?;
+            let counts = store(
+                &db,
+                crate_versions,
+                Some(committed_at),
+                batch_size,
+                Some((registry_name, 0)),
+                deadline,
+                &mut store_progress,
+                |new_versions| announce(&db, &event_sinks, &download_settings, fused.as_ref(), new_versions),
+            )?;
+            set_last_seen(&db, &index, registry_name, is_mirror, last_seen_git_object)?;
+            fetches.complete(registry_name)?;
+            record_counts(&db, start, counts, None)
+        }
+    })
+    .await??;
+    Ok(())
+}
+
+/// Reconcile the crates of the crates.io index from `index_source` up to the commit we saw last with the ones in the
+/// most recently ingested db dump and our own tables, and record the outcome in the context and the status file.
+///
+/// As this reads all crates of the index and the db dump, it's done once per db dump instead of after every fetch.
+/// The sparse index can't be reconciled with, and neither can a git index which wasn't fetched yet.
+pub async fn reconcile_with_index(
+    index_source: IndexSource,
+    db: persistence::Db,
+    mut progress: prodash::tree::Item,
+    deadline: Option<SystemTime>,
+) -> Result<()> {
+    let (index_path, is_mirror) = match index_source {
+        IndexSource::Git(path) => (path, false),
+        IndexSource::Mirror(path) => (path, true),
+        IndexSource::Sparse(_) => return Ok(()),
+    };
+    let without_time_limit_unless_one_is_set =
+        deadline.unwrap_or_else(|| SystemTime::now().add(Duration::from_secs(24 * 60 * 60)));
+    enforce_threaded(without_time_limit_unless_one_is_set, move || {
+        if !index_path.is_dir() {
+            return Ok(());
+        }
+        let index = Index::from_path_or_cloned(index_path)?;
+        let last_seen = match last_seen(&db, &index, CRATES_IO_REGISTRY_NAME, is_mirror)? {
+            Some(commit) => commit,
+            None => return Ok(()),
+        };
+        let connection = db.open_connection_no_async_with_busy_wait()?;
+        let reconciliation = reconcile::reconcile(&index, last_seen, &connection, &mut progress)?;
+        if !reconciliation.is_healthy() {
+            progress.fail(format!(
+                "{} crates in the index are missing in the database, e.g. {}",
+                reconciliation.missing_in_db.count,
+                reconciliation.missing_in_db.sample.join(", ")
+            ));
+        }
+        let context = db
+            .open_context()?
+            .update_today(|c| c.reconciliation = Some(reconciliation.clone()))?;
+        reconcile::write_status_file(&db, &context)
+    })
+    .await?
+}
+
+/// Changed crate versions along with the commit of the index they were found in
+type IndexChanges = (Vec<crates_index_diff::CrateVersion>, crates_index_diff::git2::Oid);
+
+/// Fetch the git `index` and return its changes since the commit we saw last, along with the commit we fetched.
+/// Fetches failing due to the network are retried with exponential backoff, unless they were `cancel`led.
+fn fetch_changes(index: &Index, cancel: &Cancellation, progress: &mut prodash::tree::Item) -> Result<IndexChanges> {
+    let mut attempt = 0;
+    loop {
+        let res = {
             let mut cbs = crates_index_diff::git2::RemoteCallbacks::new();
             let mut opts = {
+                cbs.sideband_progress(|_| !cancel.is_cancelled());
                 cbs.transfer_progress(|p| {
-                    subprogress.set_name(format!(
+                    progress.set_name(format!(
                         "Fetching crates index ({} received)",
                         bytesize::ByteSize(p.received_bytes() as u64)
                     ));
-                    subprogress.init(Some(p.total_deltas() + p.total_objects()), Some("objects".into()));
-                    subprogress.set(p.indexed_deltas() + p.received_objects());
-                    true
+                    progress.init(Some(p.total_deltas() + p.total_objects()), Some("objects".into()));
+                    progress.set(p.indexed_deltas() + p.received_objects());
+                    !cancel.is_cancelled()
                 });
                 let mut opts = crates_index_diff::git2::FetchOptions::new();
                 opts.remote_callbacks(cbs);
                 opts
             };
-
             index.peek_changes_with_options(Some(&mut opts))
-        },
-    )
-    .await??;
+        };
+        match res {
+            Ok(changes) => return Ok(changes),
+            Err(err) if is_network_error(&err) && attempt < INDEX_FETCH_RETRIES && !cancel.is_cancelled() => {
+                let backoff = INDEX_FETCH_BACKOFF * 2u32.pow(attempt as u32);
+                attempt += 1;
+                progress.info(format!("Retrying in {:?} after fetch failed: {}", backoff, err));
+                std::thread::sleep(backoff);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
 
-    progress.done(format!("Fetched {} changed crates", crate_versions.len()));
+/// Return the changes of the index `mirror` between the `last_seen` commit and the one its HEAD points to, along with
+/// the latter. Unlike fetching, this neither talks to the network nor writes to the mirror.
+fn mirror_changes(mirror: &Index, last_seen: Option<crates_index_diff::git2::Oid>) -> Result<IndexChanges> {
+    let repo = mirror.repository();
+    let target = repo.head()?.peel_to_commit()?;
+    // Without a last seen commit, all crates are new
+    let from = match last_seen {
+        Some(last_seen) => repo.find_object(last_seen, None)?,
+        None => repo.find_object(crates_index_diff::git2::Oid::from_str(EMPTY_TREE_ID)?, None)?,
+    };
+    let changes = mirror.changes_from_objects(&from, target.as_object())?;
+    Ok((changes, target.id()))
+}
 
-    let mut store_progress = progress.add_child("processing new crates");
-    store_progress.init(Some(crate_versions.len()), Some("crate versions".into()));
+/// Return the commit of the index of `registry_name` up to which all changes were stored. It's kept in the database
+/// for mirrors, as these aren't written to, and in a reference of the `index` otherwise.
+fn last_seen(
+    db: &persistence::Db,
+    index: &Index,
+    registry_name: &str,
+    is_mirror: bool,
+) -> Result<Option<crates_index_diff::git2::Oid>> {
+    if !is_mirror {
+        return Ok(index.last_seen_reference().ok().and_then(|r| r.target()));
+    }
+    Ok(match db.open_index_fetches()?.last_seen(registry_name)? {
+        Some(id) => Some(crates_index_diff::git2::Oid::from_str(&id)?),
+        None => None,
+    })
+}
+
+/// Remember that all changes of the index of `registry_name` up to `commit` were stored, see `last_seen()`.
+fn set_last_seen(
+    db: &persistence::Db,
+    index: &Index,
+    registry_name: &str,
+    is_mirror: bool,
+    commit: crates_index_diff::git2::Oid,
+) -> Result<()> {
+    if is_mirror {
+        db.open_index_fetches()?
+            .set_last_seen(registry_name, &commit.to_string())
+    } else {
+        Ok(index.set_last_seen_reference(commit)?)
+    }
+}
+
+/// Store the changes of the index at `index_path` of the registry with the given name which a previous fetch recorded
+/// but didn't store as it was cut short, and advance its last seen reference to the commit that fetch was going to.
+/// New versions are passed to `on_commit` as they are stored. Return None if there is no such fetch.
+#[allow(clippy::too_many_arguments)]
+fn resume_fetch(
+    db: &persistence::Db,
+    index_path: PathBuf,
+    registry_name: &str,
+    is_mirror: bool,
+    batch_size: usize,
+    deadline: Option<SystemTime>,
+    progress: &mut prodash::tree::Item,
+    on_commit: impl FnMut(&[NewCrateVersion]),
+) -> Result<Option<StoredCounts>> {
+    let fetches = db.open_index_fetches()?;
+    let target = match fetches.pending(registry_name)? {
+        Some(target) => target,
+        None => return Ok(None),
+    };
+    let (first_seq, changes) = fetches.pending_changes(registry_name)?;
+    // Without recorded changes, the fetch is done again from the last seen commit to be sure nothing is missed
+    if changes.is_empty() {
+        return Ok(None);
+    }
+    progress.info(format!(
+        "Resuming the interrupted fetch of the {} index up to {} with {} changes left",
+        registry_name,
+        target,
+        changes.len()
+    ));
+    let index = Index::from_path_or_cloned(index_path)?;
+    let target = crates_index_diff::git2::Oid::from_str(&target)?;
+    let committed_at = index.repository().find_commit(target)?.time().seconds();
+    let stored = store(
+        db,
+        changes,
+        Some(std::time::UNIX_EPOCH + Duration::from_secs(committed_at.max(0) as u64)),
+        batch_size,
+        Some((registry_name, first_seq)),
+        deadline,
+        progress,
+        on_commit,
+    )?;
+    set_last_seen(db, &index, registry_name, is_mirror, target)?;
+    fetches.complete(registry_name)?;
+    Ok(Some(stored))
+}
+
+/// Clone the crates.io git index into `path` unless it contains a clone already.
+///
+/// Only the branch with the index is fetched and tags are skipped. Shallow or partial clones would be faster still,
+/// but the version of libgit2 we use can neither create them nor fetch changes into them.
+fn clone_index_if_missing(path: &Path, progress: &mut prodash::tree::Item) -> Result<()> {
+    use crates_index_diff::git2;
+    if path.is_dir() && std::fs::read_dir(path)?.next().is_some() {
+        return Ok(());
+    }
+    let mut cbs = git2::RemoteCallbacks::new();
+    cbs.transfer_progress(|p| {
+        progress.set_name(format!(
+            "Cloning crates.io index ({} received)",
+            bytesize::ByteSize(p.received_bytes() as u64)
+        ));
+        progress.init(Some(p.total_deltas() + p.total_objects()), Some("objects".into()));
+        progress.set(p.indexed_deltas() + p.received_objects());
+        true
+    });
+    let mut opts = git2::FetchOptions::new();
+    opts.remote_callbacks(cbs).download_tags(git2::AutotagOption::None);
+    git2::build::RepoBuilder::new()
+        .bare(true)
+        .remote_create(|repo, name, url| repo.remote_with_fetch(name, url, INDEX_REFSPEC))
+        .fetch_options(opts)
+        .clone(CRATES_IO_INDEX_URL, path)?;
+    Ok(())
+}
+
+/// What a resync of the crates.io git index found
+pub struct Resync {
+    /// The amount of crates in the index
+    pub crates: usize,
+    /// The amount of crate versions which were missing in the database or differed from it, and were stored
+    pub drifted_versions: usize,
+    pub reconciliation: model::Reconciliation,
+}
+
+/// Fetch the crates.io git index at `index_path` and walk all of its crates instead of the changes since the last
+/// fetch, storing each version which is missing in `db` or differs from it. Afterwards the last seen reference points
+/// to the walked commit and the crates are reconciled.
+///
+/// This repairs versions missed due to a corrupted last seen reference or a database restored from an older backup.
+/// Versions stored this way have no publish time, as the index doesn't know it.
+pub async fn resync(
+    index_path: PathBuf,
+    db: persistence::Db,
+    batch_size: usize,
+    mut progress: prodash::tree::Item,
+    deadline: Option<SystemTime>,
+) -> Result<Resync> {
+    let start = SystemTime::now();
+    let mut fetch_progress = progress.add_child("Fetching changes from crates.io index");
+    let mut walk_progress = progress.add_child("walking all crates");
+    let mut store_progress = progress.add_child("processing drifted crate versions");
+    let mut reconcile_progress = progress.add_child("reconciling crate counts");
 
     let without_time_limit_unless_one_is_set =
         deadline.unwrap_or_else(|| SystemTime::now().add(Duration::from_secs(24 * 60 * 60)));
-    enforce_threaded(without_time_limit_unless_one_is_set, {
-        let db = db.clone();
-        let index_path = crates_io_path.as_ref().to_path_buf();
-        move || {
-            let mut connection = db.open_connection_no_async_with_busy_wait()?;
-            let mut crates_lut: BTreeMap<_, _> = {
-                let transaction = connection.transaction()?;
-                store_progress.blocked("caching crates", None);
-                let mut statement = new_key_value_query_old_to_new(CrateTable::table_name(), &transaction)?;
-                let iter = key_value_iter::<model::Crate>(&mut statement)?.flat_map(Result::ok);
-                iter.collect()
-            };
+    enforce_threaded(without_time_limit_unless_one_is_set, move || {
+        fetch_progress.blocked("potentially cloning", None);
+        clone_index_if_missing(&index_path, &mut fetch_progress)?;
+        let index = Index::from_path_or_cloned(index_path)?;
+        let repo = index.repository();
+        {
+            let mut cbs = crates_index_diff::git2::RemoteCallbacks::new();
+            cbs.transfer_progress(|p| {
+                fetch_progress.init(Some(p.total_deltas() + p.total_objects()), Some("objects".into()));
+                fetch_progress.set(p.indexed_deltas() + p.received_objects());
+                true
+            });
+            let mut opts = crates_index_diff::git2::FetchOptions::new();
+            opts.remote_callbacks(cbs);
+            // The last seen reference isn't trusted, so the remote is fetched without asking the index for changes
+            repo.find_remote("origin")?
+                .fetch(&[] as &[&str], Some(&mut opts), None)?;
+        }
+        let target = repo.refname_to_id(INDEX_REMOTE_BRANCH)?;
+        fetch_progress.done(format!("Fetched crates.io index up to {}", target));
 
-            let mut key_buf = String::new();
-            let crate_versions_len = crate_versions.len();
-            let mut new_crate_versions = 0;
-            let mut new_crates = 0;
-            store_progress.blocked("write lock for crate versions", None);
-            let transaction = connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
-            {
-                let mut statement = new_key_value_insertion(CrateVersionTable::table_name(), &transaction)?;
-                for version in crate_versions.into_iter().map(model::CrateVersion::from) {
-                    key_buf.clear();
-                    version.key_buf(&mut key_buf);
-                    statement.execute(params![&key_buf, rmp_serde::to_vec(&version)?])?;
-                    new_crate_versions += 1;
-
-                    key_buf.clear();
-                    model::Crate::key_from_version_buf(&version, &mut key_buf);
-                    if crates_lut
-                        .entry(key_buf.to_owned())
-                        .or_default()
-                        .merge_mut(&version)
-                        .versions
-                        .len()
-                        == 1
-                    {
-                        new_crates += 1;
-                    }
+        let (crates, drifted) = drifted_crate_versions(&db, repo, target, &mut walk_progress)?;
+        let drifted_versions = drifted.len();
+        let observed_in = model::IndexCommit {
+            id: target.to_string(),
+            committed_at: std::time::UNIX_EPOCH
+                + Duration::from_secs(repo.find_commit(target)?.time().seconds().max(0) as u64),
+        };
+        let drifted = drifted
+            .into_iter()
+            .map(|version| model::CrateVersion {
+                observed_in: Some(observed_in.clone()),
+                ..model::CrateVersion::from(version)
+            })
+            .collect();
+        let counts = store(
+            &db,
+            drifted,
+            None,
+            batch_size,
+            None,
+            deadline,
+            &mut store_progress,
+            |_| {},
+        )?;
+        index.set_last_seen_reference(target)?;
+        db.open_index_fetches()?.complete(CRATES_IO_REGISTRY_NAME)?;
 
-                    store_progress.inc();
-                }
+        let connection = db.open_connection_no_async_with_busy_wait()?;
+        let reconciliation = reconcile::reconcile(&index, target, &connection, &mut reconcile_progress)?;
+        record_counts(&db, start, counts, Some(reconciliation.clone()))?;
+        Ok(Resync {
+            crates,
+            drifted_versions,
+            reconciliation,
+        })
+    })
+    .await?
+}
+
+/// Return the amount of crates in the tree of the `target` commit of the crates.io index `repo`, along with all of
+/// their versions which are unknown to `db`, differ from what's stored about them or are missing in their crate.
+fn drifted_crate_versions(
+    db: &persistence::Db,
+    repo: &crates_index_diff::git2::Repository,
+    target: crates_index_diff::git2::Oid,
+    progress: &mut prodash::tree::Item,
+) -> Result<(usize, Vec<crates_index_diff::CrateVersion>)> {
+    progress.blocked("listing crates in index", None);
+    let files = reconcile::crate_files(repo, target)?;
+
+    progress.init(Some(files.len()), Some("crates".into()));
+    let (crates, versions) = (db.open_crates()?, db.open_crate_versions()?);
+    let mut key_buf = String::new();
+    let mut drifted = Vec::new();
+    for (name, id) in &files {
+        progress.inc();
+        let blob = repo.find_blob(*id)?;
+        let mut crate_versions = Vec::new();
+        for line in blob.content().split(|b| *b == b'\n').filter(|line| !line.is_empty()) {
+            match serde_json::from_slice::<crates_index_diff::CrateVersion>(line) {
+                Ok(version) => crate_versions.push(version),
+                Err(err) => progress.fail(format!(
+                    "Skipping a version of '{}' which can't be decoded: {}",
+                    name, err
+                )),
+            }
+        }
+        let krate = match crate_versions.first() {
+            Some(version) => crates.get(&version.name)?,
+            None => continue,
+        };
+        for version in crate_versions {
+            key_buf.clear();
+            model::CrateVersion::key_from(&version.name, &version.version, &mut key_buf);
+            let is_in_crate = krate
+                .as_ref()
+                .map(|krate| krate.versions.contains(&version.version))
+                .unwrap_or(false);
+            let is_unchanged = versions
+                .get(&key_buf)?
+                .map(|stored| {
+                    stored.kind == model::ChangeKind::from(&version.kind) && stored.checksum == version.checksum
+                })
+                .unwrap_or(false);
+            if !(is_in_crate && is_unchanged) {
+                drifted.push(version);
             }
+        }
+    }
+    progress.done(format!(
+        "Found {} drifted crate versions in {} crates",
+        drifted.len(),
+        files.len()
+    ));
+    Ok((files.len(), drifted))
+}
 
-            store_progress.blocked("commit crate versions", None);
-            transaction.commit()?;
+/// Returns true if `err` is likely caused by the network being down or a remote being unreachable, which is temporary.
+/// Fetches cancelled by us and failures to authenticate aren't.
+fn is_network_error(err: &crates_index_diff::git2::Error) -> bool {
+    use crates_index_diff::git2::{ErrorClass::*, ErrorCode};
+    let is_permanent = matches!(err.code(), ErrorCode::User | ErrorCode::Auth | ErrorCode::Certificate);
+    !is_permanent && matches!(err.class(), Net | Ssl | Http | Ssh | Os)
+}
 
-            let transaction = {
-                store_progress.blocked("write lock for crates", None);
-                let mut t = connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
-                t.set_drop_behavior(rusqlite::DropBehavior::Commit);
-                t
-            };
-            {
-                let mut statement = new_key_value_insertion(CrateTable::table_name(), &transaction)?;
-                store_progress.init(Some(crates_lut.len()), Some("crates".into()));
-                for (key, value) in crates_lut.into_iter() {
-                    statement.execute(params![key, rmp_serde::to_vec(&value)?])?;
-                    store_progress.inc();
+/// Tell all `event_sinks` about the `new_versions` which were just stored, with webhooks being sent requests like
+/// downloads with `download_settings`, and queue their downloads if they are `fused`. Failing to queue them is logged,
+/// as the next processing run schedules them as well.
+fn announce(
+    db: &persistence::Db,
+    event_sinks: &[EventSink],
+    download_settings: &DownloadSettings,
+    fused: Option<&FusedDownloads>,
+    new_versions: &[NewCrateVersion],
+) {
+    notify::emit(db, event_sinks, download_settings, new_versions);
+    if let Some(fused) = fused {
+        if let Err(err) = fused.schedule(new_versions) {
+            log::warn!(
+                "Could not queue the downloads of {} new crate versions: {}",
+                new_versions.len(),
+                err
+            );
+        }
+    }
+}
+
+/// Fetch the files of all crates we know in the sparse index at `index_url` which changed since we saw them last,
+/// and store the crate versions which are new or changed their yanked state.
+///
+/// The sparse index can't tell which crates are new, so only crates known to the database, the most recently ingested
+/// db dump or a previous fetch are looked at, and only those matching `glob` if it is set.
+#[allow(clippy::too_many_arguments)]
+async fn fetch_sparse(
+    index_url: String,
+    db: persistence::Db,
+    glob: Option<glob::Pattern>,
+    batch_size: usize,
+    event_sinks: Vec<EventSink>,
+    fused: Option<FusedDownloads>,
+    download_settings: DownloadSettings,
+    mut progress: prodash::tree::Item,
+    deadline: Option<SystemTime>,
+) -> Result<()> {
+    let start = SystemTime::now();
+    let request_rate = &download_settings.request_rate;
+    let mut subprogress = progress.add_child("Fetching changes from crates.io sparse index");
+    subprogress.blocked("collecting crate names", None);
+    let known_validators = blocking::unblock({
+        let db = db.clone();
+        move || -> Result<_> {
+            let connection = db.open_connection_no_async_with_busy_wait()?;
+            let mut validators = db.open_sparse_index()?.all()?;
+            for table_name in &[CrateTable::table_name(), "crates.io-crate"] {
+                let mut statement = connection.prepare(&format!("SELECT key FROM '{}'", table_name))?;
+                let names: rusqlite::Result<Vec<String>> = statement.query_map(NO_PARAMS, |r| r.get(0))?.collect();
+                // Crates of alternate registries are only fetched from their git index
+                for name in names?
+                    .into_iter()
+                    .filter(|name| !name.contains(model::REGISTRY_SEP_CHAR))
+                {
+                    validators.entry(name.to_lowercase()).or_default();
                 }
             }
-            store_progress.blocked("commit crates", None);
-            transaction.commit()?;
-
-            Index::from_path_or_cloned(index_path)?.set_last_seen_reference(last_seen_git_object)?;
-            db.open_context()?.update_today(|c| {
-                c.counts.crate_versions += new_crate_versions;
-                c.counts.crates += new_crates;
-                c.durations.fetch_crate_versions += SystemTime::now()
-                    .duration_since(start)
-                    .unwrap_or_else(|_| Duration::default())
-            })?;
-            store_progress.done(format!("Stored {} crate versions to database", crate_versions_len));
-            Ok::<_, Error>(())
+            Ok(validators
+                .into_iter()
+                .filter(|(name, _)| glob.as_ref().map(|glob| glob.matches(name)).unwrap_or(true))
+                .collect::<BTreeMap<_, _>>())
+        }
+    })
+    .await?;
+
+    subprogress.init(Some(known_validators.len()), Some("crates".into()));
+    let waited_before = request_rate.waited();
+    let client = iobound::client_builder(&download_settings, &index_url)?
+        .timeout(SPARSE_INDEX_REQUEST_TIMEOUT)
+        .build()?;
+    let request_log = db.open_request_log()?;
+    let index_url = index_url.trim_end_matches('/').to_owned();
+    let mut changed_crates = Vec::new();
+    {
+        let mut responses = futures_util::stream::iter(known_validators.iter())
+            .map(|(crate_name, validators)| {
+                fetch_sparse_crate(&client, request_rate, &request_log, &index_url, crate_name, validators)
+            })
+            .buffer_unordered(SPARSE_INDEX_CONCURRENCY);
+        while let Some(response) = responses.next().await {
+            subprogress.inc();
+            check(deadline)?;
+            match response {
+                Ok(Some(changed_crate)) => changed_crates.push(changed_crate),
+                Ok(None) => {}
+                Err(err) => subprogress.fail(err.to_string()),
+            }
         }
+    }
+    progress.done(format!("Fetched {} changed crates", changed_crates.len()));
+    let waited = request_rate.waited() - waited_before;
+    if waited > Duration::default() {
+        progress.info(format!(
+            "Requests to crates.io waited {:.1}s for the rate limit while fetching",
+            waited.as_secs_f32()
+        ));
+    }
+
+    let mut store_progress = progress.add_child("processing new crates");
+    let without_time_limit_unless_one_is_set =
+        deadline.unwrap_or_else(|| SystemTime::now().add(Duration::from_secs(24 * 60 * 60)));
+    enforce_threaded(without_time_limit_unless_one_is_set, move || {
+        let crate_versions = changed_crate_versions(&db, &changed_crates)?;
+        let crate_versions = crate_versions.into_iter().map(model::CrateVersion::from).collect();
+        let counts = store(
+            &db,
+            crate_versions,
+            None,
+            batch_size,
+            None,
+            deadline,
+            &mut store_progress,
+            |new_versions| announce(&db, &event_sinks, &download_settings, fused.as_ref(), new_versions),
+        )?;
+
+        let validators: Vec<_> = changed_crates
+            .iter()
+            .map(|(crate_name, _, validators)| (crate_name.as_str(), validators))
+            .collect();
+        db.open_sparse_index()?.record(&validators)?;
+        record_counts(&db, start, counts, None)
     })
     .await??;
     Ok(())
 }
+
+/// Fetch the file of the crate with the given `crate_name` in the sparse index at `index_url` unless it didn't change
+/// according to its `validators`, and return its crate versions along with its new validators.
+/// The request is recorded in `request_log`.
+async fn fetch_sparse_crate(
+    client: &reqwest::Client,
+    request_rate: &RequestRate,
+    request_log: &persistence::RequestLogTable,
+    index_url: &str,
+    crate_name: &str,
+    validators: &model::HttpValidators,
+) -> Result<Option<(String, Vec<crates_index_diff::CrateVersion>, model::HttpValidators)>> {
+    let url = format!("{}/{}/{}", index_url, sparse_index_path(crate_name), crate_name);
+    let mut request = client.get(&url);
+    if let Some(etag) = &validators.etag {
+        request = request.header(http::header::IF_NONE_MATCH, etag.as_str());
+    }
+    if let Some(last_modified) = &validators.last_modified {
+        request = request.header(http::header::IF_MODIFIED_SINCE, last_modified.as_str());
+    }
+    request_rate.wait_for(&url, None).await;
+    let requested_at = SystemTime::now();
+    let (mut status, mut bytes) = (None, 0);
+    let res: Result<_> = async {
+        let response = request.send().await?;
+        status = Some(response.status().as_u16());
+        match response.status().as_u16() {
+            // Crates which are unknown to the index were deleted or are yet to be published
+            304 | 404 | 410 => Ok(None),
+            200 => {
+                let new_validators = iobound::validators_of(response.headers());
+                let body = response.text().await?;
+                bytes = body.len() as u64;
+                let versions: serde_json::Result<Vec<_>> = body
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(serde_json::from_str)
+                    .collect();
+                Ok(Some((crate_name.to_owned(), versions?, new_validators)))
+            }
+            status => Err(Error::Message(format!("{}: unexpected HTTP status {}", url, status))),
+        }
+    }
+    .await;
+    if let Err(err) = request_log.record(
+        &url,
+        requested_at,
+        status,
+        bytes,
+        res.as_ref().err().map(ToString::to_string).as_deref(),
+    ) {
+        log::warn!("Could not record request to '{}' in request log: {}", url, err);
+    }
+    res
+}
+
+/// The directory of the file of a crate in the sparse index, which is named after the lower-case crate name
+fn sparse_index_path(crate_name: &str) -> String {
+    schedule::crate_prefix(crate_name).to_lowercase()
+}
+
+/// Return all versions of the `changed_crates` which are unknown to the database or whose yanked state changed.
+fn changed_crate_versions(
+    db: &persistence::Db,
+    changed_crates: &[(String, Vec<crates_index_diff::CrateVersion>, model::HttpValidators)],
+) -> Result<Vec<crates_index_diff::CrateVersion>> {
+    let versions = db.open_crate_versions()?;
+    let mut key_buf = String::new();
+    let mut changed = Vec::new();
+    for version in changed_crates.iter().flat_map(|(_, versions, _)| versions) {
+        key_buf.clear();
+        model::CrateVersion::key_from(&version.name, &version.version, &mut key_buf);
+        let is_unchanged = versions
+            .get(&key_buf)?
+            .map(|stored| stored.kind == model::ChangeKind::from(&version.kind))
+            .unwrap_or(false);
+        if !is_unchanged {
+            changed.push(version.clone());
+        }
+    }
+    Ok(changed)
+}
+
+/// The amount of new crate versions and new crates that were stored, not counting those that were known already
+struct StoredCounts {
+    crate_versions: u64,
+    crates: u32,
+}
+
+/// Store `crate_versions` along with their crates, and update the latest versions of the crates that changed.
+/// New versions are considered published at `published_at` unless it's unknown.
+/// Return what was stored.
+///
+/// Versions are written in transactions of `batch_size` versions each, along with their crates, to keep the write lock
+/// for a bounded time while still avoiding the cost of a transaction per version. The `deadline` is checked between
+/// batches. Once a batch is committed, `on_commit` is called with the versions it stored for the first time, so these
+/// are announced even if storing is cut short. Storing them again later doesn't see them as new anymore.
+///
+/// If `crate_versions` are changes recorded for a fetch, `cursor` is the name of the registry along with the position
+/// of the first of them, and changes are forgotten along with the batch that stores them.
+#[allow(clippy::too_many_arguments)]
+fn store(
+    db: &persistence::Db,
+    crate_versions: Vec<model::CrateVersion>,
+    published_at: Option<SystemTime>,
+    batch_size: usize,
+    cursor: Option<(&str, i64)>,
+    deadline: Option<SystemTime>,
+    store_progress: &mut prodash::tree::Item,
+    mut on_commit: impl FnMut(&[NewCrateVersion]),
+) -> Result<StoredCounts> {
+    store_progress.init(Some(crate_versions.len()), Some("crate versions".into()));
+    let mut connection = db.open_connection_no_async_with_busy_wait()?;
+    let mut crates_lut: BTreeMap<_, _> = {
+        let transaction = connection.transaction()?;
+        store_progress.blocked("caching crates", None);
+        let mut statement = new_key_value_query_old_to_new(CrateTable::table_name(), &transaction)?;
+        let iter = key_value_iter::<model::Crate>(&mut statement)?.flat_map(Result::ok);
+        iter.collect()
+    };
+
+    let mut key_buf = String::new();
+    let crate_versions_len = crate_versions.len();
+    let mut counts = StoredCounts {
+        crate_versions: 0,
+        crates: 0,
+    };
+    let mut crate_versions = crate_versions.into_iter();
+    let mut is_first_batch = true;
+    let mut num_stored = 0;
+    loop {
+        let batch: Vec<_> = crate_versions.by_ref().take(batch_size.max(1)).collect();
+        // The first batch runs even without versions, to set all latest versions if there are none yet
+        if batch.is_empty() && !is_first_batch {
+            break;
+        }
+        if !is_first_batch {
+            check(deadline)?;
+        }
+        num_stored += batch.len();
+        store_progress.blocked("write lock for crate versions", None);
+        let transaction = connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        let mut changed_crates = BTreeSet::new();
+        let mut new_versions = Vec::new();
+        if is_first_batch && LatestVersionTable::is_empty(&transaction)? {
+            changed_crates.extend(crates_lut.keys().cloned());
+        }
+        is_first_batch = false;
+        {
+            let mut statement = new_key_value_insertion(CrateVersionTable::table_name(), &transaction)?;
+            for mut version in batch {
+                key_buf.clear();
+                model::Crate::key_from_version_buf(&version, &mut key_buf);
+                changed_crates.insert(key_buf.clone());
+                let krate = crates_lut.entry(key_buf.to_owned()).or_default();
+                // Yanking or storing a version again doesn't publish it anew, so the time it was first published sticks
+                version.published_at = krate.published_at(&version.version).or(match version.kind {
+                    model::ChangeKind::Added => published_at,
+                    model::ChangeKind::Yanked => None,
+                });
+                // Storing the same versions again, like after an interrupted fetch, must not count them again
+                if !krate.versions.contains(&version.version) {
+                    counts.crate_versions += 1;
+                    if krate.versions.is_empty() {
+                        counts.crates += 1;
+                    }
+                    new_versions.push(NewCrateVersion::from(&version));
+                }
+                krate.merge_mut(&version);
+
+                key_buf.clear();
+                version.key_buf(&mut key_buf);
+                statement.execute(params![&key_buf, rmp_serde::to_vec(&version)?])?;
+
+                store_progress.inc();
+            }
+        }
+        {
+            let mut statement = new_key_value_insertion(CrateTable::table_name(), &transaction)?;
+            let mut select_version = transaction.prepare(&format!(
+                "SELECT data FROM {} WHERE key = ?1",
+                CrateVersionTable::table_name()
+            ))?;
+            for key in &changed_crates {
+                let krate = &crates_lut[key];
+                statement.execute(params![key, rmp_serde::to_vec(krate)?])?;
+                let latest = latest_unyanked_version(&mut select_version, key, &krate.versions, &mut key_buf)?;
+                LatestVersionTable::set_latest(&transaction, key, latest)?;
+            }
+        }
+        if let Some((registry_name, first_seq)) = cursor {
+            IndexFetchTable::forget_changes_before(&transaction, registry_name, first_seq + num_stored as i64)?;
+        }
+        store_progress.blocked("commit crate versions", None);
+        transaction.commit()?;
+        if !new_versions.is_empty() {
+            on_commit(&new_versions);
+        }
+    }
+    store_progress.done(format!("Stored {} crate versions to database", crate_versions_len));
+    Ok(counts)
+}
+
+/// Add the `counts` of a fetch that started at `start` to today's context, along with its `reconciliation` if there
+/// is one, and update the status file accordingly.
+fn record_counts(
+    db: &persistence::Db,
+    start: SystemTime,
+    counts: StoredCounts,
+    reconciliation: Option<model::Reconciliation>,
+) -> Result<()> {
+    let context = db.open_context()?.update_today(|c| {
+        c.counts.crate_versions += counts.crate_versions;
+        c.counts.crates += counts.crates;
+        c.durations.fetch_crate_versions += SystemTime::now()
+            .duration_since(start)
+            .unwrap_or_else(|_| Duration::default());
+        if let Some(reconciliation) = &reconciliation {
+            c.reconciliation = Some(reconciliation.clone());
+        }
+    })?;
+    reconcile::write_status_file(db, &context)
+}
+
+/// Return the names of all crates whose file is in the tree of the `from` commit of the index `repo`, but not in the
+/// one of the `to` commit, as they were deleted from the registry. Without a `from` commit nothing was seen yet, and
+/// nothing can be deleted.
+fn deleted_crates(
+    repo: &crates_index_diff::git2::Repository,
+    from: Option<crates_index_diff::git2::Oid>,
+    to: crates_index_diff::git2::Oid,
+) -> std::result::Result<Vec<String>, crates_index_diff::git2::Error> {
+    let from = match from {
+        Some(from) => repo.find_commit(from)?.tree()?,
+        None => return Ok(Vec::new()),
+    };
+    let to = repo.find_commit(to)?.tree()?;
+    let mut names = Vec::new();
+    for delta in repo.diff_tree_to_tree(Some(&from), Some(&to), None)?.deltas() {
+        if delta.status() != crates_index_diff::git2::Delta::Deleted {
+            continue;
+        }
+        let file = delta.old_file();
+        let path = match file.path() {
+            Some(path) => path,
+            None => continue,
+        };
+        // Files at the top-level are configuration, all crates are in sub-directories
+        let is_crate = path.parent().map(|dir| dir != Path::new("")).unwrap_or(false)
+            && !path
+                .components()
+                .any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+        if !is_crate {
+            continue;
+        }
+        // Files are named after the crate in lower case, so its actual name is taken from one of its versions
+        let blob = repo.find_blob(file.id())?;
+        let name = blob
+            .content()
+            .split(|b| *b == b'\n')
+            .find_map(|line| serde_json::from_slice::<crates_index_diff::CrateVersion>(line).ok())
+            .map(|version| version.name)
+            .or_else(|| path.file_name().map(|name| name.to_string_lossy().into_owned()));
+        names.extend(name);
+    }
+    Ok(names)
+}
+
+/// Mark the crates with the given `crate_names` and all of their versions as deleted at `deleted_at`, unless they are
+/// unknown or were marked already. Their data is kept, but they aren't processed anymore.
+fn tombstone(
+    db: &persistence::Db,
+    crate_names: &[String],
+    deleted_at: SystemTime,
+    progress: &mut prodash::tree::Item,
+) -> Result<()> {
+    if crate_names.is_empty() {
+        return Ok(());
+    }
+    let crates = db.open_crates()?;
+    let mut num_deleted = 0;
+    for name in crate_names {
+        if crates.tombstone(name, deleted_at)? {
+            num_deleted += 1;
+        }
+    }
+    progress.info(format!("Marked {} crates deleted from the index", num_deleted));
+    Ok(())
+}
+
+/// Return the most recent of the `versions` of the crate with the given `crate_name`, sorted from oldest to newest,
+/// which isn't yanked. Versions unknown to the crate versions table are considered yanked.
+fn latest_unyanked_version<'a>(
+    select_version: &mut rusqlite::Statement<'_>,
+    crate_name: &str,
+    versions: &'a [String],
+    key_buf: &mut String,
+) -> Result<Option<&'a str>> {
+    for version in versions.iter().rev() {
+        key_buf.clear();
+        model::CrateVersion::key_from(crate_name, version, key_buf);
+        let data: Option<Vec<u8>> = select_version
+            .query_row(params![key_buf.as_str()], |r| r.get(0))
+            .optional()?;
+        let is_yanked = data
+            .map(|data| model::CrateVersion::from(data.as_slice()).kind == model::ChangeKind::Yanked)
+            .unwrap_or(true);
+        if !is_yanked {
+            return Ok(Some(version));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,120 @@
+//! Open db dumps regardless of their compression, which is detected by the magic bytes at the start of the file.
+use crate::Result;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// The compression of a tar archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    /// A plain tar archive
+    None,
+}
+
+impl Compression {
+    /// Detect the compression from the first bytes of a file, assuming an uncompressed tar archive if they are unknown.
+    pub fn detect(magic: &[u8]) -> Compression {
+        if magic.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if magic.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Open the tar archive at `path`, decompressing it on the fly if needed.
+pub fn open(path: &Path) -> Result<tar::Archive<Box<dyn Read>>> {
+    from_reader(File::open(path)?)
+}
+
+/// Open the tar archive at `path` like `open()`, and report the amount of compressed bytes consumed by the decoder
+/// to `progress` along with the estimated time it takes to consume the rest.
+pub fn open_with_progress(path: &Path, mut progress: prodash::tree::Item) -> Result<tar::Archive<Box<dyn Read>>> {
+    let file = File::open(path)?;
+    let total_bytes = file.metadata()?.len();
+    progress.init(Some((total_bytes / 1024) as usize), Some("KB compressed".into()));
+    from_reader(ProgressReader {
+        inner: file,
+        progress,
+        bytes_read: 0,
+        total_bytes,
+        started_at: Instant::now(),
+        last_update: None,
+    })
+}
+
+fn from_reader(reader: impl Read + 'static) -> Result<tar::Archive<Box<dyn Read>>> {
+    let mut file = BufReader::new(reader);
+    let reader: Box<dyn Read> = match Compression::detect(file.fill_buf()?) {
+        Compression::Gzip => Box::new(libflate::gzip::Decoder::new(file)?),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(file)?),
+        Compression::None => Box::new(file),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// The minimal time between updates of the estimated time of arrival, to avoid flickering
+const ETA_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A reader keeping track of how many bytes were read from the `inner` reader so far.
+struct ProgressReader<R> {
+    inner: R,
+    progress: prodash::tree::Item,
+    bytes_read: u64,
+    total_bytes: u64,
+    started_at: Instant,
+    last_update: Option<Instant>,
+}
+
+impl<R> ProgressReader<R> {
+    fn update_eta(&mut self) {
+        let now = Instant::now();
+        let updated_recently = match self.last_update {
+            Some(last_update) => now.duration_since(last_update) < ETA_UPDATE_INTERVAL,
+            None => false,
+        };
+        if updated_recently || self.bytes_read == 0 {
+            return;
+        }
+        self.last_update = Some(now);
+        let remaining_bytes = self.total_bytes.saturating_sub(self.bytes_read);
+        let elapsed = now.duration_since(self.started_at);
+        let eta = Duration::from_secs(
+            (elapsed.as_secs_f64() * remaining_bytes as f64 / self.bytes_read as f64).round() as u64,
+        );
+        self.progress.set_name(format!(
+            "decompressing ({:.1}%, ETA {})",
+            self.bytes_read as f64 * 100.0 / self.total_bytes.max(1) as f64,
+            humantime::format_duration(eta)
+        ));
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes = self.inner.read(buf)?;
+        self.bytes_read += bytes as u64;
+        self.progress.set((self.bytes_read / 1024) as usize);
+        self.update_eta();
+        Ok(bytes)
+    }
+}
+
+/// Read the tar archive at `path` to its end, failing if it is truncated or its compressed stream is corrupt.
+pub fn verify(path: &Path) -> Result<()> {
+    let mut archive = open(path)?;
+    for entry in archive.entries()? {
+        std::io::copy(&mut entry?, &mut std::io::sink())?;
+    }
+    Ok(())
+}
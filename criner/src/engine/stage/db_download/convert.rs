@@ -1,5 +1,5 @@
 use super::csv_model;
-use crate::{model::db_dump, utils::parse_semver};
+use crate::{model::db_dump, redact::Redaction, utils::parse_semver};
 use std::collections::BTreeMap;
 use std::time::SystemTime;
 
@@ -64,6 +64,7 @@ impl From<csv_model::Version> for db_dump::CrateVersion {
             semver,
             published_by: _,
             is_yanked,
+            checksum,
         }: csv_model::Version,
     ) -> Self {
         db_dump::CrateVersion {
@@ -82,6 +83,7 @@ impl From<csv_model::Version> for db_dump::CrateVersion {
             semver,
             published_by: None,
             is_yanked,
+            checksum,
         }
     }
 }
@@ -99,8 +101,12 @@ impl From<csv_model::Crate> for db_dump::Crate {
             homepage,
             readme,
             repository,
+            badges,
         }: csv_model::Crate,
     ) -> Self {
+        let repository_host = repository
+            .as_deref()
+            .and_then(criner_waste_report::hosting::repository_host);
         db_dump::Crate {
             versions: Vec::new(),
             keywords: Vec::new(),
@@ -117,6 +123,14 @@ impl From<csv_model::Crate> for db_dump::Crate {
             homepage,
             readme,
             repository,
+            badges: badges
+                .into_iter()
+                .map(|b| db_dump::Badge {
+                    badge_type: b.badge_type,
+                    attributes: b.attributes,
+                })
+                .collect(),
+            repository_host,
         }
     }
 }
@@ -175,9 +189,11 @@ impl From<csv_model::UserKind> for db_dump::ActorKind {
     }
 }
 
+/// Convert all `users` and `teams` into actors, with their personally identifiable fields treated according to `redaction`.
 pub fn into_actors_by_id(
     users: BTreeMap<csv_model::Id, csv_model::User>,
     teams: BTreeMap<csv_model::Id, csv_model::Team>,
+    redaction: Redaction,
     mut progress: prodash::tree::Item,
 ) -> BTreeMap<(db_dump::Id, db_dump::ActorKind), db_dump::Actor> {
     progress.init(Some(users.len() + teams.len()), Some("users and teams".into()));
@@ -187,14 +203,16 @@ pub fn into_actors_by_id(
     for (id, actor) in users.into_iter() {
         count += 1;
         progress.set(count);
-        let actor: db_dump::Actor = actor.into();
+        let mut actor: db_dump::Actor = actor.into();
+        redaction.apply(&mut actor);
         map.insert((id, actor.kind), actor);
     }
 
     for (id, actor) in teams.into_iter() {
         count += 1;
         progress.set(count);
-        let actor: db_dump::Actor = actor.into();
+        let mut actor: db_dump::Actor = actor.into();
+        redaction.apply(&mut actor);
         map.insert((id, actor.kind), actor);
     }
 
@@ -247,11 +265,11 @@ pub fn into_versions_by_crate_id(
 #[allow(clippy::too_many_arguments)]
 pub fn into_crates(
     crates: Vec<csv_model::Crate>,
-    mut keywords_by_id: BTreeMap<csv_model::Id, csv_model::Keyword>,
+    keywords_by_id: &BTreeMap<csv_model::Id, csv_model::Keyword>,
     crates_keywords: Vec<csv_model::CratesKeyword>,
-    mut categories_by_id: BTreeMap<csv_model::Id, csv_model::Category>,
+    categories_by_id: &BTreeMap<csv_model::Id, csv_model::Category>,
     crates_categories: Vec<csv_model::CratesCategory>,
-    actors_by_id: BTreeMap<(db_dump::Id, db_dump::ActorKind), db_dump::Actor>,
+    actors_by_id: &BTreeMap<(db_dump::Id, db_dump::ActorKind), db_dump::Actor>,
     crate_owners: Vec<csv_model::CrateOwner>,
     mut versions_by_crate_id: BTreeMap<db_dump::Id, Vec<db_dump::CrateVersion>>,
     mut progress: prodash::tree::Item,
@@ -287,7 +305,7 @@ pub fn into_crates(
             .keywords
             .push(
                 keywords_by_id
-                    .get_mut(&keyword_id)
+                    .get(&keyword_id)
                     .expect("keyword for id")
                     .to_owned()
                     .into(),
@@ -305,7 +323,7 @@ pub fn into_crates(
             .categories
             .push(
                 categories_by_id
-                    .get_mut(&category_id)
+                    .get(&category_id)
                     .expect("category for id")
                     .to_owned()
                     .into(),
@@ -42,6 +42,15 @@ pub struct Crate {
     pub homepage: Option<String>,
     pub readme: Option<String>,
     pub repository: Option<String>,
+    /// Only present in older dumps, as crates.io stopped displaying badges
+    #[serde(default, deserialize_with = "deserialize_badges")]
+    pub badges: Vec<Badge>,
+}
+
+pub struct Badge {
+    /// The kind of badge, like 'travis-ci' or 'maintenance'
+    pub badge_type: String,
+    pub attributes: BTreeMap<String, String>,
 }
 
 pub enum UserKind {
@@ -92,6 +101,32 @@ where
     Ok(val.into_iter().map(|(name, crates)| Feature { name, crates }).collect())
 }
 
+fn deserialize_badges<'de, D>(deserializer: D) -> Result<Vec<Badge>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::Deserialize;
+    let val = std::borrow::Cow::<'de, str>::deserialize(deserializer)?;
+    if val.is_empty() {
+        return Ok(Vec::new());
+    }
+    let val: BTreeMap<String, BTreeMap<String, serde_json::Value>> =
+        serde_json::from_str(&val).map_err(serde::de::Error::custom)?;
+    Ok(val
+        .into_iter()
+        .map(|(badge_type, attributes)| Badge {
+            badge_type,
+            attributes: attributes
+                .into_iter()
+                .map(|(name, value)| match value {
+                    serde_json::Value::String(value) => (name, value),
+                    value => (name, value.to_string()),
+                })
+                .collect(),
+        })
+        .collect())
+}
+
 fn deserialize_yanked<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -136,6 +171,9 @@ pub struct Version {
     pub published_by: Option<UserId>,
     #[serde(deserialize_with = "deserialize_yanked", rename = "yanked")]
     pub is_yanked: bool,
+    /// The hex-encoded SHA-256 checksum of the crate archive, not present in older db dumps
+    #[serde(default)]
+    pub checksum: Option<String>,
 }
 
 #[derive(Deserialize)]
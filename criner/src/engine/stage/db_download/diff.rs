@@ -0,0 +1,185 @@
+//! Compare two db dumps row by row, to learn how the registry changed between them.
+use super::{csv_model, extract, from_csv, Tables, TABLE_NAMES};
+use crate::Result;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+    path::Path,
+};
+
+/// The rows of one table of a db dump by their key, along with a fingerprint of the columns whose changes matter.
+/// Columns that change all the time, like download counts, are not part of it.
+type Rows = BTreeMap<String, u64>;
+
+/// How a table of the db dump changed, with rows identified by their key, like the name of a crate or
+/// `<crate> <owner>` for its owners.
+#[derive(Debug, Default)]
+pub struct TableDiff {
+    pub table: &'static str,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+/// The differences of all tables of two db dumps
+pub struct DumpDiff {
+    pub tables: Vec<TableDiff>,
+    /// The amount of rows of the old and the new db dump respectively which couldn't be decoded and were skipped
+    pub skipped_rows: (usize, usize),
+}
+
+/// Return the differences of all tables between the db dump at `old` and the one at `new`.
+/// Rows which can't be decoded are skipped instead of failing the comparison.
+pub fn diff(old: &Path, new: &Path, progress: &mut prodash::tree::Item) -> Result<DumpDiff> {
+    let (old_rows, old_skipped) = rows_of(old, progress)?;
+    let (new_rows, new_skipped) = rows_of(new, progress)?;
+    let tables = old_rows
+        .into_iter()
+        .zip(new_rows)
+        .map(|((table, old), (_, new))| diff_rows(table, old, new))
+        .collect();
+    Ok(DumpDiff {
+        tables,
+        skipped_rows: (old_skipped, new_skipped),
+    })
+}
+
+fn diff_rows(table: &'static str, old: Rows, mut new: Rows) -> TableDiff {
+    let mut diff = TableDiff {
+        table,
+        ..Default::default()
+    };
+    for (key, old_fingerprint) in old {
+        match new.remove(&key) {
+            None => diff.removed.push(key),
+            Some(new_fingerprint) if new_fingerprint != old_fingerprint => diff.changed.push(key),
+            Some(_) => {}
+        }
+    }
+    diff.added = new.into_iter().map(|(key, _)| key).collect();
+    diff
+}
+
+/// Decode all tables of the db dump at `path` and return their rows, along with the amount of skipped rows.
+/// Only one db dump is kept in memory at a time as they are large.
+fn rows_of(path: &Path, progress: &mut prodash::tree::Item) -> Result<(Vec<(&'static str, Rows)>, usize)> {
+    let mut progress = progress.add_child(format!("decoding '{}'", path.display()));
+    let quarantine = from_csv::Quarantine::new(true);
+    let (tables, _) = extract(&mut progress, path, TABLE_NAMES, &quarantine)?;
+    Ok((rows(tables), quarantine.take().len()))
+}
+
+fn rows(tables: Tables) -> Vec<(&'static str, Rows)> {
+    let Tables {
+        users,
+        teams,
+        versions,
+        crates,
+        keywords,
+        crates_keywords,
+        categories,
+        crates_categories,
+        crate_owners,
+    } = tables;
+    let crate_names: BTreeMap<_, _> = crates.iter().map(|c| (c.id, c.name.as_str())).collect();
+    let crate_name = |id: csv_model::Id| crate_names.get(&id).copied().unwrap_or("<unknown crate>");
+
+    let owners: Rows = crate_owners
+        .iter()
+        .map(|owner| {
+            let login = match owner.owner_kind {
+                csv_model::UserKind::User => users.get(&owner.owner_id).map(|user| user.github_login.as_str()),
+                csv_model::UserKind::Team => teams.get(&owner.owner_id).map(|team| team.github_login.as_str()),
+            };
+            let key = format!("{} {}", crate_name(owner.crate_id), login.unwrap_or("<unknown owner>"));
+            (key, 0)
+        })
+        .collect();
+    let versions: Rows = versions
+        .iter()
+        .map(|version| {
+            let features: Vec<_> = version
+                .features
+                .iter()
+                .map(|feature| (&feature.name, &feature.crates))
+                .collect();
+            (
+                format!("{}@{}", crate_name(version.crate_id), version.semver),
+                fingerprint((
+                    version.is_yanked,
+                    &version.license,
+                    &version.checksum,
+                    version.crate_size,
+                    features,
+                )),
+            )
+        })
+        .collect();
+    let crates_keywords: Rows = crates_keywords
+        .iter()
+        .map(|ck| {
+            let keyword = keywords.get(&ck.keyword_id).map(|k| k.name.as_str());
+            let key = format!("{} {}", crate_name(ck.crate_id), keyword.unwrap_or("<unknown keyword>"));
+            (key, 0)
+        })
+        .collect();
+    let crates_categories: Rows = crates_categories
+        .iter()
+        .map(|cc| {
+            let category = categories.get(&cc.category_id).map(|c| c.slug.as_str());
+            let key = format!(
+                "{} {}",
+                crate_name(cc.crate_id),
+                category.unwrap_or("<unknown category>")
+            );
+            (key, 0)
+        })
+        .collect();
+
+    vec![
+        (
+            "crates",
+            crates
+                .iter()
+                .map(|c| {
+                    (
+                        c.name.clone(),
+                        fingerprint((&c.description, &c.documentation, &c.homepage, &c.readme, &c.repository)),
+                    )
+                })
+                .collect(),
+        ),
+        ("versions", versions),
+        ("crate_owners", owners),
+        (
+            "users",
+            users
+                .values()
+                .map(|user| (user.github_login.clone(), fingerprint(&user.name)))
+                .collect(),
+        ),
+        (
+            "teams",
+            teams
+                .values()
+                .map(|team| (team.github_login.clone(), fingerprint(&team.name)))
+                .collect(),
+        ),
+        ("keywords", keywords.values().map(|k| (k.name.clone(), 0)).collect()),
+        ("crates_keywords", crates_keywords),
+        (
+            "categories",
+            categories
+                .values()
+                .map(|c| (c.slug.clone(), fingerprint((&c.name, &c.description, &c.path))))
+                .collect(),
+        ),
+        ("crates_categories", crates_categories),
+    ]
+}
+
+fn fingerprint(columns: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    columns.hash(&mut hasher);
+    hasher.finish()
+}
@@ -1,5 +1,6 @@
-use super::csv_model;
-use std::collections::BTreeMap;
+use super::{csv_model, schema};
+use crate::{model::QuarantinedRow, Error, Result};
+use std::{collections::BTreeMap, sync::Arc};
 
 pub trait AsId {
     fn as_id(&self) -> csv_model::Id;
@@ -22,11 +23,90 @@ impl_as_id!(User);
 impl_as_id!(Team);
 impl_as_id!(Crate);
 
+/// Collects rows of the db dump which couldn't be decoded to skip them if lenient, instead of failing on the first of them.
+#[derive(Clone, Default)]
+pub struct Quarantine {
+    rows: Option<Arc<parking_lot::Mutex<Vec<QuarantinedRow>>>>,
+}
+
+impl Quarantine {
+    pub fn new(lenient: bool) -> Self {
+        Quarantine {
+            rows: if lenient { Some(Default::default()) } else { None },
+        }
+    }
+
+    /// Quarantine the `record` of `table` starting at `line` which couldn't be decoded due to `err`,
+    /// or return `err` if we are not lenient.
+    pub fn add(
+        &self,
+        table: &str,
+        line: u64,
+        column: Option<String>,
+        record: &csv::ByteRecord,
+        err: Error,
+    ) -> Result<()> {
+        let rows = match self.rows.as_ref() {
+            Some(rows) => rows,
+            None => return Err(err),
+        };
+        let mut content = Vec::new();
+        encode_record(record, &mut content);
+        rows.lock().push(QuarantinedRow {
+            table: table.to_owned(),
+            line,
+            column,
+            content,
+            error: err.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Return all rows quarantined so far, leaving none behind.
+    pub fn take(&self) -> Vec<QuarantinedRow> {
+        self.rows
+            .as_ref()
+            .map(|rows| std::mem::take(&mut *rows.lock()))
+            .unwrap_or_default()
+    }
+}
+
+/// Return the name of the column the deserialization error `err` occurred in, if known.
+pub fn failed_column(err: &csv::Error, headers: &csv::ByteRecord) -> Option<String> {
+    match err.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => err
+            .field()
+            .and_then(|index| headers.get(index as usize))
+            .map(|column| String::from_utf8_lossy(column).into_owned()),
+        _ => None,
+    }
+}
+
+/// Encode `record` as a single line of CSV with all fields quoted.
+pub fn encode_record(record: &csv::ByteRecord, out: &mut Vec<u8>) {
+    for (index, field) in record.iter().enumerate() {
+        if index != 0 {
+            out.push(b',');
+        }
+        out.push(b'"');
+        for &byte in field {
+            if byte == b'"' {
+                out.push(b'"');
+            }
+            out.push(byte);
+        }
+        out.push(b'"');
+    }
+    out.push(b'\n');
+}
+
 pub fn records<T>(
     csv: impl std::io::Read,
+    name: &'static str,
     progress: &mut prodash::tree::Item,
+    quarantine: &Quarantine,
     mut cb: impl FnMut(T),
-) -> crate::Result<()>
+) -> Result<()>
 where
     T: serde::de::DeserializeOwned,
 {
@@ -35,8 +115,18 @@ where
         .has_headers(true)
         .flexible(true)
         .from_reader(csv);
-    for item in rd.deserialize() {
-        cb(item?);
+    let headers = rd.byte_headers()?.clone();
+    schema::check(name, schema::columns::<T>(), &headers, progress)?;
+    let mut record = csv::ByteRecord::new();
+    while rd.read_byte_record(&mut record)? {
+        match record.deserialize(Some(&headers)) {
+            Ok(item) => cb(item),
+            Err(err) => {
+                let line = record.position().map(|p| p.line()).unwrap_or_default();
+                let column = failed_column(&err, &headers);
+                quarantine.add(name, line, column, &record, err.into())?;
+            }
+        }
         progress.inc();
     }
     Ok(())
@@ -46,28 +136,34 @@ pub fn mapping<T>(
     rd: impl std::io::Read,
     name: &'static str,
     progress: &mut prodash::tree::Item,
-) -> crate::Result<BTreeMap<csv_model::Id, T>>
+    quarantine: &Quarantine,
+) -> Result<BTreeMap<csv_model::Id, T>>
 where
     T: serde::de::DeserializeOwned + AsId,
 {
     let mut decode = progress.add_child("decoding");
     decode.init(None, Some(name.into()));
     let mut map = BTreeMap::new();
-    records(rd, &mut decode, |v: T| {
+    records(rd, name, &mut decode, quarantine, |v: T| {
         map.insert(v.as_id(), v);
     })?;
     decode.info(format!("Decoded {} {} into memory", map.len(), name));
     Ok(map)
 }
 
-pub fn vec<T>(rd: impl std::io::Read, name: &'static str, progress: &mut prodash::tree::Item) -> crate::Result<Vec<T>>
+pub fn vec<T>(
+    rd: impl std::io::Read,
+    name: &'static str,
+    progress: &mut prodash::tree::Item,
+    quarantine: &Quarantine,
+) -> Result<Vec<T>>
 where
     T: serde::de::DeserializeOwned,
 {
     let mut decode = progress.add_child("decoding");
     decode.init(None, Some(name.into()));
     let mut vec = Vec::new();
-    records(rd, &mut decode, |v: T| {
+    records(rd, name, &mut decode, quarantine, |v: T| {
         vec.push(v);
     })?;
     vec.shrink_to_fit();
@@ -1,52 +1,263 @@
-use crate::model::db_dump;
+use crate::model::{self, db_dump};
 use crate::{
-    engine::work, persistence::new_key_value_insertion, persistence::Db, persistence::TableAccess, Error, Result,
+    engine::{
+        run::{DbDownloadSettings, DownloadSettings},
+        work::{self, queue},
+    },
+    persistence::new_key_value_insertion,
+    persistence::CrateCategoryTable,
+    persistence::CrateKeywordTable,
+    persistence::CrateOwnerTable,
+    persistence::Db,
+    persistence::TableAccess,
+    persistence::TaskTable,
+    persistence::{key_value_iter, new_key_value_query_old_to_new, CrateTable, CrateVersionTable},
+    redact::Redaction,
+    Error, Result,
 };
 use bytesize::ByteSize;
 use futures_util::FutureExt;
-use rusqlite::params;
 use rusqlite::TransactionBehavior;
-use std::{collections::BTreeMap, fs::File, io::BufReader, path::PathBuf};
+use rusqlite::{params, OptionalExtension};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
+mod archive;
 mod convert;
 mod csv_model;
+pub mod diff;
 mod from_csv;
+mod parallel;
+mod schema;
+#[cfg(test)]
+mod schema_test;
+mod staged;
+
+/// The db dump is large and served by a CDN which may stall for a while, so it's not limited in total
+const DB_DUMP_TIMEOUTS: work::iobound::Timeouts = work::iobound::Timeouts {
+    connect: Duration::from_secs(30),
+    read: Duration::from_secs(60),
+    total: None,
+};
+
+/// The names of all tables in the db dump we can ingest, without their `.csv` extension
+const TABLE_NAMES: &[&str] = &[
+    "crates",
+    "crate_owners",
+    "versions",
+    "crates_categories",
+    "categories",
+    "crates_keywords",
+    "keywords",
+    "users",
+    "teams",
+];
+
+/// Tables without which no crate can be assembled, and which are always ingested
+const REQUIRED_TABLE_NAMES: &[&str] = &["crates", "versions"];
+
+/// Tables associating crates with the entries of another table, along with that table, which must be ingested too
+const JOIN_TABLE_NAMES: &[(&str, &str)] = &[("crates_keywords", "keywords"), ("crates_categories", "categories")];
+
+/// Return the tables to ingest as configured by `names`, or all of them if `names` is empty.
+fn selected_tables(names: &[String]) -> Result<Vec<&'static str>> {
+    if names.is_empty() {
+        return Ok(TABLE_NAMES.to_vec());
+    }
+    let mut tables = REQUIRED_TABLE_NAMES.to_vec();
+    for name in names {
+        let table = TABLE_NAMES
+            .iter()
+            .find(|table| **table == name.as_str())
+            .ok_or_else(|| {
+                Error::Message(format!(
+                    "Cannot ingest unknown table '{}' of the db dump, known tables are {}",
+                    name,
+                    TABLE_NAMES.join(", ")
+                ))
+            })?;
+        if !tables.contains(table) {
+            tables.push(*table);
+        }
+    }
+    for (join_table, table) in JOIN_TABLE_NAMES {
+        if tables.contains(join_table) && !tables.contains(table) {
+            return Err(Error::Message(format!(
+                "Table '{}' of the db dump can only be ingested along with table '{}'",
+                join_table, table
+            )));
+        }
+    }
+    Ok(tables)
+}
+
+/// Return the decoded `table` named `name` if it was found in the db dump, or an empty one if it wasn't selected for
+/// ingestion in `tables`.
+fn selected_or_default<T: Default>(table: Option<T>, name: &str, tables: &[&str]) -> Result<T> {
+    match table {
+        Some(table) => Ok(table),
+        None if !tables.contains(&name) => Ok(T::default()),
+        None => Err(Error::Message(format!("expected {}.csv in crates-io db dump", name))),
+    }
+}
 
+/// Store all `crates` which changed since they were stored from a previous db dump, as most crates don't change from
+/// one day to the next and rewriting all of them would be costly.
 fn store(db: Db, crates: Vec<db_dump::Crate>, mut progress: prodash::tree::Item) -> Result<()> {
     let now = std::time::SystemTime::now();
     let crates_len = crates.len();
+    let mut num_unchanged = 0;
     progress.init(Some(crates_len), Some("crates stored".into()));
     let mut connection = db.open_connection_no_async_with_busy_wait()?;
     let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
     {
+        // Keywords and categories of crates ingested before they were stored in tables of their own are only known
+        // to the crates, which won't be stored again unless they change.
+        let backfill_keywords_and_categories = CrateKeywordTable::is_empty(&transaction)?;
+        let mut select = transaction.prepare("SELECT data FROM 'crates.io-crate' WHERE key = ?1")?;
         let mut insert = new_key_value_insertion("crates.io-crate", &transaction)?;
         for mut krate in crates.into_iter() {
             progress.inc();
             krate.stored_at = now;
+            let stored: Option<Vec<u8>> = select.query_row(params![krate.name], |r| r.get(0)).optional()?;
+            if let Some(stored) = stored.and_then(|data| rmp_serde::from_slice::<db_dump::Crate>(&data).ok()) {
+                if !is_changed(stored, &krate) {
+                    num_unchanged += 1;
+                    if backfill_keywords_and_categories {
+                        store_keywords_and_categories(&transaction, &krate)?;
+                    }
+                    continue;
+                }
+            }
             let data = rmp_serde::to_vec(&krate)?;
             insert.execute(params![krate.name, data])?;
+            CrateOwnerTable::replace_owners(&transaction, &krate.name, &krate.owners)?;
+            store_keywords_and_categories(&transaction, &krate)?;
         }
     }
     transaction.commit()?;
-    progress.done(format!("Stored {} crates in database", crates_len));
+    progress.done(format!(
+        "Stored {} changed crates in database, skipped {} unchanged ones",
+        crates_len - num_unchanged,
+        num_unchanged
+    ));
     Ok(())
 }
 
-fn extract_and_ingest(db: Db, mut progress: prodash::tree::Item, db_file_path: PathBuf) -> Result<()> {
-    progress.init(None, Some("csv files".into()));
-    let mut archive = tar::Archive::new(libflate::gzip::Decoder::new(BufReader::new(File::open(db_file_path)?))?);
-    let whitelist_names = [
-        "crates",
-        "crate_owners",
-        "versions",
-        "crates_categories",
-        "categories",
-        "crates_keywords",
-        "keywords",
-        "users",
-        "teams",
-    ];
+/// Set the time at which versions were published to the time they were created at according to the db dump, if it
+/// isn't known yet, like for versions fetched from the sparse index or stored before publish times were recorded.
+fn backfill_publish_times(db: &Db, progress: &mut prodash::tree::Item) -> Result<()> {
+    let mut connection = db.open_connection_no_async_with_busy_wait()?;
+    let crates: Vec<(String, model::Crate)> = {
+        progress.blocked("finding versions without publish time", None);
+        let mut statement = new_key_value_query_old_to_new(CrateTable::table_name(), &connection)?;
+        let iter = key_value_iter::<model::Crate>(&mut statement)?.flat_map(Result::ok);
+        iter.filter(|(_, krate)| krate.versions.len() != krate.publish_times.len())
+            .collect()
+    };
+    progress.init(Some(crates.len()), Some("crates".into()));
+    let mut num_versions = 0;
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    {
+        let mut select_dump_crate = transaction.prepare("SELECT data FROM 'crates.io-crate' WHERE key = ?1")?;
+        let mut select_version = transaction.prepare(&format!(
+            "SELECT data FROM {} WHERE key = ?1",
+            CrateVersionTable::table_name()
+        ))?;
+        let mut insert_version = new_key_value_insertion(CrateVersionTable::table_name(), &transaction)?;
+        let mut insert_crate = new_key_value_insertion(CrateTable::table_name(), &transaction)?;
+        let mut key_buf = String::new();
+        for (crate_name, mut krate) in crates {
+            progress.inc();
+            let stored: Option<Vec<u8>> = select_dump_crate
+                .query_row(params![crate_name], |r| r.get(0))
+                .optional()?;
+            let dump_crate = match stored {
+                Some(data) => rmp_serde::from_slice::<db_dump::Crate>(&data)?,
+                None => continue,
+            };
+            let mut is_changed = false;
+            for dump_version in dump_crate.versions {
+                if !krate.versions.contains(&dump_version.semver)
+                    || krate.publish_times.contains_key(&dump_version.semver)
+                {
+                    continue;
+                }
+                key_buf.clear();
+                model::CrateVersion::key_from(&crate_name, &dump_version.semver, &mut key_buf);
+                let data: Option<Vec<u8>> = select_version
+                    .query_row(params![key_buf.as_str()], |r| r.get(0))
+                    .optional()?;
+                if let Some(data) = data {
+                    let mut version = model::CrateVersion::from(data.as_slice());
+                    version.published_at = Some(dump_version.created_at);
+                    insert_version.execute(params![key_buf.as_str(), rmp_serde::to_vec(&version)?])?;
+                }
+                krate.publish_times.insert(dump_version.semver, dump_version.created_at);
+                num_versions += 1;
+                is_changed = true;
+            }
+            if is_changed {
+                insert_crate.execute(params![crate_name, rmp_serde::to_vec(&krate)?])?;
+            }
+        }
+    }
+    transaction.commit()?;
+    progress.done(format!("Set the publish time of {} crate versions", num_versions));
+    Ok(())
+}
 
+fn store_keywords_and_categories(transaction: &rusqlite::Transaction, krate: &db_dump::Crate) -> Result<()> {
+    CrateKeywordTable::replace_keywords(transaction, &krate.name, &krate.keywords)?;
+    CrateCategoryTable::replace_categories(transaction, &krate.name, &krate.categories)
+}
+
+/// Return true if `krate` differs from the `stored` crate in more than the time it was stored and its download counts.
+/// These change daily for most crates, and are thus only updated along with other changes.
+fn is_changed(mut stored: db_dump::Crate, krate: &db_dump::Crate) -> bool {
+    stored.stored_at = krate.stored_at;
+    stored.downloads = krate.downloads;
+    if stored.versions.len() == krate.versions.len() {
+        for (stored, version) in stored.versions.iter_mut().zip(krate.versions.iter()) {
+            stored.downloads = version.downloads;
+        }
+    }
+    stored != *krate
+}
+
+/// Figures about the ingestion of a db dump, to record them in the context
+struct IngestStats {
+    /// The amount of rows read per table
+    rows: BTreeMap<String, u64>,
+    /// The amount of uncompressed bytes read from the db dump
+    bytes: u64,
+}
+
+fn extract_and_ingest(
+    db: Db,
+    mut progress: prodash::tree::Item,
+    db_file_path: PathBuf,
+    tables: &[&'static str],
+    quarantine: &from_csv::Quarantine,
+    redaction: Redaction,
+) -> Result<IngestStats> {
+    let (tables, stats) = extract(&mut progress, &db_file_path, tables, quarantine)?;
+    transform_and_store(db, progress, tables, redaction)?;
+    Ok(stats)
+}
+
+/// Decode the selected `tables` of the db dump at `db_file_path` into memory.
+fn extract(
+    progress: &mut prodash::tree::Item,
+    db_file_path: &Path,
+    tables: &[&'static str],
+    quarantine: &from_csv::Quarantine,
+) -> Result<(Tables, IngestStats)> {
+    progress.init(None, Some("csv files".into()));
+    let mut archive = archive::open_with_progress(db_file_path, progress.add_child("decompressing"))?;
     let mut num_files_seen = 0;
     let mut num_bytes_seen = 0;
     let mut teams = None::<BTreeMap<csv_model::Id, csv_model::Team>>;
@@ -70,7 +281,7 @@ fn extract_and_ingest(db: Db, mut progress: prodash::tree::Item, db_file_path: P
         if let Some(name) = entry
             .path()
             .ok()
-            .and_then(|p| whitelist_names.iter().find(|n| p.ends_with(format!("{}.csv", n))))
+            .and_then(|p| tables.iter().find(|n| p.ends_with(format!("{}.csv", n))))
         {
             let done_msg = format!(
                 "extracted '{}' with size {}",
@@ -78,30 +289,30 @@ fn extract_and_ingest(db: Db, mut progress: prodash::tree::Item, db_file_path: P
                 ByteSize(entry_size)
             );
             match *name {
-                "teams" => teams = Some(from_csv::mapping(entry, name, &mut progress)?),
+                "teams" => teams = Some(from_csv::mapping(entry, name, progress, quarantine)?),
                 "categories" => {
-                    categories = Some(from_csv::mapping(entry, "categories", &mut progress)?);
+                    categories = Some(from_csv::mapping(entry, "categories", progress, quarantine)?);
                 }
                 "versions" => {
-                    versions = Some(from_csv::vec(entry, "versions", &mut progress)?);
+                    versions = Some(from_csv::vec(entry, "versions", progress, quarantine)?);
                 }
                 "keywords" => {
-                    keywords = Some(from_csv::mapping(entry, "keywords", &mut progress)?);
+                    keywords = Some(from_csv::mapping(entry, "keywords", progress, quarantine)?);
                 }
                 "users" => {
-                    users = Some(from_csv::mapping(entry, "users", &mut progress)?);
+                    users = Some(from_csv::mapping(entry, "users", progress, quarantine)?);
                 }
                 "crates" => {
-                    crates = Some(from_csv::vec(entry, "crates", &mut progress)?);
+                    crates = Some(from_csv::vec(entry, "crates", progress, quarantine)?);
                 }
                 "crate_owners" => {
-                    crate_owners = Some(from_csv::vec(entry, "crate_owners", &mut progress)?);
+                    crate_owners = Some(from_csv::vec(entry, "crate_owners", progress, quarantine)?);
                 }
                 "crates_categories" => {
-                    crates_categories = Some(from_csv::vec(entry, "crates_categories", &mut progress)?);
+                    crates_categories = Some(from_csv::vec(entry, "crates_categories", progress, quarantine)?);
                 }
                 "crates_keywords" => {
-                    crates_keywords = Some(from_csv::vec(entry, "crates_keywords", &mut progress)?);
+                    crates_keywords = Some(from_csv::vec(entry, "crates_keywords", progress, quarantine)?);
                 }
                 _ => progress.fail(format!("bug or oversight: Could not parse table of type {:?}", name)),
             }
@@ -114,21 +325,75 @@ fn extract_and_ingest(db: Db, mut progress: prodash::tree::Item, db_file_path: P
         ByteSize(num_bytes_seen)
     ));
 
-    let users = users.ok_or(Error::Bug("expected users.csv in crates-io db dump"))?;
-    let teams = teams.ok_or(Error::Bug("expected teams.csv in crates-io db dump"))?;
-    let versions = versions.ok_or(Error::Bug("expected versions.csv in crates-io db dump"))?;
-    let crates = crates.ok_or(Error::Bug("expected crates.csv in crates-io db dump"))?;
-    let keywords = keywords.ok_or(Error::Bug("expected keywords.csv in crates-io db dump"))?;
-    let crates_keywords = crates_keywords.ok_or(Error::Bug("expected crates_keywords.csv in crates-io db dump"))?;
-    let categories = categories.ok_or(Error::Bug("expected categories.csv in crates-io db dump"))?;
-    let crates_categories =
-        crates_categories.ok_or(Error::Bug("expected crates_categories.csv in crates-io db dump"))?;
-    let crate_owners = crate_owners.ok_or(Error::Bug("expected crate_owners.csv in crates-io db dump"))?;
+    let selected = tables;
+    let tables = Tables {
+        users: selected_or_default(users, "users", selected)?,
+        teams: selected_or_default(teams, "teams", selected)?,
+        versions: selected_or_default(versions, "versions", selected)?,
+        crates: selected_or_default(crates, "crates", selected)?,
+        keywords: selected_or_default(keywords, "keywords", selected)?,
+        crates_keywords: selected_or_default(crates_keywords, "crates_keywords", selected)?,
+        categories: selected_or_default(categories, "categories", selected)?,
+        crates_categories: selected_or_default(crates_categories, "crates_categories", selected)?,
+        crate_owners: selected_or_default(crate_owners, "crate_owners", selected)?,
+    };
+    let stats = IngestStats {
+        rows: tables.rows(selected),
+        bytes: num_bytes_seen,
+    };
+    Ok((tables, stats))
+}
+
+/// All tables of the db dump needed to assemble crates, decoded into memory.
+struct Tables {
+    users: BTreeMap<csv_model::Id, csv_model::User>,
+    teams: BTreeMap<csv_model::Id, csv_model::Team>,
+    versions: Vec<csv_model::Version>,
+    crates: Vec<csv_model::Crate>,
+    keywords: BTreeMap<csv_model::Id, csv_model::Keyword>,
+    crates_keywords: Vec<csv_model::CratesKeyword>,
+    categories: BTreeMap<csv_model::Id, csv_model::Category>,
+    crates_categories: Vec<csv_model::CratesCategory>,
+    crate_owners: Vec<csv_model::CrateOwner>,
+}
+
+impl Tables {
+    /// Return the amount of rows per table, for all tables selected for ingestion in `selected`
+    fn rows(&self, selected: &[&str]) -> BTreeMap<String, u64> {
+        [
+            ("users", self.users.len()),
+            ("teams", self.teams.len()),
+            ("versions", self.versions.len()),
+            ("crates", self.crates.len()),
+            ("keywords", self.keywords.len()),
+            ("crates_keywords", self.crates_keywords.len()),
+            ("categories", self.categories.len()),
+            ("crates_categories", self.crates_categories.len()),
+            ("crate_owners", self.crate_owners.len()),
+        ]
+        .iter()
+        .filter(|(table, _)| selected.contains(table))
+        .map(|(table, rows)| ((*table).to_owned(), *rows as u64))
+        .collect()
+    }
+}
 
+fn transform_and_store(db: Db, mut progress: prodash::tree::Item, tables: Tables, redaction: Redaction) -> Result<()> {
+    let Tables {
+        users,
+        teams,
+        versions,
+        crates,
+        keywords,
+        crates_keywords,
+        categories,
+        crates_categories,
+        crate_owners,
+    } = tables;
     progress.init(Some(4), Some("conversion steps".into()));
     progress.set_name("transform actors");
     progress.set(1);
-    let actors_by_id = convert::into_actors_by_id(users, teams, progress.add_child("actors"));
+    let actors_by_id = convert::into_actors_by_id(users, teams, redaction, progress.add_child("actors"));
 
     progress.set_name("transform versions");
     progress.set(2);
@@ -139,11 +404,11 @@ fn extract_and_ingest(db: Db, mut progress: prodash::tree::Item, db_file_path: P
     progress.set(3);
     let crates = convert::into_crates(
         crates,
-        keywords,
+        &keywords,
         crates_keywords,
-        categories,
+        &categories,
         crates_categories,
-        actors_by_id,
+        &actors_by_id,
         crate_owners,
         versions_by_crate_id,
         progress.add_child("crates"),
@@ -154,7 +419,7 @@ fn extract_and_ingest(db: Db, mut progress: prodash::tree::Item, db_file_path: P
     store(db, crates, progress.add_child("persist"))
 }
 
-fn cleanup(db_file_path: PathBuf, mut progress: prodash::tree::Item) -> Result<()> {
+fn cleanup(db_file_path: PathBuf, settings: &DbDownloadSettings, mut progress: prodash::tree::Item) -> Result<()> {
     let glob_pattern = db_file_path
         .parent()
         .expect("parent directory for db dump")
@@ -169,35 +434,185 @@ fn cleanup(db_file_path: PathBuf, mut progress: prodash::tree::Item) -> Result<(
         )));
     }
 
-    for file in glob::glob(pattern.as_str())? {
-        let file = file?;
-        if file != db_file_path {
-            std::fs::remove_file(&file)?;
-            progress.done(format!("Deleted old db-dump at '{}'", file.display()));
+    let mut files = glob::glob(pattern.as_str())?.collect::<std::result::Result<Vec<_>, _>>()?;
+    // File names start with the date, so this sorts them from newest to oldest
+    files.sort_by(|a, b| b.file_name().cmp(&a.file_name()));
+    let today = time::OffsetDateTime::now_utc().date();
+    for (index, file) in files.into_iter().enumerate() {
+        if file == db_file_path || index < settings.keep_last.max(1) {
+            continue;
+        }
+        let is_recent = settings.keep_for.map_or(false, |keep_for| {
+            file.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.get(..10))
+                .and_then(|yyyy_mm_dd| time::Date::parse(yyyy_mm_dd, "%F").ok())
+                .map_or(false, |date| (today - date).whole_seconds() < keep_for.as_secs() as i64)
+        });
+        if is_recent {
+            continue;
+        }
+        std::fs::remove_file(&file)?;
+        progress.done(format!("Deleted old db-dump at '{}'", file.display()));
+        if let Some(csv_dir) = file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.get(..10))
+            .map(|yyyy_mm_dd| file.with_file_name(yyyy_mm_dd))
+            .filter(|dir| dir.is_dir())
+        {
+            std::fs::remove_dir_all(&csv_dir)?;
+            progress.done(format!("Deleted CSV files of old db-dump at '{}'", csv_dir.display()));
         }
     }
     Ok(())
 }
 
+/// Remove the db dump at `db_file_path` if its archive is truncated or corrupt, and record the failure in the download
+/// task at `task_key`. Otherwise the next run would resume its download, which would find it complete and ingest
+/// the same broken file again.
+async fn discard_if_corrupt(
+    tasks: &TaskTable,
+    task_key: &str,
+    db_file_path: PathBuf,
+    progress: &mut prodash::tree::Item,
+) -> Result<()> {
+    progress.blocked("verifying db dump archive", None);
+    let corruption = match blocking::unblock({
+        let db_file_path = db_file_path.clone();
+        move || archive::verify(&db_file_path)
+    })
+    .await
+    {
+        Ok(()) => return Ok(()),
+        Err(err) => err,
+    };
+    std::fs::remove_file(&db_file_path)?;
+    let msg = format!(
+        "Removed truncated or corrupt db dump at '{}' to download it anew: {}",
+        db_file_path.display(),
+        corruption
+    );
+    progress.fail(msg.clone());
+    tasks.upsert(
+        progress,
+        task_key,
+        &model::Task {
+            state: model::TaskState::AttemptsWithFailure(vec![msg]),
+            ..work::iobound::default_persisted_download_task()
+        },
+    )?;
+    Ok(())
+}
+
+/// Replace the rows quarantined while ingesting the previous db dump with the ones in `quarantine`.
+fn store_quarantined_rows(
+    db: &Db,
+    quarantine: &from_csv::Quarantine,
+    progress: &mut prodash::tree::Item,
+) -> Result<()> {
+    let rows = quarantine.take();
+    if !rows.is_empty() {
+        progress.fail(format!(
+            "Skipped {} rows which couldn't be decoded, see the '{}' table",
+            rows.len(),
+            crate::persistence::QuarantineTable::table_name()
+        ));
+    }
+    db.open_db_dump_quarantine()?.replace_all(&rows)
+}
+
+/// Record all crate versions the crates.io index and the freshly ingested db dump disagree about.
+fn cross_check_versions(db: Db, progress: &mut prodash::tree::Item) -> Result<()> {
+    let connection = db.open_connection_no_async_with_busy_wait()?;
+    let discrepancies = super::reconcile::cross_check_versions(&connection, progress)?;
+    db.open_version_discrepancies()?.replace_all(&discrepancies)
+}
+
+/// Extract the tables of the db dump at `db_file_path` into `csv_dir` to keep them,
+/// and report how much space all retained CSV files next to it take on disk.
+fn retain_csv(
+    db_file_path: PathBuf,
+    csv_dir: PathBuf,
+    tables: &[&str],
+    mut progress: prodash::tree::Item,
+) -> Result<()> {
+    if csv_dir.is_dir() {
+        std::fs::remove_dir_all(&csv_dir)?;
+    }
+    let bytes = parallel::extract(&db_file_path, &csv_dir, tables, &mut progress)?;
+    progress.done(format!(
+        "Retained {} of CSV files in '{}'",
+        ByteSize(bytes),
+        csv_dir.display()
+    ));
+    report_retained_csv_usage(&csv_dir, &mut progress)
+}
+
+fn report_retained_csv_usage(csv_dir: &Path, progress: &mut prodash::tree::Item) -> Result<()> {
+    let mut num_dirs = 0;
+    let mut total_bytes = 0;
+    for entry in std::fs::read_dir(csv_dir.parent().expect("parent directory for retained CSV files"))? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        num_dirs += 1;
+        for file in std::fs::read_dir(entry.path())? {
+            total_bytes += file?.metadata()?.len();
+        }
+    }
+    progress.info(format!(
+        "Retained CSV files of {} db-dumps take {} on disk",
+        num_dirs,
+        ByteSize(total_bytes)
+    ));
+    Ok(())
+}
+
+/// Return the date of the most recent db dump, which is published daily at `published_at_utc` if known.
+/// Otherwise it's assumed to be today's, which is wrong for a db dump downloaded before it is published on the same day.
+fn most_recent_dump_date(published_at_utc: Option<time::Time>) -> time::Date {
+    match published_at_utc {
+        Some(published_at) => {
+            let now = time::OffsetDateTime::now_utc();
+            if now.time() < published_at {
+                now.date().previous_day()
+            } else {
+                now.date()
+            }
+        }
+        None => time::OffsetDateTime::try_now_local()
+            .unwrap_or_else(|_| time::OffsetDateTime::now_utc())
+            .date(),
+    }
+}
+
 pub async fn schedule(
     db: Db,
     assets_dir: PathBuf,
     mut progress: prodash::tree::Item,
     startup_time: std::time::SystemTime,
+    settings: DbDownloadSettings,
+    download_settings: DownloadSettings,
+    deadline: Option<std::time::SystemTime>,
 ) -> Result<()> {
     let (tx_result, rx_result) = async_channel::bounded(1);
     let tx_io = {
-        let (tx_io, rx) = async_channel::bounded(1);
-        let max_retries_on_timeout = 80;
+        let (tx_io, rx) = queue::persistent(&db, "crates-io-db-dump download", 1)?;
+        // Only the most recent db dump is of interest, and it's always requested anew
+        tx_io.clear()?;
+        let clients = work::iobound::Clients::new(&download_settings, deadline)?;
         crate::spawn(
             work::generic::processor(
                 db.clone(),
                 progress.add_child("↓ IDLE"),
                 rx,
-                work::iobound::Agent::new(&db, tx_result, {
+                work::iobound::Agent::new(&db, clients, None, None, tx_result, {
                     move |_, _, output_file_path| Some(output_file_path.to_path_buf())
                 })?,
-                max_retries_on_timeout,
+                work::generic::RetryPolicy::download(80),
+                None,
             )
             .map(|r| {
                 if let Err(e) = r {
@@ -209,51 +624,190 @@ pub async fn schedule(
         tx_io
     };
 
-    let today_yyyy_mm_dd = time::OffsetDateTime::try_now_local()
-        .unwrap_or_else(|_| time::OffsetDateTime::now_utc())
-        .format("%F");
+    let dump_yyyy_mm_dd = most_recent_dump_date(settings.published_at_utc).format("%F");
     let file_suffix = "db-dump.tar.gz";
     let task_key = format!(
         "{}{}{}",
         "crates-io-db-dump",
         crate::persistence::KEY_SEP_CHAR,
-        today_yyyy_mm_dd
+        dump_yyyy_mm_dd
+    );
+    // The validators of the last dump we ingested, to not download and ingest it again while it doesn't change
+    let last_ingested_key = format!(
+        "{}{}{}",
+        "crates-io-db-dump",
+        crate::persistence::KEY_SEP_CHAR,
+        "last-ingested"
     );
 
     let db_file_path = assets_dir
         .join("crates-io-db")
-        .join(format!("{}-{}", today_yyyy_mm_dd, file_suffix));
+        .join(format!("{}-{}", dump_yyyy_mm_dd, file_suffix));
     let tasks = db.open_tasks()?;
     if tasks
         .get(&task_key)?
         .map(|t| t.can_be_started(startup_time) || t.state.is_complete()) // always allow the extractor to run - must be idempotent
         .unwrap_or(true)
     {
+        let validators = tasks
+            .get(&last_ingested_key)?
+            .and_then(|t| t.validators)
+            .unwrap_or_default();
         tx_io
             .send(work::iobound::DownloadRequest {
                 output_file_path: db_file_path.clone(),
                 progress_name: "db dump".to_string(),
-                task_key,
+                task_key: task_key.clone(),
                 crate_name_and_version: None,
-                kind: "tar.gz",
-                url: "https://static.crates.io/db-dump.tar.gz".to_string(),
+                kind: "tar.gz".into(),
+                url: settings.url.clone(),
+                fallback_urls: settings.mirrors.clone(),
+                expected_sha256: None,
+                validators: Some(validators),
+                timeouts: DB_DUMP_TIMEOUTS,
             })
-            .await
-            .map_err(Error::send_msg("Download Request"))?;
+            .await?;
         drop(tx_io);
         if let Ok(db_file_path) = rx_result.recv().await {
-            blocking::unblock({
-                let progress = progress.add_child("ingest");
-                move || extract_and_ingest(db, progress, db_file_path)
-            })
-            .await
-            .map_err(|err| {
-                progress.fail(format!("ingestion failed: {}", err));
-                err
-            })?;
+            let retained_csv_dir = if settings.retain_csv {
+                Some(assets_dir.join("crates-io-db").join(&dump_yyyy_mm_dd))
+            } else {
+                None
+            };
+            if let Err(err) = ingest(db, &mut progress, db_file_path.clone(), &settings, retained_csv_dir).await {
+                discard_if_corrupt(&tasks, &task_key, db_file_path, &mut progress).await?;
+                return Err(err);
+            }
+            if let Some(validators) = tasks.get(&task_key)?.and_then(|t| t.validators) {
+                tasks.upsert(
+                    &mut progress,
+                    &last_ingested_key,
+                    &model::Task {
+                        state: model::TaskState::Complete,
+                        validators: Some(validators),
+                        ..work::iobound::default_persisted_download_task()
+                    },
+                )?;
+            }
+
+            blocking::unblock(move || cleanup(db_file_path, &settings, progress.add_child("removing old db-dumps")))
+                .await?;
         }
     }
+    Ok(())
+}
 
-    blocking::unblock(move || cleanup(db_file_path, progress.add_child("removing old db-dumps"))).await?;
+/// Ingest the db dump at `db_file_path` which was downloaded previously, without touching the download task and
+/// without removing old db dumps. Use it to work on the ingestion without network access.
+///
+/// CSV files are not retained, as they are stored next to downloaded db dumps only.
+pub async fn ingest_local(
+    db: Db,
+    mut progress: prodash::tree::Item,
+    db_file_path: PathBuf,
+    settings: DbDownloadSettings,
+) -> Result<()> {
+    if !db_file_path.is_file() {
+        return Err(Error::Message(format!(
+            "There is no db dump at '{}'",
+            db_file_path.display()
+        )));
+    }
+    ingest(db, &mut progress, db_file_path.clone(), &settings, None).await?;
+    progress.done(format!("Ingested db dump at '{}'", db_file_path.display()));
+    Ok(())
+}
+
+/// Ingest the db dump at `db_file_path` as configured by `settings`, extracting its tables into `retained_csv_dir`
+/// if set, and cross-check its versions with the index afterwards.
+async fn ingest(
+    db: Db,
+    progress: &mut prodash::tree::Item,
+    db_file_path: PathBuf,
+    settings: &DbDownloadSettings,
+    retained_csv_dir: Option<PathBuf>,
+) -> Result<()> {
+    let (memory_budget, parallel) = (settings.ingest_memory_budget, settings.parallel_ingestion);
+    let tables = selected_tables(&settings.tables)?;
+    progress.info(format!("Ingesting tables {}", tables.join(", ")));
+    // Parallel ingestion extracts all tables anyway and can keep them, otherwise they are extracted while ingesting
+    let retain_csv = match retained_csv_dir.clone() {
+        Some(csv_dir) if memory_budget.is_some() || !parallel => Some(blocking::unblock({
+            let db_file_path = db_file_path.clone();
+            let tables = tables.clone();
+            let progress = progress.add_child("retain csv");
+            move || retain_csv(db_file_path, csv_dir, &tables, progress)
+        })),
+        _ => None,
+    };
+
+    let started_at = std::time::SystemTime::now();
+    let quarantine = from_csv::Quarantine::new(settings.lenient_csv);
+    let redaction = settings.redaction;
+    let stats = blocking::unblock({
+        let db = db.clone();
+        let progress = progress.add_child("ingest");
+        let retained_csv_dir = retained_csv_dir.clone();
+        let quarantine = quarantine.clone();
+        move || match memory_budget {
+            Some(memory_budget) => staged::extract_and_ingest(
+                db,
+                progress,
+                db_file_path,
+                &tables,
+                memory_budget,
+                &quarantine,
+                redaction,
+            ),
+            None if parallel => parallel::extract_and_ingest(
+                db,
+                progress,
+                db_file_path,
+                &tables,
+                retained_csv_dir,
+                &quarantine,
+                redaction,
+            ),
+            None => extract_and_ingest(db, progress, db_file_path, &tables, &quarantine, redaction),
+        }
+    })
+    .await
+    .map_err(|err| {
+        progress.fail(format!("ingestion failed: {}", err));
+        err
+    })?;
+    let ingest_duration = started_at.elapsed().unwrap_or_default();
+    store_quarantined_rows(&db, &quarantine, progress)?;
+    match (retain_csv, retained_csv_dir) {
+        (Some(retain_csv), _) => retain_csv.await?,
+        (None, Some(csv_dir)) => {
+            let mut progress = progress.add_child("retain csv");
+            blocking::unblock(move || report_retained_csv_usage(&csv_dir, &mut progress)).await?
+        }
+        (None, None) => {}
+    }
+    db.open_context()?.update_today(|c| {
+        for (table, rows) in &stats.rows {
+            *c.counts.db_dump_rows.entry(table.clone()).or_default() += rows;
+        }
+        c.counts.db_dump_bytes += stats.bytes;
+        c.durations.ingest_db_dump += ingest_duration;
+    })?;
+
+    blocking::unblock({
+        let db = db.clone();
+        let mut progress = progress.add_child("backfilling publish times");
+        move || backfill_publish_times(&db, &mut progress)
+    })
+    .await?;
+
+    if let Err(err) = blocking::unblock({
+        let mut progress = progress.add_child("cross-checking versions with index");
+        move || cross_check_versions(db, &mut progress)
+    })
+    .await
+    {
+        progress.fail(format!("Could not cross-check versions with the index: {}", err));
+    }
     Ok(())
 }
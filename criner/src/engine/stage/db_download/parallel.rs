@@ -0,0 +1,129 @@
+//! Ingestion of the crates.io database dump with all tables decoded in parallel.
+//!
+//! The tables are extracted into a temporary directory first as a tar archive can only be read sequentially.
+use super::{archive, from_csv, transform_and_store, IngestStats, Tables};
+use crate::{persistence::Db, redact::Redaction, Error, Result};
+use bytesize::ByteSize;
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+/// Extract all selected `tables` into a directory next to `db_file_path` to decode them in parallel, and remove it
+/// afterwards, or extract them into `retained_csv_dir` if set and keep them.
+pub fn extract_and_ingest(
+    db: Db,
+    mut progress: prodash::tree::Item,
+    db_file_path: PathBuf,
+    tables: &[&'static str],
+    retained_csv_dir: Option<PathBuf>,
+    quarantine: &from_csv::Quarantine,
+    redaction: Redaction,
+) -> Result<IngestStats> {
+    let retain = retained_csv_dir.is_some();
+    let csv_dir = retained_csv_dir.unwrap_or_else(|| db_file_path.with_extension("csv.d"));
+    if csv_dir.is_dir() {
+        std::fs::remove_dir_all(&csv_dir)?;
+    }
+    let res = extract(&db_file_path, &csv_dir, tables, &mut progress).and_then(|bytes| {
+        let decoded = futures_lite::future::block_on(decode(&csv_dir, tables, quarantine, &mut progress))?;
+        let stats = IngestStats {
+            rows: decoded.rows(tables),
+            bytes,
+        };
+        transform_and_store(db, progress, decoded, redaction)?;
+        Ok(stats)
+    });
+    if !retain {
+        std::fs::remove_dir_all(&csv_dir).ok();
+    }
+    res
+}
+
+/// Extract all selected `tables` into `csv_dir` and return the amount of bytes extracted.
+pub fn extract(
+    db_file_path: &Path,
+    csv_dir: &Path,
+    tables: &[&str],
+    progress: &mut prodash::tree::Item,
+) -> Result<u64> {
+    progress.init(None, Some("csv files".into()));
+    std::fs::create_dir_all(csv_dir)?;
+    let mut archive = archive::open_with_progress(db_file_path, progress.add_child("decompressing"))?;
+    let mut num_files_seen = 0;
+    let mut num_bytes_extracted = 0;
+    for (eid, entry) in archive.entries()?.enumerate() {
+        num_files_seen = eid + 1;
+        progress.set(eid);
+
+        let mut entry = entry?;
+        if let Some(name) = entry
+            .path()
+            .ok()
+            .and_then(|p| tables.iter().find(|n| p.ends_with(format!("{}.csv", n))))
+        {
+            let entry_size = entry.header().size()?;
+            num_bytes_extracted += entry_size;
+            entry.unpack(csv_dir.join(format!("{}.csv", name)))?;
+            progress.done(format!("extracted '{}' with size {}", name, ByteSize(entry_size)));
+        }
+    }
+    progress.done(format!(
+        "Saw {} files and extracted a total of {}",
+        num_files_seen,
+        ByteSize(num_bytes_extracted)
+    ));
+    Ok(num_bytes_extracted)
+}
+
+/// Decode all selected `tables` from `csv_dir`, leaving the others empty.
+async fn decode(
+    csv_dir: &Path,
+    tables: &[&str],
+    quarantine: &from_csv::Quarantine,
+    progress: &mut prodash::tree::Item,
+) -> Result<Tables> {
+    let open = |name: &'static str| -> Result<BufReader<File>> {
+        let path = csv_dir.join(format!("{}.csv", name));
+        if !path.is_file() {
+            return Err(Error::Message(format!("expected {}.csv in crates-io db dump", name)));
+        }
+        Ok(BufReader::new(File::open(path)?))
+    };
+    macro_rules! spawn {
+        ($decode:path, $name:literal) => {{
+            if tables.contains(&$name) {
+                let csv = open($name)?;
+                let mut progress = progress.add_child($name);
+                let quarantine = quarantine.clone();
+                blocking::unblock(move || $decode(csv, $name, &mut progress, &quarantine))
+            } else {
+                blocking::unblock(|| Ok(Default::default()))
+            }
+        }};
+    }
+
+    // All tasks start running right away, in parallel
+    let users = spawn!(from_csv::mapping, "users");
+    let teams = spawn!(from_csv::mapping, "teams");
+    let versions = spawn!(from_csv::vec, "versions");
+    let crates = spawn!(from_csv::vec, "crates");
+    let keywords = spawn!(from_csv::mapping, "keywords");
+    let crates_keywords = spawn!(from_csv::vec, "crates_keywords");
+    let categories = spawn!(from_csv::mapping, "categories");
+    let crates_categories = spawn!(from_csv::vec, "crates_categories");
+    let crate_owners = spawn!(from_csv::vec, "crate_owners");
+
+    Ok(Tables {
+        users: users.await?,
+        teams: teams.await?,
+        versions: versions.await?,
+        crates: crates.await?,
+        keywords: keywords.await?,
+        crates_keywords: crates_keywords.await?,
+        categories: categories.await?,
+        crates_categories: crates_categories.await?,
+        crate_owners: crate_owners.await?,
+    })
+}
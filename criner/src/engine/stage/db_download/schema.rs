@@ -0,0 +1,161 @@
+//! Detect changes to the schema of the crates.io db dump by comparing the headers of its tables with the fields of
+//! our `csv_model`, to fail with a useful message instead of misparsing records or failing without context.
+use super::csv_model;
+use crate::{Error, Result};
+use serde::de::{self, Visitor};
+use std::fmt;
+
+/// Return the names of the columns `T` is deserialized from, which can be expected to exist in the table `T` represents.
+pub fn columns<T>() -> &'static [&'static str]
+where
+    T: de::DeserializeOwned,
+{
+    let mut fields = None;
+    T::deserialize(FieldNames(&mut fields)).ok();
+    fields.unwrap_or_default()
+}
+
+/// Pairs of (table, column) which don't exist in all versions of the db dump, and which we can do without.
+const OPTIONAL_COLUMNS: &[(&str, &str)] = &[("crates", "badges"), ("versions", "checksum")];
+
+/// Return the expected columns of the given `table`, if it is known.
+pub fn expected_columns(table: &str) -> Option<&'static [&'static str]> {
+    Some(match table {
+        "crates" => columns::<csv_model::Crate>(),
+        "crate_owners" => columns::<csv_model::CrateOwner>(),
+        "versions" => columns::<csv_model::Version>(),
+        "crates_categories" => columns::<csv_model::CratesCategory>(),
+        "categories" => columns::<csv_model::Category>(),
+        "crates_keywords" => columns::<csv_model::CratesKeyword>(),
+        "keywords" => columns::<csv_model::Keyword>(),
+        "users" => columns::<csv_model::User>(),
+        "teams" => columns::<csv_model::Team>(),
+        _ => return None,
+    })
+}
+
+/// The difference between the columns we expect and the ones a table actually has
+pub struct Drift {
+    /// Columns we don't know about, which are ignored
+    pub added: Vec<String>,
+    /// Columns we need but which don't exist anymore
+    pub removed: Vec<String>,
+    /// Pairs of (expected, actual) columns which probably were renamed
+    pub renamed: Vec<(String, String)>,
+}
+
+impl Drift {
+    /// Compare the `expected` columns with the actual `headers` of a table.
+    pub fn compute(expected: &[&str], headers: &csv::ByteRecord) -> Drift {
+        let headers: Vec<_> = headers
+            .iter()
+            .map(|h| String::from_utf8_lossy(h).into_owned())
+            .collect();
+        let mut added: Vec<_> = headers
+            .iter()
+            .filter(|h| !expected.contains(&h.as_str()))
+            .cloned()
+            .collect();
+        let mut removed: Vec<_> = expected
+            .iter()
+            .filter(|c| !headers.iter().any(|h| h == *c))
+            .map(|c| (*c).to_owned())
+            .collect();
+        // If as many columns disappeared as appeared, it's most likely that they were renamed.
+        let renamed = if !removed.is_empty() && removed.len() == added.len() {
+            removed.drain(..).zip(added.drain(..)).collect()
+        } else {
+            Vec::new()
+        };
+        Drift {
+            added,
+            removed,
+            renamed,
+        }
+    }
+
+    /// Return true if records can't be decoded anymore due to missing columns.
+    pub fn is_breaking(&self) -> bool {
+        !self.removed.is_empty() || !self.renamed.is_empty()
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if !self.removed.is_empty() {
+            parts.push(format!("removed columns: {}", self.removed.join(", ")));
+        }
+        if !self.renamed.is_empty() {
+            parts.push(format!(
+                "probably renamed columns: {}",
+                self.renamed
+                    .iter()
+                    .map(|(from, to)| format!("{} -> {}", from, to))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        if !self.added.is_empty() {
+            parts.push(format!("added columns: {}", self.added.join(", ")));
+        }
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+/// Compare the `headers` of `table` with the `expected` columns and fail if columns we need are missing.
+/// Columns we don't know about are merely reported in `progress`.
+pub fn check(
+    table: &'static str,
+    expected: &[&str],
+    headers: &csv::ByteRecord,
+    progress: &mut prodash::tree::Item,
+) -> Result<()> {
+    let expected: Vec<_> = expected
+        .iter()
+        .copied()
+        .filter(|column| {
+            !OPTIONAL_COLUMNS.contains(&(table, *column)) || headers.iter().any(|h| h == column.as_bytes())
+        })
+        .collect();
+    let drift = Drift::compute(&expected, headers);
+    if drift.is_breaking() {
+        return Err(Error::SchemaDrift(table, drift.to_string()));
+    }
+    if !drift.added.is_empty() {
+        progress.info(format!("Ignoring unknown columns in table '{}': {}", table, drift));
+    }
+    Ok(())
+}
+
+/// A deserializer which captures the names of the fields of a struct, failing in the process.
+struct FieldNames<'a>(&'a mut Option<&'static [&'static str]>);
+
+impl<'de, 'a> de::Deserializer<'de> for FieldNames<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V>(self, _visitor: V) -> std::result::Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::custom("only structs have field names"))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        *self.0 = Some(fields);
+        Err(de::Error::custom("field names were collected"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf option unit unit_struct
+        newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
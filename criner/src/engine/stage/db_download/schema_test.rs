@@ -0,0 +1,134 @@
+use super::schema::{check, expected_columns, Drift};
+use crate::{Error, Result};
+
+fn headers(columns: &[&str]) -> csv::ByteRecord {
+    csv::ByteRecord::from(columns.to_vec())
+}
+
+/// The expected columns of `table` with `column` replaced by `replacement`, or removed if it is None
+fn columns_with(table: &str, column: &str, replacement: Option<&'static str>) -> Vec<&'static str> {
+    expected_columns(table)
+        .unwrap()
+        .iter()
+        .filter_map(|c| if *c == column { replacement } else { Some(*c) })
+        .collect()
+}
+
+/// Check the actual `columns` of `table` against the columns we expect it to have
+fn check_columns(table: &'static str, columns: &[&str]) -> Result<()> {
+    let mut progress = prodash::TreeOptions::default().create().add_child("schema check");
+    check(
+        table,
+        expected_columns(table).unwrap(),
+        &headers(columns),
+        &mut progress,
+    )
+}
+
+#[test]
+fn expected_columns_are_the_serialized_names_of_the_csv_model_fields() {
+    let crates = expected_columns("crates").unwrap();
+    assert!(crates.contains(&"name"));
+    assert!(crates.contains(&"repository"));
+    assert!(crates.contains(&"badges"), "optional columns are expected as well");
+
+    let versions = expected_columns("versions").unwrap();
+    assert!(
+        versions.contains(&"num"),
+        "renamed fields are expected by their name in the dump"
+    );
+    assert!(!versions.contains(&"semver"));
+
+    assert!(
+        expected_columns("version_downloads").is_none(),
+        "tables we don't ingest are unknown"
+    );
+}
+
+#[test]
+fn identical_columns_have_no_drift() {
+    let drift = Drift::compute(&["a", "b"], &headers(&["b", "a"]));
+    assert!(drift.added.is_empty());
+    assert!(drift.removed.is_empty());
+    assert!(drift.renamed.is_empty());
+    assert!(!drift.is_breaking());
+    assert_eq!(drift.to_string(), "");
+}
+
+#[test]
+fn added_columns_are_not_breaking() {
+    let drift = Drift::compute(&["a", "b"], &headers(&["a", "b", "c"]));
+    assert_eq!(drift.added, vec!["c".to_owned()]);
+    assert!(drift.removed.is_empty());
+    assert!(drift.renamed.is_empty());
+    assert!(!drift.is_breaking());
+    assert_eq!(drift.to_string(), "added columns: c");
+}
+
+#[test]
+fn removed_columns_are_breaking() {
+    let drift = Drift::compute(&["a", "b", "c"], &headers(&["a"]));
+    assert!(drift.added.is_empty());
+    assert_eq!(drift.removed, vec!["b".to_owned(), "c".to_owned()]);
+    assert!(drift.renamed.is_empty());
+    assert!(drift.is_breaking());
+    assert_eq!(drift.to_string(), "removed columns: b, c");
+}
+
+#[test]
+fn as_many_removed_as_added_columns_are_considered_renamed() {
+    let drift = Drift::compute(&["a", "b"], &headers(&["a", "c"]));
+    assert!(drift.added.is_empty());
+    assert!(drift.removed.is_empty());
+    assert_eq!(drift.renamed, vec![("b".to_owned(), "c".to_owned())]);
+    assert!(drift.is_breaking());
+    assert_eq!(drift.to_string(), "probably renamed columns: b -> c");
+
+    let drift = Drift::compute(&["a", "b"], &headers(&["c", "d"]));
+    assert!(
+        drift.renamed.is_empty(),
+        "a different amount of added columns isn't a rename"
+    );
+    assert_eq!(drift.removed, vec!["a".to_owned(), "b".to_owned()]);
+    assert_eq!(drift.added, vec!["c".to_owned(), "d".to_owned()]);
+}
+
+#[test]
+fn check_accepts_the_expected_columns_with_unknown_ones() {
+    let mut columns = expected_columns("crates").unwrap().to_vec();
+    columns.push("new_column");
+    assert!(check_columns("crates", &columns).is_ok());
+}
+
+#[test]
+fn check_fails_if_a_required_column_is_missing() {
+    let columns = columns_with("versions", "num", None);
+    match check_columns("versions", &columns) {
+        Err(Error::SchemaDrift(table, drift)) => {
+            assert_eq!(table, "versions");
+            assert_eq!(drift, "removed columns: num");
+        }
+        res => panic!("expected schema drift, got {:?}", res),
+    }
+
+    let columns = columns_with("versions", "num", Some("semver"));
+    match check_columns("versions", &columns) {
+        Err(Error::SchemaDrift(_, drift)) => assert_eq!(drift, "probably renamed columns: num -> semver"),
+        res => panic!("expected schema drift, got {:?}", res),
+    }
+}
+
+#[test]
+fn check_allows_optional_columns_to_be_missing() {
+    let columns = columns_with("crates", "badges", None);
+    assert!(check_columns("crates", &columns).is_ok());
+
+    let columns = columns_with("versions", "checksum", None);
+    assert!(check_columns("versions", &columns).is_ok());
+}
+
+#[test]
+fn missing_optional_columns_are_not_mistaken_for_renames() {
+    let columns = columns_with("versions", "checksum", Some("sha256"));
+    assert!(check_columns("versions", &columns).is_ok());
+}
@@ -0,0 +1,317 @@
+//! Ingestion of the crates.io database dump with bounded memory.
+//!
+//! Large tables keyed by crate are streamed into a staging database on disk first, from which crates are assembled
+//! and stored in batches. Only small lookup tables like users, teams, keywords and categories are held in memory.
+use super::{archive, convert, csv_model, from_csv, schema, selected_or_default, store, IngestStats};
+use crate::{persistence::Db, redact::Redaction, Error, Result};
+use bytesize::ByteSize;
+use rusqlite::{params, Connection};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Tables to stage on disk, along with the name of the column holding the crate id
+const STAGED_TABLES: &[(&str, &str)] = &[
+    ("crates", "id"),
+    ("crate_owners", "crate_id"),
+    ("versions", "crate_id"),
+    ("crates_categories", "crate_id"),
+    ("crates_keywords", "crate_id"),
+];
+
+/// A rough estimate of the memory needed by a crate with all of its versions while it is assembled and stored
+const ESTIMATED_BYTES_PER_CRATE: u64 = 64 * 1024;
+const MIN_CRATES_PER_BATCH: u64 = 100;
+const ROWS_PER_STAGING_TRANSACTION: usize = 10_000;
+
+pub fn extract_and_ingest(
+    db: Db,
+    mut progress: prodash::tree::Item,
+    db_file_path: PathBuf,
+    tables: &[&'static str],
+    memory_budget: u64,
+    quarantine: &from_csv::Quarantine,
+    redaction: Redaction,
+) -> Result<IngestStats> {
+    let staging_path = db_file_path
+        .parent()
+        .ok_or(Error::Bug("db dump files are always in a directory"))?
+        .join("staging.sqlite");
+    if staging_path.is_file() {
+        std::fs::remove_file(&staging_path)?;
+    }
+    let res = ingest(
+        db,
+        &mut progress,
+        &db_file_path,
+        &staging_path,
+        tables,
+        memory_budget,
+        quarantine,
+        redaction,
+    );
+    std::fs::remove_file(&staging_path).ok();
+    res
+}
+
+#[allow(clippy::too_many_arguments)]
+fn ingest(
+    db: Db,
+    progress: &mut prodash::tree::Item,
+    db_file_path: &Path,
+    staging_path: &Path,
+    tables: &[&'static str],
+    memory_budget: u64,
+    quarantine: &from_csv::Quarantine,
+    redaction: Redaction,
+) -> Result<IngestStats> {
+    progress.init(None, Some("csv files".into()));
+    let mut staging = Connection::open(staging_path)?;
+    staging.execute_batch("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;")?;
+    let mut archive = archive::open_with_progress(db_file_path, progress.add_child("decompressing"))?;
+
+    let mut num_files_seen = 0;
+    let mut num_bytes_seen = 0;
+    let mut headers = BTreeMap::<&'static str, csv::ByteRecord>::new();
+    let mut teams = None::<BTreeMap<csv_model::Id, csv_model::Team>>;
+    let mut categories = None::<BTreeMap<csv_model::Id, csv_model::Category>>;
+    let mut keywords = None::<BTreeMap<csv_model::Id, csv_model::Keyword>>;
+    let mut users = None::<BTreeMap<csv_model::Id, csv_model::User>>;
+    let mut rows = BTreeMap::<String, u64>::new();
+
+    for (eid, entry) in archive.entries()?.enumerate() {
+        num_files_seen = eid + 1;
+        progress.set(eid);
+
+        let entry = entry?;
+        let entry_size = entry.header().size()?;
+        num_bytes_seen += entry_size;
+
+        let name = match entry
+            .path()
+            .ok()
+            .and_then(|p| p.file_stem().and_then(|s| s.to_str()).map(ToOwned::to_owned))
+        {
+            Some(name) => name,
+            None => continue,
+        };
+        if !tables.contains(&name.as_str()) {
+            continue;
+        }
+        let done_msg = format!(
+            "extracted '{}' with size {}",
+            entry.path()?.display(),
+            ByteSize(entry_size)
+        );
+        match name.as_str() {
+            "teams" => teams = Some(from_csv::mapping(entry, "teams", progress, quarantine)?),
+            "categories" => categories = Some(from_csv::mapping(entry, "categories", progress, quarantine)?),
+            "keywords" => keywords = Some(from_csv::mapping(entry, "keywords", progress, quarantine)?),
+            "users" => users = Some(from_csv::mapping(entry, "users", progress, quarantine)?),
+            name => match STAGED_TABLES.iter().find(|(table, _)| *table == name) {
+                Some((table, crate_id_column)) => {
+                    let (table_headers, num_records) =
+                        stage(&mut staging, entry, table, crate_id_column, progress, quarantine)?;
+                    headers.insert(*table, table_headers);
+                    rows.insert((*table).to_owned(), num_records);
+                }
+                None => continue,
+            },
+        }
+        progress.done(done_msg);
+    }
+    progress.done(format!(
+        "Saw {} files and a total of {}",
+        num_files_seen,
+        ByteSize(num_bytes_seen)
+    ));
+
+    let users = selected_or_default(users, "users", tables)?;
+    let teams = selected_or_default(teams, "teams", tables)?;
+    let keywords = selected_or_default(keywords, "keywords", tables)?;
+    let categories = selected_or_default(categories, "categories", tables)?;
+    for (table, _) in STAGED_TABLES {
+        if tables.contains(table) && !headers.contains_key(table) {
+            return Err(Error::Message(format!("expected {}.csv in crates-io db dump", table)));
+        }
+    }
+    for (table, num_records) in &[
+        ("users", users.len()),
+        ("teams", teams.len()),
+        ("keywords", keywords.len()),
+        ("categories", categories.len()),
+    ] {
+        if tables.contains(table) {
+            rows.insert((*table).to_owned(), *num_records as u64);
+        }
+    }
+
+    let actors_by_id = convert::into_actors_by_id(users, teams, redaction, progress.add_child("actors"));
+    let crates_per_batch = (memory_budget / ESTIMATED_BYTES_PER_CRATE).max(MIN_CRATES_PER_BATCH);
+    let num_crates: u32 = staging.query_row("SELECT COUNT(*) FROM crates", params![], |r| r.get(0))?;
+    progress.init(Some(num_crates as usize), Some("crates".into()));
+    progress.set_name("assembling crates in batches");
+
+    let mut last_crate_id: Option<csv_model::Id> = None;
+    let mut num_crates_stored = 0;
+    loop {
+        let crate_ids = {
+            let mut statement =
+                staging.prepare_cached("SELECT crate_id FROM crates WHERE crate_id > ?1 ORDER BY crate_id LIMIT ?2")?;
+            let ids: rusqlite::Result<Vec<csv_model::Id>> = statement
+                .query_map(
+                    params![last_crate_id.map_or(-1, i64::from), crates_per_batch as i64],
+                    |r| r.get(0),
+                )?
+                .collect();
+            ids?
+        };
+        let range = match (crate_ids.first(), crate_ids.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => break,
+        };
+        last_crate_id = Some(range.1);
+
+        let versions_by_crate_id = convert::into_versions_by_crate_id(
+            load(&staging, quarantine, "versions", &headers, range)?,
+            &actors_by_id,
+            progress.add_child("versions"),
+        );
+        let crates = convert::into_crates(
+            load(&staging, quarantine, "crates", &headers, range)?,
+            &keywords,
+            load(&staging, quarantine, "crates_keywords", &headers, range)?,
+            &categories,
+            load(&staging, quarantine, "crates_categories", &headers, range)?,
+            &actors_by_id,
+            load(&staging, quarantine, "crate_owners", &headers, range)?,
+            versions_by_crate_id,
+            progress.add_child("crates"),
+        );
+        num_crates_stored += crates.len();
+        store(db.clone(), crates, progress.add_child("persist"))?;
+        progress.set(num_crates_stored);
+    }
+    progress.done(format!("Stored {} crates in database", num_crates_stored));
+    Ok(IngestStats {
+        rows,
+        bytes: num_bytes_seen,
+    })
+}
+
+/// Write all records of the given `table` into the staging database and return its headers along with the amount of records.
+fn stage(
+    connection: &mut Connection,
+    csv: impl std::io::Read,
+    table: &'static str,
+    crate_id_column: &str,
+    progress: &mut prodash::tree::Item,
+    quarantine: &from_csv::Quarantine,
+) -> Result<(csv::ByteRecord, u64)> {
+    connection.execute_batch(&format!(
+        "CREATE TABLE '{}' (crate_id INTEGER NOT NULL, line INTEGER NOT NULL, data BLOB NOT NULL)",
+        table
+    ))?;
+    let mut rd = csv::ReaderBuilder::new()
+        .delimiter(b',')
+        .has_headers(true)
+        .flexible(true)
+        .from_reader(csv);
+    let headers = rd.byte_headers()?.clone();
+    schema::check(
+        table,
+        schema::expected_columns(table).ok_or(Error::Bug("staged tables have known columns"))?,
+        &headers,
+        progress,
+    )?;
+    let column = headers
+        .iter()
+        .position(|h| h == crate_id_column.as_bytes())
+        .ok_or_else(|| Error::Message(format!("Column '{}' is missing in table '{}'", crate_id_column, table)))?;
+
+    let mut decode = progress.add_child("staging");
+    decode.init(None, Some(table.into()));
+    let mut record = csv::ByteRecord::new();
+    let mut buf = Vec::new();
+    let mut num_records = 0;
+    loop {
+        let transaction = connection.transaction()?;
+        let (mut rows_read, mut rows_inserted) = (0, 0);
+        {
+            let mut insert = transaction.prepare(&format!(
+                "INSERT INTO '{}' (crate_id, line, data) VALUES (?1, ?2, ?3)",
+                table
+            ))?;
+            while rows_read < ROWS_PER_STAGING_TRANSACTION && rd.read_byte_record(&mut record)? {
+                rows_read += 1;
+                decode.inc();
+                let line = record.position().map(|p| p.line()).unwrap_or_default();
+                let crate_id: csv_model::Id = match std::str::from_utf8(record.get(column).unwrap_or_default())
+                    .map_err(|err| Error::Message(format!("{}: {}", table, err)))
+                    .and_then(|id| id.parse().map_err(Into::into))
+                {
+                    Ok(crate_id) => crate_id,
+                    Err(err) => {
+                        quarantine.add(table, line, Some(crate_id_column.to_owned()), &record, err)?;
+                        continue;
+                    }
+                };
+                buf.clear();
+                from_csv::encode_record(&record, &mut buf);
+                insert.execute(params![crate_id, line as i64, buf])?;
+                rows_inserted += 1;
+            }
+        }
+        transaction.commit()?;
+        num_records += rows_inserted;
+        if rows_read < ROWS_PER_STAGING_TRANSACTION {
+            break;
+        }
+    }
+    connection.execute_batch(&format!("CREATE INDEX '{0}_by_crate' ON '{0}' (crate_id)", table))?;
+    decode.info(format!("Staged {} {} on disk", num_records, table));
+    Ok((headers, num_records as u64))
+}
+
+/// Load and decode all records of `table` belonging to crates within the inclusive `range` of crate ids,
+/// or none if the table wasn't selected for ingestion.
+fn load<T>(
+    connection: &Connection,
+    quarantine: &from_csv::Quarantine,
+    table: &'static str,
+    headers: &BTreeMap<&'static str, csv::ByteRecord>,
+    range: (csv_model::Id, csv_model::Id),
+) -> Result<Vec<T>>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let headers = match headers.get(table) {
+        Some(headers) => headers,
+        None => return Ok(Vec::new()),
+    };
+    let mut statement = connection.prepare_cached(&format!(
+        "SELECT line, data FROM '{}' WHERE crate_id >= ?1 AND crate_id <= ?2",
+        table
+    ))?;
+    let mut rows = statement.query(params![range.0, range.1])?;
+    let mut out = Vec::new();
+    let mut record = csv::ByteRecord::new();
+    while let Some(row) = rows.next()? {
+        let line: i64 = row.get(0)?;
+        let data: Vec<u8> = row.get(1)?;
+        csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(data.as_slice())
+            .read_byte_record(&mut record)?;
+        match record.deserialize(Some(headers)) {
+            Ok(item) => out.push(item),
+            Err(err) => {
+                let column = from_csv::failed_column(&err, headers);
+                quarantine.add(table, line as u64, column, &record, err.into())?;
+            }
+        }
+    }
+    Ok(out)
+}
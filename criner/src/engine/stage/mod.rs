@@ -1,5 +1,7 @@
 pub mod changes;
 pub mod db_download;
 pub mod processing;
+pub mod reconcile;
 
+#[cfg(feature = "reports")]
 pub mod report;
@@ -1,36 +1,74 @@
-use crate::persistence::{new_value_query_recent_first, value_iter, CrateVersionTable};
 use crate::{
-    engine::work,
+    engine::{
+        notify::NewCrateVersion,
+        run::{DownloadSettings, ExtractionProfile, Registry},
+        work::{self, queue},
+    },
     error::Result,
-    model::CrateVersion,
-    persistence::{Db, Keyed, TableAccess},
+    model::{db_dump, BackfillItem, CrateVersion},
+    persistence::{self, Db, Keyed, SeenFilter, TableAccess},
 };
 use futures_util::FutureExt;
-use std::{path::PathBuf, time::SystemTime};
+use rusqlite::{params, OptionalExtension};
+use std::{collections::HashMap, path::PathBuf, time::SystemTime};
 
+/// The amount of queued downloads or extractions after which scheduling waits for processors to catch up
+const QUEUE_CAPACITY: usize = 1000;
+
+/// The estimated cost of processing a crate version whose archive size is unknown, in bytes
+const UNKNOWN_CRATE_SIZE: u64 = 64 * 1024;
+
+/// The priority of downloads of crate versions which were just published, which come before all others
+const FUSED_DOWNLOAD_PRIORITY: i64 = i64::MAX;
+
+/// The priority of crate versions whose failed tasks are requeued, which come right after just published ones
+const REQUEUE_PRIORITY: i64 = i64::MAX - 1;
+
+/// Download and extract all crate versions whose tasks are incomplete, or only those of crates matching `glob` if set.
+///
+/// Without `download`, only crate versions which were downloaded before are extracted. Without `extract`, crate
+/// versions are only downloaded and their extraction is left to a later run.
+#[allow(clippy::too_many_arguments)]
 pub async fn process(
     db: Db,
+    glob: Option<String>,
     mut progress: prodash::tree::Item,
     io_bound_processors: u32,
     cpu_bound_processors: u32,
     mut processing_progress: prodash::tree::Item,
     assets_dir: PathBuf,
+    extraction_profile: ExtractionProfile,
+    download_settings: DownloadSettings,
+    alternate_registries: Vec<Registry>,
+    download: bool,
+    extract: bool,
     startup_time: SystemTime,
+    deadline: Option<SystemTime>,
 ) -> Result<()> {
     processing_progress.set_name("Downloads and Extractors");
+    // Queued work of stages which don't run stays in the persistent queues until they run again
+    let io_bound_processors = if download { io_bound_processors } else { 0 };
+    let cpu_bound_processors = if extract { cpu_bound_processors } else { 0 };
+    // Fail right away on invalid patterns, instead of in each of the processors which are detached
+    work::cpubound::selected_paths(&extraction_profile)?;
     let tx_cpu = {
-        let (tx_cpu, rx) = async_channel::bounded(1);
+        let (tx_cpu, rx) = queue::persistent(&db, "extract", QUEUE_CAPACITY)?;
         for idx in 0..cpu_bound_processors {
-            let max_retries_on_timeout = 0;
+            let retry = work::generic::RetryPolicy::never();
             let db = db.clone();
             let assets_dir = assets_dir.clone();
+            let extraction_profile = extraction_profile.clone();
             let progress = processing_progress.add_child(format!("{}:CPU IDLE", idx + 1));
             let rx = rx.clone();
+            let quarantine = work::generic::QuarantineSettings {
+                startup_time,
+                max_crashes: extraction_profile.max_crashes,
+            };
             crate::spawn(blocking::unblock(move || -> Result<_> {
-                let agent = work::cpubound::Agent::new(assets_dir, &db)?;
+                let agent = work::cpubound::Agent::new(assets_dir, extraction_profile, &db)?;
                 #[allow(clippy::unit_arg)] // don't know where the unit is supposed to be
                 Ok(futures_lite::future::block_on(
-                    work::generic::processor(db, progress, rx, agent, max_retries_on_timeout).map(|r| {
+                    work::generic::processor(db, progress, rx, agent, retry, Some(quarantine)).map(|r| {
                         if let Err(e) = r {
                             log::warn!("CPU bound processor failed: {}", e);
                         }
@@ -43,68 +81,77 @@ pub async fn process(
     };
 
     let tx_io = {
-        let (tx_io, rx) = async_channel::bounded(1);
-        for idx in 0..io_bound_processors {
-            let max_retries_on_timeout = 40;
-            crate::spawn(
-                work::generic::processor(
-                    db.clone(),
-                    processing_progress.add_child(format!("{}: ↓ IDLE", idx + 1)),
-                    rx.clone(),
-                    work::iobound::Agent::new(&db, tx_cpu.clone(), |crate_name_and_version, task, _| {
-                        crate_name_and_version.map(|(crate_name, crate_version)| work::cpubound::ExtractRequest {
-                            download_task: task.clone(),
-                            crate_name,
-                            crate_version,
-                        })
-                    })?,
-                    max_retries_on_timeout,
-                )
-                .map(|r| {
-                    if let Err(e) = r {
-                        log::warn!("iobound processor failed: {}", e);
-                    }
-                }),
-            )
-            .detach();
-        }
+        let (tx_io, rx) = queue::persistent(&db, "download", QUEUE_CAPACITY)?;
+        spawn_io_processors(
+            &db,
+            io_bound_processors,
+            &mut processing_progress,
+            &rx,
+            &download_settings,
+            &tx_cpu,
+            Some(&extraction_profile).filter(|_| extract),
+            deadline,
+        )?;
         tx_io
     };
 
     blocking::unblock(move || {
-        let versions = db.open_crate_versions()?;
-        let num_versions = versions.count();
-        progress.init(Some(num_versions as usize), Some("crate versions".into()));
+        let manifest = db.open_backfill_manifest()?;
+        let quarantine = db.open_crate_version_quarantine()?;
+        let mut backfill = manifest.progress()?;
+        // Each sweep over all crates starts with a new filter, which bounds its size and the effect of collisions
+        let seen = if backfill.is_complete() && glob.is_none() {
+            SeenFilter::rebuild(&db, &mut progress)?
+        } else {
+            SeenFilter::load_and_update(&db, &mut progress)?
+        };
+        // A focused run plans only the crates it's focused on, leaving all others to the next full plan
+        if backfill.is_complete() || glob.is_some() {
+            let items = backfill_items(&db, &seen, glob.as_deref(), &mut progress)?;
+            progress.blocked("storing backfill manifest", None);
+            manifest.replace(&items)?;
+            backfill = manifest.progress()?;
+        } else {
+            progress.info(format!("resuming backfill at {}", backfill));
+        }
+        progress.init(Some((backfill.total_cost / 1024) as usize), Some("KB of crates".into()));
+        progress.set((backfill.total_cost_done / 1024) as usize);
 
-        let auto_checkpoint_every = 10000;
+        let mut key_buf = String::with_capacity(32);
+        let versions_per_checkpoint = 10_000;
         let checkpoint_connection = db.open_connection_with_busy_wait()?;
-        let mut fetched_versions = 0;
-        let mut versions = Vec::with_capacity(auto_checkpoint_every);
         let mut last_elapsed_for_checkpointing = None;
 
+        // The most downloaded crates come first, to keep them fresh even if the processing is cut short
         loop {
-            let abort_loop = {
-                progress.blocked("fetching chunk of version to schedule", None);
-                let connection = db.open_connection_no_async_with_busy_wait()?;
-                let mut statement = new_value_query_recent_first(
-                    CrateVersionTable::table_name(),
-                    &connection,
-                    fetched_versions,
-                    auto_checkpoint_every,
-                )?;
-                let iter = value_iter::<CrateVersion>(&mut statement)?;
-                versions.clear();
-                versions.extend(iter);
-                fetched_versions += versions.len();
-
-                versions.len() != auto_checkpoint_every
+            let chunk = manifest.pending(versions_per_checkpoint)?;
+            let last_id = match chunk.last() {
+                Some((id, _)) => *id,
+                None => break,
             };
-
-            let tasks = db.open_tasks()?;
-            for (vid, version) in versions.drain(..).enumerate() {
-                let version = version?;
-
-                progress.set(vid + fetched_versions + 1);
+            let (tasks, versions) = (db.open_tasks()?, db.open_crate_versions()?);
+            for (_, item) in chunk {
+                backfill.total_cost_done += item.estimated_cost;
+                progress.set((backfill.total_cost_done / 1024) as usize);
+                if work::schedule::is_complete(&seen, &item.crate_name, &item.crate_version, &mut key_buf)
+                    || quarantine.is_quarantined(&item.crate_name, &item.crate_version)?
+                {
+                    continue;
+                }
+                key_buf.clear();
+                CrateVersion::key_from(&item.crate_name, &item.crate_version, &mut key_buf);
+                let version = match versions.get(&key_buf)? {
+                    Some(version) => version,
+                    None => continue,
+                };
+                let (crate_url_template, mirror_templates) = match work::schedule::crate_url_template(
+                    &version.name,
+                    &download_settings,
+                    &alternate_registries,
+                ) {
+                    Some(templates) => templates,
+                    None => continue,
+                };
                 progress.halted("wait for task consumers", None);
                 futures_lite::future::block_on(work::schedule::tasks(
                     &assets_dir,
@@ -112,11 +159,20 @@ pub async fn process(
                     &version,
                     progress.add_child(format!("schedule {}", version.key())),
                     work::schedule::Scheduling::AtLeastOne,
+                    item.priority,
+                    crate_url_template,
+                    mirror_templates,
                     &tx_io,
                     &tx_cpu,
+                    download,
+                    extract,
                     startup_time,
                 ))?;
             }
+            // Scheduled work is kept in persistent queues, so it's safe to not schedule it again after a restart
+            manifest.mark_done_through(last_id)?;
+            backfill = manifest.progress()?;
+            progress.info(backfill.to_string());
 
             // We have too many writers which cause the WAL to get so large that all reads are slowing to a crawl
             // Standard SQLITE autocheckpoints are passive, which are not effective in our case as they never
@@ -130,12 +186,280 @@ pub async fn process(
                 .lock()
                 .execute_batch("PRAGMA wal_checkpoint(TRUNCATE)")?;
             last_elapsed_for_checkpointing = Some(SystemTime::now().duration_since(start)?);
+        }
+        progress.blocked("storing filter of completed tasks", None);
+        seen.store(&db)
+    })
+    .await
+}
 
-            if abort_loop {
-                break;
-            }
+/// Reset the failed tasks of all crate versions of crates matching `glob`, forgetting their failed attempts, and queue
+/// their download and extraction right away, ahead of all other work but fused downloads. Without `download`, only
+/// their extraction is queued, and without `extract` only their download.
+///
+/// Use it to repair the fallout of a transient outage without sweeping all crate versions.
+#[allow(clippy::too_many_arguments)]
+pub async fn requeue_failed(
+    db: Db,
+    glob: String,
+    mut progress: prodash::tree::Item,
+    assets_dir: PathBuf,
+    download_settings: DownloadSettings,
+    alternate_registries: Vec<Registry>,
+    download: bool,
+    extract: bool,
+    startup_time: SystemTime,
+) -> Result<()> {
+    let glob = glob::Pattern::new(&glob)?;
+    blocking::unblock(move || {
+        progress.blocked("resetting failed tasks", None);
+        let tasks = db.open_tasks()?;
+        let crate_versions = tasks.reset_failed(|crate_name| glob.matches(crate_name))?;
+        progress.init(Some(crate_versions.len()), Some("crate versions".into()));
+
+        // Requeued work shouldn't wait for the work queued by processing runs, so the queues are unbounded for it
+        let (tx_io, _) = queue::persistent(&db, "download", usize::MAX)?;
+        let (tx_cpu, _) = queue::persistent(&db, "extract", usize::MAX)?;
+        let versions = db.open_crate_versions()?;
+        let mut key_buf = String::with_capacity(32);
+        let mut requeued = 0;
+        for (crate_name, crate_version) in crate_versions {
+            progress.inc();
+            key_buf.clear();
+            CrateVersion::key_from(&crate_name, &crate_version, &mut key_buf);
+            let version = match versions.get(&key_buf)? {
+                Some(version) => version,
+                None => continue,
+            };
+            let (crate_url_template, mirror_templates) =
+                match work::schedule::crate_url_template(&version.name, &download_settings, &alternate_registries) {
+                    Some(templates) => templates,
+                    None => continue,
+                };
+            futures_lite::future::block_on(work::schedule::tasks(
+                &assets_dir,
+                &tasks,
+                &version,
+                progress.add_child(format!("schedule {}", version.key())),
+                work::schedule::Scheduling::AtLeastOne,
+                REQUEUE_PRIORITY,
+                crate_url_template,
+                mirror_templates,
+                &tx_io,
+                &tx_cpu,
+                download,
+                extract,
+                startup_time,
+            ))?;
+            requeued += 1;
         }
+        progress.done(format!("Requeued {} crate versions with failed tasks", requeued));
         Ok(())
     })
     .await
 }
+
+/// Downloads crate versions as soon as the changes stage stored them for the first time, instead of waiting for the
+/// next processing run to schedule them.
+#[derive(Clone)]
+pub struct FusedDownloads {
+    db: Db,
+    tx_io: queue::Sender<work::iobound::DownloadRequest>,
+    tx_cpu: queue::Sender<work::cpubound::ExtractRequest>,
+    progress: prodash::Tree,
+    assets_dir: PathBuf,
+    download_settings: DownloadSettings,
+    alternate_registries: Vec<Registry>,
+    startup_time: SystemTime,
+}
+
+impl FusedDownloads {
+    /// Spawn `processors` IO-bound processors for the downloads queued with `schedule()`, which queue the extraction
+    /// of the crate versions they downloaded with `extraction_profile` if it's set. They stop once all clones of the
+    /// returned instance are dropped and the downloads queued by them are done.
+    #[allow(clippy::too_many_arguments)]
+    pub fn spawn(
+        db: Db,
+        processors: u32,
+        progress: prodash::Tree,
+        assets_dir: PathBuf,
+        download_settings: DownloadSettings,
+        alternate_registries: Vec<Registry>,
+        extraction_profile: Option<ExtractionProfile>,
+        startup_time: SystemTime,
+        deadline: Option<SystemTime>,
+    ) -> Result<Self> {
+        // Fetches shouldn't wait for the downloads queued by processing runs, so the queue is unbounded for them
+        let (tx_io, rx) = queue::persistent(&db, "download", usize::MAX)?;
+        let (tx_cpu, _) = queue::persistent(&db, "extract", QUEUE_CAPACITY)?;
+        spawn_io_processors(
+            &db,
+            processors,
+            &mut progress.add_child("Fused Downloads"),
+            &rx,
+            &download_settings,
+            &tx_cpu,
+            extraction_profile.as_ref(),
+            deadline,
+        )?;
+        Ok(FusedDownloads {
+            db,
+            tx_io,
+            tx_cpu,
+            progress,
+            assets_dir,
+            download_settings,
+            alternate_registries,
+            startup_time,
+        })
+    }
+
+    /// Queue the downloads of all `versions` ahead of all other downloads, as they were just published.
+    pub fn schedule(&self, versions: &[NewCrateVersion]) -> Result<()> {
+        let tasks = self.db.open_tasks()?;
+        for new_version in versions {
+            let version = CrateVersion {
+                name: new_version.name.clone(),
+                version: new_version.version.clone(),
+                checksum: new_version.checksum.clone(),
+                ..Default::default()
+            };
+            let (crate_url_template, mirror_templates) = match work::schedule::crate_url_template(
+                &version.name,
+                &self.download_settings,
+                &self.alternate_registries,
+            ) {
+                Some(templates) => templates,
+                None => continue,
+            };
+            futures_lite::future::block_on(work::schedule::tasks(
+                &self.assets_dir,
+                &tasks,
+                &version,
+                self.progress.add_child(format!("schedule {}", version.key())),
+                work::schedule::Scheduling::AtLeastOne,
+                FUSED_DOWNLOAD_PRIORITY,
+                crate_url_template,
+                mirror_templates,
+                &self.tx_io,
+                &self.tx_cpu,
+                true,
+                false,
+                self.startup_time,
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+/// Spawn `processors` IO-bound processors downloading the crate versions received from `rx`, which queue their
+/// extraction with `tx_cpu` if `extraction_profile` is set, or extract them right away if the `download_settings` ask
+/// for stream extraction. They stop once `rx` is empty and all of its senders are dropped.
+/// They share their connections, and with adaptive concurrency fewer of them download at once while the server
+/// appears overloaded. Downloads still in progress at the `deadline` are abandoned.
+#[allow(clippy::too_many_arguments)]
+fn spawn_io_processors(
+    db: &Db,
+    processors: u32,
+    progress: &mut prodash::tree::Item,
+    rx: &queue::Receiver<work::iobound::DownloadRequest>,
+    download_settings: &DownloadSettings,
+    tx_cpu: &queue::Sender<work::cpubound::ExtractRequest>,
+    extraction_profile: Option<&ExtractionProfile>,
+    deadline: Option<SystemTime>,
+) -> Result<()> {
+    let clients = work::iobound::Clients::new(download_settings, deadline)?;
+    let concurrency = Some(work::concurrency::AdaptiveConcurrency::new(processors as usize))
+        .filter(|_| download_settings.adaptive_concurrency);
+    let stream_extraction = extraction_profile.filter(|_| download_settings.stream_extraction);
+    let queue_extraction = extraction_profile.is_some() && stream_extraction.is_none();
+    for idx in 0..processors {
+        crate::spawn(
+            work::generic::processor(
+                db.clone(),
+                progress.add_child(format!("{}: ↓ IDLE", idx + 1)),
+                rx.clone(),
+                work::iobound::Agent::new(
+                    db,
+                    clients.clone(),
+                    concurrency.clone(),
+                    stream_extraction
+                        .map(|profile| work::cpubound::Extractor::new(profile.clone(), db))
+                        .transpose()?,
+                    tx_cpu.clone(),
+                    move |crate_name_and_version, task, _| {
+                        crate_name_and_version
+                            .filter(|_| queue_extraction)
+                            .map(|(crate_name, crate_version)| work::cpubound::ExtractRequest {
+                                download_task: task.clone(),
+                                crate_name,
+                                crate_version,
+                            })
+                    },
+                )?,
+                work::generic::RetryPolicy::download(40),
+                None,
+            )
+            .map(|r| {
+                if let Err(e) = r {
+                    log::warn!("iobound processor failed: {}", e);
+                }
+            }),
+        )
+        .detach();
+    }
+    Ok(())
+}
+
+/// List all crate versions with incomplete tasks along with their estimated cost, the most downloaded crates first
+/// and the most recent versions of each crate first. If `glob` is set, only crates matching it are listed.
+fn backfill_items(
+    db: &Db,
+    seen: &SeenFilter,
+    glob: Option<&str>,
+    progress: &mut prodash::tree::Item,
+) -> Result<Vec<BackfillItem>> {
+    progress.blocked("ordering crates by downloads", None);
+    let connection = db.open_connection_no_async_with_busy_wait()?;
+    let crates = persistence::crates_by_downloads(glob, &connection)?;
+    progress.init(Some(crates.len()), Some("crates planned".into()));
+
+    let crate_table = db.open_crates()?;
+    let mut select_db_dump_crate = connection.prepare("SELECT data FROM 'crates.io-crate' WHERE key = ?1")?;
+    let mut key_buf = String::with_capacity(32);
+    let mut items = Vec::new();
+    for (cid, (crate_name, downloads)) in crates.iter().enumerate() {
+        progress.set(cid + 1);
+        // Crates deleted from the index can't be downloaded anymore
+        let krate = match crate_table.get(crate_name)? {
+            Some(krate) if krate.deleted_at.is_none() => krate,
+            _ => continue,
+        };
+        let stored: Option<Vec<u8>> = select_db_dump_crate
+            .query_row(params![crate_name], |r| r.get(0))
+            .optional()?;
+        let crate_sizes: HashMap<String, u32> = match stored {
+            Some(data) => rmp_serde::from_slice::<db_dump::Crate>(&data)?
+                .versions
+                .into_iter()
+                .filter_map(|version| version.crate_size.map(|size| (version.semver, size)))
+                .collect(),
+            None => HashMap::new(),
+        };
+        for crate_version in krate.versions.iter().rev() {
+            if work::schedule::is_complete(seen, crate_name, crate_version, &mut key_buf) {
+                continue;
+            }
+            items.push(BackfillItem {
+                crate_name: crate_name.clone(),
+                crate_version: crate_version.clone(),
+                priority: work::schedule::download_priority(*downloads),
+                estimated_cost: crate_sizes
+                    .get(crate_version)
+                    .map(|size| *size as u64)
+                    .unwrap_or(UNKNOWN_CRATE_SIZE),
+            });
+        }
+    }
+    Ok(items)
+}
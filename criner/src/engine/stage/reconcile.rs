@@ -0,0 +1,224 @@
+//! Compare the crates known to the crates.io index with the ones in the most recently ingested db dump and our own tables,
+//! as a health check that is surfaced in the statistics and the status file.
+use crate::{
+    model::{self, db_dump, Discrepancy, Reconciliation, SourceCounts, VersionDiscrepancy},
+    persistence::{self, CrateTable, CrateVersionTable, TableAccess},
+    Result,
+};
+use crates_index_diff::{git2, Index};
+use criner_waste_report::discrepancy::VersionState;
+use rusqlite::{params, OptionalExtension, NO_PARAMS};
+use serde_derive::Serialize;
+use std::{collections::BTreeSet, time::SystemTime};
+
+/// The name of the file in the database directory describing the state of the database
+pub const STATUS_FILE_NAME: &str = "status.json";
+
+/// The maximum amount of crate names to list per discrepancy
+const MAX_SAMPLE: usize = 20;
+
+/// The contents of the status file
+#[derive(Serialize)]
+struct Status<'a> {
+    /// The time at which the status was written
+    written_at: String,
+    /// Statistics about the work performed today
+    context: &'a model::Context,
+    /// The most recent attempt to publish the reports, if there was one
+    last_publish: Option<model::Publish>,
+}
+
+/// Write `context` into the status file within the database directory along with the most recent attempt to publish
+/// the reports, for consumption by monitoring tools.
+pub fn write_status_file(db: &persistence::Db, context: &model::Context) -> Result<()> {
+    let status = Status {
+        written_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        context,
+        last_publish: db.open_publishes()?.most_recent()?,
+    };
+    std::fs::write(
+        db.directory().join(STATUS_FILE_NAME),
+        serde_json::to_vec_pretty(&status)?,
+    )?;
+    Ok(())
+}
+
+/// Count crates and crate versions in the `commit` of the `index` we saw last, the db dump and our own tables in
+/// `connection`, and find crates which are missing in one of them.
+pub fn reconcile(
+    index: &Index,
+    commit: git2::Oid,
+    connection: &rusqlite::Connection,
+    progress: &mut prodash::tree::Item,
+) -> Result<Reconciliation> {
+    progress.blocked("counting crates in index", None);
+    let (index_counts, index_crates) = count_index(index, commit)?;
+
+    progress.blocked("counting crates in database", None);
+    let db_crates = crate_names(connection, CrateTable::table_name())?;
+    let db_counts = SourceCounts {
+        crates: db_crates.len() as u64,
+        crate_versions: connection.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM '{}' WHERE instr(key, '{}') = 0",
+                CrateVersionTable::table_name(),
+                model::REGISTRY_SEP_CHAR
+            ),
+            NO_PARAMS,
+            |r| r.get::<_, i64>(0),
+        )? as u64,
+    };
+
+    progress.blocked("counting crates in db dump", None);
+    let (db_dump_counts, db_dump_crates) = count_db_dump(connection)?;
+
+    let reconciliation = Reconciliation {
+        created_at: Some(SystemTime::now()),
+        index: index_counts,
+        db_dump: db_dump_counts,
+        db: db_counts,
+        missing_in_db: discrepancy(index_crates.difference(&db_crates)),
+        missing_in_db_dump: if db_dump_crates.is_empty() {
+            Discrepancy::default()
+        } else {
+            discrepancy(index_crates.difference(&db_dump_crates))
+        },
+        tombstones: discrepancy(db_crates.difference(&index_crates)),
+    };
+    progress.done(format!(
+        "Reconciled {} crates in index with {} crates in db dump and {} crates in database: {} missing, {} deleted",
+        reconciliation.index.crates,
+        reconciliation.db_dump.crates,
+        reconciliation.db.crates,
+        reconciliation.missing_in_db.count,
+        reconciliation.tombstones.count
+    ));
+    Ok(reconciliation)
+}
+
+fn discrepancy<'a>(names: impl Iterator<Item = &'a String>) -> Discrepancy {
+    let mut d = Discrepancy::default();
+    for name in names {
+        d.count += 1;
+        if d.sample.len() < MAX_SAMPLE {
+            d.sample.push(name.to_owned());
+        }
+    }
+    d
+}
+
+/// Crate names are compared case-insensitively, as the index stores them in lower case.
+/// Crates of alternate registries are ignored as they aren't part of the crates.io index.
+fn crate_names(connection: &rusqlite::Connection, table_name: &str) -> Result<BTreeSet<String>> {
+    let mut statement = connection.prepare(&format!(
+        "SELECT key FROM '{}' WHERE instr(key, '{}') = 0",
+        table_name,
+        model::REGISTRY_SEP_CHAR
+    ))?;
+    let names: rusqlite::Result<BTreeSet<String>> = statement
+        .query_map(NO_PARAMS, |r| r.get::<_, String>(0).map(|n| n.to_lowercase()))?
+        .collect();
+    Ok(names?)
+}
+
+/// Return the name of each crate in the tree of the `commit` of the crates.io index `repo`, along with the id of the
+/// blob of its file.
+pub fn crate_files(repo: &git2::Repository, commit: git2::Oid) -> Result<Vec<(String, git2::Oid)>> {
+    let mut files = Vec::new();
+    repo.find_commit(commit)?
+        .tree()?
+        .walk(git2::TreeWalkMode::PreOrder, |root, entry| {
+            let name = entry.name().unwrap_or_default();
+            if name.starts_with('.') {
+                return git2::TreeWalkResult::Skip;
+            }
+            // Files at the top-level are configuration, all crates are in sub-directories
+            if !root.is_empty() && entry.kind() == Some(git2::ObjectType::Blob) {
+                files.push((name.to_owned(), entry.id()));
+            }
+            git2::TreeWalkResult::Ok
+        })?;
+    Ok(files)
+}
+
+fn count_index(index: &Index, commit: git2::Oid) -> Result<(SourceCounts, BTreeSet<String>)> {
+    let repo = index.repository();
+    let mut counts = SourceCounts::default();
+    let mut names = BTreeSet::new();
+    for (name, id) in crate_files(repo, commit)? {
+        let blob = repo.find_blob(id)?;
+        counts.crates += 1;
+        counts.crate_versions += blob
+            .content()
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .count() as u64;
+        names.insert(name.to_lowercase());
+    }
+    Ok((counts, names))
+}
+
+fn count_db_dump(connection: &rusqlite::Connection) -> Result<(SourceCounts, BTreeSet<String>)> {
+    let mut statement = connection.prepare("SELECT key, data FROM 'crates.io-crate'")?;
+    let mut rows = statement.query(NO_PARAMS)?;
+    let mut counts = SourceCounts::default();
+    let mut names = BTreeSet::new();
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        let data: Vec<u8> = row.get(1)?;
+        let krate: db_dump::Crate = rmp_serde::from_slice(&data)?;
+        counts.crates += 1;
+        counts.crate_versions += krate.versions.len() as u64;
+        names.insert(name.to_lowercase());
+    }
+    Ok((counts, names))
+}
+
+/// Compare the yanked state and checksum of all crate versions in the most recently ingested db dump with what the
+/// crates.io index told us about them, and return all versions they disagree about.
+/// Versions known to only one of them are counted by `reconcile()` instead.
+pub fn cross_check_versions(
+    connection: &rusqlite::Connection,
+    progress: &mut prodash::tree::Item,
+) -> Result<Vec<VersionDiscrepancy>> {
+    progress.init(None, Some("crates".into()));
+    let mut index_version = connection.prepare(&format!(
+        "SELECT data FROM '{}' WHERE key = ?1",
+        CrateVersionTable::table_name()
+    ))?;
+    let mut statement = connection.prepare("SELECT data FROM 'crates.io-crate'")?;
+    let mut rows = statement.query(NO_PARAMS)?;
+    let mut key = String::new();
+    let mut discrepancies = Vec::new();
+    while let Some(row) = rows.next()? {
+        progress.inc();
+        let data: Vec<u8> = row.get(0)?;
+        let krate: db_dump::Crate = rmp_serde::from_slice(&data)?;
+        for version in &krate.versions {
+            key.clear();
+            model::CrateVersion::key_from(&krate.name, &version.semver, &mut key);
+            let data: Option<Vec<u8>> = index_version.query_row(params![key], |r| r.get(0)).optional()?;
+            let index: model::CrateVersion = match data {
+                Some(data) => rmp_serde::from_slice(&data)?,
+                None => continue,
+            };
+            discrepancies.extend(VersionDiscrepancy::find(
+                &krate.name,
+                &version.semver,
+                VersionState {
+                    yanked: index.kind == model::ChangeKind::Yanked,
+                    checksum: Some(index.checksum.as_str()).filter(|checksum| !checksum.is_empty()),
+                },
+                VersionState {
+                    yanked: version.is_yanked,
+                    checksum: version.checksum.as_deref(),
+                },
+            ));
+        }
+    }
+    progress.done(format!(
+        "Found {} discrepancies between the index and the db dump",
+        discrepancies.len()
+    ));
+    Ok(discrepancies)
+}
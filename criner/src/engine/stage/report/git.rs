@@ -1,7 +1,7 @@
 use crate::utils::enforce_threaded;
 use crate::{
     engine::report::generic::{WriteCallback, WriteCallbackState, WriteInstruction, WriteRequest},
-    {Error, Result},
+    model, persistence, Error, Result,
 };
 use crates_index_diff::git2;
 use futures_util::{future::BoxFuture, FutureExt};
@@ -41,7 +41,10 @@ fn env_var(name: &str) -> Result<String> {
     })
 }
 
+/// Select the write callback for the reports in `report_dir`, which commits and pushes them if it is a git repository.
+/// Each commit along with the outcome of pushing it is recorded in `db`.
 pub fn select_callback(
+    db: persistence::Db,
     processors: u32,
     report_dir: &Path,
     mut progress: prodash::tree::Item,
@@ -123,7 +126,7 @@ pub fn select_callback(
                         }
                     }
 
-                    {
+                    let commit_oid = {
                         progress.set(3);
                         progress.blocked("writing commit", None);
                         let current_time =
@@ -137,7 +140,7 @@ pub fn select_callback(
                             .ok();
                         let mut parent_store = Vec::with_capacity(1);
 
-                        repo.commit(
+                        let oid = repo.commit(
                             Some("HEAD"),
                             &signature,
                             &signature,
@@ -152,7 +155,8 @@ pub fn select_callback(
                             },
                         )?;
                         progress.done("Commit created");
-                    }
+                        oid
+                    };
 
                     progress.set(4);
                     progress.blocked("pushing changes", None);
@@ -166,7 +170,7 @@ pub fn select_callback(
                         .map(|b| b.as_str().expect("valid utf8").to_string())
                         .unwrap_or_else(|_| "origin".into());
 
-                    futures_lite::future::block_on(enforce_threaded(
+                    let push_result = futures_lite::future::block_on(enforce_threaded(
                         SystemTime::now() + std::time::Duration::from_secs(60 * 60),
                         {
                             let mut progress = progress.add_child("git push");
@@ -209,7 +213,20 @@ pub fn select_callback(
                                 Ok(())
                             }
                         },
-                    ))??;
+                    ))
+                    .and_then(|res| res);
+                    if let Err(err) = record_publish(
+                        &db,
+                        model::Publish {
+                            published_at: SystemTime::now(),
+                            commit: commit_oid.to_string(),
+                            files: req_count,
+                            push_error: push_result.as_ref().err().map(ToString::to_string),
+                        },
+                    ) {
+                        progress.fail(format!("Could not record publish attempt: {}", err));
+                    }
+                    push_result?;
                     progress.done("Pushed changes");
                     Ok(())
                 })();
@@ -241,6 +258,15 @@ pub fn select_callback(
     }
 }
 
+/// Record `publish` and update the status file with it, if there is one.
+fn record_publish(db: &persistence::Db, publish: model::Publish) -> Result<()> {
+    db.open_publishes()?.record(&publish)?;
+    if let Some((_, context)) = db.open_context()?.most_recent()? {
+        crate::engine::stage::reconcile::write_status_file(db, &context)?;
+    }
+    Ok(())
+}
+
 pub fn repo_with_working_dir(req: WriteRequest, send: &WriteCallbackState) -> BoxFuture<Result<WriteInstruction>> {
     async move {
         send.as_ref()
@@ -1,15 +1,19 @@
 use crate::{
-    engine::report,
-    persistence::{self, new_key_value_query_old_to_new_filtered, TableAccess},
-    utils::check,
+    engine::report::{
+        self,
+        generic::{CustomWriteCallback, WriteCallback},
+    },
+    persistence::{self, TableAccess},
+    utils::{check, CancelOnDrop, Cancellation},
     {Error, Result},
 };
 use futures_util::FutureExt;
-use rusqlite::NO_PARAMS;
+use rusqlite::{params, OptionalExtension};
 use std::{path::PathBuf, time::SystemTime};
 
 mod git;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn generate(
     db: persistence::Db,
     mut progress: prodash::tree::Item,
@@ -17,31 +21,46 @@ pub async fn generate(
     glob: Option<String>,
     deadline: Option<SystemTime>,
     cpu_o_bound_processors: u32,
+    chunk_size: usize,
+    in_flight_chunks: usize,
+    write_callback: Option<CustomWriteCallback>,
 ) -> Result<()> {
     use report::generic::Generator;
-    let krates = db.open_crates()?;
     let output_dir = assets_dir
         .parent()
         .expect("assets directory to be in criner.db")
         .join("reports");
-    let glob_str = glob.as_deref();
-    let num_crates = krates.count_filtered(glob_str) as usize;
-    let chunk_size = 500.min(num_crates);
+    progress.blocked("ordering crates by downloads", None);
+    let crate_names = blocking::unblock({
+        let db = db.clone();
+        let glob = glob.clone();
+        move || crate_names_by_downloads(&db, glob.as_deref())
+    })
+    .await?;
+    let num_crates = crate_names.len();
+    let chunk_size = chunk_size.max(1).min(num_crates);
     if chunk_size == 0 {
         return Ok(());
     }
     progress.init(Some(num_crates), Some("crates".into()));
+    let cancel = Cancellation::new(deadline);
+    let _cancel_on_drop = CancelOnDrop(cancel.clone());
 
     let (processors, rx_result) = {
-        let (tx_task, rx_task) = async_channel::bounded(1);
+        let (tx_task, rx_task) = async_channel::bounded(in_flight_chunks.max(1));
         let (tx_result, rx_result) = async_channel::bounded(cpu_o_bound_processors as usize * 2);
 
         for _ in 0..cpu_o_bound_processors {
             let task = rx_task.clone();
             let result = tx_result.clone();
+            let cancel = cancel.clone();
             crate::spawn(blocking::unblock(move || {
                 futures_lite::future::block_on(async move {
                     while let Ok(f) = task.recv().await {
+                        if cancel.is_cancelled() {
+                            // drop the chunk without running it
+                            continue;
+                        }
                         result.send(f.await).await.map_err(Error::send_msg("send CPU result"))?;
                     }
                     Ok::<_, Error>(())
@@ -58,9 +77,8 @@ pub async fn generate(
         move || std::fs::create_dir_all(dir)
     })
     .await?;
-    use crate::engine::report::generic::WriteCallback;
-    let (cache_dir, (git_handle, git_state, maybe_join_handle)) = match glob.as_ref() {
-        Some(_) => (None, (git::not_available as WriteCallback, None, None)),
+    let cache_dir = match glob.as_ref() {
+        Some(_) => None,
         None => {
             let cd = waste_report_dir.join("__incremental_cache__");
             blocking::unblock({
@@ -68,16 +86,24 @@ pub async fn generate(
                 move || std::fs::create_dir_all(cd)
             })
             .await?;
-            (
-                Some(cd),
-                git::select_callback(cpu_o_bound_processors, &waste_report_dir, progress.add_child("git")),
-            )
+            Some(cd)
         }
     };
+    let (git_handle, git_state, maybe_join_handle) = match (write_callback, cache_dir.as_ref()) {
+        (Some(CustomWriteCallback { write, state }), _) => (write, state, None),
+        (None, Some(_)) => git::select_callback(
+            db.clone(),
+            cpu_o_bound_processors,
+            &waste_report_dir,
+            progress.add_child("git"),
+        ),
+        (None, None) => (git::not_available as WriteCallback, None, None),
+    };
     let merge_reports = crate::spawn({
         let mut merge_progress = progress.add_child("report aggregator");
         merge_progress.init(Some(num_crates / chunk_size), Some("Reports".into()));
         report::waste::Generator::merge_reports(
+            db.clone(),
             waste_report_dir.clone(),
             cache_dir.clone(),
             merge_progress,
@@ -89,35 +115,34 @@ pub async fn generate(
         .boxed()
     });
 
-    let mut fetched_crates = 0;
-    let mut chunk = Vec::<(String, Vec<u8>)>::with_capacity(chunk_size as usize);
-    let mut cid = 0;
-    loop {
-        let abort_loop = {
+    let mut deadline_exceeded = None;
+    for (cid, names) in crate_names.chunks(chunk_size).enumerate() {
+        let chunk = {
             progress.blocked("fetching chunk of crates to schedule", None);
             let connection = db.open_connection_no_async_with_busy_wait()?;
-            let mut statement = new_key_value_query_old_to_new_filtered(
-                persistence::CrateTable::table_name(),
-                glob_str,
-                &connection,
-                Some((fetched_crates, chunk_size as usize)),
-            )?;
-
-            chunk.clear();
-            chunk.extend(
-                statement
-                    .query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?)))?
-                    .filter_map(|r| r.ok()),
-            );
-            fetched_crates += chunk.len();
-
-            chunk.len() != chunk_size as usize
+            let mut statement = connection.prepare(&format!(
+                "SELECT key, data FROM {} WHERE key = ?1",
+                persistence::CrateTable::table_name()
+            ))?;
+            let mut chunk = Vec::<(String, Vec<u8>)>::with_capacity(names.len());
+            for name in names {
+                if let Some(row) = statement
+                    .query_row(params![name], |r| Ok((r.get(0)?, r.get(1)?)))
+                    .optional()?
+                {
+                    chunk.push(row);
+                }
+            }
+            chunk
         };
 
-        cid += 1;
-        check(deadline)?;
+        if let Err(err) = check(deadline) {
+            cancel.cancel();
+            deadline_exceeded = Some(err);
+            break;
+        }
 
-        progress.set(cid * chunk_size);
+        progress.set((cid + 1) * chunk_size);
         progress.halted("write crate report", None);
         processors
             .send(report::waste::Generator::write_files(
@@ -128,19 +153,19 @@ pub async fn generate(
                 progress.add_child(""),
                 git_handle,
                 git_state.clone(),
+                cancel.clone(),
             ))
             .await
             .map_err(Error::send_msg("Chunk of files to write"))?;
-        chunk = Vec::with_capacity(chunk_size as usize);
-        if abort_loop {
-            break;
-        }
     }
     drop(git_state);
     drop(processors);
     progress.set(num_crates);
+    // chunks finished before cancellation are still merged, as their crates are marked done
     merge_reports.await;
-    progress.done("Generating and merging waste report done");
+    if deadline_exceeded.is_none() {
+        progress.done("Generating and merging waste report done");
+    }
 
     if let Some(handle) = maybe_join_handle {
         progress.blocked("waiting for git to finish", None);
@@ -148,5 +173,15 @@ pub async fn generate(
             progress.fail("git failed with unknown error");
         }
     };
-    Ok(())
+    deadline_exceeded.map_or(Ok(()), Err)
+}
+
+/// Return the names of all crates matching `glob`, the ones with the most downloads according to the db dump first,
+/// so that a run cut short by its deadline still refreshed the reports which are looked at most.
+fn crate_names_by_downloads(db: &persistence::Db, glob: Option<&str>) -> Result<Vec<String>> {
+    let connection = db.open_connection_no_async_with_busy_wait()?;
+    Ok(persistence::crates_by_downloads(glob, &connection)?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect())
 }
@@ -0,0 +1,124 @@
+//! Adapt the amount of downloads performed at once to how well the server copes with them.
+use event_listener::Event;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Responses taking longer than this to arrive suggest the server is busy, so concurrency isn't increased
+const SLOW_RESPONSE: Duration = Duration::from_secs(2);
+/// The minimal time between two decreases, so a burst of failures of concurrent downloads only counts once
+const DECREASE_COOLDOWN: Duration = Duration::from_secs(10);
+
+/// How a download went, as far as the load of the server is concerned
+pub enum Signal {
+    /// The server responded within the given time
+    Responded(Duration),
+    /// The server asked to slow down with a 429 or 503 status, or didn't respond in time
+    Overloaded,
+    /// The download failed for reasons unrelated to the load of the server, like a missing file
+    Neutral,
+}
+
+/// A limit on the amount of downloads performed at once, shared by all clones.
+///
+/// It's halved whenever the server appears overloaded and grows by one after each limit's worth of fast responses,
+/// but never exceeds the maximum it started at.
+#[derive(Clone)]
+pub struct AdaptiveConcurrency {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    max: usize,
+    state: parking_lot::Mutex<State>,
+    /// Notified whenever a download finishes or the limit grows
+    changed: Event,
+}
+
+struct State {
+    limit: usize,
+    active: usize,
+    fast_responses: usize,
+    decreased_at: Option<Instant>,
+}
+
+/// The permission to perform a download, which is returned once dropped
+pub struct Permit {
+    inner: Arc<Inner>,
+}
+
+impl AdaptiveConcurrency {
+    /// Allow up to `max` downloads at once, which is also where the limit starts.
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        AdaptiveConcurrency {
+            inner: Arc::new(Inner {
+                max,
+                state: parking_lot::Mutex::new(State {
+                    limit: max,
+                    active: 0,
+                    fast_responses: 0,
+                    decreased_at: None,
+                }),
+                changed: Event::new(),
+            }),
+        }
+    }
+
+    /// Wait until fewer downloads than the current limit are performed, and return the permission to perform one.
+    pub async fn acquire(&self) -> Permit {
+        loop {
+            let listener = self.inner.changed.listen();
+            {
+                let mut state = self.inner.state.lock();
+                if state.active < state.limit {
+                    state.active += 1;
+                    return Permit {
+                        inner: self.inner.clone(),
+                    };
+                }
+            }
+            listener.await;
+        }
+    }
+}
+
+impl Permit {
+    /// Adjust the limit according to how the download performed with this permit went.
+    pub fn finish(self, signal: Signal) {
+        let mut state = self.inner.state.lock();
+        match signal {
+            Signal::Responded(latency) if latency < SLOW_RESPONSE => {
+                state.fast_responses += 1;
+                if state.fast_responses >= state.limit && state.limit < self.inner.max {
+                    state.limit += 1;
+                    state.fast_responses = 0;
+                }
+            }
+            Signal::Responded(_) | Signal::Neutral => {}
+            Signal::Overloaded => {
+                let cooled_down = state
+                    .decreased_at
+                    .map(|at| at.elapsed() >= DECREASE_COOLDOWN)
+                    .unwrap_or(true);
+                if cooled_down {
+                    state.limit = (state.limit / 2).max(1);
+                    state.fast_responses = 0;
+                    state.decreased_at = Some(Instant::now());
+                    log::info!(
+                        "Reduced download concurrency to {} as the server appears overloaded",
+                        state.limit
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.inner.state.lock().active -= 1;
+        self.inner.changed.notify(usize::MAX);
+    }
+}
@@ -0,0 +1,97 @@
+use super::concurrency::{AdaptiveConcurrency, Permit, Signal};
+use futures_lite::future::{block_on, poll_once};
+use std::time::Duration;
+
+const FAST: Duration = Duration::from_millis(10);
+
+/// The amount of downloads which may be performed at once right now
+fn limit(concurrency: &AdaptiveConcurrency) -> usize {
+    let mut permits = Vec::new();
+    while let Some(permit) = block_on(poll_once(concurrency.acquire())) {
+        permits.push(permit);
+    }
+    permits.len()
+}
+
+fn permit(concurrency: &AdaptiveConcurrency) -> Permit {
+    block_on(poll_once(concurrency.acquire())).expect("a download to be allowed")
+}
+
+fn finish_with(concurrency: &AdaptiveConcurrency, signal: impl Fn() -> Signal, times: usize) {
+    for _ in 0..times {
+        permit(concurrency).finish(signal());
+    }
+}
+
+#[test]
+fn the_limit_starts_at_the_maximum_which_is_at_least_one() {
+    assert_eq!(limit(&AdaptiveConcurrency::new(4)), 4);
+    assert_eq!(limit(&AdaptiveConcurrency::new(0)), 1);
+}
+
+#[test]
+fn permits_are_returned_once_dropped() {
+    let concurrency = AdaptiveConcurrency::new(2);
+    let first = permit(&concurrency);
+    assert_eq!(limit(&concurrency), 1);
+    drop(first);
+    assert_eq!(limit(&concurrency), 2);
+}
+
+#[test]
+fn an_overloaded_server_halves_the_limit_once_per_cooldown() {
+    let concurrency = AdaptiveConcurrency::new(8);
+    finish_with(&concurrency, || Signal::Overloaded, 1);
+    assert_eq!(limit(&concurrency), 4);
+
+    finish_with(&concurrency, || Signal::Overloaded, 3);
+    assert_eq!(
+        limit(&concurrency),
+        4,
+        "failures within the cooldown are considered part of the same burst"
+    );
+}
+
+#[test]
+fn the_limit_never_drops_below_one() {
+    let concurrency = AdaptiveConcurrency::new(1);
+    finish_with(&concurrency, || Signal::Overloaded, 1);
+    assert_eq!(limit(&concurrency), 1);
+}
+
+#[test]
+fn the_limit_grows_by_one_after_as_many_fast_responses() {
+    let concurrency = AdaptiveConcurrency::new(8);
+    finish_with(&concurrency, || Signal::Overloaded, 1);
+
+    finish_with(&concurrency, || Signal::Responded(FAST), 3);
+    assert_eq!(limit(&concurrency), 4);
+    finish_with(&concurrency, || Signal::Responded(FAST), 1);
+    assert_eq!(limit(&concurrency), 5);
+
+    finish_with(&concurrency, || Signal::Responded(FAST), 4);
+    assert_eq!(
+        limit(&concurrency),
+        5,
+        "the limit grew, so it takes more fast responses"
+    );
+    finish_with(&concurrency, || Signal::Responded(FAST), 1);
+    assert_eq!(limit(&concurrency), 6);
+}
+
+#[test]
+fn slow_responses_and_neutral_failures_do_not_grow_the_limit() {
+    let concurrency = AdaptiveConcurrency::new(4);
+    finish_with(&concurrency, || Signal::Overloaded, 1);
+
+    finish_with(&concurrency, || Signal::Responded(Duration::from_secs(5)), 4);
+    finish_with(&concurrency, || Signal::Neutral, 4);
+    assert_eq!(limit(&concurrency), 2);
+}
+
+#[test]
+fn the_limit_is_capped_at_the_maximum() {
+    let concurrency = AdaptiveConcurrency::new(2);
+    finish_with(&concurrency, || Signal::Responded(FAST), 10);
+    assert_eq!(limit(&concurrency), 2);
+}
@@ -1,34 +1,171 @@
-use crate::engine::report::waste::{tar_path_to_utf8_str, CargoConfig};
-use crate::{error::Result, model, persistence, Error};
+use crate::{
+    engine::{run::ExtractionProfile, work::queue},
+    error::Result,
+    model, persistence, Error,
+};
 use async_trait::async_trait;
+use criner_waste_report::{tar_path_to_utf8_str, CargoConfig};
+use serde_derive::{Deserialize, Serialize};
 use std::io::Seek;
-use std::{fs::File, io::BufReader, io::Read, path::PathBuf, time::SystemTime};
+use std::{
+    fs::File,
+    io::BufReader,
+    io::Read,
+    path::PathBuf,
+    time::{Instant, SystemTime},
+};
 
 struct ProcessingState {
     downloaded_crate: PathBuf,
     key: String,
+    crate_name: String,
+    crate_version: String,
 }
 pub struct Agent {
     asset_dir: PathBuf,
-    results: persistence::TaskResultTable,
+    extractor: Extractor,
+    blobs: persistence::CrateBlobTable,
     state: Option<ProcessingState>,
-    standard_bin_path: globset::GlobMatcher,
 }
 
 impl Agent {
-    pub fn new(asset_dir: PathBuf, db: &persistence::Db) -> Result<Agent> {
-        let results = db.open_results()?;
+    pub fn new(asset_dir: PathBuf, profile: ExtractionProfile, db: &persistence::Db) -> Result<Agent> {
+        let extractor = Extractor::new(profile, db)?;
+        let blobs = db.open_crate_blobs()?;
         Ok(Agent {
             asset_dir,
-            results,
+            extractor,
+            blobs,
             state: None,
-            standard_bin_path: globset::Glob::new("src/bin/*.rs")
-                .expect("valid statically known glob")
-                .compile_matcher(),
         })
     }
 }
 
+/// Extracts crate archives and records what was learned from them, for CPU-bound processors extracting downloaded
+/// archives as well as for IO-bound ones extracting archives right after downloading them into memory.
+pub struct Extractor {
+    profile: ExtractionProfile,
+    results: persistence::TaskResultTable,
+    tar_headers: persistence::TarHeaderTable,
+    empty_artifacts: persistence::EmptyArtifactTable,
+    latest_versions: persistence::LatestVersionTable,
+    costs: persistence::ProcessingCostTable,
+    tasks: persistence::TaskTable,
+    selected_paths: globset::GlobSet,
+}
+
+impl Extractor {
+    pub fn new(profile: ExtractionProfile, db: &persistence::Db) -> Result<Extractor> {
+        Ok(Extractor {
+            selected_paths: selected_paths(&profile)?,
+            profile,
+            results: db.open_results()?,
+            tar_headers: db.open_tar_headers()?,
+            empty_artifacts: db.open_empty_artifacts()?,
+            latest_versions: db.open_latest_versions()?,
+            costs: db.open_processing_costs()?,
+            tasks: db.open_tasks()?,
+        })
+    }
+
+    /// Extract the crate `archive` of the given crate version which is held in memory, and record the outcome along
+    /// with the state of its extraction task like a CPU-bound processor would. Empty archives are recorded as such
+    /// and aren't an error.
+    pub fn extract_in_memory(
+        &self,
+        (crate_name, crate_version): (&str, &str),
+        archive: Vec<u8>,
+        progress: &mut prodash::tree::Item,
+    ) -> Result<()> {
+        use persistence::TableAccess;
+        let mut task = default_persisted_extraction_task();
+        let (task_key, key) = extraction_keys(crate_name, crate_version, &task);
+        let archive_bytes = archive.len() as u64;
+        let start = Instant::now();
+        let outcome = extract_crate_archive(
+            &self.results,
+            &self.tar_headers,
+            &key,
+            (crate_name, crate_version),
+            progress,
+            std::io::Cursor::new(archive),
+            &self.selected_paths,
+            &self.profile,
+        );
+        let res = self.record(outcome, (crate_name, crate_version), start, archive_bytes);
+        task.state = match &res {
+            Ok(()) => model::TaskState::Complete,
+            Err((Error::EmptyArtifact(reason), _)) => model::TaskState::EmptyArtifact(reason.clone()),
+            Err((err, _)) => model::TaskState::AttemptsWithFailure(vec![err.to_string()]),
+        };
+        self.tasks.upsert(progress, &task_key, &task)?;
+        match res {
+            Ok(()) | Err((Error::EmptyArtifact(_), _)) => Ok(()),
+            Err((err, msg)) => Err(Error::Message(format!("{}: {}", msg, err))),
+        }
+    }
+
+    /// Record the `outcome` of extracting an archive with `archive_bytes` of the given crate version, which started
+    /// at `start`.
+    fn record(
+        &self,
+        outcome: Result<()>,
+        (crate_name, crate_version): (&str, &str),
+        start: Instant,
+        archive_bytes: u64,
+    ) -> std::result::Result<(), (Error, String)> {
+        match outcome {
+            Err(Error::EmptyArtifact(reason)) => {
+                self.empty_artifacts
+                    .record(crate_name, crate_version, &reason)
+                    .map_err(|err| (err, "Failed to record empty crate archive".into()))?;
+                Err((Error::EmptyArtifact(reason), "Crate archive is empty".into()))
+            }
+            Err(err) => Err((err, "Failed to extract crate".into())),
+            Ok(()) => {
+                let cost = persistence::ProcessingCost {
+                    crate_name: crate_name.to_owned(),
+                    crate_version: crate_version.to_owned(),
+                    stage: persistence::ProcessingStage::Extract,
+                    duration: start.elapsed(),
+                    bytes: archive_bytes,
+                };
+                if let Err(err) = self.costs.record(&[cost]) {
+                    log::warn!("Could not record the cost of extracting a crate: {}", err);
+                }
+                self.latest_versions
+                    .record_analyzed(crate_name, crate_version)
+                    .map_err(|err| (err, "Failed to record the latest analyzed version".into()))
+            }
+        }
+    }
+}
+
+/// Return the key of the extraction `task` of the given crate version, along with the key of its result.
+fn extraction_keys(crate_name: &str, crate_version: &str, task: &model::Task) -> (String, String) {
+    let mut task_key = String::new();
+    task.fq_key(crate_name, crate_version, &mut task_key);
+    let dummy_result = model::TaskResult::ExplodedCrate {
+        entries_meta_data: vec![],
+        selected_entries: vec![],
+        truncated_entries: vec![],
+    };
+    let mut key = String::with_capacity(task_key.len() * 2);
+    dummy_result.fq_key(crate_name, crate_version, task, &mut key);
+    (task_key, key)
+}
+
+/// Match the paths of files to select besides the manifests and the targets declared in them, which are the binaries
+/// cargo finds without them being declared in the manifest along with the files selected by the `profile`
+pub(crate) fn selected_paths(profile: &ExtractionProfile) -> Result<globset::GlobSet> {
+    let mut selected_paths = globset::GlobSetBuilder::new();
+    selected_paths.add(globset::Glob::new("src/bin/*.rs")?);
+    for pattern in &profile.selected_paths {
+        selected_paths.add(globset::Glob::new(pattern)?);
+    }
+    Ok(selected_paths.build()?)
+}
+
 #[async_trait]
 impl crate::engine::work::generic::Processor for Agent {
     type Item = ExtractRequest;
@@ -47,26 +184,28 @@ impl crate::engine::work::generic::Processor for Agent {
 
         let progress_info = format!("CPU UNZIP+UNTAR {}:{}", crate_name, crate_version);
         let dummy_task = default_persisted_extraction_task();
-        let mut task_key = String::new();
-        dummy_task.fq_key(&crate_name, &crate_version, &mut task_key);
-
-        let downloaded_crate = super::schedule::download_file_path(
-            &self.asset_dir,
-            &crate_name,
-            &crate_version,
-            &download_task.process,
-            &download_task.version,
-            "crate",
-        );
-        let dummy_result = model::TaskResult::ExplodedCrate {
-            entries_meta_data: vec![],
-            selected_entries: vec![],
-        };
-
-        let mut key = String::with_capacity(task_key.len() * 2);
-        dummy_result.fq_key(&crate_name, &crate_version, &dummy_task, &mut key);
+        let (task_key, key) = extraction_keys(&crate_name, &crate_version, &dummy_task);
 
-        self.state = Some(ProcessingState { downloaded_crate, key });
+        let downloaded_crate = self
+            .blobs
+            .checksum_of(&crate_name, &crate_version)?
+            .and_then(|checksum| super::schedule::crate_blob_path(&self.asset_dir, &checksum))
+            .unwrap_or_else(|| {
+                super::schedule::download_file_path(
+                    &self.asset_dir,
+                    &crate_name,
+                    &crate_version,
+                    &download_task.process,
+                    &download_task.version,
+                    "crate",
+                )
+            });
+        self.state = Some(ProcessingState {
+            downloaded_crate,
+            key,
+            crate_name,
+            crate_version,
+        });
         Ok((dummy_task, task_key, progress_info))
     }
 
@@ -75,19 +214,47 @@ impl crate::engine::work::generic::Processor for Agent {
     }
 
     async fn process(&mut self, progress: &mut prodash::tree::Item) -> std::result::Result<(), (Error, String)> {
-        let ProcessingState { downloaded_crate, key } = self.state.take().expect("state to be set");
-        extract_crate(&self.results, &key, progress, downloaded_crate, &self.standard_bin_path)
-            .map_err(|err| (err, "Failed to extract crate".into()))
+        let ProcessingState {
+            downloaded_crate,
+            key,
+            crate_name,
+            crate_version,
+        } = self.state.take().expect("state to be set");
+        let archive_bytes = std::fs::metadata(&downloaded_crate).map(|m| m.len()).unwrap_or(0);
+        let start = Instant::now();
+        let outcome = extract_crate(
+            &self.extractor.results,
+            &self.extractor.tar_headers,
+            &key,
+            (&crate_name, &crate_version),
+            progress,
+            downloaded_crate,
+            &self.extractor.selected_paths,
+            &self.extractor.profile,
+        );
+        self.extractor
+            .record(outcome, (&crate_name, &crate_version), start, archive_bytes)
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ExtractRequest {
     pub download_task: model::Task,
     pub crate_name: String,
     pub crate_version: String,
 }
 
+impl queue::Item for ExtractRequest {
+    fn key(&self) -> String {
+        let mut key = String::new();
+        default_persisted_extraction_task().fq_key(&self.crate_name, &self.crate_version, &mut key);
+        key
+    }
+    fn crate_name_and_version(&self) -> Option<(&str, &str)> {
+        Some((&self.crate_name, &self.crate_version))
+    }
+}
+
 pub fn default_persisted_extraction_task() -> model::Task {
     const TASK_NAME: &str = "extract_crate";
     const TASK_VERSION: &str = "1.0.0";
@@ -96,23 +263,56 @@ pub fn default_persisted_extraction_task() -> model::Task {
         process: TASK_NAME.into(),
         version: TASK_VERSION.into(),
         state: Default::default(),
+        validators: None,
     }
 }
 
-fn extract_crate(
+pub(crate) fn extract_crate(
     results: &persistence::TaskResultTable,
+    tar_headers: &persistence::TarHeaderTable,
     key: &str,
+    (crate_name, crate_version): (&str, &str),
     progress: &mut prodash::tree::Item,
     downloaded_crate: PathBuf,
-    standard_bin_path: &globset::GlobMatcher,
+    selected_paths: &globset::GlobSet,
+    profile: &ExtractionProfile,
+) -> Result<()> {
+    extract_crate_archive(
+        results,
+        tar_headers,
+        key,
+        (crate_name, crate_version),
+        progress,
+        BufReader::new(File::open(downloaded_crate)?),
+        selected_paths,
+        profile,
+    )
+}
+
+/// Extract the crate archive read from `archive` and store the result under `key`, along with the tar headers of all
+/// of its entries.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn extract_crate_archive(
+    results: &persistence::TaskResultTable,
+    tar_headers: &persistence::TarHeaderTable,
+    key: &str,
+    (crate_name, crate_version): (&str, &str),
+    progress: &mut prodash::tree::Item,
+    mut archive: impl Read + Seek,
+    selected_paths: &globset::GlobSet,
+    profile: &ExtractionProfile,
 ) -> Result<()> {
     use persistence::TableAccess;
-    let mut archive = tar::Archive::new(libflate::gzip::Decoder::new(BufReader::new(File::open(
-        downloaded_crate,
-    )?))?);
+    if archive.seek(std::io::SeekFrom::End(0))? == 0 {
+        return Err(Error::EmptyArtifact(
+            "the downloaded crate archive has zero bytes".into(),
+        ));
+    }
+    archive.seek(std::io::SeekFrom::Start(0))?;
+    let mut archive = tar::Archive::new(libflate::gzip::Decoder::new(archive)?);
 
     let mut buf = Vec::new();
-    let mut interesting_paths = vec!["Cargo.toml".to_string(), "Cargo.lock".into()];
+    let mut interesting_paths = vec!["Cargo.toml".to_string(), "Cargo.toml.orig".into(), "Cargo.lock".into()];
     let mut files = Vec::new();
     for e in archive.entries()? {
         progress.inc();
@@ -127,59 +327,83 @@ fn extract_crate(
         }
     }
 
-    let mut archive = tar::Archive::new(libflate::gzip::Decoder::new(BufReader::new({
-        let mut file = archive.into_inner().into_inner();
-        file.seek(std::io::SeekFrom::Start(0))?;
-        file
-    }))?);
+    let mut archive = tar::Archive::new(libflate::gzip::Decoder::new({
+        let mut archive = archive.into_inner().into_inner();
+        archive.seek(std::io::SeekFrom::Start(0))?;
+        archive
+    })?);
 
     let mut meta_data = Vec::new();
     let mut meta_count = 0;
     let mut file_count = 0;
-    let mut max_storage_size = [0; 128 * 1024];
+    let mut truncated_entries = Vec::new();
+    let mut bytes_left = profile.max_selected_bytes.unwrap_or(usize::MAX);
     for e in archive.entries()? {
         meta_count += 1;
         progress.set(meta_count);
         let mut e: tar::Entry<_> = e?;
-        meta_data.push(model::TarHeader {
+        let header = model::TarHeader {
             path: e.path_bytes().to_vec(),
             size: e.header().size()?,
             entry_type: e.header().entry_type().as_byte(),
-        });
+        };
+        meta_data.push(header.clone());
 
-        if interesting_paths
-            .iter()
-            .any(|p| p == tar_path_to_utf8_str(e.path_bytes().as_ref()))
-            || standard_bin_path.is_match(tar_path_to_utf8_str(e.path_bytes().as_ref()))
-        {
+        let path = tar_path_to_utf8_str(&header.path);
+        if interesting_paths.iter().any(|p| p == path) || selected_paths.is_match(path) {
             file_count += 1;
 
-            let slice = if tar_path_to_utf8_str(&e.path_bytes().as_ref()) == "Cargo.toml"
-                || tar_path_to_utf8_str(&e.path_bytes().as_ref()) == "Cargo.lock"
-            {
-                buf.clear();
+            buf.clear();
+            if profile.complete_paths.iter().any(|p| p == path) {
                 e.read_to_end(&mut buf)?;
-                &buf
             } else {
-                let bytes_read = e.read(&mut max_storage_size[..])?;
-                &max_storage_size[..bytes_read]
-            };
-            files.push((
-                meta_data.last().expect("to have pushed one just now").to_owned(),
-                slice.to_owned(),
-            ));
+                let sample_size = profile.sample_size.min(bytes_left);
+                (&mut e).take(sample_size as u64).read_to_end(&mut buf)?;
+                if header.size > buf.len() as u64 {
+                    truncated_entries.push(model::ContentSample {
+                        path: header.path.clone(),
+                        len: header.size,
+                        sha256: sha256_of_sample_and_rest(&buf, &mut e)?,
+                    });
+                }
+            }
+            bytes_left = bytes_left.saturating_sub(buf.len());
+            files.push((header, buf.clone()));
         }
     }
+    if meta_count == 0 {
+        return Err(Error::EmptyArtifact("the crate archive has no entries".into()));
+    }
     progress.info(format!(
-        "Recorded {} files and stored {} in full",
-        meta_count, file_count
+        "Recorded {} files and stored {}, {} of which were truncated",
+        meta_count,
+        file_count,
+        truncated_entries.len()
     ));
 
+    tar_headers.insert_all(progress, crate_name, crate_version, &meta_data)?;
     let task_result = model::TaskResult::ExplodedCrate {
-        entries_meta_data: meta_data,
+        entries_meta_data: Vec::new(),
         selected_entries: files,
+        truncated_entries,
     };
     results.insert(progress, &key, &task_result)?;
 
     Ok(())
 }
+
+/// Compute the hash of an entry of which the `sample` was read already, with the `rest` still to be read.
+fn sha256_of_sample_and_rest(sample: &[u8], rest: &mut impl Read) -> Result<String> {
+    use sha2::Digest;
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(sample);
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let bytes_read = rest.read(&mut buf)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buf[..bytes_read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
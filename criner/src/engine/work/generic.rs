@@ -1,5 +1,125 @@
-use crate::{model, persistence, persistence::TableAccess, Error, Result};
+use crate::{engine::work::queue, model, persistence, persistence::TableAccess, Error, Result};
 use async_trait::async_trait;
+use event_listener::Event;
+use futures_lite::FutureExt;
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+/// The reason of crashes noticed after a restart, as all we know is that the process stopped while processing
+const INTERRUPTED_CRASH_REASON: &str = "the process stopped while working on it, possibly as it ran out of memory";
+
+/// How long a task is leased to this process, unless the lease is renewed while working on it
+const LEASE_DURATION: Duration = Duration::from_secs(2 * 60);
+/// How often leases are renewed while working on a task
+const LEASE_RENEWAL_INTERVAL: Duration = Duration::from_secs(30);
+/// How long processors may take to put the tasks they abandoned back into the state they had before
+const ABANDON_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Set once processors should stop starting on new requests, as the process is shutting down
+static DRAINING: AtomicBool = AtomicBool::new(false);
+/// Set once processors should abandon the requests they are still processing, as the shutdown grace period is over
+static ABANDONING: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// The keys of the requests all processors are working on, each with an event notified once it's done.
+    /// Requests queued again while they are processed, like by overlapping sweeps, wait for the original instead.
+    static ref IN_FLIGHT: parking_lot::Mutex<BTreeMap<String, Arc<Event>>> = Default::default();
+    /// Identifies this process as owner of task leases, telling it apart from other processes sharing the database
+    static ref LEASE_OWNER: String = format!(
+        "{}-{}",
+        std::process::id(),
+        SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+    );
+    /// Notified whenever a request stops being processed, and once processors should abandon their requests
+    static ref DRAIN_PROGRESS: Event = Event::new();
+}
+
+/// When to stop processing crate versions whose processing crashes repeatedly
+#[derive(Clone, Copy)]
+pub struct QuarantineSettings {
+    /// The time at which the process started. Tasks which were still in progress before crashed the process.
+    pub startup_time: SystemTime,
+    /// The amount of crashes after which a crate version is quarantined
+    pub max_crashes: usize,
+}
+
+/// How often and how soon processing a request is attempted again after it failed in a way that may pass by itself
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    /// The amount of attempts after which the failure is recorded in the task, including the first one
+    pub max_attempts: usize,
+    /// The time to wait before the first retry, which doubles with each of the following ones
+    pub backoff: Duration,
+    /// The longest time to wait before a retry
+    pub max_backoff: Duration,
+    /// If set, wait a random duration of up to the backoff instead, so failures which happened together don't
+    /// lead to retries at the same time
+    pub jitter: bool,
+    /// Return true if processing failed with an error which may pass by itself
+    pub is_retryable: fn(&Error) -> bool,
+}
+
+impl RetryPolicy {
+    /// Never retry, and record all failures right away
+    pub fn never() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            backoff: Duration::default(),
+            max_backoff: Duration::default(),
+            jitter: false,
+            is_retryable: |_| false,
+        }
+    }
+
+    /// Retry downloads up to `max_attempts` times if they time out or the server fails or asks to slow down,
+    /// backing off exponentially with jitter.
+    pub fn download(max_attempts: usize) -> Self {
+        RetryPolicy {
+            max_attempts,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            jitter: true,
+            is_retryable: |err| match err {
+                Error::Timeout(_, _) => true,
+                Error::HttpStatus(status) => status.as_u16() == 429 || status.is_server_error(),
+                _ => false,
+            },
+        }
+    }
+
+    /// Return the time to wait before the given `retry`, with 1 being the first one, or None if no retry is due
+    /// for `err`. `seed` varies the jitter between requests.
+    fn backoff(&self, err: &Error, retry: usize, seed: &str) -> Option<Duration> {
+        if retry >= self.max_attempts || !(self.is_retryable)(err) {
+            return None;
+        }
+        let backoff = self
+            .backoff
+            .checked_mul(1 << (retry - 1).min(31) as u32)
+            .map(|backoff| backoff.min(self.max_backoff))
+            .unwrap_or(self.max_backoff);
+        if !self.jitter {
+            return Some(backoff);
+        }
+        let nanos = SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        let random = crate::utils::fnv1a64(format!("{}{}{}", seed, retry, nanos).as_bytes());
+        Some(backoff.mul_f64((random % 1024) as f64 / 1024.0))
+    }
+}
 
 #[async_trait]
 pub trait Processor {
@@ -12,24 +132,63 @@ pub trait Processor {
     async fn schedule_next(&mut self, _progress: &mut prodash::tree::Item) -> Result<()> {
         Ok(())
     }
+    /// Record what was learned while processing successfully in `task`, before it is stored
+    fn update_task(&mut self, _task: &mut model::Task) {}
 }
 
-pub async fn processor<T: Clone>(
+pub async fn processor<T: queue::Item + Clone>(
     db: persistence::Db,
     mut progress: prodash::tree::Item,
-    r: async_channel::Receiver<T>,
+    r: queue::Receiver<T>,
     mut agent: impl Processor<Item = T> + Send,
-    max_retries_on_timeout: usize,
+    retry: RetryPolicy,
+    quarantine: Option<QuarantineSettings>,
 ) -> Result<()> {
     let tasks = db.open_tasks()?;
+    let leases = db.open_task_leases()?;
+    let quarantine = match quarantine {
+        Some(settings) => Some((settings, db.open_crate_version_quarantine()?)),
+        None => None,
+    };
 
-    while let Ok(request) = r.recv().await {
+    'requests: while let Some((id, request)) = r.recv().await? {
+        let key = request.key();
+        let _in_flight = loop {
+            if let Some(in_flight) = claim(&key).await {
+                break in_flight;
+            }
+            let state = tasks.get(&key)?.map(|task| task.state);
+            if let Some(model::TaskState::Complete) | Some(model::TaskState::EmptyArtifact(_)) = state {
+                progress.info(format!("skipping {} as another processor just finished it", key));
+                r.done(id)?;
+                continue 'requests;
+            }
+        };
+        if DRAINING.load(Ordering::SeqCst) {
+            // The request stays queued, to be picked up again on next start
+            r.release(id)?;
+            return Ok(());
+        }
+        // Another process sharing the database may still work on the request after its claim expired. The request
+        // stays claimed by us until our claim expires as well, and is removed once the other process is done with it.
+        if !leases.acquire(&key, &LEASE_OWNER, LEASE_DURATION)? {
+            progress.info(format!("skipping {} as another process is working on it", key));
+            continue;
+        }
+        if let Some((settings, quarantine)) = quarantine.as_ref() {
+            if is_quarantined(settings, quarantine, &tasks, &request, &mut progress)? {
+                leases.release(&key, &LEASE_OWNER)?;
+                r.done(id)?;
+                continue;
+            }
+        }
         let mut try_count = 0;
         let (task, task_key) = loop {
             let (dummy_task, task_key, progress_name) = agent.set(request.clone(), &mut progress)?;
             progress.set_name(progress_name);
 
             let mut task = tasks.update(Some(&mut progress), &task_key, |mut t| {
+                t.stored_at = SystemTime::now();
                 t.process = dummy_task.process.clone();
                 t.version = dummy_task.version.clone();
                 t.state.merge_with(&model::TaskState::InProgress(None));
@@ -38,18 +197,75 @@ pub async fn processor<T: Clone>(
 
             try_count += 1;
             progress.blocked("working", None);
-            let res = agent.process(&mut progress).await;
+            let res = match AssertUnwindSafe(agent.process(&mut progress))
+                .catch_unwind()
+                .or(renew_lease(&leases, &key, &r, id))
+                .or(abandoned(Ok(Err((Error::Interrupted, "Abandoned processing".into())))))
+                .await
+            {
+                Ok(res) => res,
+                Err(panic) => {
+                    let reason = format!("it panicked: {}", panic_message(&*panic));
+                    if let (Some((settings, quarantine)), Some((crate_name, crate_version))) =
+                        (quarantine.as_ref(), request.crate_name_and_version())
+                    {
+                        if quarantine.record_crash(crate_name, crate_version, &reason, settings.max_crashes)? {
+                            progress.fail(format!(
+                                "quarantined {}:{} as processing it crashed {} times",
+                                crate_name, crate_version, settings.max_crashes
+                            ));
+                        }
+                    }
+                    Err((Error::Message(reason), "Processing crashed".into()))
+                }
+            };
 
-            task.state = match res {
-                Err((err @ Error::Timeout(_, _), _)) if try_count < max_retries_on_timeout => {
-                    progress.fail(format!("{} → retrying ({}/{})", err, try_count, max_retries_on_timeout));
+            if let Err((Error::DeadlineExceeded(_), msg)) = &res {
+                // The task stays in progress and the request stays queued, to be picked up again on next start
+                progress.info(format!("{} as the deadline was reached", msg));
+                leases.release(&key, &LEASE_OWNER)?;
+                r.release(id)?;
+                return Ok(());
+            }
+            if let Err((Error::Interrupted, msg)) = &res {
+                // Put back the task into the state it had before, as it would count as crash on next start otherwise
+                progress.info(format!("{} as the process is shutting down", msg));
+                tasks.update(Some(&mut progress), &task_key, |mut t| {
+                    t.state = t.state.abandoned();
+                    t
+                })?;
+                leases.release(&key, &LEASE_OWNER)?;
+                r.release(id)?;
+                return Ok(());
+            }
+            if let Err((err, _)) = &res {
+                // Failures are recorded right away while draining, instead of waiting to retry
+                let backoff = retry
+                    .backoff(err, try_count, &task_key)
+                    .filter(|_| !DRAINING.load(Ordering::SeqCst));
+                if let Some(backoff) = backoff {
+                    progress.fail(format!(
+                        "{} → retrying in {:.1}s ({}/{})",
+                        err,
+                        backoff.as_secs_f32(),
+                        try_count,
+                        retry.max_attempts
+                    ));
+                    async_io::Timer::after(backoff).await;
                     continue;
                 }
+            }
+            task.state = match res {
+                Err((Error::EmptyArtifact(reason), msg)) => {
+                    progress.info(format!("{}: {}", msg, reason));
+                    model::TaskState::EmptyArtifact(reason)
+                }
                 Err((err, msg)) => {
                     progress.fail(format!("{}: {}", msg, err));
                     model::TaskState::AttemptsWithFailure(vec![err.to_string()])
                 }
                 Ok(_) => {
+                    agent.update_task(&mut task);
                     agent.schedule_next(&mut progress).await.ok();
                     model::TaskState::Complete
                 }
@@ -58,8 +274,157 @@ pub async fn processor<T: Clone>(
         };
 
         tasks.upsert(&mut progress, &task_key, &task)?;
+        leases.release(&key, &LEASE_OWNER)?;
+        r.done(id)?;
         progress.set_name(agent.idle_message());
         progress.init(None, None);
     }
     Ok(())
 }
+
+/// Stop all processors from starting on new requests, and wait up to `grace_period` for them to be done with the ones
+/// they are processing. Requests still being processed by then are abandoned where possible, with their tasks put back
+/// into the state they had before, and stay queued. Return the amount of requests which were still being processed.
+pub async fn drain(grace_period: Duration) -> usize {
+    DRAINING.store(true, Ordering::SeqCst);
+    if until_idle(Instant::now() + grace_period).await {
+        return 0;
+    }
+    let abandoned = IN_FLIGHT.lock().len();
+    ABANDONING.store(true, Ordering::SeqCst);
+    DRAIN_PROGRESS.notify(usize::MAX);
+    until_idle(Instant::now() + ABANDON_TIMEOUT).await;
+    abandoned
+}
+
+/// Wait until no request is processed anymore and return true, or return false if that's not the case at `deadline`.
+async fn until_idle(deadline: Instant) -> bool {
+    loop {
+        let listener = DRAIN_PROGRESS.listen();
+        if IN_FLIGHT.lock().is_empty() {
+            return true;
+        }
+        let timed_out = async {
+            listener.await;
+            false
+        }
+        .or(async {
+            async_io::Timer::at(deadline).await;
+            true
+        })
+        .await;
+        if timed_out {
+            return IN_FLIGHT.lock().is_empty();
+        }
+    }
+}
+
+/// Wait until processors should abandon the requests they are processing, and return `value`.
+async fn abandoned<T>(value: T) -> T {
+    loop {
+        let listener = DRAIN_PROGRESS.listen();
+        if ABANDONING.load(Ordering::SeqCst) {
+            return value;
+        }
+        listener.await;
+    }
+}
+
+/// Renew the lease on the task with `key` along with the claim on the request with `id` in `queue` periodically, for as
+/// long as it's polled.
+async fn renew_lease<T, R: queue::Item>(
+    leases: &persistence::TaskLeaseTable,
+    key: &str,
+    queue: &queue::Receiver<R>,
+    id: i64,
+) -> T {
+    loop {
+        async_io::Timer::after(LEASE_RENEWAL_INTERVAL).await;
+        if let Err(err) = leases.acquire(key, &LEASE_OWNER, LEASE_DURATION) {
+            log::warn!("Could not renew the lease on task '{}': {}", key, err);
+        }
+        if let Err(err) = queue.renew(id) {
+            log::warn!("Could not renew the claim on the request for task '{}': {}", key, err);
+        }
+    }
+}
+
+/// A request being processed, which stops being in flight once dropped
+struct InFlight {
+    key: String,
+    done: Arc<Event>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.lock().remove(&self.key);
+        self.done.notify(usize::MAX);
+        DRAIN_PROGRESS.notify(usize::MAX);
+    }
+}
+
+/// Mark the request with `key` as in flight, or wait until the processor working on it is done and return None.
+async fn claim(key: &str) -> Option<InFlight> {
+    let listener = {
+        let mut in_flight = IN_FLIGHT.lock();
+        match in_flight.get(key) {
+            Some(done) => done.listen(),
+            None => {
+                let done = Arc::new(Event::new());
+                in_flight.insert(key.to_owned(), done.clone());
+                return Some(InFlight {
+                    key: key.to_owned(),
+                    done,
+                });
+            }
+        }
+    };
+    listener.await;
+    None
+}
+
+/// Return true if the crate version of `request` is quarantined, which happens if its processing crashed
+/// too often. A task still in progress from before the process started counts as crash.
+fn is_quarantined(
+    settings: &QuarantineSettings,
+    quarantine: &persistence::CrateVersionQuarantineTable,
+    tasks: &persistence::TaskTable,
+    request: &impl queue::Item,
+    progress: &mut prodash::tree::Item,
+) -> Result<bool> {
+    let (crate_name, crate_version) = match request.crate_name_and_version() {
+        Some(crate_name_and_version) => crate_name_and_version,
+        None => return Ok(false),
+    };
+    if quarantine.is_quarantined(crate_name, crate_version)? {
+        progress.info(format!("skipping quarantined {}:{}", crate_name, crate_version));
+        return Ok(true);
+    }
+    let was_interrupted = tasks
+        .get(&request.key())?
+        .map(|task| matches!(task.state, model::TaskState::InProgress(_)) && task.stored_at < settings.startup_time)
+        .unwrap_or(false);
+    if was_interrupted
+        && quarantine.record_crash(
+            crate_name,
+            crate_version,
+            INTERRUPTED_CRASH_REASON,
+            settings.max_crashes,
+        )?
+    {
+        progress.fail(format!(
+            "quarantined {}:{} as processing it crashed {} times",
+            crate_name, crate_version, settings.max_crashes
+        ));
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause")
+}
@@ -1,4 +1,11 @@
 use crate::{
+    engine::{
+        run::{Bandwidth, DownloadSettings, RequestRate, TlsPolicy, TlsVersion},
+        work::{
+            concurrency::{AdaptiveConcurrency, Signal},
+            cpubound, queue,
+        },
+    },
     model,
     persistence::{self, TableAccess},
     Error, Result,
@@ -6,29 +13,222 @@ use crate::{
 use bytesize::ByteSize;
 use futures_lite::{io::AsyncWriteExt, FutureExt};
 
-use crate::utils::timeout_after;
+use crate::utils::{available_space, enforce, timeout_after};
 use async_trait::async_trait;
+use serde_derive::{Deserialize, Serialize};
 use std::{
     path::{Path, PathBuf},
-    time::{Duration, SystemTime},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
 
-const CONNECT_AND_FETCH_HEAD_TIMEOUT: Duration = Duration::from_secs(15);
-const FETCH_CHUNK_TIMEOUT_SECONDS: Duration = Duration::from_secs(10);
+/// How often to probe idle connections, which keeps them alive for the next download to reuse
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+/// Identifies criner and where to learn more about it, as asked for by the crawler policy of crates.io
+pub const DEFAULT_USER_AGENT: &str = concat!(
+    "criner/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/the-lean-crate/criner)"
+);
+/// How often to check whether disk space was reclaimed while downloads are paused for the lack of it
+const DISK_SPACE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// The most memory reserved up front for crate archives downloaded into memory, as their size is claimed by the server
+const MAX_PREALLOCATED_ARCHIVE_SIZE: usize = 16 * 1024 * 1024;
 
 struct ProcessingState {
     url: String,
-    kind: &'static str,
+    fallback_urls: Vec<String>,
+    kind: String,
     output_file_path: PathBuf,
     result_key: Option<String>,
+    crate_name_and_version: Option<(String, String)>,
+    expected_sha256: Option<String>,
+    validators: Option<model::HttpValidators>,
+    timeouts: Timeouts,
 }
+/// What we learned about a request, for recording it in the request log and the task
+#[derive(Default)]
+struct RequestOutcome {
+    status: Option<u16>,
+    /// The time it took until the response headers arrived
+    latency: Option<Duration>,
+    bytes: u64,
+    /// True if the server responded that the resource didn't change since it was downloaded last
+    not_modified: bool,
+    /// The validators of the response if the request was conditional
+    validators: Option<model::HttpValidators>,
+}
+
+/// HTTP clients to download with, with dedicated ones for hosts with a TLS policy.
+///
+/// Clones share their pools of kept-alive connections, so processors downloading from the same hosts should share them
+/// to not connect anew for each download.
+#[derive(Clone)]
+pub struct Clients {
+    default: reqwest::Client,
+    by_host: Vec<(String, reqwest::Client)>,
+    bandwidth: Bandwidth,
+    request_rate: RequestRate,
+    min_free_space: Option<u64>,
+    deadline: Option<SystemTime>,
+}
+
+impl Clients {
+    /// Create clients according to `settings`, whose downloads are abandoned once the `deadline` is reached.
+    pub fn new(settings: &DownloadSettings, deadline: Option<SystemTime>) -> Result<Clients> {
+        Ok(Clients {
+            default: new_client_builder(None, settings)?.build()?,
+            by_host: settings
+                .tls
+                .iter()
+                .map(|policy| {
+                    Ok((
+                        policy.host.clone(),
+                        new_client_builder(Some(policy), settings)?.build()?,
+                    ))
+                })
+                .collect::<Result<_>>()?,
+            bandwidth: settings.bandwidth.clone(),
+            request_rate: settings.request_rate.clone(),
+            min_free_space: settings.min_free_space,
+            deadline,
+        })
+    }
+
+    /// Return the client to use for `url`, along with the host if it has a TLS policy.
+    fn for_url(&self, url: &str) -> (&reqwest::Client, Option<&str>) {
+        let url = reqwest::Url::parse(url).ok();
+        let host = url.as_ref().and_then(|url| url.host_str());
+        self.by_host
+            .iter()
+            .find(|(policy_host, _)| Some(policy_host.as_str()) == host)
+            .map(|(host, client)| (client, Some(host.as_str())))
+            .unwrap_or((&self.default, None))
+    }
+}
+
+/// Return a builder of clients for requests to `url`, which send the User-Agent and use the proxy of `settings` along
+/// with the TLS policy of the host of `url` if it has one. All requests criner sends should use clients built by it,
+/// to honor the crawler policy of crates.io no matter which of them sends the request.
+pub fn client_builder(settings: &DownloadSettings, url: &str) -> Result<reqwest::ClientBuilder> {
+    let url = reqwest::Url::parse(url).ok();
+    let host = url.as_ref().and_then(|url| url.host_str());
+    let policy = settings.tls.iter().find(|policy| Some(policy.host.as_str()) == host);
+    new_client_builder(policy, settings)
+}
+
+fn new_client_builder(policy: Option<&TlsPolicy>, settings: &DownloadSettings) -> Result<reqwest::ClientBuilder> {
+    let mut builder = reqwest::ClientBuilder::new()
+        .gzip(true)
+        .tcp_keepalive(Some(TCP_KEEPALIVE))
+        .user_agent(settings.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT));
+    if let Some(proxy) = settings.proxy.as_deref() {
+        builder = builder.proxy(explicit_proxy(proxy)?);
+    }
+    let policy = match policy {
+        Some(policy) => policy,
+        None => return Ok(builder),
+    };
+    let mut tls = native_tls::TlsConnector::builder();
+    if !policy.pinned_certificates.is_empty() {
+        tls.disable_built_in_roots(true);
+        for pem in &policy.pinned_certificates {
+            tls.add_root_certificate(native_tls::Certificate::from_pem(pem)?);
+        }
+    }
+    tls.min_protocol_version(policy.min_version.map(|version| match version {
+        TlsVersion::Tls10 => native_tls::Protocol::Tlsv10,
+        TlsVersion::Tls11 => native_tls::Protocol::Tlsv11,
+        TlsVersion::Tls12 => native_tls::Protocol::Tlsv12,
+    }));
+    Ok(builder.use_preconfigured_tls(tls.build()?))
+}
+
+/// The schemes of proxy urls, with `socks5h` resolving host names through the proxy unlike `socks5`
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+
+/// A proxy at `url` for all requests except for those to hosts listed in `NO_PROXY`, which explicitly configured
+/// proxies don't honor by themselves. Like with curl, `*` matches all hosts and `example.com` matches its subdomains.
+fn explicit_proxy(url: &str) -> Result<reqwest::Proxy> {
+    let proxy =
+        reqwest::Url::parse(url).map_err(|err| Error::Message(format!("Invalid proxy url '{}': {}", url, err)))?;
+    if !PROXY_SCHEMES.contains(&proxy.scheme()) {
+        return Err(Error::Message(format!(
+            "Invalid proxy url '{}': the scheme must be one of {}",
+            url,
+            PROXY_SCHEMES.join(", ")
+        )));
+    }
+    let no_proxy: Vec<String> = std::env::var("NO_PROXY")
+        .or_else(|_| std::env::var("no_proxy"))
+        .unwrap_or_default()
+        .split(',')
+        .map(|host| host.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect();
+    Ok(reqwest::Proxy::custom(move |target| {
+        let host = target.host_str()?;
+        let bypass = no_proxy
+            .iter()
+            .any(|pattern| pattern == "*" || host == pattern || host.ends_with(&format!(".{}", pattern)));
+        if bypass {
+            None
+        } else {
+            Some(proxy.clone())
+        }
+    }))
+}
+
+/// Tell how the server coped with a request from its result `res` and `outcome`.
+fn load_signal(res: &Result<()>, outcome: &RequestOutcome) -> Signal {
+    match (res, outcome.status, outcome.latency) {
+        (_, Some(429), _) | (_, Some(503), _) | (Err(Error::Timeout(_, _)), _, _) => Signal::Overloaded,
+        (Err(Error::Reqwest(err)), _, _) if err.is_timeout() || err.is_connect() => Signal::Overloaded,
+        (Ok(()), _, Some(latency)) => Signal::Responded(latency),
+        _ => Signal::Neutral,
+    }
+}
+
+/// Turn failed TLS handshakes with hosts that have a TLS policy into their own kind of error, as retrying won't help.
+fn classify_tls_error(err: Error, host_with_policy: Option<&str>) -> Error {
+    match (err, host_with_policy) {
+        (Error::Reqwest(err), Some(host)) if is_tls_error(&err) => Error::TlsPinMismatch(host.to_owned(), err),
+        (err, _) => err,
+    }
+}
+
+fn is_tls_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if err.is::<native_tls::Error>() {
+            return true;
+        }
+        // Connectors wrap TLS errors into IO errors, which don't expose them as their source.
+        if let Some(inner) = err.downcast_ref::<std::io::Error>().and_then(|err| err.get_ref()) {
+            if inner.is::<native_tls::Error>() {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
 pub struct Agent<Fn, FnResult> {
-    client: reqwest::Client,
+    clients: Clients,
     results: persistence::TaskResultTable,
-    channel: async_channel::Sender<FnResult>,
+    request_log: persistence::RequestLogTable,
+    costs: persistence::ProcessingCostTable,
+    context: persistence::MetaTable,
+    blobs: persistence::CrateBlobTable,
+    /// If set, crates are extracted right after downloading them into memory, without storing their archives
+    extractor: Option<Arc<cpubound::Extractor>>,
+    channel: Box<dyn queue::Enqueue<FnResult>>,
+    concurrency: Option<AdaptiveConcurrency>,
     state: Option<ProcessingState>,
     make_state: Fn,
     next_action_state: Option<FnResult>,
+    validators: Option<model::HttpValidators>,
 }
 
 impl<Fn, FnResult> Agent<Fn, FnResult>
@@ -37,21 +237,71 @@ where
 {
     pub fn new(
         db: &persistence::Db,
-        channel: async_channel::Sender<FnResult>,
+        clients: Clients,
+        concurrency: Option<AdaptiveConcurrency>,
+        extractor: Option<cpubound::Extractor>,
+        channel: impl queue::Enqueue<FnResult> + 'static,
         make_state: Fn,
     ) -> Result<Agent<Fn, FnResult>> {
-        let client = reqwest::ClientBuilder::new().gzip(true).build()?;
-
         let results = db.open_results()?;
+        let request_log = db.open_request_log()?;
+        let costs = db.open_processing_costs()?;
+        let context = db.open_context()?;
+        let blobs = db.open_crate_blobs()?;
         Ok(Agent {
-            client,
+            clients,
             results,
-            channel,
+            request_log,
+            costs,
+            context,
+            blobs,
+            extractor: extractor.map(Arc::new),
+            channel: Box::new(channel),
+            concurrency,
             state: None,
             next_action_state: None,
             make_state,
+            validators: None,
         })
     }
+
+    fn record_cost(&self, crate_name: String, crate_version: String, requested_at: SystemTime, bytes: u64) {
+        let cost = persistence::ProcessingCost {
+            crate_name,
+            crate_version,
+            stage: persistence::ProcessingStage::Download,
+            duration: SystemTime::now().duration_since(requested_at).unwrap_or_default(),
+            bytes,
+        };
+        if let Err(err) = self.costs.record(&[cost]) {
+            log::warn!("Could not record the cost of downloading a crate: {}", err);
+        }
+    }
+
+    /// Remember that the given crate version is stored in the content-addressed blob for `checksum`,
+    /// if that's where it was downloaded to.
+    fn record_blob(&self, crate_name: &str, crate_version: &str, checksum: &str, stored_at: &Path) {
+        let checksum = checksum.to_ascii_lowercase();
+        if stored_at.file_name() != Some(std::ffi::OsStr::new(&format!("{}.crate", checksum))) {
+            return;
+        }
+        if let Err(err) = self.blobs.record(crate_name, crate_version, &checksum) {
+            log::warn!("Could not record the blob of a downloaded crate: {}", err);
+        }
+    }
+
+    /// Add the bytes received by a request made at `requested_at` and the time it took to today's statistics,
+    /// counting it as download if it `completed`.
+    fn record_throughput(&self, requested_at: SystemTime, bytes: u64, completed: bool) {
+        let duration = SystemTime::now().duration_since(requested_at).unwrap_or_default();
+        if let Err(err) = self.context.update_today(|c| {
+            c.counts.downloads += completed as u64;
+            c.counts.downloaded_bytes += bytes;
+            c.durations.download += duration;
+        }) {
+            log::warn!("Could not record download throughput: {}", err);
+        }
+    }
 }
 
 #[async_trait]
@@ -75,20 +325,29 @@ where
             crate_name_and_version,
             kind,
             url,
+            fallback_urls,
+            expected_sha256,
+            validators,
+            timeouts,
         } = request;
         let dummy_task = default_persisted_download_task();
         let progress_name = format!("↓ {}", progress_name);
 
         let task_result = model::TaskResult::Download {
-            kind: kind.to_owned(),
+            kind: kind.clone(),
             url: String::new(),
             content_length: 0,
             content_type: None,
+            etag: None,
+            last_modified: None,
+            final_url: None,
+            response_time: None,
         };
 
         self.next_action_state = (self.make_state)(crate_name_and_version.clone(), &dummy_task, &output_file_path);
         self.state = Some(ProcessingState {
             url,
+            fallback_urls,
             kind,
             output_file_path,
             result_key: crate_name_and_version.as_ref().map(|(crate_name, crate_version)| {
@@ -96,6 +355,10 @@ where
                 task_result.fq_key(&crate_name, &crate_version, &dummy_task, &mut result_key);
                 result_key
             }),
+            crate_name_and_version,
+            expected_sha256,
+            validators,
+            timeouts,
         });
         Ok((dummy_task, task_key, progress_name))
     }
@@ -107,21 +370,110 @@ where
     async fn process(&mut self, progress: &mut prodash::tree::Item) -> std::result::Result<(), (Error, String)> {
         let ProcessingState {
             url,
+            fallback_urls,
             kind,
             output_file_path,
             result_key,
+            crate_name_and_version,
+            expected_sha256,
+            validators,
+            timeouts,
         } = self.state.take().expect("initialized state");
-        download_file_and_store_result(
-            progress,
-            result_key,
-            &self.results,
-            &self.client,
-            kind,
-            &url,
-            output_file_path,
-        )
-        .await
-        .map_err(|err| (err, format!("Failed to download '{}'", url)))
+        let num_urls = 1 + fallback_urls.len();
+        let mut last_error = None;
+        for (attempt, url) in std::iter::once(url).chain(fallback_urls).enumerate() {
+            let requested_at = SystemTime::now();
+            let mut outcome = RequestOutcome::default();
+            let permit = match &self.concurrency {
+                Some(concurrency) => {
+                    progress.blocked("waiting for a download slot", None);
+                    Some(concurrency.acquire().await)
+                }
+                None => None,
+            };
+            self.clients.request_rate.wait_for(&url, Some(progress)).await;
+            let (client, host_with_policy) = self.clients.for_url(&url);
+            let stream_extraction = self.extractor.as_ref().zip(crate_name_and_version.as_ref());
+            let download = match stream_extraction {
+                Some((extractor, (crate_name, crate_version))) => download_and_extract(
+                    progress,
+                    result_key.clone(),
+                    &self.results,
+                    extractor,
+                    (crate_name, crate_version),
+                    client,
+                    &self.clients.bandwidth,
+                    &kind,
+                    &url,
+                    expected_sha256.as_deref(),
+                    timeouts,
+                    &mut outcome,
+                )
+                .boxed(),
+                None => download_file_and_store_result(
+                    progress,
+                    result_key.clone(),
+                    &self.results,
+                    client,
+                    &self.clients.bandwidth,
+                    self.clients.min_free_space,
+                    &kind,
+                    &url,
+                    output_file_path.clone(),
+                    expected_sha256.as_deref(),
+                    validators.as_ref(),
+                    timeouts,
+                    &mut outcome,
+                )
+                .boxed(),
+            };
+            // Partial downloads to disk abandoned at the deadline are resumed once the request is processed again
+            let res = enforce(self.clients.deadline, download)
+                .await
+                .and_then(|res| res)
+                .map_err(|err| classify_tls_error(err, host_with_policy));
+            if let Some(permit) = permit {
+                permit.finish(load_signal(&res, &outcome));
+            }
+            self.record_throughput(requested_at, outcome.bytes, res.is_ok() && !outcome.not_modified);
+            if let Err(err) = self.request_log.record(
+                &url,
+                requested_at,
+                outcome.status,
+                outcome.bytes,
+                res.as_ref().err().map(ToString::to_string).as_deref(),
+            ) {
+                log::warn!("Could not record request to '{}' in request log: {}", url, err);
+            }
+            match res {
+                Ok(()) => {
+                    if let (Some((crate_name, crate_version)), Some(checksum), None) =
+                        (&crate_name_and_version, &expected_sha256, stream_extraction)
+                    {
+                        // Archives which were extracted in memory were never stored
+                        self.record_blob(crate_name, crate_version, checksum, &output_file_path);
+                    }
+                    if outcome.not_modified {
+                        // There is nothing new to hand to the next stage
+                        self.next_action_state = None;
+                    } else if let Some((crate_name, crate_version)) = crate_name_and_version {
+                        self.record_cost(crate_name, crate_version, requested_at, outcome.bytes);
+                    }
+                    self.validators = outcome.validators;
+                    return Ok(());
+                }
+                Err(err @ Error::DeadlineExceeded(_)) => {
+                    return Err((err, format!("Abandoned download of '{}'", url)));
+                }
+                Err(err) => {
+                    if attempt + 1 < num_urls {
+                        progress.info(format!("Failed to download '{}', trying next mirror: {}", url, err));
+                    }
+                    last_error = Some((err, format!("Failed to download '{}'", url)));
+                }
+            }
+        }
+        Err(last_error.expect("at least one url"))
     }
 
     async fn schedule_next(&mut self, progress: &mut prodash::tree::Item) -> Result<()> {
@@ -131,23 +483,85 @@ where
             // we take the risk of duplicate work for keeping more processors busy.
             // NOTE: We assume there is no risk of double-scheduling, also we assume the consumer is faster
             // then the producer (us), so we are ok with blocking until the task is scheduled.
-            self.channel
-                .send(request)
-                .await
-                .map_err(Error::send_msg("IO Bound: Schedule next task"))?;
+            self.channel.enqueue(request).await?;
         }
         Ok(())
     }
+
+    fn update_task(&mut self, task: &mut model::Task) {
+        if let Some(validators) = self.validators.take() {
+            task.validators = Some(validators);
+        }
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct DownloadRequest {
     pub output_file_path: PathBuf,
     pub progress_name: String,
     pub task_key: String,
     pub crate_name_and_version: Option<(String, String)>,
-    pub kind: &'static str,
+    pub kind: String,
     pub url: String,
+    /// Urls to try in order if downloading from `url` fails, pointing to the same file
+    pub fallback_urls: Vec<String>,
+    /// If set, the downloaded file is verified to have the given sha256 checksum, hex encoded
+    pub expected_sha256: Option<String>,
+    /// If set, the file is only downloaded if it changed since it was served with these validators, which are
+    /// empty if it wasn't downloaded before. The validators of the response are recorded in the task.
+    #[serde(default)]
+    pub validators: Option<model::HttpValidators>,
+    /// How long downloading may take
+    #[serde(default)]
+    pub timeouts: Timeouts,
+}
+
+/// How long a download may take, which differs between large files like the db dump and small ones like crates
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Timeouts {
+    /// How long to wait for the connection and the response headers
+    pub connect: Duration,
+    /// How long to wait for each chunk of the response body
+    pub read: Duration,
+    /// How long the whole request may take, or None if it's only limited by the other timeouts
+    pub total: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    /// Timeouts suitable for small files like crates
+    fn default() -> Self {
+        Timeouts {
+            connect: Duration::from_secs(15),
+            read: Duration::from_secs(10),
+            total: Some(Duration::from_secs(10 * 60)),
+        }
+    }
+}
+
+impl Timeouts {
+    /// Return the time to wait for the next chunk of a response to a request made at `requested_at`, or an error if
+    /// the request took longer than allowed in total.
+    fn chunk_timeout(&self, requested_at: Instant) -> Result<Duration> {
+        let total = match self.total {
+            Some(total) => total,
+            None => return Ok(self.read),
+        };
+        match total.checked_sub(requested_at.elapsed()) {
+            Some(left) if left > Duration::default() => Ok(self.read.min(left)),
+            _ => Err(Error::Timeout(total, "downloading the whole file".into())),
+        }
+    }
+}
+
+impl queue::Item for DownloadRequest {
+    fn key(&self) -> String {
+        self.task_key.clone()
+    }
+    fn crate_name_and_version(&self) -> Option<(&str, &str)> {
+        self.crate_name_and_version
+            .as_ref()
+            .map(|(crate_name, crate_version)| (crate_name.as_str(), crate_version.as_str()))
+    }
 }
 
 pub fn default_persisted_download_task() -> model::Task {
@@ -158,49 +572,111 @@ pub fn default_persisted_download_task() -> model::Task {
         process: TASK_NAME.into(),
         version: TASK_VERSION.into(),
         state: Default::default(),
+        validators: None,
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn download_file_and_store_result(
     progress: &mut prodash::tree::Item,
     result_key: Option<String>,
     results: &persistence::TaskResultTable,
     client: &reqwest::Client,
+    bandwidth: &Bandwidth,
+    min_free_space: Option<u64>,
     kind: &str,
     url: &str,
     out_file: PathBuf,
+    expected_sha256: Option<&str>,
+    validators: Option<&model::HttpValidators>,
+    timeouts: Timeouts,
+    outcome: &mut RequestOutcome,
 ) -> Result<()> {
     blocking::unblock({
         let out_file = out_file.clone();
         move || std::fs::create_dir_all(&out_file.parent().expect("parent directory"))
     })
     .await?;
+    if let Some(min_free_space) = min_free_space {
+        wait_for_disk_space(out_file.parent().expect("parent directory"), min_free_space, progress).await?;
+    }
 
     // NOTE: We assume that the files we download never change, and we assume the server supports resumption!
-    let (start_byte, truncate) = blocking::unblock({
+    // Downloads are written to a partial file which is moved into place once complete, so the output file is never
+    // partial. Output files written by older versions may be, and are resumed in place.
+    let (write_to, mut start_byte) = blocking::unblock({
         let out_file = out_file.clone();
-        move || std::fs::metadata(&out_file)
+        move || match std::fs::metadata(&out_file) {
+            Ok(meta) => (out_file, meta.len()),
+            Err(_) => {
+                let partial_file = partial_file_path(&out_file);
+                let start_byte = std::fs::metadata(&partial_file).map(|meta| meta.len()).unwrap_or(0);
+                (partial_file, start_byte)
+            }
+        }
     })
-    .await
-    .map(|meta| (meta.len(), false))
-    .unwrap_or((0, true));
+    .await;
+
+    let mut request = client
+        .get(url)
+        .header(http::header::RANGE, format!("bytes={}-", start_byte));
+    if let (Some(validators), 0) = (validators, start_byte) {
+        if let Some(etag) = &validators.etag {
+            request = request.header(http::header::IF_NONE_MATCH, etag.as_str());
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(http::header::IF_MODIFIED_SINCE, last_modified.as_str());
+        }
+    }
 
     progress.blocked("fetch HEAD", None);
-    let mut response = timeout_after(
-        CONNECT_AND_FETCH_HEAD_TIMEOUT,
-        "fetching HEAD",
-        client
-            .get(url)
-            .header(http::header::RANGE, format!("bytes={}-", start_byte))
-            .send(),
-    )
-    .await??;
+    let requested_at = Instant::now();
+    let mut response = timeout_after(timeouts.connect, "fetching HEAD", request.send()).await??;
+    outcome.latency = Some(requested_at.elapsed());
+    outcome.status = Some(response.status().as_u16());
+    if validators.is_some() {
+        outcome.validators = Some(validators_of(response.headers()));
+    }
 
     match response.status().as_u16() {
-        200..=299 => {}
+        206 => {
+            if let Some(range_start) = content_range(response.headers()).map(|(start, _total)| start) {
+                if range_start != start_byte {
+                    remove_file(write_to.clone()).await;
+                    return Err(Error::Message(format!(
+                        "Server resumed download of '{}' at byte {}, but we asked for byte {}. Removed partial download.",
+                        url, range_start, start_byte
+                    )));
+                }
+            }
+        }
+        200..=299 => {
+            if start_byte != 0 {
+                progress.info(format!("{}: server does not support resumption - starting over", url));
+                start_byte = 0;
+            }
+        }
+        304 => {
+            progress.done(format!("{}: not modified since the last download - skipping", url));
+            outcome.not_modified = true;
+            return Ok(());
+        }
         416 => {
             // we assume that this means we have fully downloaded the item previously, and that the DB result was written already
             // but not checked
+            if let Some(total) = content_range(response.headers()).map(|(_start, total)| total) {
+                if total != start_byte {
+                    remove_file(write_to.clone()).await;
+                    return Err(Error::Message(format!(
+                        "File on disk for '{}' has {} bytes, but the server has {} bytes. Removed it to start over.",
+                        url, start_byte, total
+                    )));
+                }
+            }
+            if let Some(expected_sha256) = expected_sha256 {
+                verify_sha256(progress, &write_to, url, expected_sha256).await?;
+            }
+            finalize(&write_to, &out_file).await?;
             progress.done(format!(
                 "GET{}:{}: body-size = {}",
                 if start_byte != 0 {
@@ -229,26 +705,28 @@ async fn download_file_and_store_result(
         ByteSize(content_length as u64)
     ));
 
-    if remaining_content_length != 0 {
+    // An empty body still creates an empty file, to let the extraction tell empty crate archives apart from missing ones
+    if remaining_content_length != 0 || start_byte == 0 {
+        let truncate = start_byte == 0;
         let mut out = blocking::Unblock::new(
             blocking::unblock({
-                let out_file = out_file.clone();
+                let write_to = write_to.clone();
                 move || {
                     std::fs::OpenOptions::new()
                         .create(truncate)
                         .truncate(truncate)
                         .write(truncate)
                         .append(!truncate)
-                        .open(out_file)
+                        .open(write_to)
                 }
             })
             .await
-            .map_err(|err| crate::Error::Message(format!("Failed to open '{}': {}", out_file.display(), err)))?,
+            .map_err(|err| crate::Error::Message(format!("Failed to open '{}': {}", write_to.display(), err)))?,
         );
 
         let mut bytes_received = start_byte as usize;
         while let Some(chunk) = timeout_after(
-            FETCH_CHUNK_TIMEOUT_SECONDS,
+            timeouts.chunk_timeout(requested_at)?,
             format!(
                 "fetched {} of {}",
                 ByteSize(bytes_received as u64),
@@ -260,7 +738,18 @@ async fn download_file_and_store_result(
         {
             out.write_all(&chunk).await?;
             bytes_received += chunk.len();
+            outcome.bytes += chunk.len() as u64;
             progress.set(bytes_received / 1024);
+            bandwidth.consume(chunk.len()).await;
+        }
+        out.flush().await?;
+        if bytes_received != content_length {
+            return Err(Error::Message(format!(
+                "Download of '{}' ended after {} of {} - will resume on next attempt",
+                url,
+                ByteSize(bytes_received as u64),
+                ByteSize(content_length as u64)
+            )));
         }
         progress.done(format!(
             "GET{}:{}: body-size = {}",
@@ -268,23 +757,260 @@ async fn download_file_and_store_result(
             url,
             ByteSize(bytes_received as u64)
         ));
-        out.flush().await?;
     } else {
         progress.done(format!("{} already on disk - skipping", url))
     }
 
+    if let Some(expected_sha256) = expected_sha256 {
+        verify_sha256(progress, &write_to, url, expected_sha256).await?;
+    }
+    finalize(&write_to, &out_file).await?;
+
     if let Some(result_key) = result_key {
-        let task_result = model::TaskResult::Download {
-            kind: kind.to_owned(),
-            url: url.to_owned(),
-            content_length: content_length as u32,
-            content_type: response
-                .headers()
-                .get(http::header::CONTENT_TYPE)
-                .and_then(|t| t.to_str().ok())
-                .map(Into::into),
-        };
+        let task_result = download_result(kind, url, content_length, &response, outcome.latency);
         results.insert(progress, &result_key, &task_result)?;
     }
     Ok(())
 }
+
+/// Download the crate archive at `url` of the given crate version into memory and extract it with `extractor` right
+/// away, so it's never written to disk. The result of the download is stored under `result_key` once the extraction
+/// was recorded. Partial downloads can't be resumed, so they start over on the next attempt.
+///
+/// As there is no archive to extract from later, failing to extract fails the download as well, so the next attempt
+/// downloads the archive again. The extraction records its failure with its own task.
+#[allow(clippy::too_many_arguments)]
+async fn download_and_extract(
+    progress: &mut prodash::tree::Item,
+    result_key: Option<String>,
+    results: &persistence::TaskResultTable,
+    extractor: &Arc<cpubound::Extractor>,
+    (crate_name, crate_version): (&str, &str),
+    client: &reqwest::Client,
+    bandwidth: &Bandwidth,
+    kind: &str,
+    url: &str,
+    expected_sha256: Option<&str>,
+    timeouts: Timeouts,
+    outcome: &mut RequestOutcome,
+) -> Result<()> {
+    progress.blocked("fetch GET", None);
+    let requested_at = Instant::now();
+    let mut response = timeout_after(timeouts.connect, "fetching GET", client.get(url).send()).await??;
+    outcome.latency = Some(requested_at.elapsed());
+    outcome.status = Some(response.status().as_u16());
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus(response.status()));
+    }
+
+    let content_length = response
+        .content_length()
+        .ok_or(Error::InvalidHeader("expected content-length"))? as usize;
+    progress.init(Some(content_length / 1024), Some("Kb".into()));
+    let mut archive = Vec::with_capacity(content_length.min(MAX_PREALLOCATED_ARCHIVE_SIZE));
+    while let Some(chunk) = timeout_after(
+        timeouts.chunk_timeout(requested_at)?,
+        format!(
+            "fetched {} of {}",
+            ByteSize(archive.len() as u64),
+            ByteSize(content_length as u64)
+        ),
+        response.chunk().boxed(),
+    )
+    .await??
+    {
+        if archive.len() + chunk.len() > content_length {
+            return Err(Error::Message(format!(
+                "Download of '{}' exceeded its content length of {}",
+                url,
+                ByteSize(content_length as u64)
+            )));
+        }
+        archive.extend_from_slice(&chunk);
+        outcome.bytes += chunk.len() as u64;
+        progress.set(archive.len() / 1024);
+        bandwidth.consume(chunk.len()).await;
+    }
+    if archive.len() != content_length {
+        return Err(Error::Message(format!(
+            "Download of '{}' ended after {} of {} - will start over on next attempt",
+            url,
+            ByteSize(archive.len() as u64),
+            ByteSize(content_length as u64)
+        )));
+    }
+    progress.done(format!(
+        "GET(in memory):{}: body-size = {}",
+        url,
+        ByteSize(archive.len() as u64)
+    ));
+
+    if let Some(expected_sha256) = expected_sha256 {
+        use sha2::Digest;
+        progress.blocked("verifying checksum", None);
+        let actual_sha256 = format!("{:x}", sha2::Sha256::digest(&archive));
+        if actual_sha256 != expected_sha256 {
+            return Err(Error::Message(format!(
+                "Checksum mismatch for '{}': expected {}, got {}. Discarded the download to start over.",
+                url, expected_sha256, actual_sha256
+            )));
+        }
+    }
+
+    progress.blocked("extracting crate", None);
+    blocking::unblock({
+        let extractor = extractor.clone();
+        let (crate_name, crate_version) = (crate_name.to_owned(), crate_version.to_owned());
+        let mut progress = progress.add_child("extract");
+        move || extractor.extract_in_memory((&crate_name, &crate_version), archive, &mut progress)
+    })
+    .await?;
+
+    if let Some(result_key) = result_key {
+        let task_result = download_result(kind, url, content_length, &response, outcome.latency);
+        results.insert(progress, &result_key, &task_result)?;
+    }
+    Ok(())
+}
+
+/// The result of downloading `content_length` bytes of the given `kind` from `url`, which was answered with `response`
+/// after `response_time`.
+fn download_result(
+    kind: &str,
+    url: &str,
+    content_length: usize,
+    response: &reqwest::Response,
+    response_time: Option<Duration>,
+) -> model::TaskResult {
+    let validators = validators_of(response.headers());
+    model::TaskResult::Download {
+        kind: kind.to_owned(),
+        url: url.to_owned(),
+        content_length: content_length as u32,
+        content_type: response
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|t| t.to_str().ok())
+            .map(Into::into),
+        etag: validators.etag,
+        last_modified: validators.last_modified,
+        final_url: Some(response.url().to_string()).filter(|final_url| final_url != url),
+        response_time,
+    }
+}
+
+/// Parse `bytes <start>-<end>/<total>` or `bytes */<total>` into the start of the range, if present, and the total length
+fn content_range(headers: &http::HeaderMap) -> Option<(u64, u64)> {
+    let value = headers.get(http::header::CONTENT_RANGE)?.to_str().ok()?;
+    let mut tokens = value.strip_prefix("bytes ")?.splitn(2, '/');
+    let range = tokens.next()?;
+    let total = tokens.next()?.parse().ok()?;
+    let start = match range {
+        "*" => 0,
+        range => range.splitn(2, '-').next()?.parse().ok()?,
+    };
+    Some((start, total))
+}
+
+pub(crate) fn validators_of(headers: &http::HeaderMap) -> model::HttpValidators {
+    let get = |name: http::header::HeaderName| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned)
+    };
+    model::HttpValidators {
+        etag: get(http::header::ETAG),
+        last_modified: get(http::header::LAST_MODIFIED),
+    }
+}
+
+/// Wait while the volume `dir` is on has less than `min_free_space` bytes available, until enough was reclaimed.
+async fn wait_for_disk_space(dir: &Path, min_free_space: u64, progress: &mut prodash::tree::Item) -> Result<()> {
+    let mut paused = false;
+    loop {
+        let available = blocking::unblock({
+            let dir = dir.to_owned();
+            move || available_space(&dir)
+        })
+        .await?;
+        if available >= min_free_space {
+            if paused {
+                progress.info(format!(
+                    "resuming as {} of disk space are available",
+                    ByteSize(available)
+                ));
+            }
+            return Ok(());
+        }
+        if !paused {
+            progress.info(format!(
+                "pausing as only {} of disk space are left, less than {}",
+                ByteSize(available),
+                ByteSize(min_free_space)
+            ));
+            paused = true;
+        }
+        progress.blocked("waiting for free disk space", None);
+        async_io::Timer::after(DISK_SPACE_POLL_INTERVAL).await;
+    }
+}
+
+/// The path of the file a download to `out_file` is written to until it's complete
+fn partial_file_path(out_file: &Path) -> PathBuf {
+    let mut file_name = out_file.file_name().expect("file name").to_owned();
+    file_name.push(".part");
+    out_file.with_file_name(file_name)
+}
+
+/// Move the complete download at `written_to` into place at `out_file`, unless it was written there directly.
+async fn finalize(written_to: &Path, out_file: &Path) -> Result<()> {
+    if written_to == out_file {
+        return Ok(());
+    }
+    let (written_to, out_file) = (written_to.to_owned(), out_file.to_owned());
+    Ok(blocking::unblock(move || std::fs::rename(written_to, out_file)).await?)
+}
+
+async fn remove_file(path: PathBuf) {
+    blocking::unblock(move || std::fs::remove_file(path)).await.ok();
+}
+
+/// Return the hex-encoded sha256 checksum of the file at `path`.
+pub(crate) fn sha256_of_file(path: &Path) -> std::io::Result<String> {
+    use sha2::Digest;
+    use std::io::Read;
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = sha2::Sha256::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let bytes_read = file.read(&mut buf)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buf[..bytes_read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+async fn verify_sha256(
+    progress: &mut prodash::tree::Item,
+    path: &Path,
+    url: &str,
+    expected_sha256: &str,
+) -> Result<()> {
+    progress.blocked("verifying checksum", None);
+    let actual_sha256 = blocking::unblock({
+        let path = path.to_owned();
+        move || sha256_of_file(&path)
+    })
+    .await?;
+    if actual_sha256 != expected_sha256 {
+        remove_file(path.to_owned()).await;
+        return Err(Error::Message(format!(
+            "Checksum mismatch for '{}': expected {}, got {}. Removed the download to start over.",
+            url, expected_sha256, actual_sha256
+        )));
+    }
+    Ok(())
+}
@@ -1,5 +1,9 @@
+pub mod concurrency;
+#[cfg(test)]
+mod concurrency_test;
 pub mod generic;
 pub mod iobound;
+pub mod queue;
 pub mod schedule;
 
 pub mod cpubound;
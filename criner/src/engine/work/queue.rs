@@ -0,0 +1,214 @@
+//! Persistent work queues between schedulers and processors, which survive restarts of the process.
+use crate::{persistence, Error, Result};
+use async_trait::async_trait;
+use event_listener::{Event, EventListener};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// How often to look for changes without being notified, as other processes may use the same queue
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long claimed items are left alone unless their claim is renewed, before they are handed out again
+const CLAIM_DURATION: Duration = Duration::from_secs(2 * 60);
+
+/// Work which can be stored in a queue
+pub trait Item: Serialize + DeserializeOwned + Send {
+    /// A key identifying the work to do, which is queued only once
+    fn key(&self) -> String;
+    /// The crate version the work is for, if any, which is quarantined if processing it crashes too often
+    fn crate_name_and_version(&self) -> Option<(&str, &str)> {
+        None
+    }
+}
+
+/// Something to send work to, either a persistent queue or a channel
+#[async_trait]
+pub trait Enqueue<T>: Send + Sync {
+    async fn enqueue(&self, item: T) -> Result<()>;
+}
+
+#[async_trait]
+impl<T: Item + 'static> Enqueue<T> for Sender<T> {
+    async fn enqueue(&self, item: T) -> Result<()> {
+        self.send(item).await
+    }
+}
+
+#[async_trait]
+impl<T: Send + 'static> Enqueue<T> for async_channel::Sender<T> {
+    async fn enqueue(&self, item: T) -> Result<()> {
+        self.send(item).await.map_err(Error::send_msg("Enqueue work"))
+    }
+}
+
+struct Shared {
+    table: persistence::QueueTable,
+    name: &'static str,
+    capacity: u64,
+    senders: AtomicUsize,
+    /// Notified whenever items are added or claimed, and when the last sender is dropped
+    changed: Event,
+}
+
+/// Open the queue with the given `name` and return its sending and receiving end.
+///
+/// Senders wait while `capacity` items are queued. Items whose claim wasn't renewed within `CLAIM_DURATION` are
+/// claimed again, as the processor working on them appears to be gone.
+pub fn persistent<T: Item>(
+    db: &persistence::Db,
+    name: &'static str,
+    capacity: usize,
+) -> Result<(Sender<T>, Receiver<T>)> {
+    let shared = Arc::new(Shared {
+        table: db.open_queue()?,
+        name,
+        capacity: capacity as u64,
+        senders: AtomicUsize::new(1),
+        changed: Event::new(),
+    });
+    Ok((
+        Sender {
+            shared: shared.clone(),
+            _item: PhantomData,
+        },
+        Receiver {
+            shared,
+            _item: PhantomData,
+        },
+    ))
+}
+
+async fn wait_for_change(listener: EventListener) {
+    futures_lite::future::or(listener, async {
+        async_io::Timer::after(POLL_INTERVAL).await;
+    })
+    .await
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared>,
+    _item: PhantomData<fn(T)>,
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::SeqCst);
+        Sender {
+            shared: self.shared.clone(),
+            _item: PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.shared.changed.notify(usize::MAX);
+        }
+    }
+}
+
+impl<T: Item> Sender<T> {
+    /// Queue `item` with the default priority, waiting while the queue is full.
+    pub async fn send(&self, item: T) -> Result<()> {
+        self.send_with_priority(item, 0).await
+    }
+
+    /// Queue `item` to be claimed before all items with a lower `priority`, waiting while the queue is full.
+    /// Nothing happens if the same work is queued already.
+    pub async fn send_with_priority(&self, item: T, priority: i64) -> Result<()> {
+        let Shared {
+            table,
+            name,
+            capacity,
+            changed,
+            ..
+        } = &*self.shared;
+        let payload = rmp_serde::to_vec(&item)?;
+        loop {
+            let listener = changed.listen();
+            if table.len(name)? < *capacity {
+                break;
+            }
+            wait_for_change(listener).await;
+        }
+        if table.push(name, &item.key(), priority, &payload)? {
+            changed.notify(usize::MAX);
+        }
+        Ok(())
+    }
+
+    /// Remove all queued work, including work which is currently processed.
+    pub fn clear(&self) -> Result<()> {
+        self.shared.table.clear(self.shared.name)
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared>,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Receiver {
+            shared: self.shared.clone(),
+            _item: PhantomData,
+        }
+    }
+}
+
+impl<T: Item> Receiver<T> {
+    /// Claim the next item along with its id, which is passed to `done()` once it was processed.
+    /// Return None if the queue is empty and all senders are dropped.
+    pub async fn recv(&self) -> Result<Option<(i64, T)>> {
+        let Shared {
+            table,
+            name,
+            senders,
+            changed,
+            ..
+        } = &*self.shared;
+        loop {
+            let listener = changed.listen();
+            if let Some((id, payload)) = table.claim(name, CLAIM_DURATION)? {
+                changed.notify(usize::MAX);
+                match rmp_serde::from_slice(&payload) {
+                    Ok(item) => return Ok(Some((id, item))),
+                    Err(err) => {
+                        log::warn!("Dropping undecodable item from queue '{}': {}", name, err);
+                        table.remove(id)?;
+                        continue;
+                    }
+                }
+            }
+            if senders.load(Ordering::SeqCst) == 0 {
+                return Ok(None);
+            }
+            wait_for_change(listener).await;
+        }
+    }
+
+    /// Remove the item with the given `id` from the queue after it was processed.
+    pub fn done(&self, id: i64) -> Result<()> {
+        self.shared.table.remove(id)
+    }
+
+    /// Keep the item with the given `id` claimed for another `CLAIM_DURATION`, while it's processed.
+    pub fn renew(&self, id: i64) -> Result<()> {
+        self.shared.table.renew(id)
+    }
+
+    /// Put back the item with the given `id` without processing it, to be claimed again right away.
+    pub fn release(&self, id: i64) -> Result<()> {
+        self.shared.table.release(id)?;
+        self.shared.changed.notify(usize::MAX);
+        Ok(())
+    }
+}
@@ -1,5 +1,10 @@
 use crate::{
-    engine::{work::cpubound, work::iobound},
+    engine::{
+        run::{DownloadSettings, Registry},
+        work::cpubound,
+        work::iobound,
+        work::queue,
+    },
     error::Result,
     model, persistence,
     persistence::{TableAccess, TaskTable},
@@ -28,6 +33,70 @@ pub enum AsyncResult {
     Done,
 }
 
+/// Return the priority of new work for a crate with the given amount of `downloads`, which grows with the order of
+/// magnitude of its downloads so that the most used crates are processed first.
+/// It's never negative to keep new work ahead of retries.
+pub fn download_priority(downloads: u64) -> i64 {
+    (64 - downloads.leading_zeros()) as i64
+}
+
+/// The template of the url to download crate versions from crates.io
+pub const CRATES_IO_CRATE_URL_TEMPLATE: &str = "https://crates.io/api/v1/crates";
+
+/// Return the template of the url to download versions of the crate with the given `crate_name` from, which is
+/// qualified if the crate is one of the `alternate_registries`, along with the templates of mirrors to try if it fails.
+/// Returns None if the registry of the crate isn't configured (anymore).
+pub fn crate_url_template<'a>(
+    crate_name: &str,
+    download_settings: &'a DownloadSettings,
+    alternate_registries: &'a [Registry],
+) -> Option<(&'a str, &'a [String])> {
+    match model::split_qualified_crate_name(crate_name).0 {
+        None => Some((
+            download_settings
+                .crate_url_template
+                .as_deref()
+                .unwrap_or(CRATES_IO_CRATE_URL_TEMPLATE),
+            &download_settings.crate_mirror_templates,
+        )),
+        Some(registry) => alternate_registries
+            .iter()
+            .find(|r| r.name == registry)
+            .map(|r| (r.crate_url_template.as_str(), &[][..])),
+    }
+}
+
+/// The markers supported in crate url templates, as defined for the `dl` field in the `config.json` of a registry index
+const CRATE_URL_MARKERS: &[&str] = &["{crate}", "{version}", "{prefix}", "{lowerprefix}", "{sha256-checksum}"];
+
+/// Return the url to download `krate` from according to `template`, in which all markers are replaced.
+/// Like cargo does it, `/{crate}/{version}/download` is appended to templates without any marker.
+pub fn crate_download_url(template: &str, krate: &model::CrateVersion) -> String {
+    let mut url = template.to_owned();
+    if !CRATE_URL_MARKERS.iter().any(|marker| template.contains(marker)) {
+        url.push_str("/{crate}/{version}/download");
+    }
+    let crate_name = model::split_qualified_crate_name(&krate.name).1;
+    let prefix = crate_prefix(crate_name);
+    url.replace("{crate}", crate_name)
+        .replace("{version}", &krate.version)
+        .replace("{prefix}", &prefix)
+        .replace("{lowerprefix}", &prefix.to_lowercase())
+        .replace("{sha256-checksum}", &krate.checksum)
+}
+
+/// The directory of a crate in a registry index, like `se/rd` for `serde`
+pub(crate) fn crate_prefix(crate_name: &str) -> String {
+    match crate_name.len() {
+        1 => "1".into(),
+        2 => "2".into(),
+        3 => format!("3/{}", &crate_name[..1]),
+        _ => format!("{}/{}", &crate_name[..2], &crate_name[2..4]),
+    }
+}
+
+/// Queue the download of `krate` if `download` is set, and its extraction once it's downloaded if `extract` is set.
+/// It's downloaded from `crate_url_template`, or from the `mirror_templates` in order if that fails.
 #[allow(clippy::too_many_arguments)]
 pub async fn tasks(
     assets_dir: &Path,
@@ -35,8 +104,13 @@ pub async fn tasks(
     krate: &model::CrateVersion,
     mut progress: prodash::tree::Item,
     _mode: Scheduling,
-    perform_io: &async_channel::Sender<iobound::DownloadRequest>,
-    perform_cpu: &async_channel::Sender<cpubound::ExtractRequest>,
+    priority: i64,
+    crate_url_template: &str,
+    mirror_templates: &[String],
+    perform_io: &queue::Sender<iobound::DownloadRequest>,
+    perform_cpu: &queue::Sender<cpubound::ExtractRequest>,
+    download: bool,
+    extract: bool,
     startup_time: SystemTime,
 ) -> Result<AsyncResult> {
     use SubmitResult::*;
@@ -44,50 +118,89 @@ pub async fn tasks(
     let io_task = task_or_default(tasks, &mut key_buf, krate, iobound::default_persisted_download_task)?;
 
     let kind = "crate";
-    let submit_result = submit_single(startup_time, io_task, &mut progress, perform_io, 1, 1, || {
-        let dummy_task = iobound::default_persisted_download_task();
-        let mut task_key = String::new();
-        dummy_task.fq_key(&krate.name, &krate.version, &mut task_key);
-
-        iobound::DownloadRequest {
-            output_file_path: download_file_path(
-                &assets_dir,
-                &krate.name,
-                &krate.version,
-                &dummy_task.process,
-                &dummy_task.version,
-                kind,
-            ),
-            progress_name: format!("{}:{}", krate.name, krate.version),
-            task_key,
-            crate_name_and_version: Some((krate.name.clone(), krate.version.clone())),
-            kind,
-            url: format!(
-                "https://crates.io/api/v1/crates/{name}/{version}/download",
-                name = krate.name,
-                version = krate.version
-            ),
+    let submit_result = if download {
+        submit_single(startup_time, io_task, &mut progress, perform_io, priority, 1, 1, || {
+            let dummy_task = iobound::default_persisted_download_task();
+            let mut task_key = String::new();
+            dummy_task.fq_key(&krate.name, &krate.version, &mut task_key);
+
+            iobound::DownloadRequest {
+                output_file_path: crate_blob_path(&assets_dir, &krate.checksum).unwrap_or_else(|| {
+                    download_file_path(
+                        &assets_dir,
+                        &krate.name,
+                        &krate.version,
+                        &dummy_task.process,
+                        &dummy_task.version,
+                        kind,
+                    )
+                }),
+                progress_name: format!("{}:{}", krate.name, krate.version),
+                task_key,
+                crate_name_and_version: Some((krate.name.clone(), krate.version.clone())),
+                kind: kind.into(),
+                url: crate_download_url(crate_url_template, krate),
+                fallback_urls: mirror_templates
+                    .iter()
+                    .map(|template| crate_download_url(template, krate))
+                    .collect(),
+                expected_sha256: Some(krate.checksum.clone()).filter(|checksum| !checksum.is_empty()),
+                validators: None,
+                timeouts: Default::default(),
+            }
+        })
+        .await?
+    } else {
+        match io_task.state {
+            model::TaskState::Complete => Done(io_task),
+            _ => Skipped,
         }
-    })
-    .await;
+    };
 
     Ok(match submit_result {
-        PermanentFailure | Submitted => AsyncResult::Done,
+        PermanentFailure | Submitted | Skipped => AsyncResult::Done,
+        Done(_) if !extract => AsyncResult::Done,
         Done(download_crate_task) => {
             let cpu_task = task_or_default(tasks, &mut key_buf, krate, cpubound::default_persisted_extraction_task)?;
-            submit_single(startup_time, cpu_task, &mut progress, perform_cpu, 2, 2, || {
-                cpubound::ExtractRequest {
+            submit_single(
+                startup_time,
+                cpu_task,
+                &mut progress,
+                perform_cpu,
+                priority,
+                2,
+                2,
+                || cpubound::ExtractRequest {
                     download_task: download_crate_task,
                     crate_name: krate.name.clone(),
                     crate_version: krate.version.clone(),
-                }
-            })
-            .await;
+                },
+            )
+            .await?;
             AsyncResult::Done
         }
     })
 }
 
+/// Returns true if all tasks of the given crate version are known to be complete, without querying the task table.
+pub fn is_complete(
+    seen: &persistence::SeenFilter,
+    crate_name: &str,
+    crate_version: &str,
+    key_buf: &mut String,
+) -> bool {
+    [
+        iobound::default_persisted_download_task(),
+        cpubound::default_persisted_extraction_task(),
+    ]
+    .iter()
+    .all(|task| {
+        key_buf.clear();
+        task.fq_key(crate_name, crate_version, key_buf);
+        seen.contains(key_buf)
+    })
+}
+
 fn task_or_default(
     tasks: &TaskTable,
     key_buf: &mut String,
@@ -102,19 +215,25 @@ fn task_or_default(
 
 enum SubmitResult {
     Submitted,
+    /// The task wasn't submitted as its stage doesn't run
+    Skipped,
     Done(model::Task),
     PermanentFailure,
 }
 
-async fn submit_single<R>(
+/// Queue the work to do for `task` with the given `priority` unless it is complete or failed too often.
+/// Retries are queued with a lower priority than new work, to not hold it up with work which is likely to fail again.
+#[allow(clippy::too_many_arguments)]
+async fn submit_single<R: queue::Item>(
     startup_time: SystemTime,
     task: model::Task,
     progress: &mut prodash::tree::Item,
-    channel: &async_channel::Sender<R>,
+    queue: &queue::Sender<R>,
+    priority: i64,
     step: usize,
     max_step: usize,
     f: impl FnOnce() -> R,
-) -> SubmitResult {
+) -> Result<SubmitResult> {
     use model::TaskState::*;
     use SubmitResult::*;
     let mut configure = || {
@@ -122,31 +241,35 @@ async fn submit_single<R>(
         progress.set(max_step);
         progress.blocked("wait for consumer", None);
     };
-    match task.state {
+    Ok(match task.state {
         InProgress(_) => {
             if startup_time > task.stored_at {
                 configure();
-                channel.send(f()).await.unwrap();
+                queue.send_with_priority(f(), priority).await?;
             };
             Submitted
         }
         NotStarted => {
             configure();
-            channel.send(f()).await.unwrap();
+            queue.send_with_priority(f(), priority).await?;
             Submitted
         }
         AttemptsWithFailure(ref v) if v.len() < MAX_ATTEMPTS_BEFORE_WE_GIVE_UP => {
             configure();
             progress.info(format!("Retrying task, attempt {}", v.len() + 1));
-            channel.send(f()).await.unwrap();
+            queue.send_with_priority(f(), -(v.len() as i64)).await?;
             Submitted
         }
-        AttemptsWithFailure(_) => PermanentFailure,
+        AttemptsWithFailure(_) | EmptyArtifact(_) => PermanentFailure,
         Complete => Done(task),
-    }
+    })
 }
 
 fn crate_dir(assets_dir: &Path, crate_name: &str) -> PathBuf {
+    let (assets_dir, crate_name) = match model::split_qualified_crate_name(crate_name) {
+        (Some(registry), crate_name) => (assets_dir.join(registry), crate_name),
+        (None, crate_name) => (assets_dir.to_owned(), crate_name),
+    };
     // we can safely assume ascii here - otherwise we panic
     let crate_path = match crate_name.len() {
         1 => Path::new("1").join(crate_name),
@@ -157,6 +280,23 @@ fn crate_dir(assets_dir: &Path, crate_name: &str) -> PathBuf {
     assets_dir.join(crate_path)
 }
 
+/// Return the path of the blob in the content-addressed asset store holding crate archives with the given sha256
+/// `checksum`, sharded by its first two bytes, or None if it isn't a hex-encoded sha256 checksum.
+/// Crate versions with identical archives share the same blob.
+pub fn crate_blob_path(assets_dir: &Path, checksum: &str) -> Option<PathBuf> {
+    if checksum.len() != 64 || !checksum.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let checksum = checksum.to_ascii_lowercase();
+    Some(
+        assets_dir
+            .join("blobs")
+            .join(&checksum[..2])
+            .join(&checksum[2..4])
+            .join(format!("{}.crate", checksum)),
+    )
+}
+
 pub fn download_file_path(
     assets_dir: &Path,
     crate_name: &str,
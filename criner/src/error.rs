@@ -1,3 +1,4 @@
+#[cfg(feature = "engine")]
 use crates_index_diff::git2;
 use std::{fmt, time};
 
@@ -30,6 +31,7 @@ quick_error! {
         InvalidHeader(d: &'static str) {
             display("{}", d)
         }
+        #[cfg(feature = "engine")]
         HttpStatus(status: http::StatusCode) {
             display("{}", status)
         }
@@ -42,14 +44,32 @@ quick_error! {
         Timeout(d: std::time::Duration, msg: String) {
             display("{} - timeout after {:?}.", msg, d)
         }
+        VersionSkew(epoch: u32, writer_version: String) {
+            display("The database was written by criner {} with schema epoch {}, which is newer than ours ({}). Refusing to open it as data may be misinterpreted - allow newer databases explicitly to proceed anyway.", writer_version, epoch, crate::persistence::SCHEMA_EPOCH)
+        }
+        #[cfg(feature = "engine")]
+        TlsPinMismatch(host: String, err: reqwest::Error) {
+            display("The TLS handshake with '{}' failed, its certificate is not signed by a pinned certificate or it doesn't support the minimum TLS version: {}", host, err)
+            source(err)
+        }
+        EmptyArtifact(reason: String) {
+            display("Empty artifact: {}", reason)
+        }
+        SchemaDrift(table: &'static str, drift: String) {
+            display("The schema of table '{}' in the crates.io db dump changed ({}). The csv_model needs to be updated to match before the dump can be ingested.", table, drift)
+        }
         RmpSerdeEncode(err: rmp_serde::encode::Error) {
             from()
             source(err)
         }
-        Git2(err: git2::Error) {
+        RmpSerdeDecode(err: rmp_serde::decode::Error) {
             from()
             source(err)
         }
+        #[cfg(feature = "engine")]
+        Git2(err: git2::Error) {
+            source(err)
+        }
         Io(err: std::io::Error) {
             from()
             source(err)
@@ -58,8 +78,12 @@ quick_error! {
             from()
             source(err)
         }
+        #[cfg(feature = "engine")]
         Reqwest(err: reqwest::Error) {
-            from()
+            source(err)
+        }
+        #[cfg(feature = "engine")]
+        NativeTls(err: native_tls::Error) {
             source(err)
         }
         ParseInt(err: std::num::ParseIntError) {
@@ -74,8 +98,8 @@ quick_error! {
             from()
             source(err)
         }
+        #[cfg(feature = "reports")]
         Horrorshow(err: horrorshow::Error) {
-            from()
             source(err)
         }
         SystemTime(err: std::time::SystemTimeError) {
@@ -86,8 +110,8 @@ quick_error! {
             from()
             source(err)
         }
+        #[cfg(feature = "engine")]
         Csv(err: csv::Error) {
-            from()
             source(err)
         }
         GlobPattern(err: glob::PatternError) {
@@ -98,12 +122,52 @@ quick_error! {
             from()
             source(err)
         }
+        Json(err: serde_json::Error) {
+            from()
+            source(err)
+        }
         ChannelSendMessage(msg: &'static str) {
             display("{}: Sending into a closed channel", msg)
         }
     }
 }
 
+// `quick_error!` doesn't apply the `cfg` of variants to their `From` implementations, so these are written by hand.
+#[cfg(feature = "engine")]
+impl From<git2::Error> for Error {
+    fn from(err: git2::Error) -> Self {
+        Error::Git2(err)
+    }
+}
+
+#[cfg(feature = "engine")]
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Reqwest(err)
+    }
+}
+
+#[cfg(feature = "engine")]
+impl From<native_tls::Error> for Error {
+    fn from(err: native_tls::Error) -> Self {
+        Error::NativeTls(err)
+    }
+}
+
+#[cfg(feature = "reports")]
+impl From<horrorshow::Error> for Error {
+    fn from(err: horrorshow::Error) -> Self {
+        Error::Horrorshow(err)
+    }
+}
+
+#[cfg(feature = "engine")]
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Self {
+        Error::Csv(err)
+    }
+}
+
 impl Error {
     pub fn send_msg<T>(msg: &'static str) -> impl FnOnce(async_channel::SendError<T>) -> Error {
         move |_err| Error::ChannelSendMessage(msg)
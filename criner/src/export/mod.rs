@@ -1,4 +1,7 @@
+#[cfg(feature = "reports")]
+pub mod patches;
 mod run;
 mod to_sql;
 
+pub use crate::redact::Redaction;
 pub use run::run_blocking;
@@ -0,0 +1,100 @@
+//! Write patches for the manifests of crates with suggested fixes to reduce their size, along with a JSON file describing
+//! one pull request per crate for use by tooling which opens them upstream.
+use crate::{
+    engine::report::{generic::Generator, waste},
+    model, persistence,
+    persistence::TableAccess,
+    Result,
+};
+use rusqlite::NO_PARAMS;
+use std::path::Path;
+
+/// The name of the file with all pull requests within the output directory
+pub const PULL_REQUESTS_FILE_NAME: &str = "pull-requests.json";
+
+/// The name of the file with the JSON schema of the pull requests file within the output directory
+pub const PULL_REQUESTS_SCHEMA_FILE_NAME: &str = "pull-requests.schema.json";
+
+/// Write a patch file for the latest version of each crate matching `glob` that has a suggested fix into `out_dir/patches`,
+/// and all pull requests into `out_dir/pull-requests.json`, along with their schema in `out_dir/pull-requests.schema.json`.
+///
+/// Patches are made against the manifest as written by the crate authors if available, or the one normalized by cargo otherwise.
+pub fn run_blocking(db: impl AsRef<Path>, out_dir: impl AsRef<Path>, glob: Option<String>) -> Result<()> {
+    let db = persistence::Db::open_for_reading(db)?;
+    let patches_dir = out_dir.as_ref().join("patches");
+    std::fs::create_dir_all(&patches_dir)?;
+
+    let connection = db.open_connection()?;
+    let crates = {
+        let guard = connection.lock();
+        let mut statement = persistence::new_key_value_query_old_to_new_filtered(
+            persistence::CrateTable::table_name(),
+            glob.as_deref(),
+            &guard,
+            None,
+        )?;
+        let crates: rusqlite::Result<Vec<(String, Vec<u8>)>> = statement
+            .query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect();
+        crates?
+    };
+
+    let mut key_buf = String::new();
+    let mut pull_requests = Vec::new();
+    for (crate_name, data) in crates {
+        let krate: model::Crate = data.as_slice().into();
+        let crate_version = match krate.versions.last() {
+            Some(v) => v,
+            None => continue,
+        };
+        key_buf.clear();
+        let (entries_meta_data, entries) =
+            match waste::Generator::get_result(connection.clone(), &crate_name, crate_version, &mut key_buf)? {
+                Some(model::TaskResult::ExplodedCrate {
+                    entries_meta_data,
+                    selected_entries,
+                    ..
+                }) => (entries_meta_data, selected_entries),
+                _ => continue,
+            };
+        let manifest = match ["Cargo.toml.orig", "Cargo.toml"]
+            .iter()
+            .find_map(|path| {
+                entries
+                    .iter()
+                    .find(|(header, _)| waste::tar_path_to_utf8_str(&header.path) == *path)
+            })
+            .and_then(|(_, content)| String::from_utf8(content.clone()).ok())
+        {
+            Some(manifest) => manifest,
+            None => continue,
+        };
+
+        let report = waste::Report::from_package(
+            &crate_name,
+            crate_version,
+            waste::TarPackage {
+                entries_meta_data,
+                entries,
+            },
+        );
+        if let Some(pull_request) = report.pull_request(&manifest) {
+            std::fs::write(
+                patches_dir.join(format!("{}-{}.patch", crate_name, crate_version)),
+                &pull_request.patch,
+            )?;
+            pull_requests.push(pull_request);
+        }
+    }
+
+    log::info!("Wrote {} patches to '{}'", pull_requests.len(), patches_dir.display());
+    std::fs::write(
+        out_dir.as_ref().join(PULL_REQUESTS_FILE_NAME),
+        serde_json::to_vec_pretty(&pull_requests)?,
+    )?;
+    std::fs::write(
+        out_dir.as_ref().join(PULL_REQUESTS_SCHEMA_FILE_NAME),
+        serde_json::to_vec_pretty(&waste::schema::pull_requests())?,
+    )?;
+    Ok(())
+}
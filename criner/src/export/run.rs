@@ -1,14 +1,63 @@
-use super::to_sql::SqlConvert;
-use crate::model;
-use rusqlite::{Connection, NO_PARAMS};
-use std::path::Path;
-
-pub fn run_blocking(source_db: impl AsRef<Path>, destination_db: impl AsRef<Path>) -> crate::Result<()> {
-    if destination_db.as_ref().is_file() {
-        return Err(crate::Error::Message(format!(
-            "Destination database at '{}' does already exist - this is currently unsupported",
-            destination_db.as_ref().display()
-        )));
+use super::{to_sql::SqlConvert, Redaction};
+use crate::{model, redact};
+use rusqlite::{params, Connection, OptionalExtension, NO_PARAMS};
+use std::{io::Read, path::Path, time::SystemTime};
+
+/// The extension of destination paths which are compressed with zstd, like `export.sqlite.zst`
+const ZSTD_EXTENSION: &str = "zst";
+
+/// The zstd compression level of compressed exports, with 0 being the default of zstd
+const ZSTD_COMPRESSION_LEVEL: i32 = 0;
+
+/// Export the database at `source_db` into a new database at `destination_db`, along with a manifest of its contents.
+///
+/// If `destination_db` ends with `.zst`, the database is written next to it without that extension first and
+/// compressed into `destination_db` afterwards, removing the uncompressed database.
+///
+/// Personally identifiable fields of actors are treated according to `redaction`, which is recorded in the manifest.
+pub fn run_blocking(
+    source_db: impl AsRef<Path>,
+    destination_db: impl AsRef<Path>,
+    redaction: Redaction,
+) -> crate::Result<()> {
+    let destination_db = destination_db.as_ref();
+    if destination_db.extension().and_then(|ext| ext.to_str()) != Some(ZSTD_EXTENSION) {
+        return export(source_db.as_ref(), destination_db, redaction);
+    }
+    if destination_db.is_file() {
+        return Err(destination_exists(destination_db));
+    }
+    let uncompressed_db = destination_db.with_extension("");
+    export(source_db.as_ref(), &uncompressed_db, redaction)?;
+    let start = SystemTime::now();
+    let res = compress(&uncompressed_db, destination_db);
+    std::fs::remove_file(&uncompressed_db)?;
+    res?;
+    log::info!(
+        "Compressed export into '{}' in {:?}",
+        destination_db.display(),
+        SystemTime::now().duration_since(start).unwrap_or_default()
+    );
+    Ok(())
+}
+
+fn destination_exists(destination_db: &Path) -> crate::Error {
+    crate::Error::Message(format!(
+        "Destination database at '{}' does already exist - this is currently unsupported",
+        destination_db.display()
+    ))
+}
+
+fn compress(source: &Path, destination: &Path) -> crate::Result<()> {
+    let input = std::fs::File::open(source)?;
+    let output = std::fs::File::create(destination)?;
+    zstd::stream::copy_encode(input, output, ZSTD_COMPRESSION_LEVEL)?;
+    Ok(())
+}
+
+fn export(source_db: &Path, destination_db: &Path, redaction: Redaction) -> crate::Result<()> {
+    if destination_db.is_file() {
+        return Err(destination_exists(destination_db));
     }
     let mut input = Connection::open(source_db)?;
     let mut output = Connection::open(destination_db)?;
@@ -27,11 +76,198 @@ pub fn run_blocking(source_db: impl AsRef<Path>, destination_db: impl AsRef<Path
     transfer::<model::Task>(&mut input, &mut output)?;
     transfer::<model::Context>(&mut input, &mut output)?;
     transfer::<model::CrateVersion>(&mut input, &mut output)?;
+    normalize_features(&output)?;
     transfer::<model::TaskResult>(&mut input, &mut output)?;
+    transfer_tar_headers(&mut input, &mut output)?;
+    transfer_latest_versions(&input, &mut output)?;
+    redact::actors(&mut output, redaction)?;
+    write_manifest(source_db, &output, redaction)?;
+
+    Ok(())
+}
 
+/// Describe the contents of the export in `output` and the database at `source_db` it was exported from, to be able
+/// to tell exports apart once they were passed around.
+fn write_manifest(source_db: &Path, output: &Connection, redaction: Redaction) -> crate::Result<()> {
+    output.execute_batch(
+        "CREATE TABLE export_manifest (
+             criner_version  TEXT NOT NULL,
+             exported_at     INTEGER NOT NULL, -- seconds since epoch
+             source_sha256   TEXT NOT NULL, -- the hex-encoded SHA-256 hash of the source database file
+             redaction       TEXT NOT NULL -- the policy applied to personally identifiable fields of actors
+         );
+         CREATE TABLE export_manifest_table (
+             name            TEXT PRIMARY KEY NOT NULL,
+             rows            INTEGER NOT NULL
+         );",
+    )?;
+    output.execute(
+        "INSERT INTO export_manifest (criner_version, exported_at, source_sha256, redaction) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            env!("CARGO_PKG_VERSION"),
+            SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64,
+            sha256_of_file(source_db)?,
+            redaction.as_str()
+        ],
+    )?;
+    let table_names = {
+        let mut statement = output.prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT LIKE 'export_manifest%'",
+        )?;
+        let names: rusqlite::Result<Vec<String>> = statement.query_map(NO_PARAMS, |r| r.get(0))?.collect();
+        names?
+    };
+    for table_name in table_names {
+        output.execute(
+            &format!(
+                "INSERT INTO export_manifest_table (name, rows) SELECT ?1, COUNT(*) FROM '{}'",
+                table_name
+            ),
+            params![table_name],
+        )?;
+    }
     Ok(())
 }
 
+fn sha256_of_file(path: &Path) -> crate::Result<String> {
+    use sha2::Digest;
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = sha2::Sha256::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let bytes_read = file.read(&mut buf)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buf[..bytes_read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Entries of crates extracted more recently are stored in their own table, and are added to those
+/// of the extraction results they belong to.
+fn transfer_tar_headers(input: &mut Connection, output: &mut Connection) -> crate::Result<()> {
+    let table_name = crate::persistence::TarHeaderTable::table_name();
+    if !has_table(input, table_name)? {
+        return Ok(());
+    }
+
+    let mut istm = input.prepare(&format!(
+        "SELECT crate_name, crate_version, path, size, entry_type FROM {}",
+        table_name
+    ))?;
+    let transaction = output.transaction()?;
+    let mut count = 0;
+    let start = std::time::SystemTime::now();
+    {
+        let mut parent_id = transaction.prepare(
+            "SELECT id FROM result_extract_crate WHERE crate_name = ?1 AND crate_version = ?2 ORDER BY version DESC",
+        )?;
+        let mut insert_crate_entry = transaction.prepare(
+            "
+            INSERT OR IGNORE INTO crate_entry
+                     (parent_id, path, size, entry_type, data)
+              VALUES (?1        , ?2 , ?3  , ?4        , NULL);
+        ",
+        )?;
+        let mut rows = istm.query(NO_PARAMS)?;
+        while let Some(r) = rows.next()? {
+            let crate_name: String = r.get(0)?;
+            let crate_version: String = r.get(1)?;
+            let path: Vec<u8> = r.get(2)?;
+            let size: i64 = r.get(3)?;
+            let entry_type: u8 = r.get(4)?;
+            let id: Option<i64> = parent_id
+                .query_row(params![crate_name, crate_version], |r| r.get(0))
+                .optional()?;
+            if let Some(id) = id {
+                insert_crate_entry.execute(params![
+                    id,
+                    std::str::from_utf8(&path).expect("utf8 path in crate - lets see how long this is true"),
+                    size,
+                    entry_type
+                ])?;
+                count += 1;
+            }
+        }
+        transaction.execute(
+            "UPDATE result_extract_crate SET num_crate_entries =
+                (SELECT COUNT(*) FROM crate_entry WHERE crate_entry.parent_id = result_extract_crate.id)",
+            NO_PARAMS,
+        )?;
+    }
+    transaction.commit()?;
+    log::info!(
+        "Inserted {} {} in {:?}",
+        count,
+        table_name,
+        std::time::SystemTime::now().duration_since(start).unwrap()
+    );
+    Ok(())
+}
+
+/// Features of crate versions are exported as JSON objects along with their versions, and normalized into a row per
+/// feature and what it enables here, to be able to query features across all crates.
+fn normalize_features(output: &Connection) -> crate::Result<()> {
+    output.execute_batch(
+        "CREATE TABLE crate_version_feature (
+             parent_id           INTEGER NOT NULL,
+             name                TEXT NOT NULL,
+             enables             TEXT, -- a feature, an optional dependency or one of its features, NULL if nothing
+             FOREIGN KEY (parent_id) REFERENCES crate_version(id)
+         );
+         INSERT INTO crate_version_feature (parent_id, name, enables)
+             SELECT crate_version.id, feature.key, enabled.value
+             FROM crate_version, json_each(crate_version.features) AS feature
+             LEFT JOIN json_each(feature.value) AS enabled;",
+    )?;
+    Ok(())
+}
+
+/// The latest versions of crates are copied as they are, to find them without looking at all versions of a crate.
+fn transfer_latest_versions(input: &Connection, output: &mut Connection) -> crate::Result<()> {
+    let table_name = crate::persistence::LatestVersionTable::table_name();
+    if !has_table(input, table_name)? {
+        return Ok(());
+    }
+    output.execute_batch(
+        "CREATE TABLE latest_version (
+             crate_name          TEXT PRIMARY KEY NOT NULL,
+             version             TEXT, -- the most recent version which isn't yanked, NULL if all are
+             analyzed_version    TEXT -- the most recent version which was analyzed, NULL if there is none
+         );",
+    )?;
+    let mut istm = input.prepare(&format!(
+        "SELECT crate_name, version, analyzed_version FROM {}",
+        table_name
+    ))?;
+    let transaction = output.transaction()?;
+    {
+        let mut ostm = transaction
+            .prepare("INSERT INTO latest_version (crate_name, version, analyzed_version) VALUES (?1, ?2, ?3)")?;
+        let mut rows = istm.query(NO_PARAMS)?;
+        while let Some(r) = rows.next()? {
+            let crate_name: String = r.get(0)?;
+            let version: Option<String> = r.get(1)?;
+            let analyzed_version: Option<String> = r.get(2)?;
+            ostm.execute(params![crate_name, version, analyzed_version])?;
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+fn has_table(input: &Connection, table_name: &str) -> crate::Result<bool> {
+    Ok(input
+        .query_row(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            params![table_name],
+            |_r| Ok(()),
+        )
+        .optional()?
+        .is_some())
+}
+
 fn transfer<T>(input: &mut Connection, output: &mut Connection) -> crate::Result<()>
 where
     for<'a> T: SqlConvert + From<&'a [u8]>,
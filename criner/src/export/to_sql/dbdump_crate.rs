@@ -48,10 +48,12 @@ impl<'a> SqlConvert for model::db_dump::Crate {
              homepage            TEXT,
              readme              TEXT,
              repository          TEXT,
+             repository_host     TEXT, -- The normalized host of the repository, like 'github.com'
              created_by          INTEGER,  -- Github user id as index into crates.io-actor table
              owners              JSON NOT NULL, -- Array of github user ids for indexing into the crates.io-actor table
              keywords            JSON NOT NULL, -- Array of strings, each string being a keyword
              categories          JSON NOT NULL, -- Array of category objects, providing a wealth of information for each
+             badges              JSON NOT NULL, -- Array of badge objects with their type and attributes
              PRIMARY KEY (name),
              FOREIGN KEY (created_by) REFERENCES actor(github_id)
         );
@@ -81,8 +83,8 @@ fn do_it(input_statement: &mut rusqlite::Statement, transaction: &rusqlite::Tran
     let mut insert_crate = transaction
         .prepare("
             REPLACE INTO 'crates.io-crate'
-                     (name, stored_at, created_at, updated_at, description, documentation, downloads, homepage, readme, repository, created_by, owners, keywords, categories)
-              VALUES (?1  , ?2       , ?3        , ?4        , ?5         , ?6           , ?7       , ?8      , ?9    , ?10       , ?11       , ?12   , ?13     , ?14);
+                     (name, stored_at, created_at, updated_at, description, documentation, downloads, homepage, readme, repository, repository_host, created_by, owners, keywords, categories, badges)
+              VALUES (?1  , ?2       , ?3        , ?4        , ?5         , ?6           , ?7       , ?8      , ?9    , ?10       , ?11            , ?12       , ?13   , ?14     , ?15       , ?16);
         ",)
         .unwrap();
     let mut insert_actor = transaction
@@ -128,6 +130,8 @@ fn do_it(input_statement: &mut rusqlite::Statement, transaction: &rusqlite::Tran
             categories,
             created_by,
             owners,
+            badges,
+            repository_host,
         } = bytes.as_slice().into();
 
         if let Some(actor) = created_by.as_ref() {
@@ -149,10 +153,12 @@ fn do_it(input_statement: &mut rusqlite::Statement, transaction: &rusqlite::Tran
             homepage,
             readme,
             repository,
+            repository_host,
             created_by.map(|actor| actor.github_id),
             serde_json::to_string_pretty(&owners.iter().map(|actor| actor.github_id).collect::<Vec<_>>()).unwrap(),
             serde_json::to_string_pretty(&keywords).unwrap(),
             serde_json::to_string_pretty(&categories).unwrap(),
+            serde_json::to_string_pretty(&badges).unwrap(),
         ])?;
 
         for version in versions {
@@ -166,6 +172,7 @@ fn do_it(input_statement: &mut rusqlite::Statement, transaction: &rusqlite::Tran
                 semver,
                 published_by,
                 is_yanked,
+                checksum: _,
             } = version;
             insert_crate_version.execute(params![
                 count as i32,
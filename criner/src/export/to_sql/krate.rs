@@ -1,11 +1,14 @@
-use crate::{export::to_sql::SqlConvert, model};
+use crate::{
+    export::to_sql::{to_seconds_since_epoch, SqlConvert},
+    model,
+};
 use rusqlite::{params, Statement};
 
 impl<'a> SqlConvert for model::Crate {
     fn replace_statement() -> &'static str {
         "REPLACE INTO crate
-                   (name, version)
-            VALUES (?1,   ?2)"
+                   (name, version, is_yanked, yank_state_since)
+            VALUES (?1,   ?2,      ?3,        ?4)"
     }
     fn source_table_name() -> &'static str {
         "crate"
@@ -14,6 +17,8 @@ impl<'a> SqlConvert for model::Crate {
         "CREATE TABLE crate (
              name           TEXT NOT NULL,
              version        TEXT NOT NULL,
+             is_yanked      INTEGER, -- 1 if the version is yanked, NULL if its state wasn't tracked yet
+             yank_state_since INTEGER, -- seconds since epoch at which the version was first seen in its yanked state
              PRIMARY KEY (name, version)
         )"
     }
@@ -29,9 +34,20 @@ impl<'a> SqlConvert for model::Crate {
         let name = tokens.next().unwrap();
         assert!(tokens.next().is_none());
 
-        let Self { versions } = self;
+        let Self {
+            versions,
+            yank_states,
+            publish_times: _,
+            deleted_at: _,
+        } = self;
         for version in versions.iter() {
-            stm.execute(params![name, version])?;
+            let state = yank_states.get(version);
+            stm.execute(params![
+                name,
+                version,
+                state.map(|s| s.is_yanked),
+                state.map(|s| to_seconds_since_epoch(s.since))
+            ])?;
         }
         Ok(versions.len())
     }
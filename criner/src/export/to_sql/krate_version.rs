@@ -1,11 +1,16 @@
-use crate::{export::to_sql::SqlConvert, model};
+use crate::{
+    export::to_sql::{to_seconds_since_epoch, SqlConvert},
+    model,
+};
 use rusqlite::{params, Statement};
 
 impl SqlConvert for model::CrateVersion {
     fn replace_statement() -> &'static str {
         "REPLACE INTO crate_version
-                   (id, name, version, kind, checksum, features)
-            VALUES (?1, ?2  , ?3     , ?4  , ?5      , ?6);
+                   (id, name, version, kind, checksum, features, published_at, deleted_at,
+                    index_commit, index_committed_at)
+            VALUES (?1, ?2  , ?3     , ?4  , ?5      , ?6      , ?7          , ?8        ,
+                    ?9          , ?10);
         "
     }
 
@@ -29,6 +34,10 @@ impl SqlConvert for model::CrateVersion {
             kind                TEXT NOT NULL,
             checksum            TEXT NOT NULL,
             features            JSON NOT NULL,
+            published_at        INTEGER, -- seconds since epoch, NULL if unknown
+            deleted_at          INTEGER, -- seconds since epoch at which its crate was seen deleted, NULL if it wasn't
+            index_commit        TEXT, -- the commit of the git index its most recent change was observed in, if known
+            index_committed_at  INTEGER, -- seconds since epoch at which that commit was made
             PRIMARY KEY (name, version)
         );
         CREATE TABLE crate_version_dependency (
@@ -60,9 +69,12 @@ impl SqlConvert for model::CrateVersion {
             checksum,
             features,
             dependencies,
+            published_at,
+            deleted_at,
+            observed_in,
         } = self;
 
-        use crates_index_diff::ChangeKind::*;
+        use crate::model::ChangeKind::*;
         stm.execute(params![
             uid,
             name,
@@ -72,7 +84,13 @@ impl SqlConvert for model::CrateVersion {
                 Yanked => "yanked",
             },
             checksum,
-            serde_json::to_string_pretty(features).unwrap()
+            serde_json::to_string_pretty(features).unwrap(),
+            published_at.map(to_seconds_since_epoch),
+            deleted_at.map(to_seconds_since_epoch),
+            observed_in.as_ref().map(|commit| commit.id.as_str()),
+            observed_in
+                .as_ref()
+                .map(|commit| to_seconds_since_epoch(commit.committed_at))
         ])?;
 
         let sstm = sstm.expect("secondary statement to be set");
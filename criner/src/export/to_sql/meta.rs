@@ -5,8 +5,8 @@ use rusqlite::{params, Statement};
 impl SqlConvert for model::Context {
     fn replace_statement() -> &'static str {
         "INSERT INTO runtime_statistic
-                (sample_day, num_new_crate_versions, num_new_crates, dur_s_fetch_new_crate_versions)
-         VALUES (?1        , ?2                    , ?3            , ?4);
+                (sample_day, num_new_crate_versions, num_new_crates, dur_s_fetch_new_crate_versions, num_db_dump_rows, num_db_dump_bytes, dur_s_ingest_db_dump, num_downloads, num_downloaded_bytes, dur_s_download)
+         VALUES (?1        , ?2                    , ?3            , ?4                            , ?5              , ?6               , ?7                  , ?8           , ?9                  , ?10);
         "
     }
 
@@ -20,6 +20,12 @@ impl SqlConvert for model::Context {
             num_new_crate_versions          INTEGER NOT NULL,
             num_new_crates                  INTEGER NOT NULL,
             dur_s_fetch_new_crate_versions  INTEGER NOT NULL,
+            num_db_dump_rows                JSON NOT NULL, -- Object with the amount of rows ingested per table
+            num_db_dump_bytes               INTEGER NOT NULL,
+            dur_s_ingest_db_dump            INTEGER NOT NULL,
+            num_downloads                   INTEGER NOT NULL,
+            num_downloaded_bytes            INTEGER NOT NULL,
+            dur_s_download                  INTEGER NOT NULL, -- summed up over all processors downloading at once
             PRIMARY KEY (sample_day)
         );
         "
@@ -40,15 +46,35 @@ impl SqlConvert for model::Context {
         let date_stamp = day_date.duration_since(std::time::UNIX_EPOCH).unwrap();
 
         let model::Context {
-            counts: model::Counts { crate_versions, crates },
-            durations: model::Durations { fetch_crate_versions },
+            counts:
+                model::Counts {
+                    crate_versions,
+                    crates,
+                    db_dump_rows,
+                    db_dump_bytes,
+                    downloads,
+                    downloaded_bytes,
+                },
+            durations:
+                model::Durations {
+                    fetch_crate_versions,
+                    ingest_db_dump,
+                    download,
+                },
+            reconciliation: _,
         } = self;
 
         stm.execute(params![
             date_stamp.as_secs() as i64,
             *crate_versions as i64,
             *crates as i64,
-            fetch_crate_versions.as_secs() as i64
+            fetch_crate_versions.as_secs() as i64,
+            serde_json::to_string_pretty(db_dump_rows).unwrap(),
+            *db_dump_bytes as i64,
+            ingest_db_dump.as_secs() as i64,
+            *downloads as i64,
+            *downloaded_bytes as i64,
+            download.as_secs() as i64
         ])
         .map_err(Into::into)
     }
@@ -15,8 +15,10 @@ impl<'a> SqlConvert for model::TaskResult {
                 .prepare(
                     "
             REPLACE INTO result_download
-                     (crate_name, crate_version, version, kind, url, content_length, content_type)
-              VALUES (?1        , ?2           , ?3     , ?4  , ?5 , ?6            , ?7);
+                     (crate_name, crate_version, version, kind, url, content_length, content_type,
+                      etag, last_modified, final_url, response_time_ms)
+              VALUES (?1        , ?2           , ?3     , ?4  , ?5 , ?6            , ?7          ,
+                      ?8  , ?9           , ?10      , ?11);
         ",
                 )
                 .unwrap();
@@ -63,6 +65,10 @@ impl<'a> SqlConvert for model::TaskResult {
                         url,
                         content_length,
                         content_type,
+                        etag,
+                        last_modified,
+                        final_url,
+                        response_time,
                     } => {
                         assert_eq!(process, "download");
                         assert_eq!(Some(kind.as_ref()), optional_last_key);
@@ -73,13 +79,18 @@ impl<'a> SqlConvert for model::TaskResult {
                             kind,
                             url,
                             content_length,
-                            content_type
+                            content_type,
+                            etag,
+                            last_modified,
+                            final_url,
+                            response_time.map(|d| d.as_millis() as i64)
                         ])?;
                         num_downloads += 1;
                     }
                     TaskResult::ExplodedCrate {
                         entries_meta_data,
                         selected_entries,
+                        truncated_entries: _,
                     } => {
                         assert_eq!(process, "extract_crate");
                         let id = num_extract_crates as i32;
@@ -142,6 +153,10 @@ impl<'a> SqlConvert for model::TaskResult {
             url                             TEXT NOT NULL,
             content_length                  INTEGER NOT NULL,
             content_type                    TEXT,
+            etag                            TEXT,
+            last_modified                   TEXT,
+            final_url                       TEXT, -- only set if the download was redirected
+            response_time_ms                INTEGER, -- time until the response headers arrived
             PRIMARY KEY (crate_name, crate_version, version, kind)
         );
         CREATE TABLE result_extract_crate (
@@ -53,6 +53,7 @@ impl SqlConvert for model::Task {
             process,
             version,
             state,
+            validators: _,
         } = self;
         stm.execute(params![
             uid,
@@ -65,6 +66,7 @@ impl SqlConvert for model::Task {
                 Complete => "Complete",
                 InProgress(_) => "InProgress",
                 AttemptsWithFailure(_) => "AttemptsWithFailure",
+                EmptyArtifact(_) => "EmptyArtifact",
             },
         ])?;
         match state {
@@ -74,6 +76,10 @@ impl SqlConvert for model::Task {
                     sstm.execute(params![uid, error])?;
                 }
             }
+            EmptyArtifact(reason) => {
+                let sstm = sstm.ok_or(crate::Error::Bug("need secondary statement"))?;
+                sstm.execute(params![uid, reason])?;
+            }
             _ => {}
         }
         Ok(1)
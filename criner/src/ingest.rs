@@ -0,0 +1,17 @@
+//! Ingest a crates.io database dump which was downloaded previously, for offline development and to test changes
+//! to the ingestion.
+use crate::{engine::run::DbDownloadSettings, engine::stage::db_download, persistence::Db, Result};
+use std::path::Path;
+
+/// Ingest the database dump at `db_dump_path` into the database at `db` as configured by `settings`, without
+/// downloading it. Progress is logged.
+pub fn run_blocking(db: impl AsRef<Path>, db_dump_path: impl AsRef<Path>, settings: DbDownloadSettings) -> Result<()> {
+    let db = Db::open(db)?;
+    let root = prodash::TreeOptions::default().create();
+    futures_lite::future::block_on(crate::spawn(db_download::ingest_local(
+        db,
+        root.add_child("ingest db dump"),
+        db_dump_path.as_ref().to_owned(),
+        settings,
+    )))
+}
@@ -1,6 +1,9 @@
 #![allow(clippy::unneeded_field_pattern)]
 #![deny(unsafe_code)]
+// Without the engine, some helpers and persistence accessors go unused.
+#![cfg_attr(not(feature = "engine"), allow(dead_code))]
 
+#[cfg(feature = "engine")]
 #[macro_use]
 extern crate lazy_static;
 
@@ -10,19 +13,46 @@ extern crate quick_error;
 #[cfg(feature = "migration")]
 pub mod migration;
 
+#[cfg(feature = "reports")]
+pub mod bench;
+#[cfg(feature = "engine")]
+pub mod bootstrap;
+pub mod costs;
+#[cfg(feature = "engine")]
+pub mod dump_diff;
 pub mod error;
 pub use error::{Error, Result};
 
+#[cfg(feature = "export")]
 pub mod export;
-pub(crate) mod model;
-pub(crate) mod persistence;
+#[cfg(feature = "engine")]
+pub mod ingest;
+#[cfg(feature = "reports")]
+pub mod local_crates;
+pub mod model;
+pub mod persistence;
+#[cfg(any(feature = "engine", feature = "export"))]
+mod redact;
+#[cfg(feature = "engine")]
+pub mod replay;
+#[cfg(feature = "engine")]
+pub mod resync;
+#[cfg(all(feature = "reports", feature = "export"))]
+pub mod smoke;
+pub mod status;
 pub(crate) mod utils;
+#[cfg(feature = "engine")]
+pub mod verify_assets;
 
+#[cfg(feature = "engine")]
 mod spawn;
+#[cfg(feature = "engine")]
 pub(crate) use spawn::spawn;
 
+#[cfg(feature = "engine")]
 mod engine;
 
+#[cfg(feature = "engine")]
 pub use engine::run;
 
 pub use prodash;
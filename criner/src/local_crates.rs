@@ -0,0 +1,164 @@
+//! Extract and report on a directory of crate archives, like a vendored mirror or the download cache of cargo,
+//! without talking to any registry.
+use crate::{
+    engine::{
+        report::{generic::Generator, waste},
+        run::ExtractionProfile,
+        stage,
+        work::cpubound,
+    },
+    model,
+    persistence::{Db, Keyed, TableAccess},
+    Error, Result,
+};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+lazy_static! {
+    /// Crate names may contain dashes followed by digits, so the version is the first suffix which looks like one
+    static ref CRATE_FILE_NAME: regex::Regex =
+        regex::Regex::new(r"^(?P<name>.+?)-(?P<version>\d+\.\d+\.\d+([-+].*)?)\.crate$")
+            .expect("valid statically known regex");
+}
+
+/// Extract all `<name>-<version>.crate` files in `crates_dir` and its subdirectories into the database at `db`,
+/// generate waste reports for all crates in it and write what happens to `out`.
+///
+/// Reports are generated for all crates of the database, which are exactly the ones of `crates_dir` unless the
+/// database is shared with `criner mine` or other directories.
+pub fn run_blocking(crates_dir: impl AsRef<Path>, db: impl AsRef<Path>, mut out: impl Write) -> Result<()> {
+    let db_dir = db.as_ref();
+    let assets_dir = db_dir.join("assets");
+    let db = Db::open(db_dir)?;
+    std::fs::create_dir_all(&assets_dir)?;
+    let root = prodash::TreeOptions::default().create();
+    let mut progress = root.add_child("local crates");
+
+    let mut archives = Vec::new();
+    let mut unrecognized = Vec::new();
+    for path in crate_files(crates_dir.as_ref())? {
+        match crate_name_and_version(&path) {
+            Some(name_and_version) => archives.push((path, name_and_version)),
+            None => unrecognized.push(path),
+        }
+    }
+    writeln!(
+        out,
+        "Found {} crate archives in '{}'",
+        archives.len(),
+        crates_dir.as_ref().display()
+    )?;
+    for path in &unrecognized {
+        writeln!(
+            out,
+            "Skipping '{}' as its name isn't <name>-<version>.crate",
+            path.display()
+        )?;
+    }
+
+    let (crates, versions, tasks) = (db.open_crates()?, db.open_crate_versions()?, db.open_tasks()?);
+    let (results, tar_headers, empty_artifacts) =
+        (db.open_results()?, db.open_tar_headers()?, db.open_empty_artifacts()?);
+    let profile = ExtractionProfile::default();
+    let selected_paths = cpubound::selected_paths(&profile)?;
+    let mut task = cpubound::default_persisted_extraction_task();
+    task.state = model::TaskState::Complete;
+    let (mut task_key, mut result_key) = (String::new(), String::new());
+    let mut num_failed = 0;
+    progress.init(Some(archives.len()), Some("crates extracted".into()));
+    for (path, (crate_name, crate_version)) in archives {
+        progress.inc();
+        let version = model::CrateVersion {
+            name: crate_name,
+            version: crate_version,
+            checksum: sha256_of_file(&path)?,
+            ..Default::default()
+        };
+        versions.insert(&mut progress, version.key(), &version)?;
+        crates.upsert(&mut progress, &version.name, &version)?;
+
+        task_key.clear();
+        task.fq_key(&version.name, &version.version, &mut task_key);
+        result_key.clear();
+        waste::Generator::fq_result_key(&version.name, &version.version, &mut result_key);
+        match cpubound::extract_crate(
+            &results,
+            &tar_headers,
+            &result_key,
+            (&version.name, &version.version),
+            &mut progress,
+            path.clone(),
+            &selected_paths,
+            &profile,
+        ) {
+            Ok(()) => {
+                tasks.upsert(&mut progress, &task_key, &task)?;
+            }
+            Err(Error::EmptyArtifact(reason)) => {
+                empty_artifacts.record(&version.name, &version.version, &reason)?;
+                writeln!(out, "Skipping '{}' as it is empty: {}", path.display(), reason)?;
+            }
+            Err(err) => {
+                num_failed += 1;
+                writeln!(out, "Could not extract '{}': {}", path.display(), err)?;
+            }
+        }
+    }
+
+    writeln!(out, "Generating reports")?;
+    futures_lite::future::block_on(crate::spawn(stage::report::generate(
+        db.clone(),
+        root.add_child("reports"),
+        assets_dir,
+        None,
+        None,
+        1,
+        500,
+        1,
+        None,
+    )))?;
+    writeln!(
+        out,
+        "Wrote reports to '{}'",
+        db_dir.join("reports").join(waste::Generator::name()).display()
+    )?;
+    if num_failed > 0 {
+        return Err(Error::Message(format!(
+            "{} crate archives could not be extracted",
+            num_failed
+        )));
+    }
+    Ok(())
+}
+
+/// Return the paths of all files ending in `.crate` within `dir` and its subdirectories, sorted by path.
+fn crate_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().and_then(|ext| ext.to_str()) == Some("crate") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Return the crate name and version of the crate archive at `path`, as encoded in its file name.
+fn crate_name_and_version(path: &Path) -> Option<(String, String)> {
+    let file_name = path.file_name()?.to_str()?;
+    let captures = CRATE_FILE_NAME.captures(file_name)?;
+    Some((captures["name"].to_owned(), captures["version"].to_owned()))
+}
+
+fn sha256_of_file(path: &Path) -> Result<String> {
+    use sha2::Digest;
+    Ok(format!("{:x}", sha2::Sha256::digest(&std::fs::read(path)?)))
+}
@@ -82,6 +82,10 @@ fn migrate_iterate_assets_and_update_db(db_path: impl AsRef<Path>) -> crate::Res
             .into(),
             content_length: file_size as u32,
             content_type: Some("application/x-tar".into()),
+            etag: None,
+            last_modified: None,
+            final_url: None,
+            response_time: None,
         };
         task_result.fq_key(name, version, &task, &mut key);
         results.insert(&mut progress, &key, &task_result)?;
@@ -1,6 +1,22 @@
-pub use crate::engine::report::waste::TarHeader;
+pub use criner_waste_report::{DiscrepancyKind, EmptyArtifact, TarHeader, VersionDiscrepancy};
 use serde_derive::{Deserialize, Serialize};
-use std::{collections::HashMap, ops::Add, time::Duration, time::SystemTime};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Add,
+    time::Duration,
+    time::SystemTime,
+};
+
+/// Describes the complete content of a file of which only a sample was stored
+#[derive(Clone, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Debug)]
+pub struct ContentSample {
+    /// The path of the file within the crate
+    pub path: Vec<u8>,
+    /// The size of the complete file in bytes
+    pub len: u64,
+    /// The hex-encoded SHA-256 hash of the complete file
+    pub sha256: String,
+}
 
 /// Represents a top-level crate and associated information
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -8,16 +24,58 @@ pub struct Crate {
     /// All versions published to crates.io, guaranteed to be sorted so that the most recent version is last.
     /// The format is as specified in Cargo.toml:version
     pub versions: Vec<String>,
+    /// The yanked state of each of the `versions` by version.
+    /// Versions stored before it was tracked have no state until they change.
+    #[serde(default)]
+    pub yank_states: BTreeMap<String, YankState>,
+    /// The time at which each of the `versions` was published by version, if known.
+    /// See `CrateVersion::published_at` for where it comes from.
+    #[serde(default)]
+    pub publish_times: BTreeMap<String, SystemTime>,
+    /// The time at which the crate was first seen deleted from the index, or None if it's in the index.
+    /// Deleted crates and their versions are kept as tombstones, as their archives can't be downloaded anymore.
+    #[serde(default)]
+    pub deleted_at: Option<SystemTime>,
+}
+
+impl Crate {
+    /// Return whether the given `version` is yanked, or None if its state is unknown
+    pub fn is_yanked(&self, version: &str) -> Option<bool> {
+        self.yank_states.get(version).map(|state| state.is_yanked)
+    }
+
+    /// Return the time at which the given `version` was published, if known
+    pub fn published_at(&self, version: &str) -> Option<SystemTime> {
+        self.publish_times.get(version).copied()
+    }
+
+    /// Return all versions known to be yanked, sorted by semantic version
+    pub fn yanked_versions(&self) -> Vec<String> {
+        self.versions
+            .iter()
+            .filter(|version| self.is_yanked(version) == Some(true))
+            .cloned()
+            .collect()
+    }
 }
 
 impl From<CrateVersion> for Crate {
     fn from(v: CrateVersion) -> Self {
-        Crate {
-            versions: vec![v.version],
-        }
+        let mut krate = Crate::default();
+        krate.merge_mut(&v);
+        krate
     }
 }
 
+/// Whether a version of a crate is yanked, along with the time it last changed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct YankState {
+    pub is_yanked: bool,
+    /// The time at which the version was first seen in this state, i.e. when it was published, yanked or unyanked,
+    /// or when it was first seen at all.
+    pub since: SystemTime,
+}
+
 /// Stores element counts of various kinds
 #[derive(Default, Debug, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Clone)]
 pub struct Counts {
@@ -26,12 +84,108 @@ pub struct Counts {
 
     /// The amount of crates in the database
     pub crates: u32,
+
+    /// The amount of rows read from each table of the crates.io db dump when ingesting it
+    #[serde(default)]
+    pub db_dump_rows: BTreeMap<String, u64>,
+
+    /// The amount of uncompressed bytes read from the crates.io db dump when ingesting it
+    #[serde(default)]
+    pub db_dump_bytes: u64,
+
+    /// The amount of downloads of crate versions and db dumps which completed
+    #[serde(default)]
+    pub downloads: u64,
+
+    /// The amount of bytes received by all downloads, including those which failed
+    #[serde(default)]
+    pub downloaded_bytes: u64,
 }
 
 /// Stores wall clock time that elapsed for various kinds of computation
 #[derive(Default, Debug, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Clone)]
 pub struct Durations {
     pub fetch_crate_versions: Duration,
+    /// The time it took to ingest the crates.io db dump, excluding its download
+    #[serde(default)]
+    pub ingest_db_dump: Duration,
+    /// The time spent downloading crate versions and db dumps by all processors together, including failed attempts
+    #[serde(default)]
+    pub download: Duration,
+}
+
+/// The amount of crates and crate versions known to a particular source
+#[derive(Default, Debug, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Clone)]
+pub struct SourceCounts {
+    pub crates: u64,
+    pub crate_versions: u64,
+}
+
+/// Crates that are present in one source but not in another one
+#[derive(Default, Debug, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Clone)]
+pub struct Discrepancy {
+    /// The total amount of affected crates
+    pub count: u64,
+    /// The names of some of the affected crates, for investigation
+    pub sample: Vec<String>,
+}
+
+/// A comparison of the crates known to the crates.io index, the most recently ingested database dump and our own tables
+#[derive(Default, Debug, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Clone)]
+pub struct Reconciliation {
+    /// The time at which the comparison was made
+    pub created_at: Option<SystemTime>,
+    pub index: SourceCounts,
+    pub db_dump: SourceCounts,
+    pub db: SourceCounts,
+    /// Crates in the index we don't know about, which should be picked up when fetching changes
+    pub missing_in_db: Discrepancy,
+    /// Crates in the index which are not in the db dump, usually those published after the dump was created
+    pub missing_in_db_dump: Discrepancy,
+    /// Crates we know about which aren't in the index anymore, as they were deleted from crates.io
+    pub tombstones: Discrepancy,
+}
+
+impl Reconciliation {
+    /// Returns true if there are no crates that the index has but we don't
+    pub fn is_healthy(&self) -> bool {
+        self.missing_in_db.count == 0
+    }
+}
+
+/// An attempt to publish the reports by committing them to their git repository and pushing the commit
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct Publish {
+    /// The time at which the commit was pushed, or the push failed
+    pub published_at: SystemTime,
+    /// The hex-encoded id of the commit with the reports
+    pub commit: String,
+    /// The amount of report files changed by the commit
+    pub files: u64,
+    /// The reason the commit couldn't be pushed, or None if it was pushed
+    pub push_error: Option<String>,
+}
+
+impl Publish {
+    /// Returns true if the commit made it to the remote repository
+    pub fn is_pushed(&self) -> bool {
+        self.push_error.is_none()
+    }
+}
+
+/// A row of a table of the crates.io db dump which couldn't be decoded and was skipped during a lenient ingestion
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct QuarantinedRow {
+    /// The name of the table the row belongs to, like `versions`
+    pub table: String,
+    /// The line in the CSV file at which the row starts, with the headers being on line 1
+    pub line: u64,
+    /// The name of the column which couldn't be decoded, if known
+    pub column: Option<String>,
+    /// The raw content of the row as CSV
+    pub content: Vec<u8>,
+    /// Why the row couldn't be decoded
+    pub error: String,
 }
 
 /// Stores information about the work we have performed thus far
@@ -41,6 +195,9 @@ pub struct Context {
     pub counts: Counts,
     /// Various kinds of time we took for computation
     pub durations: Durations,
+    /// The most recent comparison of crate counts between the index, the db dump and the database, if one was made
+    #[serde(default)]
+    pub reconciliation: Option<Reconciliation>,
 }
 
 impl Add<&Context> for Context {
@@ -51,10 +208,23 @@ impl Add<&Context> for Context {
             counts: Counts {
                 crate_versions: self.counts.crate_versions + rhs.counts.crate_versions,
                 crates: self.counts.crates + rhs.counts.crates,
+                db_dump_rows: {
+                    let mut rows = self.counts.db_dump_rows;
+                    for (table, count) in &rhs.counts.db_dump_rows {
+                        *rows.entry(table.clone()).or_default() += count;
+                    }
+                    rows
+                },
+                db_dump_bytes: self.counts.db_dump_bytes + rhs.counts.db_dump_bytes,
+                downloads: self.counts.downloads + rhs.counts.downloads,
+                downloaded_bytes: self.counts.downloaded_bytes + rhs.counts.downloaded_bytes,
             },
             durations: Durations {
                 fetch_crate_versions: self.durations.fetch_crate_versions + rhs.durations.fetch_crate_versions,
+                ingest_db_dump: self.durations.ingest_db_dump + rhs.durations.ingest_db_dump,
+                download: self.durations.download + rhs.durations.download,
             },
+            reconciliation: rhs.reconciliation.clone().or(self.reconciliation),
         }
     }
 }
@@ -81,6 +251,7 @@ pub struct Dependency {
     pub package: Option<String>,
 }
 
+#[cfg(feature = "engine")]
 impl From<crates_index_diff::Dependency> for Dependency {
     fn from(v: crates_index_diff::Dependency) -> Self {
         let crates_index_diff::Dependency {
@@ -106,6 +277,71 @@ impl From<crates_index_diff::Dependency> for Dependency {
     }
 }
 
+/// Separates the name of an alternate registry from the name of one of its crates, like in `my-registry/serde`.
+/// Crates of crates.io aren't qualified, and as crate names can't contain it, names of different registries never
+/// clash.
+pub const REGISTRY_SEP_CHAR: char = '/';
+
+/// Return the name under which the crate with the given `crate_name` of `registry` is stored, with `None` being
+/// crates.io.
+pub fn qualified_crate_name(registry: Option<&str>, crate_name: &str) -> String {
+    match registry {
+        Some(registry) => format!("{}{}{}", registry, REGISTRY_SEP_CHAR, crate_name),
+        None => crate_name.to_owned(),
+    }
+}
+
+/// Split a name as returned by `qualified_crate_name()` into the name of its registry, `None` for crates.io,
+/// and the name of the crate within it.
+pub fn split_qualified_crate_name(name: &str) -> (Option<&str>, &str) {
+    match name.find(REGISTRY_SEP_CHAR) {
+        Some(pos) => (Some(&name[..pos]), &name[pos + 1..]),
+        None => (None, name),
+    }
+}
+
+/// The kind of the most recent change to a version of a crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The version was published
+    Added,
+    /// The version was yanked
+    Yanked,
+}
+
+impl Default for ChangeKind {
+    fn default() -> Self {
+        ChangeKind::Added
+    }
+}
+
+/// Like in the index, the kind is stored as the yanked flag
+impl serde::Serialize for ChangeKind {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_bool(*self == ChangeKind::Yanked)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ChangeKind {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Ok(if <bool as serde::Deserialize>::deserialize(deserializer)? {
+            ChangeKind::Yanked
+        } else {
+            ChangeKind::Added
+        })
+    }
+}
+
+#[cfg(feature = "engine")]
+impl From<&crates_index_diff::ChangeKind> for ChangeKind {
+    fn from(kind: &crates_index_diff::ChangeKind) -> Self {
+        match kind {
+            crates_index_diff::ChangeKind::Added => ChangeKind::Added,
+            crates_index_diff::ChangeKind::Yanked => ChangeKind::Yanked,
+        }
+    }
+}
+
 /// Pack all information we know about a change made to a version of a crate.
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct CrateVersion {
@@ -113,7 +349,7 @@ pub struct CrateVersion {
     pub name: String,
     /// The kind of change.
     #[serde(rename = "yanked")]
-    pub kind: crates_index_diff::ChangeKind,
+    pub kind: ChangeKind,
     /// The semantic version of the crate.
     #[serde(rename = "vers")]
     pub version: String,
@@ -125,6 +361,26 @@ pub struct CrateVersion {
     /// All crate dependencies
     #[serde(rename = "deps")]
     pub dependencies: Vec<Dependency>,
+    /// The time at which the version was published, if known. It's the time of the commit of the git index in which
+    /// we first saw the version, or the time it was created at according to the db dump.
+    #[serde(default)]
+    pub published_at: Option<SystemTime>,
+    /// The time at which the crate of the version was first seen deleted from the index, or None if it's in the index
+    #[serde(default)]
+    pub deleted_at: Option<SystemTime>,
+    /// The commit of the git index in which the most recent change to the version was observed, or None if it wasn't
+    /// observed in a git index, like versions of the sparse index or those stored before it was recorded.
+    #[serde(default)]
+    pub observed_in: Option<IndexCommit>,
+}
+
+/// A commit of a git index, identifying the state of the index in which a change was observed
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct IndexCommit {
+    /// The hex-encoded id of the commit
+    pub id: String,
+    /// The time at which the commit was made according to its committer
+    pub committed_at: SystemTime,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -146,12 +402,24 @@ pub enum TaskState {
     /// a shutdown or the program is killed.
     /// Thus we cleanup in-progress tasks by checking if their stored_at time is before the process startup time.
     InProgress(Option<Vec<String>>),
+    /// The task can't ever succeed as the artifact it works on is empty, e.g. a crate archive without any entry.
+    /// Empty artifacts are excluded from aggregates and listed in a report of their own.
+    EmptyArtifact(String),
 }
 
 impl TaskState {
     pub fn is_complete(&self) -> bool {
         matches!(self, TaskState::Complete)
     }
+    /// The state of a task which was in this state when processing it was abandoned, which is the one it had before
+    /// it was started.
+    pub fn abandoned(&self) -> TaskState {
+        match self {
+            TaskState::InProgress(None) => TaskState::NotStarted,
+            TaskState::InProgress(Some(failures)) => TaskState::AttemptsWithFailure(failures.clone()),
+            state => state.clone(),
+        }
+    }
     pub fn merge_with(&mut self, other: &TaskState) {
         fn merge_vec(mut existing: Vec<String>, new: &[String]) -> Vec<String> {
             existing.extend(new.iter().cloned());
@@ -191,6 +459,9 @@ pub struct Task {
     /// Information about the process version
     pub version: String,
     pub state: TaskState,
+    /// Validators of the resource the task downloaded last, to only download it again if it changed
+    #[serde(default)]
+    pub validators: Option<HttpValidators>,
 }
 
 impl Default for Task {
@@ -200,10 +471,76 @@ impl Default for Task {
             process: Default::default(),
             version: Default::default(),
             state: Default::default(),
+            validators: None,
         }
     }
 }
 
+/// The most recent versions of a crate worth knowing about, materialized to not have to look at all of its versions
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatestVersion {
+    /// The most recent version which isn't yanked, or None if all versions are yanked
+    pub version: Option<String>,
+    /// The most recent version which was analyzed, if any, which may be yanked
+    pub analyzed_version: Option<String>,
+}
+
+/// A crate version to process as part of a backfill, as listed in its work manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackfillItem {
+    pub crate_name: String,
+    pub crate_version: String,
+    /// The priority with which the work for the crate version is scheduled
+    pub priority: i64,
+    /// The estimated cost of processing the crate version, which is the size of its compressed archive in bytes
+    pub estimated_cost: u64,
+}
+
+/// How far a backfill has progressed, in crate versions and in their estimated cost
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackfillProgress {
+    pub num_items: u64,
+    pub num_items_done: u64,
+    pub total_cost: u64,
+    pub total_cost_done: u64,
+}
+
+impl BackfillProgress {
+    /// Returns true if there is no work left to do, which is also the case if there never was any
+    pub fn is_complete(&self) -> bool {
+        self.num_items_done == self.num_items
+    }
+
+    /// The amount of estimated cost that is done, in percent
+    pub fn percent_done(&self) -> f32 {
+        if self.total_cost == 0 {
+            return 100.0;
+        }
+        self.total_cost_done as f32 * 100.0 / self.total_cost as f32
+    }
+}
+
+impl std::fmt::Display for BackfillProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.0}% of backfill complete ({} of {} crate versions)",
+            self.percent_done(),
+            self.num_items_done,
+            self.num_items
+        )
+    }
+}
+
+/// The headers of an HTTP response which allow to make a conditional request for the same resource
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct HttpValidators {
+    /// The value of the `ETag` header
+    pub etag: Option<String>,
+    /// The value of the `Last-Modified` header
+    pub last_modified: Option<String>,
+}
+
 impl Task {
     // NOTE: Racy if task should be spawned based on the outcome, only for tasks with no contention!
     pub fn can_be_started(&self, startup_time: std::time::SystemTime) -> bool {
@@ -223,12 +560,17 @@ pub enum TaskResult {
     /// Most interesting information about an unpacked crate
     ExplodedCrate {
         /// Meta data of all entries in the crate
+        /// Empty for results stored after the entries moved into their own table, see `persistence::TarHeaderTable`.
         entries_meta_data: Vec<TarHeader>,
         /// The actual content of selected files, Cargo.*, build.rs and lib/main
-        /// IMPORTANT: This file may be partial and limited in size unless it is Cargo.toml, which
-        /// is always complete.
+        /// IMPORTANT: This file may be partial and limited in size unless it is Cargo.toml or Cargo.toml.orig, which
+        /// are always complete.
         /// Note that these are also present in entries_meta_data.
         selected_entries: Vec<(TarHeader, Vec<u8>)>,
+        /// Selected entries of which only a sample was stored, as their size exceeded the one allowed by the extraction
+        /// profile. Entries not listed here are complete.
+        #[serde(default)]
+        truncated_entries: Vec<ContentSample>,
     },
     /// A download with meta data and the downloaded blob itself
     Download {
@@ -237,6 +579,18 @@ pub enum TaskResult {
         content_length: u32,
         /// The content type, it's optional because it might not be set (even though it should)
         content_type: Option<String>,
+        /// The value of the `ETag` header of the response, if it was set
+        #[serde(default)]
+        etag: Option<String>,
+        /// The value of the `Last-Modified` header of the response, if it was set
+        #[serde(default)]
+        last_modified: Option<String>,
+        /// The url the download was served from if it differs from `url`, like after a redirect
+        #[serde(default)]
+        final_url: Option<String>,
+        /// The time it took until the response headers arrived, or None if it wasn't recorded
+        #[serde(default)]
+        response_time: Option<std::time::Duration>,
     },
 }
 
@@ -246,6 +600,7 @@ impl Default for TaskResult {
     }
 }
 
+#[cfg(feature = "engine")]
 impl From<crates_index_diff::CrateVersion> for CrateVersion {
     fn from(v: crates_index_diff::CrateVersion) -> Self {
         let crates_index_diff::CrateVersion {
@@ -258,18 +613,21 @@ impl From<crates_index_diff::CrateVersion> for CrateVersion {
         } = v;
         CrateVersion {
             name,
-            kind,
+            kind: ChangeKind::from(&kind),
             version,
             checksum,
             features,
             dependencies: dependencies.into_iter().map(Into::into).collect(),
+            published_at: None,
+            deleted_at: None,
+            observed_in: None,
         }
     }
 }
 
 pub mod db_dump {
     use serde_derive::{Deserialize, Serialize};
-    use std::time::SystemTime;
+    use std::{collections::BTreeMap, time::SystemTime};
 
     pub type Id = u32;
     pub type GitHubId = i32;
@@ -305,6 +663,14 @@ pub mod db_dump {
         pub crates: Vec<String>,
     }
 
+    #[derive(Clone, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Debug)]
+    pub struct Badge {
+        /// The kind of badge, like 'travis-ci' or 'maintenance'
+        pub badge_type: String,
+        /// Attributes of the badge, like the 'repository' and 'branch' to show the CI status for
+        pub attributes: BTreeMap<String, String>,
+    }
+
     #[derive(Clone, Default, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Debug)]
     pub struct Person {
         pub name: String,
@@ -332,6 +698,9 @@ pub mod db_dump {
         pub published_by: Option<Actor>,
         /// If true, the version was yanked
         pub is_yanked: bool,
+        /// The hex-encoded SHA-256 checksum of the crate archive, if the db dump provided it
+        #[serde(default)]
+        pub checksum: Option<String>,
     }
 
     #[derive(Clone, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Debug)]
@@ -371,5 +740,11 @@ pub mod db_dump {
         pub categories: Vec<Category>,
         pub created_by: Option<Actor>,
         pub owners: Vec<Actor>,
+        /// Badges as configured in Cargo.toml, usually pointing to CI services and the upstream repository
+        #[serde(default)]
+        pub badges: Vec<Badge>,
+        /// The normalized host of `repository`, like `github.com`, if it is the url of a repository
+        #[serde(default)]
+        pub repository_host: Option<String>,
     }
 }
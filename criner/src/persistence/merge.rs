@@ -1,5 +1,6 @@
 use crate::model::{self, Context, CrateVersion, Task};
 use crate::utils::parse_semver;
+use std::time::SystemTime;
 
 pub trait Merge<T> {
     fn merge(self, other: &T) -> Self;
@@ -50,20 +51,41 @@ fn sort_semver(versions: &mut Vec<String>) {
 
 impl Merge<model::CrateVersion> for model::Crate {
     fn merge(mut self, other: &CrateVersion) -> Self {
-        if !self.versions.contains(&other.version) {
-            self.versions.push(other.version.to_owned());
-        }
-        sort_semver(&mut self.versions);
+        self.merge_mut(other);
         self
     }
 }
 
 impl model::Crate {
     pub fn merge_mut(&mut self, other: &CrateVersion) -> &mut model::Crate {
+        // A crate with versions in the index exists again, even if it was deleted before
+        if other.deleted_at.is_none() {
+            self.deleted_at = None;
+        }
         if !self.versions.contains(&other.version) {
             self.versions.push(other.version.to_owned());
         }
         sort_semver(&mut self.versions);
+        if let Some(published_at) = other.published_at {
+            self.publish_times
+                .entry(other.version.to_owned())
+                .or_insert(published_at);
+        }
+
+        let is_yanked = other.kind == model::ChangeKind::Yanked;
+        let state = self
+            .yank_states
+            .entry(other.version.to_owned())
+            .or_insert_with(|| model::YankState {
+                is_yanked,
+                since: SystemTime::now(),
+            });
+        if state.is_yanked != is_yanked {
+            *state = model::YankState {
+                is_yanked,
+                since: SystemTime::now(),
+            };
+        }
         self
     }
 }
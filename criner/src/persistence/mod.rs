@@ -5,19 +5,44 @@ mod keyed;
 mod merge;
 pub use keyed::*;
 
+mod seen;
 mod serde;
 mod table;
+pub use seen::SeenFilter;
 pub use table::*;
 
+/// Increment this whenever data is written in a way that older versions of criner would misinterpret.
+pub const SCHEMA_EPOCH: u32 = 15;
+
+/// The name of the sqlite database file within the database directory
+pub const SQLITE_FILE_NAME: &str = "db.msgpack.sqlite";
+
 #[derive(Clone)]
 pub struct Db {
     sqlite_path: PathBuf,
 }
 
 impl Db {
+    /// Open the database at `path`, refusing to do so if it was written with a newer schema epoch.
     pub fn open(path: impl AsRef<Path>) -> Result<Db> {
+        Self::open_with_skew_check(path, false)
+    }
+
+    /// Open the database at `path` like `open()`, but only to read from it, which is why it isn't recorded
+    /// as written by our version of criner.
+    pub fn open_for_reading(path: impl AsRef<Path>) -> Result<Db> {
+        Self::open_inner(path, false, false)
+    }
+
+    /// Open the database at `path`, and warn instead of failing if `allow_newer_schema` is true
+    /// and the database was written by a version of criner with a newer schema epoch.
+    pub fn open_with_skew_check(path: impl AsRef<Path>, allow_newer_schema: bool) -> Result<Db> {
+        Self::open_inner(path, allow_newer_schema, true)
+    }
+
+    fn open_inner(path: impl AsRef<Path>, allow_newer_schema: bool, is_writer: bool) -> Result<Db> {
         std::fs::create_dir_all(&path)?;
-        let sqlite_path = path.as_ref().join("db.msgpack.sqlite");
+        let sqlite_path = path.as_ref().join(SQLITE_FILE_NAME);
         {
             let mut connection = rusqlite::Connection::open(&sqlite_path)?;
             connection.execute_batch("
@@ -28,26 +53,24 @@ impl Db {
             ")?;
 
             let transaction = connection.transaction()?;
-            for name in &["meta", "crate_version", "crate", "task", "result", "crates.io-crate"] {
-                transaction.execute_batch(&format!(
-                    "CREATE TABLE IF NOT EXISTS '{}' (
-                          key             TEXT PRIMARY KEY NOT NULL,
-                          data            BLOB NOT NULL
-                    )",
-                    name
-                ))?;
+            // Readers leave the database as they found it, even if it doesn't have any tables yet
+            if is_writer {
+                create_tables(&transaction)?;
             }
-            transaction.execute_batch(
-                "CREATE TABLE IF NOT EXISTS report_done (
-                        key             TEXT PRIMARY KEY NOT NULL
-                )",
-            )?;
+            check_schema_version(&transaction, allow_newer_schema, is_writer)?;
             transaction.commit()?;
         }
 
         Ok(Db { sqlite_path })
     }
 
+    /// The directory containing the database, along with other files describing its state.
+    pub fn directory(&self) -> &Path {
+        self.sqlite_path
+            .parent()
+            .expect("the database file is always within a directory")
+    }
+
     pub fn open_connection(&self) -> Result<ThreadSafeConnection> {
         Ok(std::sync::Arc::new(parking_lot::Mutex::new(
             rusqlite::Connection::open(&self.sqlite_path)?,
@@ -86,6 +109,96 @@ impl Db {
             inner: self.open_connection()?,
         })
     }
+    pub fn open_tar_headers(&self) -> Result<TarHeaderTable> {
+        Ok(TarHeaderTable {
+            inner: self.open_connection()?,
+        })
+    }
+    pub fn open_crate_owners(&self) -> Result<CrateOwnerTable> {
+        Ok(CrateOwnerTable {
+            inner: self.open_connection()?,
+        })
+    }
+    pub fn open_latest_versions(&self) -> Result<LatestVersionTable> {
+        Ok(LatestVersionTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_crate_keywords(&self) -> Result<CrateKeywordTable> {
+        Ok(CrateKeywordTable {
+            inner: self.open_connection()?,
+        })
+    }
+    pub fn open_crate_categories(&self) -> Result<CrateCategoryTable> {
+        Ok(CrateCategoryTable {
+            inner: self.open_connection()?,
+        })
+    }
+    pub fn open_request_log(&self) -> Result<RequestLogTable> {
+        Ok(RequestLogTable {
+            inner: self.open_connection()?,
+        })
+    }
+    pub fn open_queue(&self) -> Result<QueueTable> {
+        Ok(QueueTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_version_discrepancies(&self) -> Result<VersionDiscrepancyTable> {
+        Ok(VersionDiscrepancyTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_empty_artifacts(&self) -> Result<EmptyArtifactTable> {
+        Ok(EmptyArtifactTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_backfill_manifest(&self) -> Result<BackfillManifestTable> {
+        Ok(BackfillManifestTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_sparse_index(&self) -> Result<SparseIndexTable> {
+        Ok(SparseIndexTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_crate_version_quarantine(&self) -> Result<CrateVersionQuarantineTable> {
+        Ok(CrateVersionQuarantineTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_processing_costs(&self) -> Result<ProcessingCostTable> {
+        Ok(ProcessingCostTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_index_fetches(&self) -> Result<IndexFetchTable> {
+        Ok(IndexFetchTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_db_dump_quarantine(&self) -> Result<QuarantineTable> {
+        Ok(QuarantineTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_crate_blobs(&self) -> Result<CrateBlobTable> {
+        Ok(CrateBlobTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_task_leases(&self) -> Result<TaskLeaseTable> {
+        Ok(TaskLeaseTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_publishes(&self) -> Result<PublishTable> {
+        Ok(PublishTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
     pub fn open_context(&self) -> Result<MetaTable> {
         Ok(MetaTable {
             inner: self.open_connection()?,
@@ -98,6 +211,302 @@ impl Db {
     }
 }
 
+/// Create all tables which don't exist yet.
+fn create_tables(transaction: &rusqlite::Transaction) -> Result<()> {
+    for name in &[
+        "meta",
+        "crate_version",
+        "crate",
+        "task",
+        "result",
+        "crates.io-crate",
+        "seen_filter",
+    ] {
+        transaction.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS '{}' (
+                  key             TEXT PRIMARY KEY NOT NULL,
+                  data            BLOB NOT NULL
+            )",
+            name
+        ))?;
+    }
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS report_done (
+                key             TEXT PRIMARY KEY NOT NULL
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS tar_header (
+                crate_name      TEXT NOT NULL,
+                crate_version   TEXT NOT NULL,
+                path            BLOB NOT NULL,
+                size            INTEGER NOT NULL,
+                entry_type      INTEGER NOT NULL,
+                PRIMARY KEY (crate_name, crate_version, path)
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS actor (
+                crates_io_id        INTEGER NOT NULL,
+                kind                INTEGER NOT NULL, -- 0 for users, 1 for teams
+                github_id           INTEGER NOT NULL,
+                github_login        TEXT NOT NULL,
+                github_avatar_url   TEXT NOT NULL,
+                name                TEXT,
+                PRIMARY KEY (crates_io_id, kind)
+        );
+        CREATE TABLE IF NOT EXISTS crate_owner (
+                crate_name      TEXT NOT NULL,
+                actor_id        INTEGER NOT NULL,
+                actor_kind      INTEGER NOT NULL,
+                PRIMARY KEY (crate_name, actor_id, actor_kind)
+        );
+        CREATE INDEX IF NOT EXISTS crate_owner_by_actor ON crate_owner (actor_id, actor_kind)",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS latest_version (
+                crate_name          TEXT PRIMARY KEY NOT NULL,
+                version             TEXT, -- the most recent version which isn't yanked, NULL if all are
+                analyzed_version    TEXT -- the most recent version which was analyzed, NULL if there is none
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS keyword (
+                name            TEXT PRIMARY KEY NOT NULL,
+                crates_count    INTEGER NOT NULL -- the amount of crates using the keyword according to crates.io
+        );
+        CREATE TABLE IF NOT EXISTS crate_keyword (
+                crate_name      TEXT NOT NULL,
+                keyword         TEXT NOT NULL,
+                PRIMARY KEY (crate_name, keyword)
+        );
+        CREATE INDEX IF NOT EXISTS crate_keyword_by_keyword ON crate_keyword (keyword);
+        CREATE TABLE IF NOT EXISTS category (
+                slug            TEXT PRIMARY KEY NOT NULL,
+                name            TEXT NOT NULL,
+                path            TEXT NOT NULL, -- the slugs of all parent categories and this one, separated by dots
+                description     TEXT NOT NULL,
+                crates_count    INTEGER NOT NULL -- the amount of crates in the category according to crates.io
+        );
+        CREATE TABLE IF NOT EXISTS crate_category (
+                crate_name      TEXT NOT NULL,
+                category_slug   TEXT NOT NULL,
+                PRIMARY KEY (crate_name, category_slug)
+        );
+        CREATE INDEX IF NOT EXISTS crate_category_by_category ON crate_category (category_slug)",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS request_log (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                requested_at    INTEGER NOT NULL, -- seconds since epoch
+                url             TEXT NOT NULL,
+                status          INTEGER, -- the HTTP status, if there was a response
+                duration_ms     INTEGER NOT NULL,
+                bytes           INTEGER NOT NULL,
+                error           TEXT
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS queue (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                queue           TEXT NOT NULL,
+                key             TEXT NOT NULL, -- identifies the work to do, which is only queued once
+                priority        INTEGER NOT NULL, -- items with a higher priority are claimed first
+                enqueued_at     INTEGER NOT NULL, -- seconds since epoch
+                claimed_at      INTEGER, -- seconds since epoch, set while a processor works on the item
+                payload         BLOB NOT NULL,
+                UNIQUE (queue, key)
+        );
+        CREATE INDEX IF NOT EXISTS queue_by_priority ON queue (queue, priority DESC, id)",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS version_discrepancy (
+                crate_name      TEXT NOT NULL,
+                crate_version   TEXT NOT NULL,
+                kind            TEXT NOT NULL, -- what the index and the db dump disagree about
+                index_value     TEXT NOT NULL,
+                db_dump_value   TEXT NOT NULL,
+                PRIMARY KEY (crate_name, crate_version, kind)
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS empty_artifact (
+                crate_name      TEXT NOT NULL,
+                crate_version   TEXT NOT NULL,
+                reason          TEXT NOT NULL, -- why the crate archive is considered empty
+                PRIMARY KEY (crate_name, crate_version)
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS backfill_manifest (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT, -- the order in which crate versions are processed
+                crate_name      TEXT NOT NULL,
+                crate_version   TEXT NOT NULL,
+                priority        INTEGER NOT NULL,
+                estimated_cost  INTEGER NOT NULL, -- the size of the compressed crate archive in bytes
+                done_at         INTEGER -- seconds since epoch, NULL if the crate version wasn't processed yet
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS sparse_index_crate (
+                crate_name      TEXT PRIMARY KEY NOT NULL, -- the lower-case crate name
+                etag            TEXT,
+                last_modified   TEXT,
+                fetched_at      INTEGER NOT NULL -- seconds since epoch
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS crate_version_quarantine (
+                crate_name      TEXT NOT NULL,
+                crate_version   TEXT NOT NULL,
+                crashes         INTEGER NOT NULL,
+                reason          TEXT NOT NULL, -- of the most recent crash
+                quarantined_at  INTEGER, -- seconds since epoch, NULL unless the crate version is quarantined
+                PRIMARY KEY (crate_name, crate_version)
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS processing_cost (
+                crate_name      TEXT NOT NULL,
+                crate_version   TEXT NOT NULL,
+                stage           TEXT NOT NULL, -- one of 'download', 'extract' or 'report'
+                duration_us     INTEGER NOT NULL,
+                bytes           INTEGER NOT NULL,
+                recorded_at     INTEGER NOT NULL, -- seconds since epoch
+                PRIMARY KEY (crate_name, crate_version, stage)
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS index_fetch (
+                registry        TEXT PRIMARY KEY NOT NULL, -- the name of the registry, 'crates.io' for crates.io
+                target_ref      TEXT NOT NULL, -- the hex-encoded id of the commit changes are stored up to
+                started_at      INTEGER NOT NULL -- seconds since epoch
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS index_fetch_change (
+                registry        TEXT NOT NULL,
+                seq             INTEGER NOT NULL, -- the position of the change in the list of changes
+                data            BLOB NOT NULL, -- the changed crate version, msgpack encoded
+                PRIMARY KEY (registry, seq)
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS index_last_seen (
+                registry        TEXT PRIMARY KEY NOT NULL,
+                commit_id       TEXT NOT NULL, -- the hex-encoded id of the commit all changes were stored up to
+                seen_at         INTEGER NOT NULL -- seconds since epoch
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS db_dump_quarantine (
+                table_name      TEXT NOT NULL,
+                line            INTEGER NOT NULL, -- the line at which the row starts in the CSV file
+                column_name     TEXT, -- the column which couldn't be decoded, if known
+                content         BLOB NOT NULL, -- the raw row as CSV
+                error           TEXT NOT NULL,
+                PRIMARY KEY (table_name, line)
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS publish (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                published_at    INTEGER NOT NULL, -- seconds since epoch
+                commit_id       TEXT NOT NULL, -- the hex-encoded id of the commit with the reports
+                files           INTEGER NOT NULL,
+                push_error      TEXT -- set if the commit couldn't be pushed
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS crate_blob (
+                crate_name      TEXT NOT NULL,
+                crate_version   TEXT NOT NULL,
+                checksum        TEXT NOT NULL, -- the hex-encoded sha256 of the archive, naming the blob
+                stored_at       INTEGER NOT NULL, -- seconds since epoch
+                PRIMARY KEY (crate_name, crate_version)
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS task_lease (
+                task_key        TEXT PRIMARY KEY NOT NULL,
+                owner           TEXT NOT NULL, -- identifies the process working on the task
+                expires_at      INTEGER NOT NULL -- seconds since epoch, after which others may take it over
+        )",
+    )?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+                table_name          TEXT PRIMARY KEY NOT NULL,
+                epoch               INTEGER NOT NULL, -- the highest schema epoch that ever wrote to the table
+                last_writer_version TEXT NOT NULL, -- the latest criner version writing to the table at its epoch
+                written_at          INTEGER NOT NULL -- seconds since epoch
+        )",
+    )?;
+    Ok(())
+}
+
+/// Fail if the database was written with a newer schema epoch unless `allow_newer_schema` is true, and record that
+/// all tables are written by us if we are a writer.
+fn check_schema_version(transaction: &rusqlite::Transaction, allow_newer_schema: bool, is_writer: bool) -> Result<()> {
+    use rusqlite::{params, OptionalExtension, NO_PARAMS};
+    let has_schema_version = transaction
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+            NO_PARAMS,
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !has_schema_version {
+        return Ok(());
+    }
+    if let Some((epoch, writer_version)) = transaction
+        .query_row(
+            "SELECT epoch, last_writer_version FROM schema_version ORDER BY epoch DESC LIMIT 1",
+            NO_PARAMS,
+            |r| Ok((r.get::<_, u32>(0)?, r.get::<_, String>(1)?)),
+        )
+        .optional()?
+    {
+        if epoch > SCHEMA_EPOCH {
+            if !allow_newer_schema {
+                return Err(crate::Error::VersionSkew(epoch, writer_version));
+            }
+            log::warn!(
+                "Opening database written by criner {} with schema epoch {}, which is newer than ours ({}). Data may be misinterpreted.",
+                writer_version,
+                epoch,
+                SCHEMA_EPOCH
+            );
+        }
+    }
+    if !is_writer {
+        return Ok(());
+    }
+
+    let written_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    let table_names = {
+        let mut statement =
+            transaction.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")?;
+        let names: std::result::Result<Vec<_>, _> =
+            statement.query_map(NO_PARAMS, |r| r.get::<_, String>(0))?.collect();
+        names?
+    };
+    for table_name in table_names {
+        transaction.execute(
+            "INSERT INTO schema_version (table_name, epoch, last_writer_version, written_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(table_name) DO UPDATE SET
+                epoch = max(epoch, excluded.epoch),
+                last_writer_version = CASE WHEN excluded.epoch >= epoch
+                    THEN excluded.last_writer_version ELSE last_writer_version END,
+                written_at = excluded.written_at",
+            params![table_name, SCHEMA_EPOCH, env!("CARGO_PKG_VERSION"), written_at],
+        )?;
+    }
+    Ok(())
+}
+
 fn sleeper(attempts: i32) -> bool {
     log::warn!("SQLITE_BUSY, retrying after 50ms (attempt {})", attempts);
     std::thread::sleep(std::time::Duration::from_millis(50));
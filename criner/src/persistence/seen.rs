@@ -0,0 +1,113 @@
+use crate::{
+    model::Task,
+    persistence::{Db, TableAccess, TaskTable},
+    utils::{fnv1a64, fnv1a64_with_basis},
+    Result,
+};
+use rusqlite::{params, OptionalExtension};
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashSet, time::SystemTime};
+
+const COMPLETED_TASKS_KEY: &str = "completed-tasks";
+
+/// A persistent set of hashes of the keys of all completed tasks.
+///
+/// It allows the scheduler to skip crate versions whose tasks are known to be complete without
+/// querying the task table for each of them. It is stored in the database along with the highest
+/// rowid of the task table it has seen, which allows to update it by looking only at tasks that
+/// were written since.
+/// Please note that tasks are written with `REPLACE`, which assigns a new rowid to changed rows.
+///
+/// As only hashes are stored, a task can be taken for complete if its hash collides with the one of a complete task,
+/// and its crate version is skipped until the filter is rebuilt. The hashes are seeded differently with each build of
+/// the filter, so such a collision doesn't outlive it, and `rebuild()` also drops the tasks that were deleted since.
+#[derive(Serialize, Deserialize)]
+pub struct SeenFilter {
+    seed: u64,
+    max_rowid: i64,
+    hashes: HashSet<u64>,
+}
+
+impl SeenFilter {
+    pub fn table_name() -> &'static str {
+        "seen_filter"
+    }
+
+    fn new() -> Result<SeenFilter> {
+        let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_nanos();
+        Ok(SeenFilter {
+            seed: fnv1a64(nanos.to_string().as_bytes()),
+            max_rowid: 0,
+            hashes: HashSet::new(),
+        })
+    }
+
+    /// Load the filter from the database and add all tasks that were completed since it was stored.
+    pub fn load_and_update(db: &Db, progress: &mut prodash::tree::Item) -> Result<SeenFilter> {
+        progress.blocked("loading filter of completed tasks", None);
+        let connection = db.open_connection_no_async_with_busy_wait()?;
+        let filter = match connection
+            .query_row(
+                &format!("SELECT data FROM {} WHERE key = ?1", Self::table_name()),
+                params![COMPLETED_TASKS_KEY],
+                |r| r.get::<_, Vec<u8>>(0),
+            )
+            .optional()?
+            .and_then(|data| rmp_serde::from_slice::<SeenFilter>(&data).ok())
+        {
+            Some(filter) => filter,
+            None => SeenFilter::new()?,
+        };
+        filter.update(&connection, progress)
+    }
+
+    /// Build a new filter from all tasks that are complete, ignoring the stored one.
+    pub fn rebuild(db: &Db, progress: &mut prodash::tree::Item) -> Result<SeenFilter> {
+        let connection = db.open_connection_no_async_with_busy_wait()?;
+        SeenFilter::new()?.update(&connection, progress)
+    }
+
+    fn update(mut self, connection: &rusqlite::Connection, progress: &mut prodash::tree::Item) -> Result<SeenFilter> {
+        progress.blocked("adding recently completed tasks to filter", None);
+        let mut statement = connection.prepare(&format!(
+            "SELECT _rowid_, key, data FROM {} WHERE _rowid_ > ?1",
+            TaskTable::table_name()
+        ))?;
+        let mut rows = statement.query(params![self.max_rowid])?;
+        while let Some(row) = rows.next()? {
+            let rowid: i64 = row.get(0)?;
+            let key: String = row.get(1)?;
+            let task = Task::from(row.get::<_, Vec<u8>>(2)?.as_slice());
+            // Tasks which were complete can be reset to be worked on again
+            let hash = self.hash(&key);
+            if task.state.is_complete() {
+                self.hashes.insert(hash);
+            } else {
+                self.hashes.remove(&hash);
+            }
+            self.max_rowid = self.max_rowid.max(rowid);
+        }
+        progress.info(format!("{} completed tasks are known", self.hashes.len()));
+        Ok(self)
+    }
+
+    pub fn store(&self, db: &Db) -> Result<()> {
+        db.open_connection_no_async_with_busy_wait()?.execute(
+            &format!("REPLACE INTO {} (key, data) VALUES (?1, ?2)", Self::table_name()),
+            params![COMPLETED_TASKS_KEY, rmp_serde::to_vec(self)?],
+        )?;
+        Ok(())
+    }
+
+    pub fn insert(&mut self, key: &str) {
+        self.hashes.insert(self.hash(key));
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.hashes.contains(&self.hash(key))
+    }
+
+    fn hash(&self, key: &str) -> u64 {
+        fnv1a64_with_basis(self.seed, key.as_bytes())
+    }
+}
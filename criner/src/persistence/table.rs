@@ -1,30 +1,25 @@
 use crate::persistence::KEY_SEP_CHAR;
 use crate::{
-    model::{Context, Crate, TaskResult},
-    model::{CrateVersion, Task},
+    model::{
+        db_dump, Context, Crate, DiscrepancyKind, EmptyArtifact, HttpValidators, Publish, QuarantinedRow, TarHeader,
+        TaskResult, VersionDiscrepancy,
+    },
+    model::{BackfillItem, BackfillProgress, CrateVersion, LatestVersion, Task, TaskState},
     persistence::{merge::Merge, Keyed},
+    utils::parse_semver,
     Result,
 };
 use rusqlite::{params, OptionalExtension, NO_PARAMS};
-use std::time::{Duration, SystemTime};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, SystemTime},
+};
 
 /// Required as we send futures to threads. The type system can't statically prove that in fact
 /// these connections will only ever be created while already in the thread they should execute on.
 /// Also no one can prevent futures from being resumed in after having been send to a different thread.
 pub type ThreadSafeConnection = std::sync::Arc<parking_lot::Mutex<rusqlite::Connection>>;
 
-pub fn new_value_query_recent_first<'conn>(
-    table_name: &str,
-    connection: &'conn rusqlite::Connection,
-    offset: usize,
-    limit: usize,
-) -> Result<rusqlite::Statement<'conn>> {
-    Ok(connection.prepare(&format!(
-        "SELECT data FROM {} ORDER BY _rowid_ DESC LIMIT {}, {}",
-        table_name, offset, limit
-    ))?)
-}
-
 pub fn new_key_value_query_old_to_new_filtered<'conn>(
     table_name: &str,
     glob: Option<&str>,
@@ -45,6 +40,33 @@ pub fn new_key_value_query_old_to_new_filtered<'conn>(
     ))?)
 }
 
+/// Return the names of all crates matching `glob` along with their amount of downloads according to the most recently
+/// ingested db dump, the most downloaded crates first.
+/// Crates unknown to the db dump have no downloads and come last, in the order they were first seen.
+pub fn crates_by_downloads(glob: Option<&str>, connection: &rusqlite::Connection) -> Result<Vec<(String, u64)>> {
+    let mut downloads = std::collections::HashMap::new();
+    {
+        let mut statement = connection.prepare("SELECT data FROM 'crates.io-crate'")?;
+        let mut rows = statement.query(NO_PARAMS)?;
+        while let Some(row) = rows.next()? {
+            let data: Vec<u8> = row.get(0)?;
+            let krate: db_dump::Crate = rmp_serde::from_slice(&data)?;
+            downloads.insert(krate.name, krate.downloads);
+        }
+    }
+    let mut statement = new_key_value_query_old_to_new_filtered(CrateTable::table_name(), glob, connection, None)?;
+    let names: rusqlite::Result<Vec<String>> = statement.query_map(NO_PARAMS, |r| r.get(0))?.collect();
+    let mut crates: Vec<_> = names?
+        .into_iter()
+        .map(|name| {
+            let downloads = downloads.get(&name).copied().unwrap_or(0);
+            (name, downloads)
+        })
+        .collect();
+    crates.sort_by_key(|(_, downloads)| std::cmp::Reverse(*downloads));
+    Ok(crates)
+}
+
 pub fn new_key_value_query_old_to_new<'conn>(
     table_name: &str,
     connection: &'conn rusqlite::Connection,
@@ -66,19 +88,6 @@ pub fn new_key_insertion<'conn>(
     Ok(connection.prepare(&format!("REPLACE INTO {} (key) VALUES (?1)", table_name))?)
 }
 
-pub fn value_iter<'stm, 'conn, StorageItem>(
-    statement: &'stm mut rusqlite::Statement<'conn>,
-) -> Result<impl Iterator<Item = Result<StorageItem>> + 'stm>
-where
-    StorageItem: for<'a> From<&'a [u8]>,
-{
-    Ok(statement
-        .query_map(NO_PARAMS, |r| {
-            r.get::<_, Vec<u8>>(0).map(|v| StorageItem::from(v.as_slice()))
-        })?
-        .map(|r| r.map_err(Into::into)))
-}
-
 pub fn key_value_iter<'stm, 'conn, StorageItem>(
     statement: &'stm mut rusqlite::Statement<'conn>,
 ) -> Result<impl Iterator<Item = Result<(String, StorageItem)>> + 'stm>
@@ -226,9 +235,7 @@ fn retry_on_db_busy<T>(mut progress: Option<&mut prodash::tree::Item>, mut f: im
         match f() {
             Ok(v) => return Ok(v),
             Err(
-                err
-                @
-                Error::Rusqlite(SqliteError::SqliteFailure(
+                err @ Error::Rusqlite(SqliteError::SqliteFailure(
                     SqliteFFIError {
                         code: SqliteFFIErrorCode::DatabaseBusy,
                         ..
@@ -284,6 +291,51 @@ impl TableAccess for TaskTable {
     }
 }
 
+impl TaskTable {
+    /// Reset all tasks which failed in all of their attempts so far to not being started, forgetting their failures,
+    /// if they belong to a crate whose name `matches`. Return the crate versions of the tasks which were reset.
+    pub fn reset_failed(&self, matches: impl Fn(&str) -> bool) -> Result<BTreeSet<(String, String)>> {
+        retry_on_db_busy(None, || {
+            let mut guard = self.inner.lock();
+            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            let mut failed = Vec::new();
+            {
+                let mut statement = transaction.prepare(&format!("SELECT key, data FROM {}", Self::table_name()))?;
+                let mut rows = statement.query(NO_PARAMS)?;
+                while let Some(row) = rows.next()? {
+                    let key: String = row.get(0)?;
+                    let task = Task::from(row.get::<_, Vec<u8>>(1)?.as_slice());
+                    if !matches!(task.state, TaskState::AttemptsWithFailure(_)) {
+                        continue;
+                    }
+                    // Keys are '<crate>:<version>:<process>:<process version>', while db dumps have fewer parts
+                    let mut parts = key.rsplitn(4, KEY_SEP_CHAR).skip(2);
+                    if let (Some(crate_version), Some(crate_name)) = (parts.next(), parts.next()) {
+                        if matches(crate_name) {
+                            failed.push(((crate_name.to_owned(), crate_version.to_owned()), key.clone(), task));
+                        }
+                    }
+                }
+            }
+            let mut crate_versions = BTreeSet::new();
+            for (crate_version, key, task) in failed {
+                let task = Task {
+                    state: TaskState::NotStarted,
+                    stored_at: SystemTime::now(),
+                    ..task
+                };
+                transaction.execute(
+                    &format!("REPLACE INTO {} (key, data) VALUES (?1, ?2)", Self::table_name()),
+                    params![key, rmp_serde::to_vec(&task)?],
+                )?;
+                crate_versions.insert(crate_version);
+            }
+            transaction.commit()?;
+            Ok(crate_versions)
+        })
+    }
+}
+
 pub struct ReportsTree {
     pub(crate) inner: ThreadSafeConnection,
 }
@@ -319,109 +371,1682 @@ impl ReportsTree {
     }
 }
 
-pub struct TaskResultTable {
+/// Meta data of all entries of extracted crates, one row per entry
+pub struct TarHeaderTable {
     pub(crate) inner: ThreadSafeConnection,
 }
 
-impl TableAccess for TaskResultTable {
-    type StorageItem = TaskResult;
-    type InsertItem = TaskResult;
-
-    fn connection(&self) -> &ThreadSafeConnection {
-        &self.inner
+impl TarHeaderTable {
+    pub fn table_name() -> &'static str {
+        "tar_header"
     }
-    fn table_name() -> &'static str {
-        "result"
+
+    /// Replace all entries of the given crate version with `headers`
+    ///
+    /// Archives may contain the same path more than once, in which case the last entry wins just like it would
+    /// when unpacking the archive.
+    pub fn insert_all(
+        &self,
+        progress: &mut prodash::tree::Item,
+        crate_name: &str,
+        crate_version: &str,
+        headers: &[TarHeader],
+    ) -> Result<()> {
+        retry_on_db_busy(Some(progress), || {
+            let mut guard = self.inner.lock();
+            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            transaction.execute(
+                &format!(
+                    "DELETE FROM {} WHERE crate_name = ?1 AND crate_version = ?2",
+                    Self::table_name()
+                ),
+                params![crate_name, crate_version],
+            )?;
+            {
+                let mut statement = transaction.prepare(&format!(
+                    "INSERT OR REPLACE INTO {} (crate_name, crate_version, path, size, entry_type)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    Self::table_name()
+                ))?;
+                for TarHeader { path, size, entry_type } in headers {
+                    statement.execute(params![crate_name, crate_version, path, *size as i64, entry_type])?;
+                }
+            }
+            transaction.commit()?;
+            Ok(())
+        })
     }
-    fn into_connection(self) -> ThreadSafeConnection {
-        self.inner
+
+    pub fn get_all(&self, crate_name: &str, crate_version: &str) -> Result<Vec<TarHeader>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare(&format!(
+            "SELECT path, size, entry_type FROM {} WHERE crate_name = ?1 AND crate_version = ?2 ORDER BY _rowid_ ASC",
+            Self::table_name()
+        ))?;
+        let headers = statement
+            .query_map(params![crate_name, crate_version], |r| {
+                Ok(TarHeader {
+                    path: r.get(0)?,
+                    size: r.get::<_, i64>(1)? as u64,
+                    entry_type: r.get(2)?,
+                })
+            })?
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(headers)
     }
 }
 
-pub struct MetaTable {
+/// Users and teams as known to crates.io along with the crates they own, as of the most recent db dump
+pub struct CrateOwnerTable {
     pub(crate) inner: ThreadSafeConnection,
 }
 
-impl TableAccess for MetaTable {
-    type StorageItem = Context;
-    type InsertItem = Context;
+impl CrateOwnerTable {
+    pub fn table_name() -> &'static str {
+        "crate_owner"
+    }
 
-    fn connection(&self) -> &ThreadSafeConnection {
-        &self.inner
+    pub fn actor_table_name() -> &'static str {
+        "actor"
     }
-    fn table_name() -> &'static str {
-        "meta"
+
+    /// Replace all owners of the crate with the given `crate_name` with `owners`, as part of the given `transaction`.
+    pub fn replace_owners(
+        transaction: &rusqlite::Transaction,
+        crate_name: &str,
+        owners: &[db_dump::Actor],
+    ) -> Result<()> {
+        transaction
+            .prepare_cached(&format!("DELETE FROM {} WHERE crate_name = ?1", Self::table_name()))?
+            .execute(params![crate_name])?;
+        let mut insert_actor = transaction.prepare_cached(&format!(
+            "REPLACE INTO {} (crates_io_id, kind, github_id, github_login, github_avatar_url, name) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            Self::actor_table_name()
+        ))?;
+        let mut insert_owner = transaction.prepare_cached(&format!(
+            "INSERT OR IGNORE INTO {} (crate_name, actor_id, actor_kind) VALUES (?1, ?2, ?3)",
+            Self::table_name()
+        ))?;
+        for actor in owners {
+            let kind = actor_kind_to_sql(actor.kind);
+            insert_actor.execute(params![
+                actor.crates_io_id,
+                kind,
+                actor.github_id,
+                actor.github_login,
+                actor.github_avatar_url,
+                actor.name
+            ])?;
+            insert_owner.execute(params![crate_name, actor.crates_io_id, kind])?;
+        }
+        Ok(())
     }
 
-    fn merge(new: &Context, existing_item: Option<Context>) -> Self::StorageItem {
-        existing_item.map_or_else(|| new.to_owned(), |existing| existing.merge(new))
+    /// Return all owners of the crate with the given `crate_name`.
+    pub fn owners_of(&self, crate_name: &str) -> Result<Vec<db_dump::Actor>> {
+        fn to_actor(r: &rusqlite::Row) -> rusqlite::Result<db_dump::Actor> {
+            Ok(db_dump::Actor {
+                crates_io_id: r.get(0)?,
+                kind: if r.get::<_, i64>(1)? == 0 {
+                    db_dump::ActorKind::User
+                } else {
+                    db_dump::ActorKind::Team
+                },
+                github_id: r.get(2)?,
+                github_login: r.get(3)?,
+                github_avatar_url: r.get(4)?,
+                name: r.get(5)?,
+            })
+        }
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare_cached(&format!(
+            "SELECT a.crates_io_id, a.kind, a.github_id, a.github_login, a.github_avatar_url, a.name
+             FROM {} o JOIN {} a ON a.crates_io_id = o.actor_id AND a.kind = o.actor_kind
+             WHERE o.crate_name = ?1 ORDER BY a.github_login",
+            Self::table_name(),
+            Self::actor_table_name()
+        ))?;
+        let owners: rusqlite::Result<Vec<_>> = statement.query_map(params![crate_name], to_actor)?.collect();
+        Ok(owners?)
     }
-    fn into_connection(self) -> ThreadSafeConnection {
-        self.inner
+
+    /// Return the names of all crates owned by the user or team with the given `github_login`.
+    pub fn crates_of(&self, github_login: &str) -> Result<Vec<String>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare_cached(&format!(
+            "SELECT o.crate_name FROM {} o JOIN {} a ON a.crates_io_id = o.actor_id AND a.kind = o.actor_kind
+             WHERE a.github_login = ?1 ORDER BY o.crate_name",
+            Self::table_name(),
+            Self::actor_table_name()
+        ))?;
+        let names: rusqlite::Result<Vec<String>> = statement.query_map(params![github_login], |r| r.get(0))?.collect();
+        Ok(names?)
     }
 }
 
-impl MetaTable {
-    pub fn update_today(&self, f: impl Fn(&mut Context)) -> Result<Context> {
-        self.update(None, Context::default().key(), |mut c| {
-            f(&mut c);
-            c
+/// The most recent version of each crate which isn't yanked along with the most recent one which was analyzed,
+/// maintained by the stages learning about them.
+pub struct LatestVersionTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl LatestVersionTable {
+    pub fn table_name() -> &'static str {
+        "latest_version"
+    }
+
+    /// Returns true if the latest version of no crate is known, which is the case before the table was first filled.
+    pub fn is_empty(transaction: &rusqlite::Transaction) -> Result<bool> {
+        let count: i64 = transaction.query_row(
+            &format!("SELECT COUNT(*) FROM (SELECT 1 FROM {} LIMIT 1)", Self::table_name()),
+            NO_PARAMS,
+            |r| r.get(0),
+        )?;
+        Ok(count == 0)
+    }
+
+    /// Set the most recent `version` of the crate with the given `crate_name` which isn't yanked, or None if all
+    /// of its versions are yanked, as part of the given `transaction`.
+    pub fn set_latest(transaction: &rusqlite::Transaction, crate_name: &str, version: Option<&str>) -> Result<()> {
+        transaction
+            .prepare_cached(&format!(
+                "INSERT INTO {} (crate_name, version) VALUES (?1, ?2)
+                 ON CONFLICT (crate_name) DO UPDATE SET version = excluded.version",
+                Self::table_name()
+            ))?
+            .execute(params![crate_name, version])?;
+        Ok(())
+    }
+
+    /// Record that `crate_version` of the crate with the given `crate_name` was analyzed, unless a more recent version
+    /// was analyzed already.
+    pub fn record_analyzed(&self, crate_name: &str, crate_version: &str) -> Result<()> {
+        retry_on_db_busy(None, || {
+            let mut guard = self.inner.lock();
+            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            let analyzed_version: Option<String> = transaction
+                .query_row(
+                    &format!(
+                        "SELECT analyzed_version FROM {} WHERE crate_name = ?1",
+                        Self::table_name()
+                    ),
+                    params![crate_name],
+                    |r| r.get(0),
+                )
+                .optional()?
+                .flatten();
+            let is_more_recent = analyzed_version
+                .map(|analyzed| parse_semver(&analyzed) < parse_semver(crate_version))
+                .unwrap_or(true);
+            if is_more_recent {
+                transaction.execute(
+                    &format!(
+                        "INSERT INTO {} (crate_name, analyzed_version) VALUES (?1, ?2)
+                         ON CONFLICT (crate_name) DO UPDATE SET analyzed_version = excluded.analyzed_version",
+                        Self::table_name()
+                    ),
+                    params![crate_name, crate_version],
+                )?;
+            }
+            transaction.commit()?;
+            Ok(())
         })
     }
 
-    // NOTE: impl iterator is not allowed in traits unfortunately, but one could implement one manually
-    pub fn most_recent(&self) -> Result<Option<(String, Context)>> {
-        Ok(self
-            .connection()
-            .lock()
-            .query_row("SELECT key, data FROM meta ORDER BY key DESC limit 1", NO_PARAMS, |r| {
-                Ok((r.get::<_, String>(0)?, r.get::<_, Vec<u8>>(1)?))
+    /// Return the latest versions of the crate with the given `crate_name`, or None if they are unknown.
+    pub fn get(&self, crate_name: &str) -> Result<Option<LatestVersion>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare_cached(&format!(
+            "SELECT version, analyzed_version FROM {} WHERE crate_name = ?1",
+            Self::table_name()
+        ))?;
+        Ok(statement
+            .query_row(params![crate_name], |r| {
+                Ok(LatestVersion {
+                    version: r.get(0)?,
+                    analyzed_version: r.get(1)?,
+                })
             })
-            .optional()?
-            .map(|(k, v)| (k, Context::from(v.as_slice()))))
+            .optional()?)
     }
 }
 
-#[derive(Clone)]
-pub struct CrateTable {
+/// Keywords as known to crates.io along with the crates using them, as of the most recent db dump
+pub struct CrateKeywordTable {
     pub(crate) inner: ThreadSafeConnection,
 }
 
-impl TableAccess for CrateTable {
-    type StorageItem = Crate;
-    type InsertItem = CrateVersion;
+impl CrateKeywordTable {
+    pub fn table_name() -> &'static str {
+        "crate_keyword"
+    }
 
-    fn connection(&self) -> &ThreadSafeConnection {
-        &self.inner
+    pub fn keyword_table_name() -> &'static str {
+        "keyword"
     }
-    fn table_name() -> &'static str {
-        "crate"
+
+    /// Returns true if no crate is associated with any keyword, which is the case before the first db dump with
+    /// keywords was ingested.
+    pub fn is_empty(transaction: &rusqlite::Transaction) -> Result<bool> {
+        let count: i64 = transaction.query_row(
+            &format!("SELECT COUNT(*) FROM (SELECT 1 FROM {} LIMIT 1)", Self::table_name()),
+            NO_PARAMS,
+            |r| r.get(0),
+        )?;
+        Ok(count == 0)
     }
 
-    fn merge(new_item: &CrateVersion, existing_item: Option<Crate>) -> Crate {
-        existing_item.map_or_else(|| Crate::from(new_item.to_owned()), |c| c.merge(new_item))
+    /// Replace all keywords of the crate with the given `crate_name` with `keywords`, as part of the given `transaction`.
+    pub fn replace_keywords(
+        transaction: &rusqlite::Transaction,
+        crate_name: &str,
+        keywords: &[db_dump::Keyword],
+    ) -> Result<()> {
+        transaction
+            .prepare_cached(&format!("DELETE FROM {} WHERE crate_name = ?1", Self::table_name()))?
+            .execute(params![crate_name])?;
+        let mut insert_keyword = transaction.prepare_cached(&format!(
+            "REPLACE INTO {} (name, crates_count) VALUES (?1, ?2)",
+            Self::keyword_table_name()
+        ))?;
+        let mut insert_association = transaction.prepare_cached(&format!(
+            "INSERT OR IGNORE INTO {} (crate_name, keyword) VALUES (?1, ?2)",
+            Self::table_name()
+        ))?;
+        for keyword in keywords {
+            insert_keyword.execute(params![keyword.name, keyword.crates_count])?;
+            insert_association.execute(params![crate_name, keyword.name])?;
+        }
+        Ok(())
     }
-    fn into_connection(self) -> ThreadSafeConnection {
-        self.inner
+
+    /// Return all keywords of the crate with the given `crate_name`, sorted by name.
+    pub fn keywords_of(&self, crate_name: &str) -> Result<Vec<String>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare_cached(&format!(
+            "SELECT keyword FROM {} WHERE crate_name = ?1 ORDER BY keyword",
+            Self::table_name()
+        ))?;
+        let keywords: rusqlite::Result<Vec<String>> = statement.query_map(params![crate_name], |r| r.get(0))?.collect();
+        Ok(keywords?)
+    }
+
+    /// Return the names of all crates using the given `keyword`, sorted by name.
+    pub fn crates_with(&self, keyword: &str) -> Result<Vec<String>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare_cached(&format!(
+            "SELECT crate_name FROM {} WHERE keyword = ?1 ORDER BY crate_name",
+            Self::table_name()
+        ))?;
+        let names: rusqlite::Result<Vec<String>> = statement.query_map(params![keyword], |r| r.get(0))?.collect();
+        Ok(names?)
+    }
+
+    /// Return all keywords used by at least one crate along with the amount of crates using them, the most used first.
+    pub fn counts(&self) -> Result<Vec<(String, u64)>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare(&format!(
+            "SELECT keyword, COUNT(*) AS crates FROM {} GROUP BY keyword ORDER BY crates DESC, keyword",
+            Self::table_name()
+        ))?;
+        let counts: rusqlite::Result<Vec<_>> = statement
+            .query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get::<_, i64>(1)? as u64)))?
+            .collect();
+        Ok(counts?)
     }
 }
 
-#[derive(Clone)]
-pub struct CrateVersionTable {
+/// Categories as known to crates.io along with the crates in them, as of the most recent db dump
+pub struct CrateCategoryTable {
     pub(crate) inner: ThreadSafeConnection,
 }
 
-impl TableAccess for CrateVersionTable {
-    type StorageItem = CrateVersion;
-    type InsertItem = CrateVersion;
+impl CrateCategoryTable {
+    pub fn table_name() -> &'static str {
+        "crate_category"
+    }
 
-    fn connection(&self) -> &ThreadSafeConnection {
-        &self.inner
+    pub fn category_table_name() -> &'static str {
+        "category"
     }
-    fn table_name() -> &'static str {
-        "crate_version"
+
+    /// Replace all categories of the crate with the given `crate_name` with `categories`, as part of the given
+    /// `transaction`.
+    pub fn replace_categories(
+        transaction: &rusqlite::Transaction,
+        crate_name: &str,
+        categories: &[db_dump::Category],
+    ) -> Result<()> {
+        transaction
+            .prepare_cached(&format!("DELETE FROM {} WHERE crate_name = ?1", Self::table_name()))?
+            .execute(params![crate_name])?;
+        let mut insert_category = transaction.prepare_cached(&format!(
+            "REPLACE INTO {} (slug, name, path, description, crates_count) VALUES (?1, ?2, ?3, ?4, ?5)",
+            Self::category_table_name()
+        ))?;
+        let mut insert_association = transaction.prepare_cached(&format!(
+            "INSERT OR IGNORE INTO {} (crate_name, category_slug) VALUES (?1, ?2)",
+            Self::table_name()
+        ))?;
+        for category in categories {
+            insert_category.execute(params![
+                category.slug,
+                category.name,
+                category.path,
+                category.description,
+                category.crates_count
+            ])?;
+            insert_association.execute(params![crate_name, category.slug])?;
+        }
+        Ok(())
     }
-    fn into_connection(self) -> ThreadSafeConnection {
-        self.inner
+
+    /// Return all categories of the crate with the given `crate_name`, sorted by their path.
+    pub fn categories_of(&self, crate_name: &str) -> Result<Vec<db_dump::Category>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare_cached(&format!(
+            "SELECT c.name, c.crates_count, c.description, c.path, c.slug
+             FROM {} cc JOIN {} c ON c.slug = cc.category_slug
+             WHERE cc.crate_name = ?1 ORDER BY c.path",
+            Self::table_name(),
+            Self::category_table_name()
+        ))?;
+        let categories: rusqlite::Result<Vec<_>> = statement.query_map(params![crate_name], to_category)?.collect();
+        Ok(categories?)
+    }
+
+    /// Return the names of all crates in the category with the given `slug`, sorted by name.
+    pub fn crates_in(&self, slug: &str) -> Result<Vec<String>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare_cached(&format!(
+            "SELECT crate_name FROM {} WHERE category_slug = ?1 ORDER BY crate_name",
+            Self::table_name()
+        ))?;
+        let names: rusqlite::Result<Vec<String>> = statement.query_map(params![slug], |r| r.get(0))?.collect();
+        Ok(names?)
+    }
+
+    /// Return all categories with at least one crate along with the amount of crates in them, sorted by their path.
+    pub fn counts(&self) -> Result<Vec<(db_dump::Category, u64)>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare(&format!(
+            "SELECT c.name, c.crates_count, c.description, c.path, c.slug, COUNT(*)
+             FROM {} cc JOIN {} c ON c.slug = cc.category_slug
+             GROUP BY c.slug ORDER BY c.path",
+            Self::table_name(),
+            Self::category_table_name()
+        ))?;
+        let counts: rusqlite::Result<Vec<_>> = statement
+            .query_map(NO_PARAMS, |r| Ok((to_category(r)?, r.get::<_, i64>(5)? as u64)))?
+            .collect();
+        Ok(counts?)
+    }
+}
+
+fn to_category(r: &rusqlite::Row) -> rusqlite::Result<db_dump::Category> {
+    Ok(db_dump::Category {
+        name: r.get(0)?,
+        crates_count: r.get(1)?,
+        description: r.get(2)?,
+        path: r.get(3)?,
+        slug: r.get(4)?,
+    })
+}
+
+fn actor_kind_to_sql(kind: db_dump::ActorKind) -> i64 {
+    match kind {
+        db_dump::ActorKind::User => 0,
+        db_dump::ActorKind::Team => 1,
+    }
+}
+
+/// An outbound request as recorded in the request log
+pub struct RequestLogEntry {
+    pub id: i64,
+    pub requested_at: SystemTime,
+    pub url: String,
+    /// The HTTP status code, if a response was received at all
+    pub status: Option<u16>,
+    pub duration: Duration,
+    /// The amount of bytes of the body we received
+    pub bytes: u64,
+    pub error: Option<String>,
+}
+
+/// A log of all outbound requests, which only keeps the most recent ones
+pub struct RequestLogTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl RequestLogTable {
+    const MAX_ENTRIES: i64 = 100_000;
+
+    pub fn table_name() -> &'static str {
+        "request_log"
+    }
+
+    /// Record a request made at `requested_at` which just finished
+    pub fn record(
+        &self,
+        url: &str,
+        requested_at: SystemTime,
+        status: Option<u16>,
+        bytes: u64,
+        error: Option<&str>,
+    ) -> Result<()> {
+        let duration = SystemTime::now().duration_since(requested_at).unwrap_or_default();
+        self.record_with_duration(url, requested_at, duration, status, bytes, error)
+    }
+
+    /// Record a request made at `requested_at` which took `duration`, for requests which finished a while ago
+    pub fn record_with_duration(
+        &self,
+        url: &str,
+        requested_at: SystemTime,
+        duration: Duration,
+        status: Option<u16>,
+        bytes: u64,
+        error: Option<&str>,
+    ) -> Result<()> {
+        let requested_at = requested_at.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        retry_on_db_busy(None, || {
+            let guard = self.inner.lock();
+            guard.execute(
+                &format!(
+                    "INSERT INTO {} (requested_at, url, status, duration_ms, bytes, error) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    Self::table_name()
+                ),
+                params![requested_at, url, status, duration.as_millis() as i64, bytes as i64, error],
+            )?;
+            let id = guard.last_insert_rowid();
+            guard.execute(
+                &format!("DELETE FROM {} WHERE id <= ?1", Self::table_name()),
+                params![id - Self::MAX_ENTRIES],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Get the request with the given `id`, or the most recently failed one if `None`
+    pub fn get(&self, id: Option<i64>) -> Result<Option<RequestLogEntry>> {
+        let columns = "id, requested_at, url, status, duration_ms, bytes, error";
+        fn to_entry(r: &rusqlite::Row) -> rusqlite::Result<RequestLogEntry> {
+            Ok(RequestLogEntry {
+                id: r.get(0)?,
+                requested_at: std::time::UNIX_EPOCH + Duration::from_secs(r.get::<_, i64>(1)? as u64),
+                url: r.get(2)?,
+                status: r.get(3)?,
+                duration: Duration::from_millis(r.get::<_, i64>(4)? as u64),
+                bytes: r.get::<_, i64>(5)? as u64,
+                error: r.get(6)?,
+            })
+        }
+        let guard = self.inner.lock();
+        Ok(match id {
+            Some(id) => guard
+                .query_row(
+                    &format!("SELECT {} FROM {} WHERE id = ?1", columns, Self::table_name()),
+                    params![id],
+                    to_entry,
+                )
+                .optional()?,
+            None => guard
+                .query_row(
+                    &format!(
+                        "SELECT {} FROM {} WHERE error IS NOT NULL OR status NOT BETWEEN 200 AND 299 ORDER BY id DESC LIMIT 1",
+                        columns,
+                        Self::table_name()
+                    ),
+                    NO_PARAMS,
+                    to_entry,
+                )
+                .optional()?,
+        })
+    }
+}
+
+/// All attempts to publish the reports, which only keeps the most recent ones
+pub struct PublishTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl PublishTable {
+    const MAX_ENTRIES: i64 = 10_000;
+
+    pub fn table_name() -> &'static str {
+        "publish"
+    }
+
+    pub fn record(&self, publish: &Publish) -> Result<()> {
+        let published_at = publish.published_at.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        retry_on_db_busy(None, || {
+            let guard = self.inner.lock();
+            guard.execute(
+                &format!(
+                    "INSERT INTO {} (published_at, commit_id, files, push_error) VALUES (?1, ?2, ?3, ?4)",
+                    Self::table_name()
+                ),
+                params![published_at, publish.commit, publish.files as i64, publish.push_error],
+            )?;
+            let id = guard.last_insert_rowid();
+            guard.execute(
+                &format!("DELETE FROM {} WHERE id <= ?1", Self::table_name()),
+                params![id - Self::MAX_ENTRIES],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Return the most recent publish attempt, if there was one
+    pub fn most_recent(&self) -> Result<Option<Publish>> {
+        Ok(self
+            .inner
+            .lock()
+            .query_row(
+                &format!(
+                    "SELECT published_at, commit_id, files, push_error FROM {} ORDER BY id DESC LIMIT 1",
+                    Self::table_name()
+                ),
+                NO_PARAMS,
+                |r| {
+                    Ok(Publish {
+                        published_at: std::time::UNIX_EPOCH + Duration::from_secs(r.get::<_, i64>(0)? as u64),
+                        commit: r.get(1)?,
+                        files: r.get::<_, i64>(2)? as u64,
+                        push_error: r.get(3)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+}
+
+/// Crate versions whose processing crashed, and which are quarantined once they crashed too often
+pub struct CrateVersionQuarantineTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl CrateVersionQuarantineTable {
+    pub fn table_name() -> &'static str {
+        "crate_version_quarantine"
+    }
+
+    /// Record that processing the given crate version crashed for the given `reason`, and quarantine it if it crashed
+    /// at least `max_crashes` times. Return true if the crate version was quarantined by this call.
+    pub fn record_crash(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+        reason: &str,
+        max_crashes: usize,
+    ) -> Result<bool> {
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        retry_on_db_busy(None, || {
+            let mut guard = self.inner.lock();
+            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            transaction.execute(
+                &format!(
+                    "INSERT INTO {} (crate_name, crate_version, crashes, reason) VALUES (?1, ?2, 1, ?3)
+                     ON CONFLICT (crate_name, crate_version) DO UPDATE SET
+                        crashes = crashes + 1, reason = excluded.reason",
+                    Self::table_name()
+                ),
+                params![crate_name, crate_version, reason],
+            )?;
+            let newly_quarantined = transaction.execute(
+                &format!(
+                    "UPDATE {} SET quarantined_at = ?3
+                     WHERE crate_name = ?1 AND crate_version = ?2 AND quarantined_at IS NULL AND crashes >= ?4",
+                    Self::table_name()
+                ),
+                params![crate_name, crate_version, now, max_crashes as i64],
+            )? == 1;
+            transaction.commit()?;
+            Ok(newly_quarantined)
+        })
+    }
+
+    pub fn is_quarantined(&self, crate_name: &str, crate_version: &str) -> Result<bool> {
+        Ok(self
+            .inner
+            .lock()
+            .query_row(
+                &format!(
+                    "SELECT 1 FROM {} WHERE crate_name = ?1 AND crate_version = ?2 AND quarantined_at IS NOT NULL",
+                    Self::table_name()
+                ),
+                params![crate_name, crate_version],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    /// Return all quarantined crate versions, the most recently quarantined one first
+    pub fn quarantined(&self) -> Result<Vec<QuarantinedCrateVersion>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare(&format!(
+            "SELECT crate_name, crate_version, crashes, reason, quarantined_at FROM {}
+             WHERE quarantined_at IS NOT NULL ORDER BY quarantined_at DESC, crate_name, crate_version",
+            Self::table_name()
+        ))?;
+        let versions: rusqlite::Result<Vec<_>> = statement
+            .query_map(NO_PARAMS, |r| {
+                Ok(QuarantinedCrateVersion {
+                    crate_name: r.get(0)?,
+                    crate_version: r.get(1)?,
+                    crashes: r.get::<_, i64>(2)? as u64,
+                    reason: r.get(3)?,
+                    quarantined_at: std::time::UNIX_EPOCH + Duration::from_secs(r.get::<_, i64>(4)? as u64),
+                })
+            })?
+            .collect();
+        Ok(versions?)
+    }
+}
+
+/// A crate version which isn't processed anymore as it crashed the processors working on it too often
+#[derive(Debug, Clone)]
+pub struct QuarantinedCrateVersion {
+    pub crate_name: String,
+    pub crate_version: String,
+    /// The amount of times processing the crate version crashed
+    pub crashes: u64,
+    /// The reason of the most recent crash
+    pub reason: String,
+    pub quarantined_at: SystemTime,
+}
+
+/// A stage of processing a crate version whose cost is recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessingStage {
+    Download,
+    Extract,
+    Report,
+}
+
+impl ProcessingStage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProcessingStage::Download => "download",
+            ProcessingStage::Extract => "extract",
+            ProcessingStage::Report => "report",
+        }
+    }
+}
+
+/// What it took to process a crate version in one stage
+#[derive(Debug, Clone)]
+pub struct ProcessingCost {
+    pub crate_name: String,
+    pub crate_version: String,
+    pub stage: ProcessingStage,
+    /// The time it took, which is mostly spent on the CPU unless the stage is `Download`
+    pub duration: Duration,
+    /// The amount of bytes downloaded, of the extracted crate archive, or of the written report respectively
+    pub bytes: u64,
+}
+
+/// The cost of a stage for all versions of a crate
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StageCost {
+    pub duration: Duration,
+    pub bytes: u64,
+}
+
+/// The cost of processing all versions of a crate, by stage
+#[derive(Debug, Clone)]
+pub struct CrateProcessingCost {
+    pub crate_name: String,
+    /// The amount of versions for which the cost of at least one stage was recorded
+    pub num_versions: u64,
+    pub download: StageCost,
+    pub extract: StageCost,
+    pub report: StageCost,
+}
+
+impl CrateProcessingCost {
+    pub fn total_duration(&self) -> Duration {
+        self.download.duration + self.extract.duration + self.report.duration
+    }
+}
+
+/// The cost of the most recent processing of each crate version in each stage, for capacity planning
+pub struct ProcessingCostTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl ProcessingCostTable {
+    pub fn table_name() -> &'static str {
+        "processing_cost"
+    }
+
+    /// Record the `costs` of processing crate versions, replacing the ones recorded previously for the same stages
+    pub fn record(&self, costs: &[ProcessingCost]) -> Result<()> {
+        let recorded_at = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        retry_on_db_busy(None, || {
+            let mut guard = self.inner.lock();
+            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            {
+                let mut insert = transaction.prepare(&format!(
+                    "REPLACE INTO {} (crate_name, crate_version, stage, duration_us, bytes, recorded_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    Self::table_name()
+                ))?;
+                for cost in costs {
+                    insert.execute(params![
+                        cost.crate_name,
+                        cost.crate_version,
+                        cost.stage.as_str(),
+                        cost.duration.as_micros() as i64,
+                        cost.bytes as i64,
+                        recorded_at
+                    ])?;
+                }
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+    }
+
+    /// Return up to `limit` crates whose versions took the longest to process in all stages combined,
+    /// the most expensive one first.
+    pub fn most_expensive(&self, limit: usize) -> Result<Vec<CrateProcessingCost>> {
+        let guard = self.inner.lock();
+        let stage_columns: Vec<_> = [
+            ProcessingStage::Download,
+            ProcessingStage::Extract,
+            ProcessingStage::Report,
+        ]
+        .iter()
+        .map(|stage| {
+            format!(
+                "SUM(CASE stage WHEN '{stage}' THEN duration_us ELSE 0 END), \
+                 SUM(CASE stage WHEN '{stage}' THEN bytes ELSE 0 END)",
+                stage = stage.as_str()
+            )
+        })
+        .collect();
+        let mut statement = guard.prepare(&format!(
+            "SELECT crate_name, COUNT(DISTINCT crate_version), {} FROM {}
+             GROUP BY crate_name ORDER BY SUM(duration_us) DESC LIMIT ?1",
+            stage_columns.join(", "),
+            Self::table_name()
+        ))?;
+        let stage_cost = |r: &rusqlite::Row, idx: usize| -> rusqlite::Result<StageCost> {
+            Ok(StageCost {
+                duration: Duration::from_micros(r.get::<_, i64>(idx)? as u64),
+                bytes: r.get::<_, i64>(idx + 1)? as u64,
+            })
+        };
+        let costs: rusqlite::Result<Vec<_>> = statement
+            .query_map(params![limit as i64], |r| {
+                Ok(CrateProcessingCost {
+                    crate_name: r.get(0)?,
+                    num_versions: r.get::<_, i64>(1)? as u64,
+                    download: stage_cost(r, 2)?,
+                    extract: stage_cost(r, 4)?,
+                    report: stage_cost(r, 6)?,
+                })
+            })?
+            .collect();
+        Ok(costs?)
+    }
+}
+
+/// The amount of items in a work queue
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct QueueLength {
+    /// Items waiting to be claimed by a processor
+    pub queued: u64,
+    /// Items a processor is working on
+    pub claimed: u64,
+}
+
+/// Persistent work queues, from which items are claimed by priority and in the order they were enqueued.
+///
+/// Claimed items are kept until they are marked done. Claims are leases which are renewed while working on an item,
+/// and items are handed out again once their claim expired, as whoever claimed them appears to be gone.
+pub struct QueueTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl QueueTable {
+    pub fn table_name() -> &'static str {
+        "queue"
+    }
+
+    /// Add `payload` to `queue` with the given `priority`, unless an item with the same `key` is already queued.
+    /// Return true if the item was added.
+    pub fn push(&self, queue: &str, key: &str, priority: i64, payload: &[u8]) -> Result<bool> {
+        let enqueued_at = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        retry_on_db_busy(None, || {
+            Ok(self.inner.lock().execute(
+                &format!(
+                    "INSERT OR IGNORE INTO {} (queue, key, priority, enqueued_at, payload) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    Self::table_name()
+                ),
+                params![queue, key, priority, enqueued_at, payload],
+            )? == 1)
+        })
+    }
+
+    /// Claim the item with the highest priority which was enqueued first for `lease`, and return its id and payload.
+    /// Items whose claim wasn't renewed for longer than `lease` are claimed again.
+    pub fn claim(&self, queue: &str, lease: Duration) -> Result<Option<(i64, Vec<u8>)>> {
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        let expired_before = now - lease.as_secs() as i64;
+        retry_on_db_busy(None, || loop {
+            let guard = self.inner.lock();
+            let candidate = guard
+                .query_row(
+                    &format!(
+                        "SELECT id, payload FROM {} WHERE queue = ?1 AND (claimed_at IS NULL OR claimed_at < ?2)
+                         ORDER BY priority DESC, id LIMIT 1",
+                        Self::table_name()
+                    ),
+                    params![queue, expired_before],
+                    |r| Ok((r.get::<_, i64>(0)?, r.get::<_, Vec<u8>>(1)?)),
+                )
+                .optional()?;
+            let (id, payload) = match candidate {
+                Some(candidate) => candidate,
+                None => return Ok(None),
+            };
+            // Another process may have claimed the item in the mean time, in which case we try the next one
+            let claimed = guard.execute(
+                &format!(
+                    "UPDATE {} SET claimed_at = ?1 WHERE id = ?2 AND (claimed_at IS NULL OR claimed_at < ?3)",
+                    Self::table_name()
+                ),
+                params![now, id, expired_before],
+            )? == 1;
+            if claimed {
+                return Ok(Some((id, payload)));
+            }
+        })
+    }
+
+    /// Renew the claim on the item with the given `id`, while it's processed.
+    pub fn renew(&self, id: i64) -> Result<()> {
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        retry_on_db_busy(None, || {
+            self.inner.lock().execute(
+                &format!("UPDATE {} SET claimed_at = ?1 WHERE id = ?2", Self::table_name()),
+                params![now, id],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Give up the claim on the item with the given `id` without processing it, so it can be claimed again right away.
+    pub fn release(&self, id: i64) -> Result<()> {
+        retry_on_db_busy(None, || {
+            self.inner.lock().execute(
+                &format!("UPDATE {} SET claimed_at = NULL WHERE id = ?1", Self::table_name()),
+                params![id],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Remove the item with the given `id`, after it was processed.
+    pub fn remove(&self, id: i64) -> Result<()> {
+        retry_on_db_busy(None, || {
+            self.inner.lock().execute(
+                &format!("DELETE FROM {} WHERE id = ?1", Self::table_name()),
+                params![id],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Remove all items of `queue`, including claimed ones.
+    pub fn clear(&self, queue: &str) -> Result<()> {
+        retry_on_db_busy(None, || {
+            self.inner.lock().execute(
+                &format!("DELETE FROM {} WHERE queue = ?1", Self::table_name()),
+                params![queue],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Return the amount of items in `queue` which are not claimed.
+    pub fn len(&self, queue: &str) -> Result<u64> {
+        Ok(self.inner.lock().query_row(
+            &format!(
+                "SELECT COUNT(*) FROM {} WHERE queue = ?1 AND claimed_at IS NULL",
+                Self::table_name()
+            ),
+            params![queue],
+            |r| r.get::<_, i64>(0),
+        )? as u64)
+    }
+
+    /// Return the length of all non-empty queues by their name.
+    pub fn lengths(&self) -> Result<Vec<(String, QueueLength)>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare(&format!(
+            "SELECT queue, COUNT(*) - COUNT(claimed_at), COUNT(claimed_at) FROM {} GROUP BY queue ORDER BY queue",
+            Self::table_name()
+        ))?;
+        let lengths: rusqlite::Result<Vec<_>> = statement
+            .query_map(NO_PARAMS, |r| {
+                Ok((
+                    r.get::<_, String>(0)?,
+                    QueueLength {
+                        queued: r.get::<_, i64>(1)? as u64,
+                        claimed: r.get::<_, i64>(2)? as u64,
+                    },
+                ))
+            })?
+            .collect();
+        Ok(lengths?)
+    }
+}
+
+pub struct TaskResultTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl TableAccess for TaskResultTable {
+    type StorageItem = TaskResult;
+    type InsertItem = TaskResult;
+
+    fn connection(&self) -> &ThreadSafeConnection {
+        &self.inner
+    }
+    fn table_name() -> &'static str {
+        "result"
+    }
+    fn into_connection(self) -> ThreadSafeConnection {
+        self.inner
+    }
+}
+
+pub struct MetaTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl TableAccess for MetaTable {
+    type StorageItem = Context;
+    type InsertItem = Context;
+
+    fn connection(&self) -> &ThreadSafeConnection {
+        &self.inner
+    }
+    fn table_name() -> &'static str {
+        "meta"
+    }
+
+    fn merge(new: &Context, existing_item: Option<Context>) -> Self::StorageItem {
+        existing_item.map_or_else(|| new.to_owned(), |existing| existing.merge(new))
+    }
+    fn into_connection(self) -> ThreadSafeConnection {
+        self.inner
+    }
+}
+
+impl MetaTable {
+    pub fn update_today(&self, f: impl Fn(&mut Context)) -> Result<Context> {
+        self.update(None, Context::default().key(), |mut c| {
+            f(&mut c);
+            c
+        })
+    }
+
+    // NOTE: impl iterator is not allowed in traits unfortunately, but one could implement one manually
+    pub fn most_recent(&self) -> Result<Option<(String, Context)>> {
+        Ok(self
+            .connection()
+            .lock()
+            .query_row("SELECT key, data FROM meta ORDER BY key DESC limit 1", NO_PARAMS, |r| {
+                Ok((r.get::<_, String>(0)?, r.get::<_, Vec<u8>>(1)?))
+            })
+            .optional()?
+            .map(|(k, v)| (k, Context::from(v.as_slice()))))
+    }
+}
+
+#[derive(Clone)]
+pub struct CrateTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl TableAccess for CrateTable {
+    type StorageItem = Crate;
+    type InsertItem = CrateVersion;
+
+    fn connection(&self) -> &ThreadSafeConnection {
+        &self.inner
+    }
+    fn table_name() -> &'static str {
+        "crate"
+    }
+
+    fn merge(new_item: &CrateVersion, existing_item: Option<Crate>) -> Crate {
+        existing_item.map_or_else(|| Crate::from(new_item.to_owned()), |c| c.merge(new_item))
+    }
+    fn into_connection(self) -> ThreadSafeConnection {
+        self.inner
+    }
+}
+
+impl CrateTable {
+    /// Return the names of all crates without a single version, in the order they were first seen
+    pub fn without_versions(&self) -> Result<Vec<String>> {
+        let guard = self.inner.lock();
+        let mut statement = new_key_value_query_old_to_new(Self::table_name(), &guard)?;
+        let mut names = Vec::new();
+        for item in key_value_iter(&mut statement)? {
+            let (name, krate): (String, Crate) = item?;
+            if krate.versions.is_empty() {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    /// Mark the crate with the given `crate_name` and all of its versions as deleted at `deleted_at`, keeping their
+    /// data. Return false if the crate is unknown or was marked already.
+    pub fn tombstone(&self, crate_name: &str, deleted_at: SystemTime) -> Result<bool> {
+        retry_on_db_busy(None, || {
+            let mut guard = self.inner.lock();
+            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            let select = |table_name: &str, key: &str| -> rusqlite::Result<Option<Vec<u8>>> {
+                transaction
+                    .query_row(
+                        &format!("SELECT data FROM {} WHERE key = ?1", table_name),
+                        params![key],
+                        |r| r.get(0),
+                    )
+                    .optional()
+            };
+            let mut krate = match select(Self::table_name(), crate_name)?.map(|data| Crate::from(data.as_slice())) {
+                Some(krate) if krate.deleted_at.is_none() => krate,
+                _ => return Ok(false),
+            };
+            let mut key = String::new();
+            for version in &krate.versions {
+                key.clear();
+                CrateVersion::key_from(crate_name, version, &mut key);
+                if let Some(data) = select(CrateVersionTable::table_name(), &key)? {
+                    let mut crate_version = CrateVersion::from(data.as_slice());
+                    crate_version.deleted_at = Some(deleted_at);
+                    transaction.execute(
+                        &format!(
+                            "REPLACE INTO {} (key, data) VALUES (?1, ?2)",
+                            CrateVersionTable::table_name()
+                        ),
+                        params![key, rmp_serde::to_vec(&crate_version)?],
+                    )?;
+                }
+            }
+            krate.deleted_at = Some(deleted_at);
+            transaction.execute(
+                &format!("REPLACE INTO {} (key, data) VALUES (?1, ?2)", Self::table_name()),
+                params![crate_name, rmp_serde::to_vec(&krate)?],
+            )?;
+            transaction.commit()?;
+            Ok(true)
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct CrateVersionTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl TableAccess for CrateVersionTable {
+    type StorageItem = CrateVersion;
+    type InsertItem = CrateVersion;
+
+    fn connection(&self) -> &ThreadSafeConnection {
+        &self.inner
+    }
+    fn table_name() -> &'static str {
+        "crate_version"
+    }
+    fn into_connection(self) -> ThreadSafeConnection {
+        self.inner
+    }
+}
+
+/// Versions of crates the crates.io index and the db dump disagree about, as found by the most recent cross-check
+pub struct QuarantineTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl QuarantineTable {
+    pub fn table_name() -> &'static str {
+        "db_dump_quarantine"
+    }
+
+    /// Replace all rows quarantined while ingesting the previous db dump with `rows`
+    pub fn replace_all(&self, rows: &[QuarantinedRow]) -> Result<()> {
+        retry_on_db_busy(None, || {
+            let mut guard = self.inner.lock();
+            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            transaction.execute(&format!("DELETE FROM {}", Self::table_name()), NO_PARAMS)?;
+            {
+                let mut insert = transaction.prepare(&format!(
+                    "REPLACE INTO {} (table_name, line, column_name, content, error) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    Self::table_name()
+                ))?;
+                for row in rows {
+                    insert.execute(params![row.table, row.line as i64, row.column, row.content, row.error])?;
+                }
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+    }
+}
+
+/// Versions of crates whose archive turned out to be empty during extraction
+pub struct EmptyArtifactTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl EmptyArtifactTable {
+    pub fn table_name() -> &'static str {
+        "empty_artifact"
+    }
+
+    /// Record that the archive of the given crate version is empty for the given `reason`
+    pub fn record(&self, crate_name: &str, crate_version: &str, reason: &str) -> Result<()> {
+        retry_on_db_busy(None, || {
+            self.inner.lock().execute(
+                &format!(
+                    "REPLACE INTO {} (crate_name, crate_version, reason) VALUES (?1, ?2, ?3)",
+                    Self::table_name()
+                ),
+                params![crate_name, crate_version, reason],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Return all crate versions with an empty archive
+    pub fn all(&self) -> Result<Vec<EmptyArtifact>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare(&format!(
+            "SELECT crate_name, crate_version, reason FROM {} ORDER BY crate_name, crate_version",
+            Self::table_name()
+        ))?;
+        let artifacts: rusqlite::Result<Vec<_>> = statement
+            .query_map(NO_PARAMS, |r| {
+                Ok(EmptyArtifact {
+                    crate_name: r.get(0)?,
+                    crate_version: Some(r.get(1)?),
+                    reason: r.get(2)?,
+                })
+            })?
+            .collect();
+        Ok(artifacts?)
+    }
+}
+
+/// Which blob of the content-addressed asset store holds the archive of each crate version
+pub struct CrateBlobTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl CrateBlobTable {
+    pub fn table_name() -> &'static str {
+        "crate_blob"
+    }
+
+    /// Record that the archive of the given crate version was stored as the blob with the given `checksum`
+    pub fn record(&self, crate_name: &str, crate_version: &str, checksum: &str) -> Result<()> {
+        let stored_at = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        retry_on_db_busy(None, || {
+            self.inner.lock().execute(
+                &format!(
+                    "REPLACE INTO {} (crate_name, crate_version, checksum, stored_at) VALUES (?1, ?2, ?3, ?4)",
+                    Self::table_name()
+                ),
+                params![crate_name, crate_version, checksum, stored_at],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Return the checksum of the blob holding the archive of the given crate version, if it was stored as one
+    pub fn checksum_of(&self, crate_name: &str, crate_version: &str) -> Result<Option<String>> {
+        Ok(self
+            .inner
+            .lock()
+            .query_row(
+                &format!(
+                    "SELECT checksum FROM {} WHERE crate_name = ?1 AND crate_version = ?2",
+                    Self::table_name()
+                ),
+                params![crate_name, crate_version],
+                |r| r.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Return the checksums of all blobs along with the amount of crate versions whose archive they hold
+    pub fn checksums(&self) -> Result<Vec<(String, u64)>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare(&format!(
+            "SELECT checksum, COUNT(*) FROM {} GROUP BY checksum ORDER BY checksum",
+            Self::table_name()
+        ))?;
+        let checksums: rusqlite::Result<Vec<_>> = statement
+            .query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get::<_, i64>(1)? as u64)))?
+            .collect();
+        Ok(checksums?)
+    }
+}
+
+/// Which process works on a task, to let several processes sharing a database divide the work among themselves
+pub struct TaskLeaseTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl TaskLeaseTable {
+    pub fn table_name() -> &'static str {
+        "task_lease"
+    }
+
+    /// Lease the task with `task_key` to `owner` for `duration`, unless another owner holds a lease which didn't
+    /// expire yet. Return true if `owner` holds the lease afterwards. Leases are renewed by acquiring them again.
+    pub fn acquire(&self, task_key: &str, owner: &str, duration: Duration) -> Result<bool> {
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        let expires_at = now + duration.as_secs() as i64;
+        retry_on_db_busy(None, || {
+            Ok(self.inner.lock().execute(
+                &format!(
+                    "INSERT INTO {table} (task_key, owner, expires_at) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (task_key) DO UPDATE SET owner = excluded.owner, expires_at = excluded.expires_at
+                     WHERE {table}.owner = excluded.owner OR {table}.expires_at < ?4",
+                    table = Self::table_name()
+                ),
+                params![task_key, owner, expires_at, now],
+            )? == 1)
+        })
+    }
+
+    /// Give up the lease of `owner` on the task with `task_key`, so other processes may take it over right away
+    pub fn release(&self, task_key: &str, owner: &str) -> Result<()> {
+        retry_on_db_busy(None, || {
+            self.inner.lock().execute(
+                &format!("DELETE FROM {} WHERE task_key = ?1 AND owner = ?2", Self::table_name()),
+                params![task_key, owner],
+            )?;
+            Ok(())
+        })
+    }
+}
+
+/// The state of the files of crates in the sparse index as of when we fetched them last
+pub struct SparseIndexTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl SparseIndexTable {
+    pub fn table_name() -> &'static str {
+        "sparse_index_crate"
+    }
+
+    /// Record the `validators` of the files of the given crates, which were just fetched
+    pub fn record(&self, validators: &[(&str, &HttpValidators)]) -> Result<()> {
+        let fetched_at = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        retry_on_db_busy(None, || {
+            let mut guard = self.inner.lock();
+            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            {
+                let mut insert = transaction.prepare(&format!(
+                    "REPLACE INTO {} (crate_name, etag, last_modified, fetched_at) VALUES (?1, ?2, ?3, ?4)",
+                    Self::table_name()
+                ))?;
+                for (crate_name, validators) in validators {
+                    insert.execute(params![
+                        crate_name,
+                        validators.etag,
+                        validators.last_modified,
+                        fetched_at
+                    ])?;
+                }
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+    }
+
+    /// Return the validators of the files of all crates we fetched so far, keyed by lower-case crate name
+    pub fn all(&self) -> Result<BTreeMap<String, HttpValidators>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare(&format!(
+            "SELECT crate_name, etag, last_modified FROM {}",
+            Self::table_name()
+        ))?;
+        let validators: rusqlite::Result<BTreeMap<_, _>> = statement
+            .query_map(NO_PARAMS, |r| {
+                Ok((
+                    r.get(0)?,
+                    HttpValidators {
+                        etag: r.get(1)?,
+                        last_modified: r.get(2)?,
+                    },
+                ))
+            })?
+            .collect();
+        Ok(validators?)
+    }
+}
+
+/// The commits of git indices up to which changes are being stored, so fetches interrupted by a crash are detected
+pub struct IndexFetchTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl IndexFetchTable {
+    pub fn table_name() -> &'static str {
+        "index_fetch"
+    }
+
+    /// Record that the changes of the index of `registry` up to the commit with the given `target` id are about to be
+    /// stored, before the reference of the last seen commit is advanced to it.
+    pub fn begin(&self, registry: &str, target: &str) -> Result<()> {
+        let started_at = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        retry_on_db_busy(None, || {
+            self.inner.lock().execute(
+                &format!(
+                    "REPLACE INTO {} (registry, target_ref, started_at) VALUES (?1, ?2, ?3)",
+                    Self::table_name()
+                ),
+                params![registry, target, started_at],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Return the id of the commit a previous fetch of the index of `registry` was storing changes up to, if it was
+    /// interrupted before the reference of the last seen commit was advanced.
+    pub fn pending(&self, registry: &str) -> Result<Option<String>> {
+        Ok(self
+            .inner
+            .lock()
+            .query_row(
+                &format!("SELECT target_ref FROM {} WHERE registry = ?1", Self::table_name()),
+                params![registry],
+                |r| r.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Record that the reference of the last seen commit of the index of `registry` was advanced, which also forgets
+    /// all of its changes which weren't stored yet.
+    pub fn complete(&self, registry: &str) -> Result<()> {
+        retry_on_db_busy(None, || {
+            let mut guard = self.inner.lock();
+            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            for table_name in &[Self::table_name(), Self::changes_table_name()] {
+                transaction.execute(
+                    &format!("DELETE FROM {} WHERE registry = ?1", table_name),
+                    params![registry],
+                )?;
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+    }
+
+    /// The table holding the changes of a fetch which weren't stored yet
+    pub fn changes_table_name() -> &'static str {
+        "index_fetch_change"
+    }
+
+    /// Replace the changes of the index of `registry` which are about to be stored with `changes`, so that a fetch
+    /// which is interrupted can be resumed without fetching the index again.
+    pub fn record_changes(&self, registry: &str, changes: &[CrateVersion]) -> Result<()> {
+        retry_on_db_busy(None, || {
+            let mut guard = self.inner.lock();
+            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            transaction.execute(
+                &format!("DELETE FROM {} WHERE registry = ?1", Self::changes_table_name()),
+                params![registry],
+            )?;
+            {
+                let mut statement = transaction.prepare(&format!(
+                    "INSERT INTO {} (registry, seq, data) VALUES (?1, ?2, ?3)",
+                    Self::changes_table_name()
+                ))?;
+                for (seq, change) in changes.iter().enumerate() {
+                    statement.execute(params![registry, seq as i64, rmp_serde::to_vec(change)?])?;
+                }
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+    }
+
+    /// Return the changes of the index of `registry` which weren't stored yet, in order, along with the position of
+    /// the first one of them.
+    pub fn pending_changes(&self, registry: &str) -> Result<(i64, Vec<CrateVersion>)> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare(&format!(
+            "SELECT seq, data FROM {} WHERE registry = ?1 ORDER BY seq",
+            Self::changes_table_name()
+        ))?;
+        let mut rows = statement.query(params![registry])?;
+        let (mut first_seq, mut changes) = (None, Vec::new());
+        while let Some(row) = rows.next()? {
+            first_seq.get_or_insert(row.get::<_, i64>(0)?);
+            let data: Vec<u8> = row.get(1)?;
+            changes.push(CrateVersion::from(data.as_slice()));
+        }
+        Ok((first_seq.unwrap_or(0), changes))
+    }
+
+    /// The table holding the commits of indices which are only read, up to which all of their changes were stored
+    pub fn last_seen_table_name() -> &'static str {
+        "index_last_seen"
+    }
+
+    /// Return the id of the commit of the index of `registry` up to which all of its changes were stored, if it is
+    /// only read. Other indices keep it in a reference of their own.
+    pub fn last_seen(&self, registry: &str) -> Result<Option<String>> {
+        Ok(self
+            .inner
+            .lock()
+            .query_row(
+                &format!(
+                    "SELECT commit_id FROM {} WHERE registry = ?1",
+                    Self::last_seen_table_name()
+                ),
+                params![registry],
+                |r| r.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Record that all changes of the index of `registry` up to the commit with the given `commit` id were stored.
+    pub fn set_last_seen(&self, registry: &str, commit: &str) -> Result<()> {
+        let seen_at = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        retry_on_db_busy(None, || {
+            self.inner.lock().execute(
+                &format!(
+                    "REPLACE INTO {} (registry, commit_id, seen_at) VALUES (?1, ?2, ?3)",
+                    Self::last_seen_table_name()
+                ),
+                params![registry, commit, seen_at],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Forget the changes of the index of `registry` before position `seq` as part of `transaction`, which stores them.
+    pub fn forget_changes_before(transaction: &rusqlite::Transaction, registry: &str, seq: i64) -> Result<()> {
+        transaction
+            .prepare_cached(&format!(
+                "DELETE FROM {} WHERE registry = ?1 AND seq < ?2",
+                Self::changes_table_name()
+            ))?
+            .execute(params![registry, seq])?;
+        Ok(())
+    }
+}
+
+/// The work manifest of the current backfill, listing all crate versions to process in order
+pub struct BackfillManifestTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl BackfillManifestTable {
+    pub fn table_name() -> &'static str {
+        "backfill_manifest"
+    }
+
+    /// Replace the previous manifest with one listing `items`, to be processed in the given order
+    pub fn replace(&self, items: &[BackfillItem]) -> Result<()> {
+        retry_on_db_busy(None, || {
+            let mut guard = self.inner.lock();
+            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            transaction.execute(&format!("DELETE FROM {}", Self::table_name()), NO_PARAMS)?;
+            {
+                let mut insert = transaction.prepare(&format!(
+                    "INSERT INTO {} (crate_name, crate_version, priority, estimated_cost) VALUES (?1, ?2, ?3, ?4)",
+                    Self::table_name()
+                ))?;
+                for item in items {
+                    insert.execute(params![
+                        item.crate_name,
+                        item.crate_version,
+                        item.priority,
+                        item.estimated_cost as i64
+                    ])?;
+                }
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+    }
+
+    /// Return up to `limit` items which are not done yet along with their id, in the order they are to be processed
+    pub fn pending(&self, limit: usize) -> Result<Vec<(i64, BackfillItem)>> {
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare_cached(&format!(
+            "SELECT id, crate_name, crate_version, priority, estimated_cost FROM {}
+             WHERE done_at IS NULL ORDER BY id LIMIT ?1",
+            Self::table_name()
+        ))?;
+        let items: rusqlite::Result<Vec<_>> = statement
+            .query_map(params![limit as i64], |r| {
+                Ok((
+                    r.get(0)?,
+                    BackfillItem {
+                        crate_name: r.get(1)?,
+                        crate_version: r.get(2)?,
+                        priority: r.get(3)?,
+                        estimated_cost: r.get::<_, i64>(4)? as u64,
+                    },
+                ))
+            })?
+            .collect();
+        Ok(items?)
+    }
+
+    /// Mark all items up to and including the one with the given `id` as done
+    pub fn mark_done_through(&self, id: i64) -> Result<()> {
+        let done_at = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        retry_on_db_busy(None, || {
+            self.inner.lock().execute(
+                &format!(
+                    "UPDATE {} SET done_at = ?1 WHERE id <= ?2 AND done_at IS NULL",
+                    Self::table_name()
+                ),
+                params![done_at, id],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Return how far the backfill listed in the manifest has progressed
+    pub fn progress(&self) -> Result<BackfillProgress> {
+        let guard = self.inner.lock();
+        Ok(guard.query_row(
+            &format!(
+                "SELECT COUNT(*), COUNT(done_at), TOTAL(estimated_cost),
+                        TOTAL(CASE WHEN done_at IS NULL THEN 0 ELSE estimated_cost END)
+                 FROM {}",
+                Self::table_name()
+            ),
+            NO_PARAMS,
+            |r| {
+                Ok(BackfillProgress {
+                    num_items: r.get::<_, i64>(0)? as u64,
+                    num_items_done: r.get::<_, i64>(1)? as u64,
+                    total_cost: r.get::<_, f64>(2)? as u64,
+                    total_cost_done: r.get::<_, f64>(3)? as u64,
+                })
+            },
+        )?)
+    }
+}
+
+pub struct VersionDiscrepancyTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl VersionDiscrepancyTable {
+    pub fn table_name() -> &'static str {
+        "version_discrepancy"
+    }
+
+    /// Replace all previously found discrepancies with `discrepancies`
+    pub fn replace_all(&self, discrepancies: &[VersionDiscrepancy]) -> Result<()> {
+        retry_on_db_busy(None, || {
+            let mut guard = self.inner.lock();
+            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            transaction.execute(&format!("DELETE FROM {}", Self::table_name()), NO_PARAMS)?;
+            {
+                let mut insert = transaction.prepare(&format!(
+                    "REPLACE INTO {} (crate_name, crate_version, kind, index_value, db_dump_value) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    Self::table_name()
+                ))?;
+                for d in discrepancies {
+                    insert.execute(params![
+                        d.crate_name,
+                        d.crate_version,
+                        d.kind.as_str(),
+                        d.index,
+                        d.db_dump
+                    ])?;
+                }
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+    }
+
+    /// Return all discrepancies, skipping those of kinds we don't know
+    pub fn all(&self) -> Result<Vec<VersionDiscrepancy>> {
+        fn to_discrepancy(r: &rusqlite::Row) -> rusqlite::Result<Option<VersionDiscrepancy>> {
+            let kind = match DiscrepancyKind::parse(&r.get::<_, String>(2)?) {
+                Some(kind) => kind,
+                None => return Ok(None),
+            };
+            Ok(Some(VersionDiscrepancy {
+                crate_name: r.get(0)?,
+                crate_version: r.get(1)?,
+                kind,
+                index: r.get(3)?,
+                db_dump: r.get(4)?,
+            }))
+        }
+        let guard = self.inner.lock();
+        let mut statement = guard.prepare(&format!(
+            "SELECT crate_name, crate_version, kind, index_value, db_dump_value FROM {} ORDER BY crate_name, crate_version",
+            Self::table_name()
+        ))?;
+        let discrepancies: rusqlite::Result<Vec<_>> = statement.query_map(NO_PARAMS, to_discrepancy)?.collect();
+        Ok(discrepancies?.into_iter().flatten().collect())
     }
 }
@@ -0,0 +1,146 @@
+use crate::model::db_dump;
+#[cfg(feature = "export")]
+use rusqlite::{params, Connection, NO_PARAMS};
+#[cfg(feature = "export")]
+use std::collections::BTreeMap;
+
+/// Determines what happens to personally identifiable fields of crates.io actors, i.e. users and teams, when they are
+/// ingested or exported.
+///
+/// Emails of version authors are never stored, independently of the policy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Redaction {
+    /// Keep actors as they are.
+    Keep,
+    /// Replace GitHub logins and names by their hex-encoded SHA-256 hash and drop avatar urls.
+    /// Equal logins and names still have equal hashes, also across databases.
+    Hash,
+    /// Drop GitHub logins, names and avatar urls.
+    Drop,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Redaction::Keep
+    }
+}
+
+impl Redaction {
+    /// The name of the policy as recorded in the export manifest, and as parsed by `FromStr`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Redaction::Keep => "keep",
+            Redaction::Hash => "hash",
+            Redaction::Drop => "drop",
+        }
+    }
+
+    /// Redact the personally identifiable fields of `actor`, keeping its ids.
+    pub fn apply(self, actor: &mut db_dump::Actor) {
+        if self == Redaction::Keep {
+            return;
+        }
+        let (login, name) = self.redacted(&actor.github_login, actor.name.as_deref());
+        actor.github_login = login;
+        actor.name = name;
+        actor.github_avatar_url.clear();
+    }
+
+    /// Return the GitHub `login` and `name` of an actor as they are to be stored.
+    fn redacted(self, login: &str, name: Option<&str>) -> (String, Option<String>) {
+        match self {
+            Redaction::Keep => (login.to_owned(), name.map(ToOwned::to_owned)),
+            Redaction::Hash => (sha256_hex(login), name.map(sha256_hex)),
+            Redaction::Drop => (String::new(), None),
+        }
+    }
+}
+
+impl std::str::FromStr for Redaction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "keep" => Redaction::Keep,
+            "hash" => Redaction::Hash,
+            "drop" => Redaction::Drop,
+            _ => return Err(format!("Expected one of 'keep', 'hash' or 'drop', got '{}'", s)),
+        })
+    }
+}
+
+/// Apply `redaction` to all actors in the exported database `output`, including all references to them.
+///
+/// GitHub ids are unique and public, which is why they are replaced by pseudonyms as well unless actors are kept.
+/// These are negative so they can't collide with the ids they replace.
+#[cfg(feature = "export")]
+pub fn actors(output: &mut Connection, redaction: Redaction) -> crate::Result<()> {
+    if redaction == Redaction::Keep {
+        return Ok(());
+    }
+    let transaction = output.transaction()?;
+    {
+        let actors = {
+            let mut statement = transaction.prepare("SELECT github_id, github_login, name FROM 'crates.io-actor'")?;
+            let actors: rusqlite::Result<Vec<(i32, String, Option<String>)>> = statement
+                .query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?
+                .collect();
+            actors?
+        };
+        let mut update_actor = transaction.prepare(
+            "UPDATE 'crates.io-actor' SET github_login = ?2, name = ?3, github_avatar_url = '' WHERE github_id = ?1",
+        )?;
+        for (github_id, login, name) in actors {
+            let (login, name) = redaction.redacted(&login, name.as_deref());
+            update_actor.execute(params![github_id, login, name])?;
+        }
+    }
+
+    transaction.execute_batch(
+        "CREATE TEMP TABLE actor_pseudonym AS
+             SELECT github_id, -ROW_NUMBER() OVER (ORDER BY github_id) AS pseudonym FROM 'crates.io-actor';
+         CREATE UNIQUE INDEX temp.actor_pseudonym_by_github_id ON actor_pseudonym (github_id);
+         UPDATE 'crates.io-actor' SET
+             github_id = (SELECT pseudonym FROM actor_pseudonym p WHERE p.github_id = \"crates.io-actor\".github_id),
+             crates_io_id = 0;
+         UPDATE 'crates.io-crate' SET
+             created_by = (SELECT pseudonym FROM actor_pseudonym p WHERE p.github_id = created_by)
+             WHERE created_by IS NOT NULL;
+         UPDATE 'crates.io-crate_version' SET
+             published_by = (SELECT pseudonym FROM actor_pseudonym p WHERE p.github_id = published_by)
+             WHERE published_by IS NOT NULL;",
+    )?;
+    {
+        let pseudonyms = {
+            let mut statement = transaction.prepare("SELECT github_id, pseudonym FROM actor_pseudonym")?;
+            let pseudonyms: rusqlite::Result<BTreeMap<i32, i32>> = statement
+                .query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?)))?
+                .collect();
+            pseudonyms?
+        };
+        let owners_by_crate = {
+            let mut statement = transaction.prepare("SELECT name, owners FROM 'crates.io-crate'")?;
+            let owners: rusqlite::Result<Vec<(String, String)>> = statement
+                .query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?)))?
+                .collect();
+            owners?
+        };
+        let mut update_owners = transaction.prepare("UPDATE 'crates.io-crate' SET owners = ?2 WHERE name = ?1")?;
+        for (crate_name, owners) in owners_by_crate {
+            let owners: Vec<i32> = serde_json::from_str(&owners)?;
+            let owners: Vec<_> = owners
+                .into_iter()
+                .filter_map(|github_id| pseudonyms.get(&github_id).copied())
+                .collect();
+            update_owners.execute(params![crate_name, serde_json::to_string_pretty(&owners)?])?;
+        }
+    }
+    transaction.execute_batch("DROP TABLE actor_pseudonym")?;
+    transaction.commit()?;
+    Ok(())
+}
+
+fn sha256_hex(value: &str) -> String {
+    use sha2::Digest;
+    format!("{:x}", sha2::Sha256::digest(value.as_bytes()))
+}
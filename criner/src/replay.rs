@@ -0,0 +1,95 @@
+//! Re-issue requests recorded in the request log, to debug issues with content delivery networks or the content itself.
+use crate::{
+    engine::{run::DownloadSettings, work::iobound},
+    persistence::Db,
+    Error, Result,
+};
+use std::{io::Write, path::Path, time::SystemTime};
+
+/// Re-issue the request with the given `id` from the request log of the database at `db`, or the most recently failed one if `None`,
+/// and write everything we learn about it to `out`. It's sent like downloads with `download_settings`.
+pub fn run_blocking(
+    db: impl AsRef<Path>,
+    id: Option<i64>,
+    download_settings: &DownloadSettings,
+    mut out: impl Write,
+) -> Result<()> {
+    let db = Db::open_for_reading(db)?;
+    let entry = db.open_request_log()?.get(id)?.ok_or_else(|| {
+        Error::Message(match id {
+            Some(id) => format!("There is no request with id {} in the request log", id),
+            None => "There is no failed request in the request log".into(),
+        })
+    })?;
+
+    writeln!(
+        out,
+        "Request #{} at {}",
+        entry.id,
+        humantime::format_rfc3339(entry.requested_at)
+    )?;
+    writeln!(out, "  url: {}", entry.url)?;
+    writeln!(
+        out,
+        "  status: {}",
+        entry.status.map_or_else(|| "none".into(), |s| s.to_string())
+    )?;
+    writeln!(out, "  duration: {:?}", entry.duration)?;
+    writeln!(out, "  bytes received: {}", entry.bytes)?;
+    if let Some(err) = entry.error.as_ref() {
+        writeln!(out, "  error: {}", err)?;
+    }
+
+    writeln!(out, "\nReplaying GET {}", entry.url)?;
+    let client = iobound::client_builder(download_settings, &entry.url)?.build()?;
+    let report = futures_lite::future::block_on(crate::spawn(replay(client, entry.url)))?;
+    out.write_all(report.as_bytes())?;
+    Ok(())
+}
+
+async fn replay(client: reqwest::Client, url: String) -> Result<String> {
+    use std::fmt::Write;
+    let mut report = String::new();
+    let start = SystemTime::now();
+    let mut response = client.get(&url).send().await?;
+    writeln!(report, "{:?} {}", response.version(), response.status()).ok();
+    writeln!(report, "final url: {}", response.url()).ok();
+    for (name, value) in response.headers() {
+        writeln!(report, "{}: {}", name, value.to_str().unwrap_or("<not visible ASCII>")).ok();
+    }
+
+    let content_length = response.content_length();
+    let mut received = 0;
+    let mut chunks = 0;
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                received += chunk.len() as u64;
+                chunks += 1;
+            }
+            Ok(None) => break,
+            Err(err) => {
+                writeln!(report, "failed to receive body after {} bytes: {}", received, err).ok();
+                break;
+            }
+        }
+    }
+    writeln!(
+        report,
+        "received {} bytes in {} chunks within {:?}",
+        received,
+        chunks,
+        SystemTime::now().duration_since(start).unwrap_or_default()
+    )
+    .ok();
+    match content_length {
+        Some(content_length) if content_length != received => {
+            writeln!(report, "MISMATCH: content-length announced {} bytes", content_length).ok();
+        }
+        Some(_) => {}
+        None => {
+            writeln!(report, "no content-length was announced").ok();
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,49 @@
+//! Repair a database which missed crate versions, for instance after it was restored from an older backup.
+use crate::{engine::stage::changes, persistence::Db, Result};
+use std::{io::Write, path::Path};
+
+/// Walk all crates of the crates.io git index at `index_path`, store each version the database at `db` is missing or
+/// disagrees about, and write what was found to `out`.
+pub fn run_blocking(db: impl AsRef<Path>, index_path: impl AsRef<Path>, mut out: impl Write) -> Result<()> {
+    let db = Db::open(db)?;
+    let root = prodash::TreeOptions::default().create();
+    let resync = futures_lite::future::block_on(crate::spawn(changes::resync(
+        index_path.as_ref().to_owned(),
+        db,
+        changes::DEFAULT_BATCH_SIZE,
+        root.add_child("resync"),
+        None,
+    )))?;
+    writeln!(
+        out,
+        "Stored {} crate versions which were missing or differed, out of {} crates in the index",
+        resync.drifted_versions, resync.crates
+    )?;
+
+    let reconciliation = resync.reconciliation;
+    writeln!(
+        out,
+        "{} crates and {} crate versions in the index, {} crates and {} crate versions in the database",
+        reconciliation.index.crates,
+        reconciliation.index.crate_versions,
+        reconciliation.db.crates,
+        reconciliation.db.crate_versions
+    )?;
+    if reconciliation.tombstones.count > 0 {
+        writeln!(
+            out,
+            "{} crates aren't in the index anymore, e.g. {}",
+            reconciliation.tombstones.count,
+            reconciliation.tombstones.sample.join(", ")
+        )?;
+    }
+    if !reconciliation.is_healthy() {
+        writeln!(
+            out,
+            "{} crates in the index are still missing in the database, e.g. {}",
+            reconciliation.missing_in_db.count,
+            reconciliation.missing_in_db.sample.join(", ")
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,427 @@
+//! Run all stages of the engine against a tiny synthetic registry served from a local directory, and check the
+//! resulting database, reports and export, to catch regressions in the interplay of stages without network access.
+use crate::{
+    engine::{
+        report::{generic::Generator, waste},
+        run::{DbDownloadSettings, DownloadSettings, EventSink, ExtractionProfile, IndexSource},
+        stage,
+        work::schedule,
+    },
+    export, model,
+    persistence::{self, Db, TableAccess},
+    Error, Result,
+};
+use rusqlite::{Connection, NO_PARAMS};
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+/// The crates of the synthetic registry along with their downloads and versions, which are yanked if marked so
+const CRATES: &[(&str, u64, &[(&str, bool)])] = &[
+    ("smoke-alpha", 1000, &[("0.1.0", true), ("0.2.0", false)]),
+    ("smoke-beta", 10, &[("1.0.0", false)]),
+];
+
+/// The time after which the pipeline is considered stuck
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Serve a synthetic registry with a sparse index, crate archives and a db dump from a directory within `scratch_dir`,
+/// run all stages of the engine against it, check the outcome and write what happens to `out`.
+/// The directory is removed afterwards, unless a check failed, to be able to look at what went wrong.
+pub fn run_blocking(scratch_dir: impl AsRef<Path>, mut out: impl Write) -> Result<()> {
+    let dir = scratch_dir
+        .as_ref()
+        .join(format!("criner-smoke-{}", std::process::id()));
+    let start = SystemTime::now();
+    run(&dir, &mut out)?;
+    std::fs::remove_dir_all(&dir).ok();
+    writeln!(
+        out,
+        "All checks passed in {}",
+        humantime::format_duration(SystemTime::now().duration_since(start).unwrap_or_default())
+    )?;
+    Ok(())
+}
+
+fn run(dir: &Path, out: &mut impl Write) -> Result<()> {
+    let served_dir = dir.join("served");
+    write_registry(&served_dir)?;
+    let server = FileServer::serve(served_dir)?;
+    writeln!(out, "Serving the synthetic registry at {}", server.url(""))?;
+
+    let db_dir = dir.join("criner.db");
+    let db = Db::open(&db_dir)?;
+    let assets_dir = db_dir.join("assets");
+    let root = prodash::TreeOptions::default().create();
+    let (startup_time, deadline) = (SystemTime::now(), SystemTime::now() + TIMEOUT);
+    let download_settings = DownloadSettings {
+        crate_url_template: Some(server.url("crates/{crate}/{crate}-{version}.crate")),
+        ..Default::default()
+    };
+
+    writeln!(out, "Downloading and ingesting the db dump")?;
+    block_on(stage::db_download::schedule(
+        db.clone(),
+        assets_dir.clone(),
+        root.add_child("db dump"),
+        startup_time,
+        DbDownloadSettings {
+            url: server.url("db-dump.tar.gz"),
+            tables: vec!["crates".into(), "versions".into()],
+            ..Default::default()
+        },
+        download_settings.clone(),
+        Some(deadline),
+    ))?;
+    let connection = db.open_connection_no_async_with_busy_wait()?;
+    expect_count(&connection, "crates.io-crate", CRATES.len())?;
+
+    writeln!(out, "Fetching changes from the sparse index")?;
+    let events_path = dir.join("events.jsonl");
+    block_on(stage::changes::fetch(
+        IndexSource::Sparse(server.url("index")),
+        db.clone(),
+        None,
+        stage::changes::DEFAULT_BATCH_SIZE,
+        vec![EventSink::File(events_path.clone())],
+        None,
+        download_settings.clone(),
+        root.add_child("fetch"),
+        Some(deadline),
+    ))?;
+    let num_versions = CRATES.iter().map(|(_, _, versions)| versions.len()).sum();
+    expect_file(&events_path)?;
+    let num_events = std::fs::read_to_string(&events_path)?.lines().count();
+    if num_events != num_versions {
+        return Err(failed(format!(
+            "expected an event for each of the {} new crate versions, found {}",
+            num_versions, num_events
+        )));
+    }
+    expect_count(&connection, persistence::CrateVersionTable::table_name(), num_versions)?;
+    expect_count(&connection, persistence::CrateTable::table_name(), CRATES.len())?;
+    let crates = db.open_crates()?;
+    for (crate_name, _, versions) in CRATES {
+        let krate = crates
+            .get(crate_name)?
+            .ok_or_else(|| failed(format!("crate '{}' wasn't stored", crate_name)))?;
+        for (version, is_yanked) in versions.iter() {
+            if krate.is_yanked(version) != Some(*is_yanked) {
+                return Err(failed(format!(
+                    "{} {} should be {}",
+                    crate_name,
+                    version,
+                    if *is_yanked { "yanked" } else { "available" }
+                )));
+            }
+        }
+    }
+
+    writeln!(out, "Downloading and extracting crates")?;
+    block_on(stage::processing::process(
+        db.clone(),
+        None,
+        root.add_child("process"),
+        1,
+        1,
+        root.add_child("downloads"),
+        assets_dir.clone(),
+        ExtractionProfile::default(),
+        download_settings,
+        Vec::new(),
+        true,
+        true,
+        startup_time,
+        Some(deadline),
+    ))?;
+    wait_for_extractions(&db, deadline)?;
+
+    writeln!(out, "Generating reports")?;
+    block_on(stage::report::generate(
+        db.clone(),
+        root.add_child("reports"),
+        assets_dir,
+        None,
+        Some(deadline),
+        1,
+        100,
+        1,
+        None,
+    ))?;
+    let waste_report_dir = db_dir.join("reports").join(waste::Generator::name());
+    for (crate_name, _, versions) in CRATES {
+        let crate_dir = waste_report_dir.join(crate_name);
+        expect_file(&crate_dir.join("index.html"))?;
+        for (version, _) in versions.iter() {
+            expect_file(&crate_dir.join(format!("{}.html", version)))?;
+        }
+    }
+    expect_file(&waste_report_dir.join("index.html"))?;
+
+    writeln!(out, "Exporting the database")?;
+    let export_path = dir.join("export.sqlite");
+    export::run_blocking(
+        db_dir.join(persistence::SQLITE_FILE_NAME),
+        &export_path,
+        export::Redaction::Keep,
+    )?;
+    let exported = Connection::open(&export_path)?;
+    // Exported crates have a row per version
+    expect_count(&exported, "crate", num_versions)?;
+    expect_count(&exported, "crate_version", num_versions)?;
+    expect_count(&exported, "result_extract_crate", num_versions)?;
+    Ok(())
+}
+
+/// Run `future` on the executor of the engine and wait for it.
+fn block_on<T: Send + 'static>(future: impl std::future::Future<Output = T> + Send + 'static) -> T {
+    futures_lite::future::block_on(crate::spawn(future))
+}
+
+fn failed(msg: String) -> Error {
+    Error::Message(format!("Smoke test failed: {}", msg))
+}
+
+fn expect_count(connection: &Connection, table_name: &str, expected: usize) -> Result<()> {
+    let actual: i64 = connection.query_row(&format!("SELECT COUNT(*) FROM '{}'", table_name), NO_PARAMS, |r| {
+        r.get(0)
+    })?;
+    if actual as usize != expected {
+        return Err(failed(format!(
+            "expected {} rows in table '{}', found {}",
+            expected, table_name, actual
+        )));
+    }
+    Ok(())
+}
+
+fn expect_file(path: &Path) -> Result<()> {
+    if !path.is_file() {
+        return Err(failed(format!("expected a file at '{}'", path.display())));
+    }
+    Ok(())
+}
+
+/// Wait until all crate versions were extracted by processors running in the background.
+fn wait_for_extractions(db: &Db, deadline: SystemTime) -> Result<()> {
+    let connection = db.open_connection()?;
+    let mut key_buf = String::new();
+    loop {
+        let mut missing = Vec::new();
+        for (crate_name, _, versions) in CRATES {
+            for (version, _) in versions.iter() {
+                key_buf.clear();
+                match waste::Generator::get_result(connection.clone(), crate_name, version, &mut key_buf)? {
+                    Some(model::TaskResult::ExplodedCrate { .. }) => {}
+                    _ => missing.push(format!("{} {}", crate_name, version)),
+                }
+            }
+        }
+        if missing.is_empty() {
+            return Ok(());
+        }
+        if SystemTime::now() > deadline {
+            return Err(failed(format!("{} weren't extracted in time", missing.join(", "))));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Write the sparse index, the crate archives and a db dump of the synthetic registry into `dir`.
+fn write_registry(dir: &Path) -> Result<()> {
+    let mut crates_csv =
+        String::from("id,name,created_at,updated_at,description,documentation,downloads,homepage,readme,repository\n");
+    let mut versions_csv = String::from(
+        "id,crate_id,crate_size,created_at,updated_at,downloads,features,license,num,published_by,yanked,checksum\n",
+    );
+    const TIMESTAMP: &str = "2020-01-01 00:00:00";
+    let mut version_id = 0;
+    for (crate_id, (crate_name, downloads, versions)) in CRATES.iter().enumerate() {
+        crates_csv.push_str(&format!(
+            "{},{},{},{},A synthetic crate,,{},,,\n",
+            crate_id + 1,
+            crate_name,
+            TIMESTAMP,
+            TIMESTAMP,
+            downloads
+        ));
+        let mut index_file = String::new();
+        for (version, is_yanked) in versions.iter() {
+            let archive = crate_archive(crate_name, version)?;
+            let checksum = {
+                use sha2::Digest;
+                format!("{:x}", sha2::Sha256::digest(&archive))
+            };
+            write_file(
+                &dir.join("crates")
+                    .join(crate_name)
+                    .join(format!("{}-{}.crate", crate_name, version)),
+                &archive,
+            )?;
+            index_file.push_str(&serde_json::to_string(&serde_json::json!({
+                "name": crate_name,
+                "vers": version,
+                "deps": [],
+                "cksum": checksum,
+                "features": {},
+                "yanked": is_yanked,
+            }))?);
+            index_file.push('\n');
+            version_id += 1;
+            versions_csv.push_str(&format!(
+                "{},{},{},{},{},1,{{}},MIT,{},,{},{}\n",
+                version_id,
+                crate_id + 1,
+                archive.len(),
+                TIMESTAMP,
+                TIMESTAMP,
+                version,
+                if *is_yanked { "t" } else { "f" },
+                checksum
+            ));
+        }
+        write_file(
+            &dir.join("index")
+                .join(schedule::crate_prefix(crate_name))
+                .join(crate_name),
+            index_file.as_bytes(),
+        )?;
+    }
+
+    let mut builder = tar::Builder::new(libflate::gzip::Encoder::new(Vec::new())?);
+    for (name, content) in &[("crates.csv", crates_csv), ("versions.csv", versions_csv)] {
+        append(
+            &mut builder,
+            &format!("2020-01-01-020000/data/{}", name),
+            content.as_bytes(),
+        )?;
+    }
+    write_file(
+        &dir.join("db-dump.tar.gz"),
+        &builder.into_inner()?.finish().into_result()?,
+    )
+}
+
+/// A gzip compressed crate archive with a manifest, a source file and a few files which aren't needed to build it
+fn crate_archive(crate_name: &str, version: &str) -> Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(libflate::gzip::Encoder::new(Vec::new())?);
+    let manifest = format!(
+        "[package]\nname = \"{}\"\nversion = \"{}\"\nedition = \"2018\"\n",
+        crate_name, version
+    );
+    for (path, content) in &[
+        ("Cargo.toml", manifest.as_bytes()),
+        ("src/lib.rs", &b"pub fn answer() -> u32 {\n    42\n}\n"[..]),
+        ("tests/fixture.txt", &b"not needed to build the crate\n"[..]),
+        (".github/workflows/ci.yml", &b"on: [push]\n"[..]),
+    ] {
+        append(&mut builder, &format!("{}-{}/{}", crate_name, version, path), content)?;
+    }
+    Ok(builder.into_inner()?.finish().into_result()?)
+}
+
+fn append(builder: &mut tar::Builder<impl Write>, path: &str, content: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, content)?;
+    Ok(())
+}
+
+fn write_file(path: &Path, content: &[u8]) -> Result<()> {
+    std::fs::create_dir_all(path.parent().expect("files to be in a directory"))?;
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+/// A minimal HTTP server for the files in a directory, which answers just enough of the requests of the engine.
+/// It stops once dropped.
+struct FileServer {
+    addr: SocketAddr,
+    is_stopped: Arc<AtomicBool>,
+}
+
+impl FileServer {
+    fn serve(root: PathBuf) -> Result<FileServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let is_stopped = Arc::new(AtomicBool::new(false));
+        std::thread::spawn({
+            let is_stopped = is_stopped.clone();
+            move || {
+                for stream in listener.incoming() {
+                    if is_stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let root = root.clone();
+                        std::thread::spawn(move || respond(stream, &root).ok());
+                    }
+                }
+            }
+        });
+        Ok(FileServer { addr, is_stopped })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://{}/{}", self.addr, path)
+    }
+}
+
+impl Drop for FileServer {
+    fn drop(&mut self) {
+        self.is_stopped.store(true, Ordering::SeqCst);
+        // Wake up the listener to let it notice
+        TcpStream::connect(self.addr).ok();
+    }
+}
+
+/// Answer the request on `stream` with the file below `root` it asks for, ignoring its method and headers.
+fn respond(mut stream: TcpStream, root: &Path) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut request = Vec::new();
+    let mut buf = [0; 4096];
+    let start = Instant::now();
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let bytes_read = stream.read(&mut buf)?;
+        if bytes_read == 0 || start.elapsed() > TIMEOUT {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..bytes_read]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let path = request
+        .split_whitespace()
+        .nth(1)
+        .and_then(|path| path.split('?').next())
+        .unwrap_or_default()
+        .trim_start_matches('/');
+    let content = if path.split('/').any(|component| component == "..") {
+        None
+    } else {
+        std::fs::read(root.join(path)).ok()
+    };
+    match content {
+        Some(content) => {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                content.len()
+            )?;
+            stream.write_all(&content)?;
+        }
+        None => write!(
+            stream,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )?,
+    }
+    stream.flush()
+}
@@ -0,0 +1,50 @@
+//! Summarize the state of a database, including what needs the attention of an operator.
+use crate::{persistence::Db, Result};
+use std::{io::Write, path::Path};
+
+/// Write the amount of crates, the length of work queues and all quarantined crate versions of the database
+/// at `db` to `out`.
+pub fn run_blocking(db: impl AsRef<Path>, mut out: impl Write) -> Result<()> {
+    let db = Db::open_for_reading(db)?;
+    let counts = db
+        .open_context()?
+        .most_recent()?
+        .map(|(_, context)| context.counts)
+        .unwrap_or_default();
+    writeln!(
+        out,
+        "{} crates and {} crate versions",
+        counts.crates, counts.crate_versions
+    )?;
+
+    for (queue, length) in db.open_queue()?.lengths()? {
+        writeln!(
+            out,
+            "queue '{}': {} queued, {} in progress",
+            queue, length.queued, length.claimed
+        )?;
+    }
+
+    let quarantined = db.open_crate_version_quarantine()?.quarantined()?;
+    if quarantined.is_empty() {
+        writeln!(out, "No crate version is quarantined")?;
+        return Ok(());
+    }
+    writeln!(
+        out,
+        "{} crate versions are quarantined and need to be looked at:",
+        quarantined.len()
+    )?;
+    for version in quarantined {
+        writeln!(
+            out,
+            "  {}:{} since {} after {} crashes, the last one because {}",
+            version.crate_name,
+            version.crate_version,
+            humantime::format_rfc3339_seconds(version.quarantined_at),
+            version.crashes,
+            version.reason
+        )?;
+    }
+    Ok(())
+}
@@ -8,6 +8,11 @@ use futures_util::{
 use std::{
     convert::TryInto,
     future::Future,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::{Duration, SystemTime},
 };
 
@@ -25,6 +30,40 @@ pub fn parse_semver(version: &str) -> Semver {
         .expect("semver parsing to work if violating prerelease versions are stripped")
 }
 
+/// A 64 bit FNV-1a hash, which unlike the standard library hashers is guaranteed to be stable and thus can be persisted.
+pub fn fnv1a64(bytes: &[u8]) -> u64 {
+    fnv1a64_with_basis(0xcbf2_9ce4_8422_2325, bytes)
+}
+
+/// Like `fnv1a64()`, but starting at `basis` instead of the standard offset basis, for hashes that collide on
+/// different inputs for different values of `basis`.
+pub fn fnv1a64_with_basis(basis: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(basis, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Return the amount of bytes available to unprivileged users on the volume `path` is on.
+#[cfg(unix)]
+#[allow(unsafe_code)]
+pub fn available_space(path: &Path) -> std::io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is nul-terminated, and `stat` is only read if it was initialized by a successful call
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Return the amount of bytes available on the volume `path` is on, which isn't known on this platform.
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> std::io::Result<u64> {
+    Ok(u64::MAX)
+}
+
 pub async fn wait_with_progress(
     duration_s: usize,
     mut progress: prodash::tree::Item,
@@ -69,10 +108,16 @@ fn duration_until(time: Option<time::Time>) -> Duration {
         .unwrap_or_else(|_| Duration::from_secs(1))
 }
 
-pub async fn repeat_daily_at<MakeFut, MakeProgress, Fut, T>(
+/// Run the future made by `make_future` right away and then repeatedly at the `interval` it returns, aligned to the
+/// given `time` of day or the time of day at which it's called. Intervals shorter than a day repeat in steps of the
+/// interval from that time, longer ones at that time every so many whole days.
+/// If `at_most` is set, the future runs at most that many times.
+pub async fn repeat_every_at<MakeFut, MakeProgress, Fut, T>(
     time: Option<time::Time>,
+    mut interval: impl FnMut() -> Duration,
     mut make_progress: MakeProgress,
     deadline: Option<SystemTime>,
+    at_most: Option<usize>,
     mut make_future: MakeFut,
 ) -> Result<()>
 where
@@ -80,28 +125,48 @@ where
     MakeFut: FnMut() -> Fut,
     MakeProgress: FnMut() -> prodash::tree::Item,
 {
+    const DAY_S: u64 = 24 * 60 * 60;
+    let max_iterations = at_most.unwrap_or(std::usize::MAX);
     let mut iteration = 0;
     let time = desired_launch_at(time).time();
     loop {
+        if iteration == max_iterations {
+            return Ok(());
+        }
         iteration += 1;
-        if let Err(err) = make_future().await {
+        let res = make_future().await;
+        // The interval is looked up after each run, to apply changes to it right away
+        let interval_s = interval().as_secs().max(1);
+        if let Err(err) = res {
             make_progress().fail(format!(
-                "{} : ignored by repeat_daily_at('{:?}',…) iteration {}",
-                err, time, iteration
+                "{} : ignored by repeat_every_at('{:?}', {}s,…) iteration {}",
+                err, time, interval_s, iteration
             ))
         }
+        if iteration == max_iterations {
+            return Ok(());
+        }
+        let until_time_s = duration_until(Some(time)).as_secs();
+        let wait_s = if interval_s < DAY_S {
+            match until_time_s % interval_s {
+                0 => interval_s,
+                wait_s => wait_s,
+            }
+        } else {
+            until_time_s + (interval_s / DAY_S - 1) * DAY_S
+        };
         wait_with_progress(
-            duration_until(Some(time)).as_secs() as usize,
+            wait_s as usize,
             make_progress(),
             deadline,
-            Some(time),
+            if interval_s < DAY_S { None } else { Some(time) },
         )
         .await?;
     }
 }
 
-pub async fn repeat_every_s<MakeFut, MakeProgress, Fut, T>(
-    interval_s: usize,
+pub async fn repeat_every<MakeFut, MakeProgress, Fut, T>(
+    mut interval: impl FnMut() -> Duration,
     mut make_progress: MakeProgress,
     deadline: Option<SystemTime>,
     at_most: Option<usize>,
@@ -119,7 +184,9 @@ where
             return Ok(());
         }
         iteration += 1;
-        if let Err(err) = make_future().await {
+        let res = make_future().await;
+        let interval_s = interval().as_secs() as usize;
+        if let Err(err) = res {
             make_progress().fail(format!(
                 "{} : ignored by repeat_every({}s,…) iteration {}",
                 err, interval_s, iteration
@@ -144,16 +211,58 @@ pub fn check(deadline: Option<SystemTime>) -> Result<()> {
         .unwrap_or(Ok(()))
 }
 
-pub async fn handle_ctrl_c_and_sigterm<F, T>(f: F) -> Result<T>
-where
-    F: Future<Output = T> + Unpin,
-{
+/// A token shared with workers to let them know they should abandon their work, either because it was cancelled
+/// explicitly or because the deadline was reached.
+#[derive(Clone, Default)]
+pub struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<SystemTime>,
+}
+
+impl Cancellation {
+    pub fn new(deadline: Option<SystemTime>) -> Self {
+        Cancellation {
+            cancelled: Default::default(),
+            deadline,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst) || check(self.deadline).is_err()
+    }
+}
+
+/// Cancels the contained token when dropped, to stop workers if we return early or are dropped ourselves.
+pub struct CancelOnDrop(pub Cancellation);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Receives a message for each interrupt or termination signal. The handler can only be installed once per process,
+/// so all callers share it.
+static INTERRUPTS: once_cell::sync::Lazy<async_channel::Receiver<()>> = once_cell::sync::Lazy::new(|| {
     let (s, r) = async_channel::bounded(100);
     ctrlc::set_handler(move || {
         s.send(()).now_or_never();
     })
     .ok();
-    let selector = future::select(async move { r.recv().await }.boxed_local(), f);
+    r
+});
+
+/// Run `f` to completion, unless an interrupt or termination signal is received first. Signals received while
+/// no one is waiting for them interrupt the next caller right away.
+pub async fn handle_ctrl_c_and_sigterm<F, T>(f: F) -> Result<T>
+where
+    F: Future<Output = T> + Unpin,
+{
+    let selector = future::select(async move { INTERRUPTS.recv().await }.boxed_local(), f);
     match selector.await {
         Either::Left((_, _f)) => Err(Error::Interrupted),
         Either::Right((r, _interrupt)) => Ok(r),
@@ -171,6 +280,26 @@ where
     }
 }
 
+/// Run `f` to completion, unless the `deadline` is reached first, in which case `f` is dropped wherever it's waiting.
+pub async fn enforce<F, T>(deadline: Option<SystemTime>, f: F) -> Result<T>
+where
+    F: Future<Output = T>,
+{
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return Ok(f.await),
+    };
+    futures_util::pin_mut!(f);
+    let selector = future::select(
+        Timer::after(deadline.duration_since(SystemTime::now()).unwrap_or_default()),
+        f,
+    );
+    match selector.await {
+        Either::Left((_, _f)) => Err(Error::DeadlineExceeded(FormatDeadline(deadline))),
+        Either::Right((res, _delay)) => Ok(res),
+    }
+}
+
 /// Use this if `f()` might block forever, due to code that doesn't implement timeouts like libgit2 fetch does as it has no timeout
 /// on 'recv' bytes.
 ///
@@ -0,0 +1,64 @@
+//! Re-verify the integrity of the content-addressed store of downloaded crate archives.
+use crate::{
+    engine::work::{iobound::sha256_of_file, schedule::crate_blob_path},
+    persistence::Db,
+    Error, Result,
+};
+use std::{io::Write, path::Path};
+
+/// The amount of missing or corrupt blobs to name individually
+const MAX_LISTED_BLOBS: usize = 25;
+
+/// Hash every blob of the asset store belonging to the database at `db` and compare it to the checksum it's stored
+/// under, writing missing and corrupt blobs to `out`. Fails if there is at least one of them.
+pub fn run_blocking(db: impl AsRef<Path>, mut out: impl Write) -> Result<()> {
+    let assets_dir = db.as_ref().join("assets");
+    let db = Db::open_for_reading(db)?;
+    let blobs = db.open_crate_blobs()?.checksums()?;
+
+    let (mut crate_versions, mut missing, mut corrupt) = (0, 0, 0);
+    for (checksum, num_crate_versions) in &blobs {
+        crate_versions += num_crate_versions;
+        let path = crate_blob_path(&assets_dir, checksum);
+        let problem = match path.as_deref().map(sha256_of_file) {
+            Some(Ok(actual)) if actual == *checksum => continue,
+            Some(Ok(actual)) => {
+                corrupt += 1;
+                format!("corrupt, its content has checksum {}", actual)
+            }
+            Some(Err(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+                missing += 1;
+                "missing".to_owned()
+            }
+            Some(Err(err)) => return Err(err.into()),
+            None => {
+                corrupt += 1;
+                "not a valid sha256 checksum".to_owned()
+            }
+        };
+        if missing + corrupt <= MAX_LISTED_BLOBS {
+            writeln!(
+                out,
+                "{}: {} (holds {} crate versions)",
+                checksum, problem, num_crate_versions
+            )?;
+        }
+    }
+
+    writeln!(
+        out,
+        "Verified {} blobs holding {} crate versions: {} missing, {} corrupt",
+        blobs.len(),
+        crate_versions,
+        missing,
+        corrupt
+    )?;
+    if missing + corrupt > 0 {
+        return Err(Error::Message(format!(
+            "{} of {} blobs in the asset store are missing or corrupt",
+            missing + corrupt,
+            blobs.len()
+        )));
+    }
+    Ok(())
+}
@@ -5,6 +5,60 @@ fn parse_local_time(src: &str) -> Result<time::Time, time::ParseError> {
     time::parse(src, "%R")
 }
 
+fn split_host(src: &str) -> Result<(String, &str), String> {
+    let mut tokens = src.splitn(2, '=');
+    match (tokens.next(), tokens.next()) {
+        (Some(host), Some(value)) if !host.is_empty() => Ok((host.to_owned(), value)),
+        _ => Err(format!("Expected HOST=VALUE, got '{}'", src)),
+    }
+}
+
+fn parse_host_and_path(src: &str) -> Result<(String, PathBuf), String> {
+    split_host(src).map(|(host, path)| (host, path.into()))
+}
+
+fn parse_registry_and_path(src: &str) -> Result<(String, PathBuf), String> {
+    let (name, path) = split_host(src).map_err(|_| format!("Expected NAME=PATH, got '{}'", src))?;
+    if name.contains('/') {
+        return Err(format!("Registry names must not contain '/', got '{}'", name));
+    }
+    Ok((name, path.into()))
+}
+
+fn parse_registry_and_template(src: &str) -> Result<(String, String), String> {
+    split_host(src)
+        .map(|(name, template)| (name, template.to_owned()))
+        .map_err(|_| format!("Expected NAME=TEMPLATE, got '{}'", src))
+}
+
+fn parse_host_and_tls_version(src: &str) -> Result<(String, criner::run::TlsVersion), String> {
+    let (host, version) = split_host(src)?;
+    let version = match version {
+        "1.0" => criner::run::TlsVersion::Tls10,
+        "1.1" => criner::run::TlsVersion::Tls11,
+        "1.2" => criner::run::TlsVersion::Tls12,
+        _ => return Err(format!("Expected TLS version 1.0, 1.1 or 1.2, got '{}'", version)),
+    };
+    Ok((host, version))
+}
+
+fn parse_request_rate(src: &str) -> Result<f64, String> {
+    match src.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!(
+            "Expected a positive amount of requests per second, got '{}'",
+            src
+        )),
+    }
+}
+
+fn parse_download_rate(src: &str) -> Result<u64, String> {
+    match src.parse::<u64>() {
+        Ok(rate) if rate > 0 => Ok(rate),
+        _ => Err(format!("Expected a positive amount of KiB per second, got '{}'", src)),
+    }
+}
+
 #[derive(Debug, Clap)]
 #[clap(about = "Interact with crates.io from the command-line")]
 #[clap(setting = clap::AppSettings::ColoredHelp)]
@@ -62,6 +116,52 @@ pub enum SubCommands {
         #[clap(short = 'c', long, name = "REPO")]
         repository: Option<PathBuf>,
 
+        /// The url of a sparse index to fetch new and yanked crate versions from via HTTP, like 'https://index.crates.io'.
+        ///
+        /// If set, the git index isn't used. As the sparse index can't list all crates, only the ones known to the database
+        /// or the crates.io db dump are looked at, so new crates are discovered once they are part of a db dump.
+        #[clap(long, value_name = "URL", conflicts_with = "REPO")]
+        sparse_index: Option<String>,
+
+        /// Path to a bare clone of the crates.io index which is kept up to date by another process, like a mirror.
+        ///
+        /// If set, the index is never fetched or written to, which suits deployments without access to the network.
+        /// Changes are read up to the commit its HEAD points to. The first fetch from a mirror looks at all crates.
+        #[clap(long, value_name = "PATH", conflicts_with = "REPO")]
+        index_mirror: Option<PathBuf>,
+
+        /// The template of the url to download crate versions from, if the index isn't the one of crates.io.
+        ///
+        /// It's the 'dl' field of the 'config.json' file of the index, and supports the markers '{crate}', '{version}',
+        /// '{prefix}', '{lowerprefix}' and '{sha256-checksum}'. Without any, '/{crate}/{version}/download' is appended.
+        #[clap(long, value_name = "TEMPLATE")]
+        crate_url_template: Option<String>,
+
+        /// The template of the url of a mirror to download crate versions from if downloading them from the index fails
+        /// or times out, with the same format as '--crate-url-template'.
+        ///
+        /// Can be specified multiple times, and mirrors are tried in the given order. They don't apply to registries
+        /// added with '--registry'.
+        #[clap(long = "crate-mirror", value_name = "TEMPLATE")]
+        crate_mirror_templates: Vec<String>,
+
+        /// An alternate registry to mine alongside crates.io, in the form NAME=PATH, with PATH pointing to a clone of
+        /// its git index made with 'git clone --bare'.
+        ///
+        /// Can be specified multiple times. Crates of the registry are stored as 'NAME/crate' and need a crate url
+        /// template to be downloaded, see '--registry-crate-url-template'.
+        #[clap(long = "registry", value_name = "NAME=PATH", parse(try_from_str = parse_registry_and_path))]
+        registries: Vec<(String, PathBuf)>,
+
+        /// The template of the url to download crate versions of the alternate registry NAME from, in the form
+        /// NAME=TEMPLATE. See '--crate-url-template' for the supported markers.
+        #[clap(
+            long = "registry-crate-url-template",
+            value_name = "NAME=TEMPLATE",
+            parse(try_from_str = parse_registry_and_template)
+        )]
+        registry_crate_url_templates: Vec<(String, String)>,
+
         /// The amount of time we can take for the computation. Specified in humantime, like 10s, 5min, or 2h, or '3h 2min 2s'
         #[clap(long, short = 't')]
         time_limit: Option<humantime::Duration>,
@@ -74,6 +174,12 @@ pub enum SubCommands {
         #[clap(long, short = 'F')]
         fetch_at_most: Option<usize>,
 
+        /// The amount of new or changed crate versions to store per database transaction when fetching.
+        ///
+        /// Larger batches store faster, smaller ones hold the write lock of the database for less time.
+        #[clap(long, value_name = "N", default_value = "10000")]
+        fetch_batch_size: usize,
+
         /// The time between each processing run, specified in humantime, like 10s, 5min, or 2h, or '3h 2min 2s'
         #[clap(long, short = 'p', default_value = "5min")]
         process_every: humantime::Duration,
@@ -82,6 +188,40 @@ pub enum SubCommands {
         #[clap(long, short = 'P')]
         process_at_most: Option<usize>,
 
+        /// If set, reset the failed tasks of crate versions of crates matching the given standard unix glob once at
+        /// startup, and download and extract them again ahead of all other work.
+        ///
+        /// Use '*' to repair the fallout of a transient outage without waiting for the next sweep of all crates.
+        #[clap(long, value_name = "GLOB")]
+        requeue_failed: Option<String>,
+
+        /// The maximum amount of kilobytes to store of each file selected when extracting crates.
+        ///
+        /// Larger files are truncated, with their size and hash recorded instead. Manifests like Cargo.toml are always stored in full.
+        #[clap(long, value_name = "KB", default_value = "128")]
+        extraction_sample_size: usize,
+
+        /// A glob pattern of further files to select when extracting crates, relative to the crate root, like
+        /// 'README*' or 'src/**/*.rs'.
+        ///
+        /// Can be specified multiple times. Manifests, build scripts and the targets declared in manifests are always
+        /// selected.
+        #[clap(long = "extraction-select", value_name = "GLOB")]
+        extraction_selected_paths: Vec<String>,
+
+        /// The maximum amount of kilobytes to store of all files selected when extracting a crate together.
+        ///
+        /// Once reached, further selected files are truncated to their size and hash. If unset, it's unlimited.
+        #[clap(long, value_name = "KB")]
+        extraction_max_selected_size: Option<usize>,
+
+        /// The amount of times extracting a crate version may crash before it is quarantined and skipped from then on.
+        ///
+        /// Crashes are panics, or the process stopping while extracting, which happens if it is killed for using
+        /// too much memory. Quarantined crate versions are listed by `criner status`.
+        #[clap(long, value_name = "N", default_value = "3")]
+        quarantine_after_crashes: usize,
+
         /// The time between each reporting and processing run, specified in humantime, like 10s, 5min, or 2h, or '3h 2min 2s'
         #[clap(long, short = 'r', default_value = "5min")]
         report_every: humantime::Duration,
@@ -90,17 +230,242 @@ pub enum SubCommands {
         #[clap(long, short = 'R')]
         report_at_most: Option<usize>,
 
+        /// The amount of crates to generate reports for in one unit of work of a CPU- and output-bound processor.
+        #[clap(long, default_value = "500")]
+        report_chunk_size: usize,
+
+        /// The amount of chunks of crates that may wait for a CPU- and output-bound processor to pick them up.
+        ///
+        /// Chunks that are waiting or in progress when the time limit is reached are abandoned at the next crate.
+        #[clap(long, default_value = "1")]
+        report_chunks_in_flight: usize,
+
         /// If set, declare at which local time to download the crates.io database and digest it.
         ///
-        /// This job runs every 24h, as the database is updated that often.
+        /// This job runs every 24h by default, as the database is updated that often, see '--db-dump-every'.
         /// If unset, the job starts right away.
         /// Format is HH:MM, e.g. '14:30' for 2:30 pm or 03:15 for quarter past 3 in the morning.
         #[clap(long, short = 'd', parse(try_from_str = parse_local_time))]
         download_crates_io_database_every_24_hours_starting_at: Option<time::Time>,
 
-        /// If set, the reporting stage will only iterate over crates that match the given standard unix glob.
+        /// The time between each download of the crates.io database, specified in humantime, like 12h or 2days.
+        ///
+        /// Downloads stay aligned to the time of day they start at, so shorter intervals should divide a day evenly.
+        #[clap(long, default_value = "24h")]
+        db_dump_every: humantime::Duration,
+
+        /// A TOML file with the time between runs of some or all stages, overriding the ones given on the command-line.
+        ///
+        /// Tables named 'fetch', 'process', 'report' and 'db_dump' set the interval of their stage in humantime with
+        /// their 'every' key, like 'every = "1h"'. The file is read again whenever a stage ran, to apply changes
+        /// without restarting.
+        #[clap(long, value_name = "FILE")]
+        schedule: Option<PathBuf>,
+
+        /// The time of day in UTC at which crates.io publishes a new database dump, in the format HH:MM.
+        ///
+        /// If set, a database dump downloaded before that time is considered to be the one of the previous day,
+        /// so the new one is still downloaded once it's published on the same day.
+        #[clap(long, value_name = "HH:MM", parse(try_from_str = parse_local_time))]
+        db_dump_published_at_utc: Option<time::Time>,
+
+        /// What to do with personally identifiable fields of crates.io users and teams in the database dump before storing them.
+        ///
+        /// 'keep' stores them as they are, 'hash' replaces GitHub logins and names by their SHA-256 hash, and 'drop' removes them.
+        /// Unless they are kept, avatar urls are removed too.
+        #[clap(long, default_value = "keep", possible_values = &["keep", "hash", "drop"])]
+        db_dump_redact: criner::export::Redaction,
+
+        /// The url from which to download the crates.io database dump.
+        #[clap(
+            long,
+            env = "CRINER_DB_DUMP_URL",
+            value_name = "URL",
+            default_value = "https://static.crates.io/db-dump.tar.gz"
+        )]
+        db_dump_url: String,
+
+        /// A url to download the crates.io database dump from if the download from the primary url fails.
+        ///
+        /// Can be specified multiple times, mirrors are tried in the given order.
+        /// The environment variable is a comma-separated list of urls.
+        #[clap(
+            long = "db-dump-mirror",
+            env = "CRINER_DB_DUMP_MIRRORS",
+            value_name = "URL",
+            use_delimiter = true
+        )]
+        db_dump_mirrors: Vec<String>,
+
+        /// The amount of most recent crates.io database dumps to keep on disk after a successful ingestion.
+        #[clap(long, default_value = "1")]
+        db_dump_keep_last: usize,
+
+        /// If set, crates.io database dumps younger than the given duration are kept on disk as well.
+        ///
+        /// Specified in humantime, like 7days or 2weeks.
+        #[clap(long)]
+        db_dump_keep_for: Option<humantime::Duration>,
+
+        /// If set, ingest the crates.io database dump with roughly the given amount of megabytes of memory.
+        ///
+        /// Large tables are then streamed through a staging database on disk, which is slower but works on small machines.
+        /// If unset, all tables are held in memory, which requires multiple gigabytes of it.
+        #[clap(long, value_name = "MB")]
+        db_dump_memory_budget: Option<u64>,
+
+        /// If set, extract all tables of the crates.io database dump to disk and decode them in parallel.
+        ///
+        /// This reduces the time it takes to ingest the dump on machines with many cores, but is ignored if a memory budget is set.
+        #[clap(long)]
+        db_dump_parallel: bool,
+
+        /// If set, keep the CSV files of all ingested tables of the crates.io database dump in 'assets/crates-io-db/<date>/'.
+        ///
+        /// They are extracted alongside the ingestion and removed along with the database dump they were extracted from.
+        #[clap(long)]
+        db_dump_retain_csv: bool,
+
+        /// The name of a table of the crates.io database dump to ingest, like 'crate_owners'. If unset, all tables are ingested.
+        ///
+        /// Can be specified multiple times. 'crates' and 'versions' are always ingested. The information of tables which
+        /// aren't ingested is missing from all crates, like their owners, keywords or categories.
+        #[clap(long = "db-dump-table", value_name = "TABLE", use_delimiter = true)]
+        db_dump_tables: Vec<String>,
+
+        /// If set, rows of the crates.io database dump which can't be decoded are skipped instead of failing the ingestion.
+        ///
+        /// Skipped rows are recorded in the 'db_dump_quarantine' table along with their line, column and content.
+        #[clap(long)]
+        db_dump_lenient: bool,
+
+        /// Trust only the given certificate as root when connecting to a host, in the form HOST=PATH, with PATH
+        /// pointing to a PEM encoded certificate.
+        ///
+        /// Can be specified multiple times, also for the same host to trust multiple certificates.
+        /// Downloads from a host whose certificate isn't signed by one of its pinned certificates fail without retry.
+        #[clap(long = "tls-pin", value_name = "HOST=PATH", parse(try_from_str = parse_host_and_path))]
+        tls_pins: Vec<(String, PathBuf)>,
+
+        /// The oldest version of TLS to accept when connecting to a host, in the form HOST=VERSION, with VERSION
+        /// being one of 1.0, 1.1 or 1.2.
+        ///
+        /// Can be specified multiple times for different hosts.
+        #[clap(long = "tls-min-version", value_name = "HOST=VERSION", parse(try_from_str = parse_host_and_tls_version))]
+        tls_min_versions: Vec<(String, criner::run::TlsVersion)>,
+
+        /// The amount of KiB per second all downloads of crate versions and db dumps may receive together.
+        ///
+        /// Use it to avoid saturating a home connection or the one of a shared server. If unset, it's unlimited.
+        #[clap(long, value_name = "KiB/s", parse(try_from_str = parse_download_rate))]
+        max_download_rate: Option<u64>,
+
+        /// The amount of requests per second to send to crates.io and its subdomains at most, like for downloading
+        /// crate versions and db dumps or fetching from the sparse index.
+        ///
+        /// The crawler policy of crates.io asks for at most 1 request per second. If unset, it's unlimited.
+        #[clap(long, value_name = "REQUESTS/s", parse(try_from_str = parse_request_rate))]
+        crates_io_request_rate: Option<f64>,
+
+        /// The amount of requests to crates.io which may be sent at once after a pause, without waiting for the
+        /// '--crates-io-request-rate'.
+        #[clap(long, value_name = "REQUESTS", default_value = "1")]
+        crates_io_request_burst: u32,
+
+        /// The url of a proxy to send all downloads of crate versions and db dumps through, like 'http://proxy:3128'.
+        ///
+        /// SOCKS5 proxies are supported as well, like 'socks5://localhost:1080' for an SSH tunnel opened with
+        /// 'ssh -D 1080', or 'socks5h://localhost:9050' for Tor, which also resolves host names through the proxy.
+        /// If unset, the proxies in the HTTP_PROXY and HTTPS_PROXY environment variables are used.
+        /// Either way, hosts listed in NO_PROXY are connected to directly.
+        #[clap(long, value_name = "URL")]
+        proxy: Option<String>,
+
+        /// The User-Agent header to send with all downloads of crate versions and db dumps.
+        ///
+        /// The crawler policy of crates.io asks for one which says how to contact you, like
+        /// 'criner (mailto:you@example.com)'. If unset, it names the criner version and repository.
+        #[clap(long, value_name = "USER-AGENT")]
+        user_agent: Option<String>,
+
+        /// If set, adapt the amount of crate versions downloaded at once to how well the server copes with it.
+        ///
+        /// Up to '--io-bound-processors' downloads are performed at once, and half as many each time the server
+        /// times out or responds with a 429 or 503 status. It grows again while the server responds quickly.
+        #[clap(long)]
+        adaptive_download_concurrency: bool,
+
+        /// If set, pause all downloads while less than the given amount of megabytes are available on the volume of the
+        /// database, and resume them once enough space was reclaimed.
+        #[clap(long, value_name = "MB")]
+        min_free_space: Option<u64>,
+
+        /// If set, extract crate versions right after downloading them into memory, without ever writing their
+        /// archives to disk.
+        ///
+        /// This saves the disk space of all crate archives, but they have to be downloaded again to extract them anew.
+        #[clap(long)]
+        stream_extraction: bool,
+
+        /// A url to post an event to for each crate version that is stored for the first time, as soon as it is stored.
+        ///
+        /// Can be specified multiple times. The events of each fetch are posted as JSON arrays of up to 500 events.
+        #[clap(long = "notify-webhook", value_name = "URL")]
+        notify_webhooks: Vec<String>,
+
+        /// A file to append an event to for each crate version that is stored for the first time, as a JSON object
+        /// per line. It is created if it doesn't exist.
+        ///
+        /// Can be specified multiple times.
+        #[clap(long = "notify-file", value_name = "PATH")]
+        notify_files: Vec<PathBuf>,
+
+        /// The amount of IO-bound processors downloading crate versions as soon as a fetch stored them for the first
+        /// time.
+        ///
+        /// If set to 0, new crate versions are downloaded with all others by the next processing run. Otherwise
+        /// fresh publishes are downloaded within seconds, ahead of all other crate versions.
+        #[clap(long, value_name = "N", default_value = "0")]
+        fused_download_processors: u32,
+
+        /// The time downloads and extractions in progress may take to finish once all work is done or the process
+        /// is interrupted, specified in humantime, like 10s or 2min. No new ones are started meanwhile.
+        ///
+        /// Those still in progress afterwards are abandoned and done again on next start. Interrupt once more to
+        /// abandon them right away.
+        #[clap(long, default_value = "30s")]
+        shutdown_grace_period: humantime::Duration,
+
+        /// If set, a database written by a newer version of criner will be opened with a warning instead of failing.
         ///
-        /// moz* would match only crates starting with 'moz' for example.
+        /// Use with care as data might be misinterpreted, for example after rolling back to an older version.
+        #[clap(long)]
+        allow_newer_db: bool,
+
+        /// A stage of the pipeline to run, one of 'changes', 'db-download', 'download', 'extract', 'report' or
+        /// 'export'. If unset, all stages but 'export' run.
+        ///
+        /// Can be specified multiple times or as a comma-separated list, which makes it easy to debug a single stage.
+        /// 'download' alone only downloads crate versions, leaving their extraction to a run with 'extract'.
+        /// 'export' runs once all other stages are done, see '--export-to'.
+        #[clap(
+            long = "stage",
+            value_name = "STAGE",
+            use_delimiter = true,
+            possible_values = &["changes", "db-download", "download", "extract", "report", "export"]
+        )]
+        stages: Vec<criner::run::PipelineStage>,
+
+        /// The path to export the database to with the 'export' stage, which must not exist yet.
+        ///
+        /// Personally identifiable fields of users and teams are handled according to '--db-dump-redact'.
+        #[clap(long, value_name = "PATH")]
+        export_to: Option<PathBuf>,
+
+        /// If set, all stages only fetch, download, extract and report on crates matching the given standard unix glob.
+        ///
+        /// moz* would match only crates starting with 'moz' for example. Fetches limited this way don't advance the
+        /// crates index, so the next fetch without a glob still sees the changes to all other crates.
         #[clap(long, short = 'g')]
         glob: Option<String>,
 
@@ -122,7 +487,208 @@ pub enum SubCommands {
         input_db_path: PathBuf,
 
         /// Path to which to write the exported data. If it exists the operation will fail.
+        ///
+        /// If it ends with '.zst', like 'export.sqlite.zst', the exported database is compressed with zstd.
+        /// It's written uncompressed next to it first, and removed once it was compressed.
         export_db_path: PathBuf,
+
+        /// What to do with personally identifiable fields of crates.io users and teams, like their GitHub login.
+        ///
+        /// 'keep' exports them as they are, 'hash' replaces logins and names by their SHA-256 hash, and 'drop' removes them.
+        /// Unless they are kept, GitHub ids are replaced by pseudonyms and avatar urls are removed.
+        /// The policy is recorded in the 'export_manifest' table.
+        #[clap(long, default_value = "keep", possible_values = &["keep", "hash", "drop"])]
+        redact: criner::export::Redaction,
+    },
+    /// Write patches for the manifests of crates which publish files not needed to build them, along with a JSON file
+    /// describing a pull request for each of them.
+    ///
+    /// Patches are created for the latest version of each crate for which a waste report can be generated.
+    /// The JSON file is suitable for tooling which opens pull requests upstream automatically.
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    ExportPatches {
+        /// If set, only crates matching the given standard unix glob are considered.
+        #[clap(long, short = 'g')]
+        glob: Option<String>,
+
+        /// The path to the database containing extracted crates
+        input_db_path: PathBuf,
+
+        /// The directory into which to write 'pull-requests.json' and the 'patches' directory
+        output_dir: PathBuf,
+    },
+    /// Re-issue a request recorded in the request log with full verbosity, to debug issues with downloads.
+    #[clap(display_order = 3)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Replay {
+        /// The id of the request in the request log. If unset, the most recently failed request is replayed.
+        #[clap(long)]
+        id: Option<i64>,
+
+        /// The url of a proxy to send the request through, like with '--proxy' of 'mine'.
+        #[clap(long, value_name = "URL")]
+        proxy: Option<String>,
+
+        /// The User-Agent header to send with the request, like with '--user-agent' of 'mine'.
+        #[clap(long, value_name = "USER-AGENT")]
+        user_agent: Option<String>,
+
+        /// Path to the database containing the request log
+        #[clap(default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Ingest a crates.io database dump which was downloaded previously, without downloading it.
+    ///
+    /// Use it to work on the ingestion without network access. Old database dumps are not removed.
+    #[clap(display_order = 4)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    IngestDbDump {
+        /// If set, ingest the database dump with roughly the given amount of megabytes of memory.
+        #[clap(long, value_name = "MB")]
+        memory_budget: Option<u64>,
+
+        /// If set, extract all tables to disk and decode them in parallel. Ignored if a memory budget is set.
+        #[clap(long)]
+        parallel: bool,
+
+        /// The name of a table to ingest, like 'crate_owners'. If unset, all tables are ingested.
+        #[clap(long = "table", value_name = "TABLE", use_delimiter = true)]
+        tables: Vec<String>,
+
+        /// If set, skip rows which can't be decoded and record them in the 'db_dump_quarantine' table instead of failing.
+        #[clap(long)]
+        lenient: bool,
+
+        /// The path to the 'db-dump.tar.gz' file to ingest
+        db_dump_path: PathBuf,
+
+        /// Path to the database to ingest the dump into
+        #[clap(default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Measure how fast this machine stores and reads rows, extracts crates and computes and renders reports.
+    ///
+    /// Use the summary to choose the amount of processors and the size of report chunks for 'mine'.
+    #[clap(display_order = 5)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Bench {
+        /// The amount of times each operation is performed
+        #[clap(long, default_value = "1000")]
+        rounds: usize,
+
+        /// The directory in which to create a scratch database, which is removed afterwards.
+        /// Put it on the disk holding the database used for mining. If unset, the temporary directory is used.
+        scratch_dir: Option<PathBuf>,
+    },
+    /// Create a new database from a snapshot of the database of another instance, instead of mining from scratch.
+    ///
+    /// The snapshot contains the results of crate extractions, which is all that is needed to work on reports right away,
+    /// and continue mining from there. Snapshots of databases in use must be made with
+    /// `sqlite3 criner.db/db.msgpack.sqlite "VACUUM INTO 'snapshot.sqlite'"` to be consistent.
+    #[clap(display_order = 6)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Bootstrap {
+        /// If set, a snapshot written by a newer version of criner will be used with a warning instead of failing.
+        #[clap(long)]
+        allow_newer_db: bool,
+
+        /// The url of a proxy to download the snapshot through, like with '--proxy' of 'mine'.
+        #[clap(long, value_name = "URL")]
+        proxy: Option<String>,
+
+        /// The User-Agent header to send when downloading the snapshot, like with '--user-agent' of 'mine'.
+        #[clap(long, value_name = "USER-AGENT")]
+        user_agent: Option<String>,
+
+        /// The URL or path of the snapshot, which is decompressed if it ends with '.zst', like 'db.msgpack.sqlite.zst'
+        source: String,
+
+        /// Path to the database to create. It must not contain a database yet.
+        #[clap(default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Run all stages against a tiny synthetic registry which is served locally, and check the database, reports and
+    /// export they produce.
+    ///
+    /// It needs no network access and fails with a description of the first check that didn't pass, leaving its
+    /// scratch directory behind for inspection.
+    #[clap(display_order = 7)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Smoke {
+        /// The directory in which to create the registry and the database, which are removed if all checks pass.
+        /// If unset, the temporary directory is used.
+        scratch_dir: Option<PathBuf>,
+    },
+    /// List the crates which took the longest to process in all of their versions, as recorded while mining.
+    ///
+    /// Use it to decide which versions to process and which crates to exclude if capacity is limited.
+    #[clap(display_order = 8)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Costs {
+        /// The amount of crates to list, the most expensive one first
+        #[clap(long, default_value = "25")]
+        top: usize,
+
+        /// Path to the database containing the recorded processing costs
+        #[clap(default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Show the amount of crates, the length of work queues and the crate versions which need attention, like the ones
+    /// which are quarantined as processing them crashed too often.
+    #[clap(display_order = 10)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Status {
+        /// Path to the database to show the status of
+        #[clap(default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Work with crates.io database dumps without ingesting them
+    #[clap(display_order = 11)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Dump {
+        #[clap(subcommand)]
+        cmd: DumpCommands,
+    },
+    /// Extract and report on a directory of '.crate' files, like a vendored mirror or '~/.cargo/registry/cache',
+    /// without talking to any registry.
+    ///
+    /// Files are found in all subdirectories and named '<name>-<version>.crate'. Reports are written to
+    /// '<db-path>/reports' and cover all crates of the database, so use one per directory to report on it alone.
+    #[clap(display_order = 12)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    LocalCrates {
+        /// The directory containing the crate archives
+        crates_dir: PathBuf,
+
+        /// Path to the database to store extracted crates and reports in, which is created if needed
+        #[clap(default_value = "criner-local.db")]
+        db_path: PathBuf,
+    },
+    /// Walk all crates of the crates.io git index and store each version the database is missing or disagrees about.
+    ///
+    /// Use it to repair a database restored from an older backup or whose last seen reference in the index is broken,
+    /// which both cause versions to be missed silently. Afterwards, crates are reconciled with the index.
+    #[clap(display_order = 13)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Resync {
+        /// Path to the possibly existing crates.io repository clone. If unset, it will be cloned to a temporary spot.
+        #[clap(short = 'c', long, name = "REPO")]
+        repository: Option<PathBuf>,
+
+        /// Path to the database to repair
+        #[clap(default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Hash every crate archive in the content-addressed asset store and compare it to the checksum it's stored under.
+    ///
+    /// Missing and corrupt archives are listed, and the command fails if there is at least one.
+    #[clap(display_order = 14)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    VerifyAssets {
+        /// Path to the database whose assets to verify
+        #[clap(default_value = "criner.db")]
+        db_path: PathBuf,
     },
     #[cfg(feature = "migration")]
     /// A special purpose command only to be executed in special circumstances
@@ -130,6 +696,26 @@ pub enum SubCommands {
     Migrate,
 }
 
+#[derive(Debug, Clap)]
+pub enum DumpCommands {
+    /// Compare two db dumps row by row and show the crates, versions, owners and other rows which were added, removed
+    /// or changed in each table.
+    ///
+    /// Columns which change all the time, like download counts, are ignored. Rows which can't be decoded are skipped.
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Diff {
+        /// The amount of added, removed and changed rows to show per table
+        #[clap(long, default_value = "10")]
+        samples: usize,
+
+        /// Path to the older db dump, like 'db-dump.tar.gz'
+        old: PathBuf,
+
+        /// Path to the newer db dump
+        new: PathBuf,
+    },
+}
+
 impl Default for SubCommands {
     fn default() -> Self {
         SubCommands::Mine {
@@ -140,16 +726,60 @@ impl Default for SubCommands {
             cpu_bound_processors: 2,
             cpu_o_bound_processors: 10,
             repository: None,
+            sparse_index: None,
+            index_mirror: None,
+            crate_url_template: None,
+            crate_mirror_templates: Vec::new(),
+            registries: Vec::new(),
+            registry_crate_url_templates: Vec::new(),
             time_limit: None,
             fetch_every: std::time::Duration::from_secs(60).into(),
             fetch_at_most: None,
+            fetch_batch_size: 10_000,
             process_every: std::time::Duration::from_secs(60).into(),
+            requeue_failed: None,
             process_at_most: None,
+            extraction_sample_size: 128,
+            extraction_selected_paths: Vec::new(),
+            extraction_max_selected_size: None,
+            quarantine_after_crashes: 3,
             download_crates_io_database_every_24_hours_starting_at: Some(
                 parse_local_time("3:00").expect("valid statically known time"),
             ),
+            db_dump_every: std::time::Duration::from_secs(24 * 60 * 60).into(),
+            schedule: None,
+            db_dump_published_at_utc: None,
+            db_dump_redact: criner::export::Redaction::Keep,
+            db_dump_url: "https://static.crates.io/db-dump.tar.gz".into(),
+            db_dump_mirrors: Vec::new(),
+            db_dump_keep_last: 1,
+            db_dump_keep_for: None,
+            db_dump_memory_budget: None,
+            db_dump_parallel: false,
+            db_dump_retain_csv: false,
+            db_dump_tables: Vec::new(),
+            db_dump_lenient: false,
+            tls_pins: Vec::new(),
+            tls_min_versions: Vec::new(),
+            max_download_rate: None,
+            crates_io_request_rate: None,
+            crates_io_request_burst: 1,
+            proxy: None,
+            user_agent: None,
+            adaptive_download_concurrency: false,
+            min_free_space: None,
+            stream_extraction: false,
+            notify_webhooks: Vec::new(),
+            notify_files: Vec::new(),
+            fused_download_processors: 0,
+            shutdown_grace_period: std::time::Duration::from_secs(30).into(),
             report_every: std::time::Duration::from_secs(60).into(),
             report_at_most: None,
+            report_chunk_size: 500,
+            report_chunks_in_flight: 1,
+            allow_newer_db: false,
+            stages: Vec::new(),
+            export_to: None,
             db_path: PathBuf::from("criner.db"),
             glob: None,
         }
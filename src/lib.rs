@@ -4,6 +4,91 @@ mod args;
 pub mod error;
 pub use args::*;
 
+#[allow(clippy::too_many_arguments)]
+fn download_settings(
+    tls_pins: Vec<(String, std::path::PathBuf)>,
+    tls_min_versions: Vec<(String, criner::run::TlsVersion)>,
+    crate_url_template: Option<String>,
+    crate_mirror_templates: Vec<String>,
+    max_download_rate: Option<u64>,
+    crates_io_request_rate: Option<f64>,
+    crates_io_request_burst: u32,
+    proxy: Option<String>,
+    user_agent: Option<String>,
+    adaptive_concurrency: bool,
+    min_free_space: Option<u64>,
+    stream_extraction: bool,
+) -> criner::error::Result<criner::run::DownloadSettings> {
+    fn policy(tls: &mut Vec<criner::run::TlsPolicy>, host: String) -> &mut criner::run::TlsPolicy {
+        match tls.iter().position(|p| p.host == host) {
+            Some(idx) => &mut tls[idx],
+            None => {
+                tls.push(criner::run::TlsPolicy {
+                    host,
+                    pinned_certificates: Vec::new(),
+                    min_version: None,
+                });
+                tls.last_mut().expect("just pushed")
+            }
+        }
+    }
+    let mut tls = Vec::new();
+    for (host, path) in tls_pins {
+        policy(&mut tls, host).pinned_certificates.push(std::fs::read(path)?);
+    }
+    for (host, version) in tls_min_versions {
+        policy(&mut tls, host).min_version = Some(version);
+    }
+    Ok(criner::run::DownloadSettings {
+        tls,
+        crate_url_template,
+        crate_mirror_templates,
+        bandwidth: max_download_rate
+            .map(|kib| criner::run::Bandwidth::limited_to(kib * 1024))
+            .unwrap_or_default(),
+        request_rate: crates_io_request_rate
+            .map(|rate| criner::run::RequestRate::limited_to(rate, crates_io_request_burst))
+            .unwrap_or_default(),
+        proxy,
+        user_agent,
+        adaptive_concurrency,
+        min_free_space: min_free_space.map(|mb| mb * 1024 * 1024),
+        stream_extraction,
+    })
+}
+
+fn alternate_registries(
+    registries: Vec<(String, std::path::PathBuf)>,
+    crate_url_templates: Vec<(String, String)>,
+) -> criner::error::Result<Vec<criner::run::Registry>> {
+    registries
+        .into_iter()
+        .map(|(name, index_path)| {
+            let crate_url_template = crate_url_templates
+                .iter()
+                .find(|(template_name, _)| *template_name == name)
+                .map(|(_, template)| template.to_owned())
+                .ok_or_else(|| {
+                    criner::Error::Message(format!(
+                        "The registry '{}' needs a crate url template, set it with \
+                         --registry-crate-url-template {}=TEMPLATE",
+                        name, name
+                    ))
+                })?;
+            Ok(criner::run::Registry {
+                name,
+                index_path,
+                crate_url_template,
+            })
+        })
+        .collect()
+}
+
+/// The path to the clone of the crates.io git index, which defaults to one in the temporary directory
+fn crates_io_index_path(repository: Option<std::path::PathBuf>) -> std::path::PathBuf {
+    repository.unwrap_or_else(|| std::env::temp_dir().join("criner-crates-io-bare-index.git"))
+}
+
 pub fn run_blocking(args: Args) -> criner::error::Result<()> {
     use SubCommands::*;
     let cmd = args.sub.unwrap_or_default();
@@ -13,9 +98,91 @@ pub fn run_blocking(args: Args) -> criner::error::Result<()> {
         Export {
             input_db_path,
             export_db_path,
-        } => criner::export::run_blocking(input_db_path, export_db_path),
+            redact,
+        } => criner::export::run_blocking(input_db_path, export_db_path, redact),
+        ExportPatches {
+            glob,
+            input_db_path,
+            output_dir,
+        } => criner::export::patches::run_blocking(input_db_path, output_dir, glob),
+        Replay {
+            id,
+            proxy,
+            user_agent,
+            db_path,
+        } => criner::replay::run_blocking(
+            db_path,
+            id,
+            &criner::run::DownloadSettings {
+                proxy,
+                user_agent,
+                ..Default::default()
+            },
+            std::io::stdout(),
+        ),
+        Costs { top, db_path } => criner::costs::run_blocking(db_path, top, std::io::stdout()),
+        Status { db_path } => criner::status::run_blocking(db_path, std::io::stdout()),
+        Dump {
+            cmd: DumpCommands::Diff { samples, old, new },
+        } => criner::dump_diff::run_blocking(old, new, samples, std::io::stdout()),
+        Resync { repository, db_path } => {
+            criner::resync::run_blocking(db_path, crates_io_index_path(repository), std::io::stdout())
+        }
+        VerifyAssets { db_path } => criner::verify_assets::run_blocking(db_path, std::io::stdout()),
+        LocalCrates { crates_dir, db_path } => {
+            criner::local_crates::run_blocking(crates_dir, db_path, std::io::stdout())
+        }
+        Bench { rounds, scratch_dir } => criner::bench::run_blocking(
+            scratch_dir.unwrap_or_else(std::env::temp_dir),
+            rounds,
+            std::io::stdout(),
+        ),
+        Smoke { scratch_dir } => {
+            criner::smoke::run_blocking(scratch_dir.unwrap_or_else(std::env::temp_dir), std::io::stdout())
+        }
+        Bootstrap {
+            allow_newer_db,
+            proxy,
+            user_agent,
+            source,
+            db_path,
+        } => criner::bootstrap::run_blocking(
+            &source,
+            db_path,
+            allow_newer_db,
+            &criner::run::DownloadSettings {
+                proxy,
+                user_agent,
+                ..Default::default()
+            },
+            std::io::stdout(),
+        ),
+        IngestDbDump {
+            memory_budget,
+            parallel,
+            tables,
+            lenient,
+            db_dump_path,
+            db_path,
+        } => criner::ingest::run_blocking(
+            db_path,
+            db_dump_path,
+            criner::run::DbDownloadSettings {
+                ingest_memory_budget: memory_budget.map(|mb| mb * 1024 * 1024),
+                parallel_ingestion: parallel,
+                tables,
+                lenient_csv: lenient,
+                ..Default::default()
+            },
+        ),
         Mine {
             repository,
+            sparse_index,
+            index_mirror,
+            crate_url_template,
+            crate_mirror_templates,
+            registries,
+            registry_crate_url_templates,
             db_path,
             fps,
             time_limit,
@@ -26,50 +193,176 @@ pub fn run_blocking(args: Args) -> criner::error::Result<()> {
             progress_message_scrollback_buffer_size,
             fetch_every,
             fetch_at_most,
+            fetch_batch_size,
             process_at_most,
             process_every,
+            requeue_failed,
+            extraction_sample_size,
+            extraction_selected_paths,
+            extraction_max_selected_size,
+            quarantine_after_crashes,
             download_crates_io_database_every_24_hours_starting_at,
+            db_dump_every,
+            schedule,
+            db_dump_url,
+            db_dump_mirrors,
+            db_dump_keep_last,
+            db_dump_keep_for,
+            db_dump_memory_budget,
+            db_dump_parallel,
+            db_dump_retain_csv,
+            db_dump_tables,
+            db_dump_lenient,
+            db_dump_published_at_utc,
+            db_dump_redact,
+            tls_pins,
+            tls_min_versions,
+            max_download_rate,
+            crates_io_request_rate,
+            crates_io_request_burst,
+            proxy,
+            user_agent,
+            adaptive_download_concurrency,
+            min_free_space,
+            stream_extraction,
+            notify_webhooks,
+            notify_files,
+            fused_download_processors,
+            shutdown_grace_period,
             report_every,
             report_at_most,
+            report_chunk_size,
+            report_chunks_in_flight,
+            allow_newer_db,
+            stages,
+            export_to,
             glob,
-        } => criner::run::blocking(
-            db_path,
-            repository.unwrap_or_else(|| std::env::temp_dir().join("criner-crates-io-bare-index.git")),
-            time_limit.map(|d| std::time::SystemTime::now().add(*d)),
-            io_bound_processors,
-            cpu_bound_processors,
-            cpu_o_bound_processors,
-            criner::run::StageRunSettings {
-                every: fetch_every.into(),
-                at_most: fetch_at_most,
-            },
-            criner::run::StageRunSettings {
-                every: process_every.into(),
-                at_most: process_at_most,
-            },
-            criner::run::GlobStageRunSettings {
-                run: criner::run::StageRunSettings {
-                    every: report_every.into(),
-                    at_most: report_at_most,
+        } => {
+            let stages = if stages.is_empty() {
+                criner::run::PipelineStage::ENGINE.to_vec()
+            } else {
+                stages
+            };
+            let export_to = match export_to {
+                Some(path) if stages.contains(&criner::run::PipelineStage::Export) => Some(path),
+                None if stages.contains(&criner::run::PipelineStage::Export) => {
+                    return Err(criner::Error::Message(
+                        "The 'export' stage needs the path to export to, see '--export-to'".into(),
+                    ))
+                }
+                _ => None,
+            };
+            criner::run::blocking(
+                db_path.clone(),
+                match (sparse_index, index_mirror) {
+                    (Some(_), Some(_)) => {
+                        return Err(criner::Error::Message(
+                            "Only one of '--sparse-index' and '--index-mirror' can be set".into(),
+                        ))
+                    }
+                    (Some(url), None) => criner::run::IndexSource::Sparse(url),
+                    (None, Some(path)) => criner::run::IndexSource::Mirror(path),
+                    (None, None) => criner::run::IndexSource::Git(crates_io_index_path(repository)),
                 },
-                glob,
-            },
-            download_crates_io_database_every_24_hours_starting_at,
-            criner::prodash::TreeOptions {
-                message_buffer_capacity: progress_message_scrollback_buffer_size,
-                ..criner::prodash::TreeOptions::default()
+                alternate_registries(registries, registry_crate_url_templates)?,
+                time_limit.map(|d| std::time::SystemTime::now().add(*d)),
+                io_bound_processors,
+                cpu_bound_processors,
+                cpu_o_bound_processors,
+                stages,
+                criner::run::GlobStageRunSettings {
+                    run: criner::run::StageRunSettings {
+                        every: fetch_every.into(),
+                        at_most: fetch_at_most,
+                    },
+                    glob: glob.clone(),
+                },
+                fetch_batch_size,
+                criner::run::GlobStageRunSettings {
+                    run: criner::run::StageRunSettings {
+                        every: process_every.into(),
+                        at_most: process_at_most,
+                    },
+                    glob: glob.clone(),
+                },
+                requeue_failed,
+                criner::run::ExtractionProfile {
+                    sample_size: extraction_sample_size * 1024,
+                    selected_paths: extraction_selected_paths,
+                    max_selected_bytes: extraction_max_selected_size.map(|kb| kb * 1024),
+                    max_crashes: quarantine_after_crashes,
+                    ..Default::default()
+                },
+                criner::run::GlobStageRunSettings {
+                    run: criner::run::StageRunSettings {
+                        every: report_every.into(),
+                        at_most: report_at_most,
+                    },
+                    glob,
+                },
+                criner::run::ReportGenerationSettings {
+                    chunk_size: report_chunk_size,
+                    in_flight_chunks: report_chunks_in_flight,
+                    ..Default::default()
+                },
+                download_crates_io_database_every_24_hours_starting_at,
+                db_dump_every.into(),
+                criner::run::Schedule::from_file(schedule)?,
+                criner::run::DbDownloadSettings {
+                    url: db_dump_url,
+                    mirrors: db_dump_mirrors,
+                    keep_last: db_dump_keep_last,
+                    keep_for: db_dump_keep_for.map(Into::into),
+                    ingest_memory_budget: db_dump_memory_budget.map(|mb| mb * 1024 * 1024),
+                    parallel_ingestion: db_dump_parallel,
+                    retain_csv: db_dump_retain_csv,
+                    tables: db_dump_tables,
+                    lenient_csv: db_dump_lenient,
+                    published_at_utc: db_dump_published_at_utc,
+                    redaction: db_dump_redact,
+                },
+                download_settings(
+                    tls_pins,
+                    tls_min_versions,
+                    crate_url_template,
+                    crate_mirror_templates,
+                    max_download_rate,
+                    crates_io_request_rate,
+                    crates_io_request_burst,
+                    proxy,
+                    user_agent,
+                    adaptive_download_concurrency,
+                    min_free_space,
+                    stream_extraction,
+                )?,
+                notify_webhooks
+                    .into_iter()
+                    .map(criner::run::EventSink::Webhook)
+                    .chain(notify_files.into_iter().map(criner::run::EventSink::File))
+                    .collect(),
+                fused_download_processors,
+                shutdown_grace_period.into(),
+                allow_newer_db,
+                criner::prodash::TreeOptions {
+                    message_buffer_capacity: progress_message_scrollback_buffer_size,
+                    ..criner::prodash::TreeOptions::default()
+                }
+                .create(),
+                if no_gui {
+                    None
+                } else {
+                    Some(criner::prodash::render::tui::Options {
+                        title: "Criner".into(),
+                        frames_per_second: fps,
+                        recompute_column_width_every_nth_frame: Option::from(fps as usize),
+                        ..criner::prodash::render::tui::Options::default()
+                    })
+                },
+            )?;
+            match export_to {
+                Some(export_to) => criner::export::run_blocking(db_path, export_to, db_dump_redact),
+                None => Ok(()),
             }
-            .create(),
-            if no_gui {
-                None
-            } else {
-                Some(criner::prodash::render::tui::Options {
-                    title: "Criner".into(),
-                    frames_per_second: fps,
-                    recompute_column_width_every_nth_frame: Option::from(fps as usize),
-                    ..criner::prodash::render::tui::Options::default()
-                })
-            },
-        ),
+        }
     }
 }